@@ -0,0 +1,119 @@
+use std::error::Error;
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+
+use super::{Image, Uuid};
+
+/// Whether an [`AclUpdate`] adds accounts to or removes accounts from an image's ACL.
+#[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone, Copy)]
+pub enum AclAction {
+    Add,
+    Remove,
+}
+
+impl fmt::Display for AclAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Add => "add",
+            Self::Remove => "remove",
+        }
+        .fmt(f)
+    }
+}
+
+/// A change to the ACL of a private image, as sent to the AddImageAcl and RemoveImageAcl
+/// endpoints.
+///
+/// The action is sent as the `action` query parameter; the serialized form of this type is the
+/// request body, which is just the JSON array of account UUIDs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclUpdate {
+    pub action: AclAction,
+    pub accounts: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AclUpdateError {
+    message: String,
+}
+
+impl fmt::Display for AclUpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid ACL update: {}", self.message)
+    }
+}
+
+impl Error for AclUpdateError {}
+
+impl AclUpdate {
+    /// Creates an update, dropping duplicate accounts while preserving their order.
+    pub fn new(action: AclAction, accounts: impl IntoIterator<Item = Uuid>) -> Self {
+        let mut deduped = Vec::new();
+        for a in accounts {
+            if !deduped.contains(&a) {
+                deduped.push(a);
+            }
+        }
+        Self {
+            action,
+            accounts: deduped,
+        }
+    }
+
+    pub fn add(accounts: impl IntoIterator<Item = Uuid>) -> Self {
+        Self::new(AclAction::Add, accounts)
+    }
+
+    pub fn remove(accounts: impl IntoIterator<Item = Uuid>) -> Self {
+        Self::new(AclAction::Remove, accounts)
+    }
+
+    /// Checks that the update makes sense for `image`: it must name at least one account, must
+    /// not contain duplicates, and must not include the image's owner, who always has access.
+    pub fn validate(&self, image: &Image) -> Result<(), AclUpdateError> {
+        let err = |message: String| Err(AclUpdateError { message });
+        if self.accounts.is_empty() {
+            return err("no accounts given".to_string());
+        }
+        for (i, a) in self.accounts.iter().enumerate() {
+            if self.accounts[..i].contains(a) {
+                return err(format!("account {} given more than once", a));
+            }
+        }
+        if self.accounts.contains(&image.owner) {
+            return err(format!("account {} owns the image", image.owner));
+        }
+        Ok(())
+    }
+
+    /// Applies the update to a local copy of the manifest, as the server would.
+    ///
+    /// Removing the last account resets [`Image::acl`] to `None`.
+    pub fn apply_to(&self, image: &mut Image) {
+        match self.action {
+            AclAction::Add => {
+                let acl = image.acl.get_or_insert_with(Vec::new);
+                for a in &self.accounts {
+                    if !acl.contains(a) {
+                        acl.push(*a);
+                    }
+                }
+            }
+            AclAction::Remove => {
+                if let Some(acl) = image.acl.as_mut() {
+                    acl.retain(|a| !self.accounts.contains(a));
+                    if acl.is_empty() {
+                        image.acl = None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Serialize for AclUpdate {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        self.accounts.serialize(s)
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+
+use super::{Image, ImageSet, Uuid};
+
+/// Why an [`Ancestry`] could not be built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AncestryError {
+    /// The image whose ancestry was requested is not in the set.
+    NotFound(Uuid),
+
+    /// `image` has an origin that is not in the set.
+    DanglingOrigin { image: Uuid, origin: Uuid },
+
+    /// Following origins from the leaf led back to `image`.
+    Cycle(Uuid),
+}
+
+impl fmt::Display for AncestryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotFound(uuid) => write!(f, "image {} not found", uuid),
+            Self::DanglingOrigin { image, origin } => {
+                write!(f, "origin {} of image {} not found", origin, image)
+            }
+            Self::Cycle(uuid) => write!(f, "origin chain loops back to image {}", uuid),
+        }
+    }
+}
+
+impl Error for AncestryError {}
+
+/// The origin chain of an incremental image, ordered from the base image to the leaf.
+///
+/// Images must be installed (or mirrored) in this order.
+#[derive(Debug, Clone)]
+pub struct Ancestry {
+    images: Vec<Image>,
+}
+
+impl Ancestry {
+    /// Builds the ancestry of the image `leaf` by following origins through `set`.
+    ///
+    /// No network access is involved: every ancestor must already be in `set`.
+    pub fn from_set(set: &ImageSet, leaf: &Uuid) -> Result<Self, AncestryError> {
+        let mut images = Vec::new();
+        let mut seen = HashSet::new();
+        let mut current = set.get(leaf).ok_or(AncestryError::NotFound(*leaf))?;
+        loop {
+            if !seen.insert(current.uuid) {
+                return Err(AncestryError::Cycle(current.uuid));
+            }
+            images.push(current.clone());
+            match current.origin {
+                None => break,
+                Some(origin) => {
+                    current = set.get(&origin).ok_or(AncestryError::DanglingOrigin {
+                        image: current.uuid,
+                        origin,
+                    })?;
+                }
+            }
+        }
+        images.reverse();
+        Ok(Self { images })
+    }
+
+    /// Builds an ancestry from images already ordered from base to leaf, checking that each
+    /// image's origin is the one before it.
+    pub fn from_images(images: Vec<Image>) -> Result<Self, AncestryError> {
+        let mut seen = HashSet::new();
+        for (i, image) in images.iter().enumerate() {
+            if !seen.insert(image.uuid) {
+                return Err(AncestryError::Cycle(image.uuid));
+            }
+            let expected = if i == 0 {
+                None
+            } else {
+                Some(images[i - 1].uuid)
+            };
+            if image.origin != expected {
+                return match image.origin {
+                    Some(origin) => Err(AncestryError::DanglingOrigin {
+                        image: image.uuid,
+                        origin,
+                    }),
+                    None => Err(AncestryError::DanglingOrigin {
+                        image: images[i - 1].uuid,
+                        origin: image.uuid,
+                    }),
+                };
+            }
+        }
+        Ok(Self { images })
+    }
+
+    /// The number of images in the chain, including the leaf. An image without an origin has a
+    /// depth of 1.
+    pub fn depth(&self) -> usize {
+        self.images.len()
+    }
+
+    /// The image at the root of the chain, which has no origin.
+    pub fn base(&self) -> Option<&Image> {
+        self.images.first()
+    }
+
+    /// The image whose ancestry this is.
+    pub fn leaf(&self) -> Option<&Image> {
+        self.images.last()
+    }
+
+    /// Iterates over the images from base to leaf.
+    pub fn iter(&self) -> std::slice::Iter<'_, Image> {
+        self.images.iter()
+    }
+
+    /// The total number of bytes that must be downloaded to install the leaf image.
+    pub fn total_download_size(&self) -> u64 {
+        self.images
+            .iter()
+            .fold(0u64, |acc, i| acc.saturating_add(i.total_file_size()))
+    }
+
+    /// Returns the images in the chain that `set` doesn't contain, from base to leaf.
+    pub fn missing_from(&self, set: &ImageSet) -> Vec<&Image> {
+        self.images
+            .iter()
+            .filter(|i| !set.contains(&i.uuid))
+            .collect()
+    }
+
+    pub fn into_vec(self) -> Vec<Image> {
+        self.images
+    }
+}
+
+impl IntoIterator for Ancestry {
+    type Item = Image;
+    type IntoIter = std::vec::IntoIter<Image>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.images.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Ancestry {
+    type Item = &'a Image;
+    type IntoIter = std::slice::Iter<'a, Image>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.images.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testutil::{image, uuid};
+
+    /// Image `n`, with `origin` as its origin if it has one and a file of `n` bytes.
+    fn child(n: u128, origin: Option<u128>) -> Image {
+        image(
+            n,
+            json!({
+                "origin": origin.map(uuid),
+                "files": [{"sha1": "", "size": n as u64, "compression": "gzip"}],
+            }),
+        )
+    }
+
+    fn uuids(ancestry: &Ancestry) -> Vec<Uuid> {
+        ancestry.iter().map(|i| i.uuid).collect()
+    }
+
+    #[test]
+    fn follows_a_chain_from_base_to_leaf() {
+        let set: ImageSet = vec![child(3, Some(2)), child(1, None), child(2, Some(1))]
+            .into_iter()
+            .collect();
+        let ancestry = Ancestry::from_set(&set, &uuid(3)).unwrap();
+        assert_eq!(uuids(&ancestry), [uuid(1), uuid(2), uuid(3)]);
+        assert_eq!(ancestry.depth(), 3);
+        assert_eq!(ancestry.base().map(|i| i.uuid), Some(uuid(1)));
+        assert_eq!(ancestry.leaf().map(|i| i.uuid), Some(uuid(3)));
+        assert_eq!(ancestry.total_download_size(), 6);
+
+        let installed: ImageSet = vec![child(1, None)].into_iter().collect();
+        let missing: Vec<_> = ancestry
+            .missing_from(&installed)
+            .iter()
+            .map(|i| i.uuid)
+            .collect();
+        assert_eq!(missing, [uuid(2), uuid(3)]);
+
+        let base = Ancestry::from_set(&set, &uuid(1)).unwrap();
+        assert_eq!(uuids(&base), [uuid(1)]);
+
+        let ordered = Ancestry::from_images(ancestry.into_vec()).unwrap();
+        assert_eq!(uuids(&ordered), [uuid(1), uuid(2), uuid(3)]);
+    }
+
+    #[test]
+    fn a_chain_that_loops_is_a_cycle() {
+        let set: ImageSet = vec![child(1, Some(3)), child(2, Some(1)), child(3, Some(2))]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            Ancestry::from_set(&set, &uuid(3)).unwrap_err(),
+            AncestryError::Cycle(uuid(3))
+        );
+        let twice = vec![child(1, None), child(1, None)];
+        assert_eq!(
+            Ancestry::from_images(twice).unwrap_err(),
+            AncestryError::Cycle(uuid(1))
+        );
+    }
+
+    #[test]
+    fn a_missing_origin_is_dangling() {
+        let set: ImageSet = vec![child(2, Some(1)), child(3, Some(2))]
+            .into_iter()
+            .collect();
+        let dangling = AncestryError::DanglingOrigin {
+            image: uuid(2),
+            origin: uuid(1),
+        };
+        assert_eq!(Ancestry::from_set(&set, &uuid(3)).unwrap_err(), dangling);
+        assert_eq!(
+            dangling.to_string(),
+            format!("origin {} of image {} not found", uuid(1), uuid(2))
+        );
+
+        let out_of_order = vec![child(2, Some(1)), child(1, None)];
+        assert_eq!(
+            Ancestry::from_images(out_of_order).unwrap_err(),
+            AncestryError::DanglingOrigin {
+                image: uuid(2),
+                origin: uuid(1),
+            }
+        );
+    }
+
+    #[test]
+    fn an_image_not_in_the_set_isnt_found() {
+        let set: ImageSet = vec![child(1, None)].into_iter().collect();
+        assert_eq!(
+            Ancestry::from_set(&set, &uuid(9)).unwrap_err(),
+            AncestryError::NotFound(uuid(9))
+        );
+    }
+}
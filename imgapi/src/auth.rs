@@ -0,0 +1,529 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use base64::engine::general_purpose::{STANDARD as BASE64, STANDARD_NO_PAD as BASE64_NO_PAD};
+use base64::Engine;
+use chrono::Utc;
+use md5::{Digest, Md5};
+use openssl::bn::{BigNum, BigNumContext, BigNumRef};
+use openssl::ec::{EcGroup, EcKey, EcPoint, PointConversionForm};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::sign::Signer;
+use reqwest::blocking::RequestBuilder;
+use reqwest::header;
+use sha2::Sha256;
+
+/// The environment variable with the path of the SSH agent's socket.
+const AGENT_SOCK_VAR: &str = "SSH_AUTH_SOCK";
+
+/// Why a [`RequestSigner`] couldn't be set up.
+#[derive(Debug)]
+pub enum KeyError {
+    /// The key file couldn't be read.
+    Io(PathBuf, io::Error),
+
+    /// The key file is encrypted, and no passphrase was given.
+    NeedsPassphrase(PathBuf),
+
+    /// The passphrase didn't decrypt the key file.
+    BadPassphrase(PathBuf),
+
+    /// The key file isn't a private key in a format that can be read.
+    Invalid(PathBuf, String),
+
+    /// The key can't sign requests, e.g. because it's an ed25519 key.
+    Unsupported(String),
+
+    /// The key doesn't have the fingerprint it was given with.
+    WrongKey {
+        path: PathBuf,
+        key_id: String,
+        fingerprint: String,
+    },
+
+    /// The SSH agent couldn't be reached, or doesn't have the key.
+    Agent(String),
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            Self::NeedsPassphrase(path) => {
+                write!(f, "{}: the key needs a passphrase", path.display())
+            }
+            Self::BadPassphrase(path) => {
+                write!(
+                    f,
+                    "{}: the passphrase doesn't decrypt the key",
+                    path.display()
+                )
+            }
+            Self::Invalid(path, e) => write!(f, "{}: not a private key: {}", path.display(), e),
+            Self::Unsupported(e) => e.fmt(f),
+            Self::WrongKey {
+                path,
+                key_id,
+                fingerprint,
+            } => write!(
+                f,
+                "{}: the key's fingerprint is {}, not {}",
+                path.display(),
+                fingerprint,
+                key_id
+            ),
+            Self::Agent(e) => write!(f, "SSH agent: {}", e),
+        }
+    }
+}
+
+impl Error for KeyError {}
+
+/// Where a [`RequestSigner`]'s private key is.
+#[derive(Clone)]
+enum Key {
+    Private(PKey<Private>),
+
+    /// In the SSH agent listening on this socket.
+    Agent(PathBuf),
+}
+
+/// Signs requests with the HTTP Signature scheme that IMGAPI and the rest of Triton use, so that
+/// private servers accept them.
+///
+/// Each request gets a `Date` header and an `Authorization` header with a signature of it, made
+/// either with a private key read from a file or by the SSH agent. RSA and ECDSA keys are
+/// supported.
+#[derive(Clone)]
+pub struct RequestSigner {
+    account: String,
+
+    /// The key's MD5 fingerprint, which is how the server looks it up.
+    fingerprint: String,
+
+    /// The key in SSH wire format.
+    public_key: Vec<u8>,
+
+    key: Key,
+}
+
+impl fmt::Debug for RequestSigner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RequestSigner")
+            .field("key_id", &self.key_id())
+            .field("agent", &matches!(self.key, Key::Agent(_)))
+            .finish()
+    }
+}
+
+impl RequestSigner {
+    /// Signs requests as `account` with the private key in the file at `path`, in PEM or OpenSSH
+    /// format. Encrypted keys need `passphrase`, and fail with [`KeyError::NeedsPassphrase`]
+    /// without it. Keys encrypted in OpenSSH's own format can't be read, and have to be used
+    /// through the SSH agent.
+    ///
+    /// If `key_id` is given, it's checked against the key's fingerprint, which may be given as
+    /// MD5 (`aa:bb:...`) or SHA-256 (`SHA256:...`), as `ssh-keygen -l` prints them.
+    pub fn from_key_file(
+        account: &str,
+        key_id: Option<&str>,
+        path: &Path,
+        passphrase: Option<&[u8]>,
+    ) -> Result<Self, KeyError> {
+        let key = read_key(path, passphrase)?;
+        let public_key = public_key_blob(&key)
+            .map_err(|e| KeyError::Invalid(path.to_path_buf(), e.to_string()))?;
+        if let Some(key_id) = key_id {
+            if !has_fingerprint(&public_key, key_id) {
+                return Err(KeyError::WrongKey {
+                    path: path.to_path_buf(),
+                    key_id: key_id.to_string(),
+                    fingerprint: md5_fingerprint(&public_key),
+                });
+            }
+        }
+        Ok(Self {
+            account: account.to_string(),
+            fingerprint: md5_fingerprint(&public_key),
+            public_key,
+            key: Key::Private(key),
+        })
+    }
+
+    /// Signs requests as `account` with the key in the SSH agent that has the fingerprint `key_id`.
+    pub fn from_agent(account: &str, key_id: &str) -> Result<Self, KeyError> {
+        let socket = std::env::var_os(AGENT_SOCK_VAR)
+            .map(PathBuf::from)
+            .ok_or_else(|| KeyError::Agent(format!("{} isn't set", AGENT_SOCK_VAR)))?;
+        let public_key = agent::identities(&socket)
+            .map_err(|e| KeyError::Agent(e.to_string()))?
+            .into_iter()
+            .find(|blob| has_fingerprint(blob, key_id))
+            .ok_or_else(|| KeyError::Agent(format!("no key with fingerprint {}", key_id)))?;
+        algorithm(&public_key)?;
+        Ok(Self {
+            account: account.to_string(),
+            fingerprint: md5_fingerprint(&public_key),
+            public_key,
+            key: Key::Agent(socket),
+        })
+    }
+
+    /// The account requests are signed as.
+    pub fn account(&self) -> &str {
+        &self.account
+    }
+
+    /// The `keyId` the server is told about: `/<account>/keys/<MD5 fingerprint>`.
+    pub fn key_id(&self) -> String {
+        format!("/{}/keys/{}", self.account, self.fingerprint)
+    }
+
+    /// Adds the `Date` and `Authorization` headers to `req`.
+    pub(crate) fn sign(&self, req: RequestBuilder) -> Result<RequestBuilder, Box<dyn Error>> {
+        let date = Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        let (algorithm, digest) = algorithm(&self.public_key)?;
+        let data = format!("date: {}", date);
+        let signature = match &self.key {
+            Key::Private(key) => {
+                let mut signer = Signer::new(digest, key)?;
+                signer.update(data.as_bytes())?;
+                signer.sign_to_vec()?
+            }
+            Key::Agent(socket) => agent::sign(socket, &self.public_key, data.as_bytes())?,
+        };
+        let authorization = format!(
+            "Signature keyId=\"{}\",algorithm=\"{}\",headers=\"date\",signature=\"{}\"",
+            self.key_id(),
+            algorithm,
+            BASE64.encode(signature)
+        );
+        Ok(req
+            .header(header::DATE, date)
+            .header(header::AUTHORIZATION, authorization))
+    }
+}
+
+/// The HTTP Signature algorithm for a key in SSH wire format, and the digest it uses.
+fn algorithm(public_key: &[u8]) -> Result<(&'static str, MessageDigest), KeyError> {
+    let key_type = Wire(public_key).string().unwrap_or_default();
+    match key_type {
+        b"ssh-rsa" => Ok(("rsa-sha256", MessageDigest::sha256())),
+        b"ecdsa-sha2-nistp256" => Ok(("ecdsa-sha256", MessageDigest::sha256())),
+        b"ecdsa-sha2-nistp384" => Ok(("ecdsa-sha384", MessageDigest::sha384())),
+        b"ecdsa-sha2-nistp521" => Ok(("ecdsa-sha512", MessageDigest::sha512())),
+        t => Err(KeyError::Unsupported(format!(
+            "{} keys can't sign requests; use an RSA or ECDSA key",
+            String::from_utf8_lossy(t)
+        ))),
+    }
+}
+
+/// The SSH curve name for an OpenSSL curve.
+fn curve_name(nid: Option<Nid>) -> Option<&'static str> {
+    match nid? {
+        Nid::X9_62_PRIME256V1 => Some("nistp256"),
+        Nid::SECP384R1 => Some("nistp384"),
+        Nid::SECP521R1 => Some("nistp521"),
+        _ => None,
+    }
+}
+
+fn curve_nid(name: &[u8]) -> Option<Nid> {
+    match name {
+        b"nistp256" => Some(Nid::X9_62_PRIME256V1),
+        b"nistp384" => Some(Nid::SECP384R1),
+        b"nistp521" => Some(Nid::SECP521R1),
+        _ => None,
+    }
+}
+
+/// `key`'s public key in SSH wire format, as in `authorized_keys`, which is what fingerprints are
+/// the digest of.
+fn public_key_blob(key: &PKey<Private>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut blob = Vec::new();
+    match key.id() {
+        Id::RSA => {
+            let rsa = key.rsa()?;
+            put_string(&mut blob, b"ssh-rsa");
+            put_mpint(&mut blob, rsa.e());
+            put_mpint(&mut blob, rsa.n());
+        }
+        Id::EC => {
+            let ec = key.ec_key()?;
+            let curve = curve_name(ec.group().curve_name()).ok_or("unsupported ECDSA curve")?;
+            let mut ctx = BigNumContext::new()?;
+            let point = ec.public_key().to_bytes(
+                ec.group(),
+                PointConversionForm::UNCOMPRESSED,
+                &mut ctx,
+            )?;
+            put_string(&mut blob, format!("ecdsa-sha2-{}", curve).as_bytes());
+            put_string(&mut blob, curve.as_bytes());
+            put_string(&mut blob, &point);
+        }
+        _ => return Err("only RSA and ECDSA keys can sign requests".into()),
+    }
+    Ok(blob)
+}
+
+/// E.g. `9f:0b:50:ae:...`, as IMGAPI expects in a `keyId`.
+fn md5_fingerprint(public_key: &[u8]) -> String {
+    Md5::digest(public_key)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// E.g. `SHA256:pS1bM...`, as recent versions of `ssh-keygen -l` print fingerprints.
+fn sha256_fingerprint(public_key: &[u8]) -> String {
+    format!(
+        "SHA256:{}",
+        BASE64_NO_PAD.encode(Sha256::digest(public_key))
+    )
+}
+
+/// Whether `key_id` is the MD5 or SHA-256 fingerprint of `public_key`.
+fn has_fingerprint(public_key: &[u8], key_id: &str) -> bool {
+    let md5 = key_id.strip_prefix("MD5:").unwrap_or(key_id);
+    md5.eq_ignore_ascii_case(&md5_fingerprint(public_key))
+        || key_id == sha256_fingerprint(public_key)
+}
+
+/// Reads a private key in PEM or OpenSSH format, decrypting it with `passphrase` if it's
+/// encrypted.
+fn read_key(path: &Path, passphrase: Option<&[u8]>) -> Result<PKey<Private>, KeyError> {
+    let pem = fs::read(path).map_err(|e| KeyError::Io(path.to_path_buf(), e))?;
+    let contains = |s: &[u8]| pem.windows(s.len()).any(|w| w == s);
+    if contains(b"BEGIN OPENSSH PRIVATE KEY") {
+        return read_openssh_key(path, &pem);
+    }
+    let encrypted = contains(b"ENCRYPTED");
+    let invalid =
+        |e: openssl::error::ErrorStack| KeyError::Invalid(path.to_path_buf(), e.to_string());
+    match (encrypted, passphrase) {
+        (true, None) => Err(KeyError::NeedsPassphrase(path.to_path_buf())),
+        (true, Some(passphrase)) => PKey::private_key_from_pem_passphrase(&pem, passphrase)
+            .map_err(|_| KeyError::BadPassphrase(path.to_path_buf())),
+        (false, _) => PKey::private_key_from_pem(&pem).map_err(invalid),
+    }
+}
+
+/// Reads an unencrypted private key in the format `ssh-keygen` writes by default.
+fn read_openssh_key(path: &Path, pem: &[u8]) -> Result<PKey<Private>, KeyError> {
+    let invalid = |e: &dyn fmt::Display| KeyError::Invalid(path.to_path_buf(), e.to_string());
+    let text = String::from_utf8_lossy(pem);
+    let body: String = text
+        .lines()
+        .filter(|l| !l.starts_with("-----"))
+        .flat_map(|l| l.chars().filter(|c| !c.is_whitespace()))
+        .collect();
+    let bytes = BASE64.decode(body).map_err(|e| invalid(&e))?;
+    let key = parse_openssh_key(&bytes).map_err(|e| invalid(&e))?;
+    match key {
+        Some(key) => Ok(key),
+        None => Err(KeyError::Unsupported(format!(
+            "{}: keys encrypted in OpenSSH's format can't be read; add the key to the SSH agent \
+             instead, or convert it to PEM with `ssh-keygen -p -m PEM -f {}`",
+            path.display(),
+            path.display()
+        ))),
+    }
+}
+
+/// Parses the decoded body of an OpenSSH private key file, returning `None` if it's encrypted.
+fn parse_openssh_key(bytes: &[u8]) -> Result<Option<PKey<Private>>, Box<dyn Error>> {
+    let mut wire = Wire(
+        bytes
+            .strip_prefix(b"openssh-key-v1\0")
+            .ok_or("not an OpenSSH private key")?,
+    );
+    if wire.string()? != b"none" {
+        return Ok(None);
+    }
+    let _kdf = wire.string()?;
+    let _kdf_options = wire.string()?;
+    if wire.u32()? != 1 {
+        return Err("the file has more than one key".into());
+    }
+    let _public_key = wire.string()?;
+    let mut private = Wire(wire.string()?);
+    if private.u32()? != private.u32()? {
+        return Err("the key's check bytes don't match".into());
+    }
+    let bn = |b: &[u8]| BigNum::from_slice(b);
+    let key_type = private.string()?;
+    let key = match key_type {
+        b"ssh-rsa" => {
+            let (n, e, d) = (private.string()?, private.string()?, private.string()?);
+            let (iqmp, p, q) = (private.string()?, private.string()?, private.string()?);
+            let (d, p, q) = (bn(d)?, bn(p)?, bn(q)?);
+            let mut ctx = BigNumContext::new()?;
+            let one = BigNum::from_u32(1)?;
+            let crt =
+                |prime: &BigNumRef, ctx: &mut BigNumContext| -> Result<BigNum, Box<dyn Error>> {
+                    let mut minus_one = BigNum::new()?;
+                    minus_one.checked_sub(prime, &one)?;
+                    let mut r = BigNum::new()?;
+                    r.checked_rem(&d, &minus_one, ctx)?;
+                    Ok(r)
+                };
+            let (dmp1, dmq1) = (crt(&p, &mut ctx)?, crt(&q, &mut ctx)?);
+            let rsa = Rsa::from_private_components(bn(n)?, bn(e)?, d, p, q, dmp1, dmq1, bn(iqmp)?)?;
+            PKey::from_rsa(rsa)?
+        }
+        t if t.starts_with(b"ecdsa-sha2-") => {
+            let nid = curve_nid(private.string()?).ok_or("unsupported ECDSA curve")?;
+            let (point, d) = (private.string()?, private.string()?);
+            let group = EcGroup::from_curve_name(nid)?;
+            let mut ctx = BigNumContext::new()?;
+            let point = EcPoint::from_bytes(&group, point, &mut ctx)?;
+            let d = bn(d)?;
+            PKey::from_ec_key(EcKey::from_private_components(&group, &d, &point)?)?
+        }
+        t => {
+            return Err(format!(
+                "{} keys can't sign requests; use an RSA or ECDSA key",
+                String::from_utf8_lossy(t)
+            )
+            .into())
+        }
+    };
+    Ok(Some(key))
+}
+
+/// Reads the SSH wire format: big-endian `u32`s, and strings prefixed with their length.
+struct Wire<'a>(&'a [u8]);
+
+impl<'a> Wire<'a> {
+    fn u32(&mut self) -> Result<u32, &'static str> {
+        if self.0.len() < 4 {
+            return Err("truncated SSH message");
+        }
+        let (n, rest) = self.0.split_at(4);
+        self.0 = rest;
+        Ok(u32::from_be_bytes([n[0], n[1], n[2], n[3]]))
+    }
+
+    fn string(&mut self) -> Result<&'a [u8], &'static str> {
+        let len = self.u32()? as usize;
+        if self.0.len() < len {
+            return Err("truncated SSH message");
+        }
+        let (s, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(s)
+    }
+}
+
+fn put_string(out: &mut Vec<u8>, s: &[u8]) {
+    out.extend_from_slice(&(s.len() as u32).to_be_bytes());
+    out.extend_from_slice(s);
+}
+
+/// Writes a non-negative number as an SSH `mpint`, with a leading zero byte if its top bit is set.
+fn put_mpint(out: &mut Vec<u8>, n: &BigNumRef) {
+    let mut bytes = n.to_vec();
+    if bytes.first().is_some_and(|b| b & 0x80 != 0) {
+        bytes.insert(0, 0);
+    }
+    put_string(out, &bytes);
+}
+
+/// Just enough of the SSH agent protocol to list keys and sign with them.
+mod agent {
+    use std::error::Error;
+    use std::path::Path;
+
+    use openssl::bn::BigNum;
+    use openssl::ecdsa::EcdsaSig;
+
+    use super::{put_string, Wire};
+
+    const REQUEST_IDENTITIES: u8 = 11;
+    const IDENTITIES_ANSWER: u8 = 12;
+    const SIGN_REQUEST: u8 = 13;
+    const SIGN_RESPONSE: u8 = 14;
+
+    /// Asks for an RSA signature with SHA-256 rather than SHA-1.
+    const RSA_SHA2_256: u32 = 2;
+
+    /// The longest reply that's accepted, so that a confused agent can't exhaust memory.
+    const MAX_REPLY: usize = 256 * 1024;
+
+    #[cfg(unix)]
+    fn request(socket: &Path, message: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        use std::io::{Read, Write};
+        use std::os::unix::net::UnixStream;
+
+        let mut stream = UnixStream::connect(socket)
+            .map_err(|e| format!("can't connect to {}: {}", socket.display(), e))?;
+        let mut framed = Vec::with_capacity(message.len() + 4);
+        put_string(&mut framed, message);
+        stream.write_all(&framed)?;
+        let mut len = [0; 4];
+        stream.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len == 0 || len > MAX_REPLY {
+            return Err(format!("the agent sent a {} byte reply", len).into());
+        }
+        let mut reply = vec![0; len];
+        stream.read_exact(&mut reply)?;
+        Ok(reply)
+    }
+
+    #[cfg(not(unix))]
+    fn request(_socket: &Path, _message: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        Err("SSH agents are only supported on Unix".into())
+    }
+
+    /// The public keys the agent has, in SSH wire format.
+    pub fn identities(socket: &Path) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        let reply = request(socket, &[REQUEST_IDENTITIES])?;
+        if reply[0] != IDENTITIES_ANSWER {
+            return Err("the agent refused to list its keys".into());
+        }
+        let mut wire = Wire(&reply[1..]);
+        let count = wire.u32()?;
+        let mut keys = Vec::new();
+        for _ in 0..count {
+            keys.push(wire.string()?.to_vec());
+            let _comment = wire.string()?;
+        }
+        Ok(keys)
+    }
+
+    /// Has the agent sign `data` with `public_key`'s private key, returning the signature as
+    /// HTTP Signatures expects it: the raw signature for RSA, and DER for ECDSA.
+    pub fn sign(socket: &Path, public_key: &[u8], data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let rsa = Wire(public_key).string()? == b"ssh-rsa";
+        let mut message = vec![SIGN_REQUEST];
+        put_string(&mut message, public_key);
+        put_string(&mut message, data);
+        let flags = if rsa { RSA_SHA2_256 } else { 0 };
+        message.extend_from_slice(&flags.to_be_bytes());
+        let reply = request(socket, &message)?;
+        if reply[0] != SIGN_RESPONSE {
+            return Err("the agent refused to sign the request".into());
+        }
+        let mut signature = Wire(Wire(&reply[1..]).string()?);
+        let _format = signature.string()?;
+        let blob = signature.string()?;
+        if rsa {
+            return Ok(blob.to_vec());
+        }
+        let mut rs = Wire(blob);
+        let (r, s) = (
+            BigNum::from_slice(rs.string()?)?,
+            BigNum::from_slice(rs.string()?)?,
+        );
+        Ok(EcdsaSig::from_private_components(r, s)?.to_der()?)
+    }
+}
@@ -1,21 +1,2167 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, trace, warn};
+use reqwest::blocking::{Body, RequestBuilder, Response};
+use reqwest::{header, Method, StatusCode};
+use serde::de::DeserializeOwned;
+
+use crate::cache::{catalog_channel, catalog_key, Cached};
+use crate::digest::DigestReader;
+use crate::download::{Cancellable, ProgressReader};
+use crate::pool::run_bounded;
+use crate::throttle;
+
+/// How much of a response body is logged at trace level.
+const MAX_LOGGED_BODY: usize = 2048;
+
+/// `url` with any password taken out, for logging.
+fn redacted(url: &Url) -> Url {
+    let mut url = url.clone();
+    if url.password().is_some() {
+        let _ = url.set_password(Some("REDACTED"));
+    }
+    url
+}
+
+/// Describes `req` for a dry run, with its credentials redacted.
+fn dry_run(req: &reqwest::blocking::Request) -> DryRun {
+    let headers = req
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            let value = match value.find("signature=") {
+                Some(i) if *name == header::AUTHORIZATION => {
+                    format!("{}signature=\"REDACTED\"", &value[..i])
+                }
+                _ if *name == header::AUTHORIZATION => "REDACTED".to_string(),
+                _ => value.into_owned(),
+            };
+            (name.to_string(), value)
+        })
+        .collect();
+    let body = req.body();
+    DryRun {
+        method: req.method().to_string(),
+        url: redacted(req.url()).to_string(),
+        headers,
+        body: body.and_then(Body::as_bytes).map(<[u8]>::to_vec),
+        streamed_body: body.is_some_and(|b| b.as_bytes().is_none()),
+    }
+}
+
+/// Logs a response body at trace level, cut short if it's long.
+fn trace_body(body: &[u8]) {
+    if !log::log_enabled!(log::Level::Trace) {
+        return;
+    }
+    let text = String::from_utf8_lossy(&body[..body.len().min(MAX_LOGGED_BODY)]);
+    match body.len().checked_sub(MAX_LOGGED_BODY) {
+        Some(more) if more > 0 => trace!("< {}... ({} more bytes)", text, more),
+        _ => trace!("< {}", text),
+    }
+}
+
+/// How long a request may take, or a download stall for, if the client wasn't given a timeout.
+/// This is the HTTP client's default.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
 use super::*;
 
-/// List images.
-pub fn list(filter: Option<&ImageFilter>) -> Result<Vec<Image>, Box<dyn Error>> {
-    let url = match filter {
-        Some(f) => Url::parse(&format!("{}?{}", JOYENT_IMGAPI_URL, f.to_string()))?,
-        None => Url::parse(JOYENT_IMGAPI_URL)?,
+/// The result of [`Client::add_file`].
+#[derive(Debug, Clone)]
+pub struct AddFileReport {
+    /// The image, as returned by the server after the upload.
+    pub image: Image,
+
+    /// The compression the file was uploaded with.
+    pub compression: Compression,
+
+    /// The digest of the uploaded file.
+    pub digest: FileDigest,
+
+    /// Problems noticed along the way that didn't prevent the upload.
+    pub warnings: Vec<String>,
+}
+
+/// Where [`Client::export_to_manta`] put an image.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MantaExport {
+    /// The Manta the image was exported to, e.g. `https://us-east.manta.joyent.com`.
+    pub manta_url: String,
+
+    /// The path of the image's file in Manta.
+    pub image_path: String,
+
+    /// The path of the image's manifest in Manta.
+    pub manifest_path: String,
+}
+
+/// Checks that `path` looks like somewhere in Manta an image can be exported to: an absolute path
+/// under an account's `stor` or `public` directory, e.g. `/jill/stor/images/`.
+fn check_manta_path(path: &str) -> Result<(), String> {
+    let mut parts = path.strip_prefix('/').unwrap_or_default().split('/');
+    let account = parts.next().unwrap_or_default();
+    match parts.next() {
+        Some("stor" | "public") if path.starts_with('/') && !account.is_empty() => Ok(()),
+        _ => Err(format!(
+            "{:?} isn't a Manta path under an account's stor or public directory, e.g. \
+             /<account>/stor/images/",
+            path
+        )),
+    }
+}
+
+/// A blocking client for an IMGAPI server.
+#[derive(Debug, Clone)]
+pub struct Client {
+    base_url: Url,
+    http: reqwest::blocking::Client,
+    rate_limit: Option<BytesPerSec>,
+    cache: Option<CatalogCache>,
+    cache_mode: CacheMode,
+    channel: Option<Channel>,
+    signer: Option<RequestSigner>,
+    timeout: Duration,
+    connect_timeout: Option<Duration>,
+    insecure: bool,
+    dry_run: DryRunMode,
+}
+
+/// Which requests a [`Client`] doesn't send, see [`Client::with_dry_run`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DryRunMode {
+    /// Send every request.
+    #[default]
+    Off,
+
+    /// Send no requests at all.
+    All,
+
+    /// Send only requests that don't change anything (GET and HEAD), so that a change can be
+    /// looked up and worked out before it's reported.
+    Changes,
+}
+
+/// The path of GetImage for `uuid`, relative to the server.
+fn image_path(uuid: &Uuid, channel: Option<&Channel>) -> String {
+    match channel {
+        Some(channel) => format!("images/{}?channel={}", uuid, channel),
+        None => format!("images/{}", uuid),
+    }
+}
+
+/// The images in `catalog` that the server would list for `filter`: those that match it, from
+/// its marker on, and no more than its limit, or the server's page size.
+fn select(catalog: Vec<Image>, filter: Option<&ImageFilter>) -> Vec<Image> {
+    let default = ImageFilter::default();
+    let filter = filter.unwrap_or(&default);
+    let from = match &filter.marker {
+        Some(Marker::Image(uuid)) => catalog
+            .iter()
+            .find(|i| i.uuid == *uuid)
+            .and_then(|i| i.published_at),
+        Some(Marker::PublishedAt(at)) => Some(*at),
+        None => None,
+    };
+    let limit = filter.limit.unwrap_or(MAX_PAGE_SIZE) as usize;
+    let mut images: Vec<Image> = catalog
+        .into_iter()
+        .filter(|i| from.is_none_or(|from| i.published_at.is_some_and(|at| at >= from)))
+        .filter(|i| filter.matches(i))
+        .collect();
+    images.sort_by_key(|i| i.published_at);
+    images.truncate(limit);
+    images
+}
+
+impl Default for Client {
+    /// Returns a client for the public Joyent IMGAPI server.
+    fn default() -> Self {
+        Self::new(JOYENT_IMGAPI_SERVER).expect("JOYENT_IMGAPI_SERVER is a valid URL")
+    }
+}
+
+impl Client {
+    /// Creates a client for the IMGAPI server at `base_url`, e.g. `https://images.joyent.com`.
+    pub fn new(base_url: &str) -> Result<Self, Box<dyn Error>> {
+        let mut base_url = Url::parse(base_url)?;
+        // Make sure relative joins append to the path instead of replacing its last segment.
+        if !base_url.path().ends_with('/') {
+            base_url.set_path(&format!("{}/", base_url.path()));
+        }
+        Ok(Self {
+            base_url,
+            http: reqwest::blocking::Client::new(),
+            rate_limit: None,
+            cache: None,
+            cache_mode: CacheMode::Off,
+            channel: None,
+            signer: None,
+            timeout: DEFAULT_TIMEOUT,
+            connect_timeout: None,
+            insecure: false,
+            dry_run: DryRunMode::Off,
+        })
+    }
+
+    /// Limits the bandwidth used by file uploads and downloads, unless overridden with
+    /// [`DownloadOptions::rate_limit`].
+    pub fn with_rate_limit(mut self, limit: Option<BytesPerSec>) -> Self {
+        self.rate_limit = limit;
+        self
+    }
+
+    /// Answers [`Client::list`] and [`Client::get`] from `cache`, as `mode` allows. Everything
+    /// that changes an image invalidates the entries it could have made stale.
+    pub fn with_cache(mut self, cache: CatalogCache, mode: CacheMode) -> Self {
+        self.cache = Some(cache);
+        self.cache_mode = mode;
+        self
+    }
+
+    /// Scopes every image request to `channel` (IMGAPI's `?channel=`), rather than the server's
+    /// default channel, unless the request names a channel itself, e.g. with
+    /// [`ImageFilter::channel`].
+    pub fn with_channel(mut self, channel: Option<Channel>) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    /// The channel the client is scoped to, if any.
+    pub fn channel(&self) -> Option<&Channel> {
+        self.channel.as_ref()
+    }
+
+    /// Gives up on requests that the server takes longer than `timeout` to answer (30 seconds by
+    /// default), and on connections that take longer than `connect_timeout` to make (however long
+    /// the operating system allows by default), failing with a [`RequestTimeout`].
+    ///
+    /// Responses are read with the same timeout, but for each read, rather than for the whole
+    /// response, so that a long download only times out if it stalls.
+    pub fn with_timeouts(
+        mut self,
+        timeout: Option<Duration>,
+        connect_timeout: Option<Duration>,
+    ) -> Result<Self, Box<dyn Error>> {
+        self.timeout = timeout.unwrap_or(DEFAULT_TIMEOUT);
+        self.connect_timeout = connect_timeout;
+        self.rebuild_http()?;
+        Ok(self)
+    }
+
+    /// Accepts any TLS certificate from the server, however invalid, e.g. a lab server's
+    /// self-signed one. This makes it easy to impersonate the server, so it's off by default.
+    pub fn with_insecure(mut self, insecure: bool) -> Result<Self, Box<dyn Error>> {
+        self.insecure = insecure;
+        self.rebuild_http()?;
+        Ok(self)
+    }
+
+    /// Holds back the requests `mode` says not to send, each of which fails with a [`DryRun`]
+    /// describing it instead. The cache isn't consulted in a dry run.
+    pub fn with_dry_run(mut self, dry_run: DryRunMode) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Whether the client accepts invalid TLS certificates.
+    pub fn is_insecure(&self) -> bool {
+        self.insecure
+    }
+
+    /// Replaces the HTTP client with one built with the client's current settings.
+    fn rebuild_http(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut builder = reqwest::blocking::Client::builder()
+            .timeout(self.timeout)
+            .danger_accept_invalid_certs(self.insecure);
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        self.http = builder.build()?;
+        Ok(())
+    }
+
+    /// Signs every request with `signer`, as private IMGAPI servers require.
+    pub fn with_signer(mut self, signer: Option<RequestSigner>) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    /// What the client signs requests with, if anything.
+    pub fn signer(&self) -> Option<&RequestSigner> {
+        self.signer.as_ref()
+    }
+
+    /// The client's cache, if it has one.
+    pub fn cache(&self) -> Option<&CatalogCache> {
+        self.cache.as_ref()
+    }
+
+    /// Fetches every cached request from the server again, however fresh, returning how many
+    /// entries were refreshed.
+    pub fn refresh(&self) -> Result<usize, Box<dyn Error>> {
+        let cache = match &self.cache {
+            Some(cache) if self.cache_mode != CacheMode::OfflineOnly => cache,
+            Some(_) => return Err("can't refresh an offline-only cache".into()),
+            None => return Ok(0),
+        };
+        let keys = cache.keys()?;
+        for key in &keys {
+            match catalog_channel(key) {
+                Some(channel) => {
+                    self.fetch_catalog(cache, channel.as_ref(), |_, _| {})?;
+                }
+                None => {
+                    self.fetch_into_cache(cache, key, None)?;
+                }
+            }
+        }
+        Ok(keys.len())
+    }
+
+    /// Fetches every image in the client's channel, in any state, and saves them in the cache as
+    /// its catalog, which a client in [`CacheMode::OfflineOnly`] looks lists and images up in.
+    /// `on_page` is called as for [`Client::list_all`]. Returns the number of images saved.
+    pub fn refresh_catalog<F: FnMut(usize, usize)>(
+        &self,
+        on_page: F,
+    ) -> Result<usize, Box<dyn Error>> {
+        match &self.cache {
+            Some(cache) if self.cache_mode != CacheMode::OfflineOnly => {
+                self.fetch_catalog(cache, self.channel.as_ref(), on_page)
+            }
+            Some(_) => Err("can't refresh the catalog of an offline-only cache".into()),
+            None => Err("can't refresh the catalog without a cache".into()),
+        }
+    }
+
+    fn fetch_catalog<F: FnMut(usize, usize)>(
+        &self,
+        cache: &CatalogCache,
+        channel: Option<&Channel>,
+        on_page: F,
+    ) -> Result<usize, Box<dyn Error>> {
+        let filter = ImageFilter {
+            state: Some(StateFilter::All),
+            channel: channel.cloned(),
+            ..Default::default()
+        };
+        let images = self.list_all(&filter, on_page)?;
+        cache.store(&catalog_key(channel), None, &serde_json::to_value(&images)?)?;
+        Ok(images.len())
+    }
+
+    /// The cached catalog of `channel`, or of the client's channel, if the client is offline and
+    /// there is one.
+    fn offline_catalog(&self, channel: Option<&Channel>) -> Option<Catalog> {
+        match &self.cache {
+            Some(cache) if self.cache_mode == CacheMode::OfflineOnly => {
+                cache.catalog(channel.or(self.channel.as_ref()))
+            }
+            _ => None,
+        }
+    }
+
+    /// The base URL of the server.
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
+    }
+
+    /// Returns the URL for `path`, relative to the server's base URL, in the client's channel.
+    fn url(&self, path: &str) -> Result<Url, Box<dyn Error>> {
+        Ok(self.base_url.join(&self.in_channel(path))?)
+    }
+
+    /// Adds the client's channel, if any, to the query of `path` if it's for an image and doesn't
+    /// have a channel already.
+    fn in_channel(&self, path: &str) -> String {
+        let channel = match &self.channel {
+            Some(channel) if path.starts_with("images") => channel,
+            _ => return path.to_string(),
+        };
+        match path.split_once('?') {
+            Some((_, query)) if query.split('&').any(|p| p.starts_with("channel=")) => {
+                path.to_string()
+            }
+            Some((_, "")) => format!("{}channel={}", path, channel),
+            Some(_) => format!("{}&channel={}", path, channel),
+            None => format!("{}?channel={}", path, channel),
+        }
+    }
+
+    /// Signs and sends a request, turning error responses into an [`ApiError`].
+    ///
+    /// Each request is logged at debug level with its response's status and how long it took, and
+    /// the signing key at trace level, though never the signature.
+    fn send(&self, req: RequestBuilder) -> Result<Response, Box<dyn Error>> {
+        let req = match &self.signer {
+            Some(signer) => {
+                trace!("> signed with {}", signer.key_id());
+                signer.sign(req)?
+            }
+            None => req,
+        };
+        let req = req.build()?;
+        let (method, url) = (req.method().clone(), redacted(req.url()));
+        let held = match self.dry_run {
+            DryRunMode::Off => false,
+            DryRunMode::All => true,
+            DryRunMode::Changes => !matches!(*req.method(), Method::GET | Method::HEAD),
+        };
+        if held {
+            return Err(dry_run(&req).into());
+        }
+        if self.cache_mode == CacheMode::OfflineOnly {
+            return Err(Offline {
+                method: method.to_string(),
+                url: url.to_string(),
+            }
+            .into());
+        }
+        let started = Instant::now();
+        let resp = self.http.execute(req).map_err(|e| {
+            debug!("{} {} failed: {}", method, url, e);
+            let connecting = e.is_connect() && self.connect_timeout.is_some();
+            self.timed_out(&url, e.into(), connecting)
+        })?;
+        let status = resp.status();
+        debug!(
+            "{} {} -> {} ({} ms)",
+            method,
+            url,
+            status.as_u16(),
+            started.elapsed().as_millis()
+        );
+        if status.is_success() || status == StatusCode::NOT_MODIFIED {
+            return Ok(resp);
+        }
+        let body = resp.bytes().unwrap_or_default();
+        trace_body(&body);
+        let mut err: ApiError = serde_json::from_slice(&body).unwrap_or_default();
+        err.status = status.as_u16();
+        if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+            err.identity = self.signer.as_ref().map(RequestSigner::key_id);
+        }
+        Err(err.into())
+    }
+
+    fn send_json<T: DeserializeOwned>(&self, req: RequestBuilder) -> Result<T, Box<dyn Error>> {
+        self.json_body(self.send(req)?)
+    }
+
+    /// Reads a JSON response body, logging it at trace level.
+    fn json_body<T: DeserializeOwned>(&self, resp: Response) -> Result<T, Box<dyn Error>> {
+        let url = resp.url().clone();
+        let body = resp
+            .bytes()
+            .map_err(|e| self.timed_out(&url, e.into(), false))?;
+        trace_body(&body);
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Replaces `e` with a [`RequestTimeout`] if it's the HTTP client timing out on `url`.
+    fn timed_out(&self, url: &Url, e: Box<dyn Error>, connecting: bool) -> Box<dyn Error> {
+        if !RequestTimeout::is_timeout(e.as_ref()) {
+            return e;
+        }
+        let after = match self.connect_timeout {
+            Some(timeout) if connecting => timeout,
+            _ => self.timeout,
+        };
+        Box::new(RequestTimeout {
+            url: redacted(url).to_string(),
+            after,
+            connecting,
+        })
+    }
+
+    /// GETs `path`, going through the cache according to the cache mode.
+    fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T, Box<dyn Error>> {
+        // The channel is part of the cache key: images differ between channels.
+        let path = &self.in_channel(path);
+        let cache = match &self.cache {
+            Some(cache) if self.cache_mode != CacheMode::Off && self.dry_run == DryRunMode::Off => {
+                cache
+            }
+            _ => return self.send_json(self.http.get(self.url(path)?)),
+        };
+        // An entry that no longer deserializes is as good as missing.
+        let cached =
+            cache
+                .lookup(path)
+                .and_then(|c| match serde_json::from_value::<T>(c.value.clone()) {
+                    Ok(value) => Some((c, value)),
+                    Err(_) => {
+                        cache.record(|s| s.corrupt += 1);
+                        None
+                    }
+                });
+        match (self.cache_mode, cached) {
+            (CacheMode::OfflineOnly, Some((_, value))) => {
+                debug!("{} answered from the cache (offline)", path);
+                cache.record(|s| s.hits += 1);
+                Ok(value)
+            }
+            (CacheMode::OfflineOnly, None) => Err(NotCached {
+                key: path.to_string(),
+            }
+            .into()),
+            (CacheMode::ReadThrough { ttl }, Some((c, value))) if c.age < ttl => {
+                debug!(
+                    "{} answered from the cache ({}s old)",
+                    path,
+                    c.age.as_secs()
+                );
+                cache.record(|s| s.hits += 1);
+                Ok(value)
+            }
+            (_, cached) => {
+                let stale = cached.map(|(c, _)| c);
+                if let Some(c) = &stale {
+                    debug!("{} is stale in the cache ({}s old)", path, c.age.as_secs());
+                }
+                Ok(serde_json::from_value(
+                    self.fetch_into_cache(cache, path, stale)?,
+                )?)
+            }
+        }
+    }
+
+    /// GETs `path` from the server and caches the response. If `stale` has an ETag, the request
+    /// is conditional, and the stale value is kept if the server says it's still current.
+    fn fetch_into_cache(
+        &self,
+        cache: &CatalogCache,
+        path: &str,
+        stale: Option<Cached>,
+    ) -> Result<Value, Box<dyn Error>> {
+        let mut req = self.http.get(self.url(path)?);
+        if let Some(etag) = stale.as_ref().and_then(|c| c.etag.as_deref()) {
+            req = req.header(header::IF_NONE_MATCH, etag);
+        }
+        let resp = self.send(req)?;
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            if let Some(stale) = stale {
+                debug!("{} is still current, keeping the cached copy", path);
+                cache.touch(path)?;
+                cache.record(|s| s.revalidated += 1);
+                return Ok(stale.value);
+            }
+            return Err(format!("{}: unexpected HTTP 304", path).into());
+        }
+        let etag = resp
+            .headers()
+            .get(header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let value: Value = self.json_body(resp)?;
+        cache.store(path, etag, &value)?;
+        cache.record(|s| s.misses += 1);
+        Ok(value)
+    }
+
+    /// Removes cache entries that a change to an image could have made stale.
+    fn invalidate(&self, uuid: Option<&Uuid>) -> Result<(), Box<dyn Error>> {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(uuid)?;
+        }
+        Ok(())
+    }
+
+    /// List images. Offline, with a cached catalog, the catalog is filtered instead, as the
+    /// server would.
+    ///
+    /// Images whose homepage or EULA isn't a valid URL are listed as they are, with a warning
+    /// logged for each.
+    pub fn list(&self, filter: Option<&ImageFilter>) -> Result<Vec<Image>, Box<dyn Error>> {
+        let images =
+            if let Some(catalog) = self.offline_catalog(filter.and_then(|f| f.channel.as_ref())) {
+                debug!("listing images from the cached catalog (offline)");
+                select(catalog.images, filter)
+            } else {
+                // An empty filter has no query, so there's nothing to follow a `?`.
+                let query = filter.map(ToString::to_string).unwrap_or_default();
+                let path = match query.as_str() {
+                    "" => "images".to_string(),
+                    query => format!("images?{}", query),
+                };
+                self.get_json(&path)?
+            };
+        for warning in url_warnings(&images) {
+            warn!("{}", warning);
+        }
+        Ok(images)
+    }
+
+    /// List every image matching `filter`, fetching as many pages as needed, where [`Client::list`]
+    /// stops at the server's page size. `on_page` is called after each page with the number of
+    /// pages and images fetched so far.
+    ///
+    /// The filter's [`limit`](ImageFilter::limit), if any, bounds the total number of images
+    /// rather than the size of each page, and its [`marker`](ImageFilter::marker) is where the
+    /// first page starts. Each page starts at the last image of the previous page, which is only
+    /// returned once.
+    pub fn list_all<F: FnMut(usize, usize)>(
+        &self,
+        filter: &ImageFilter,
+        mut on_page: F,
+    ) -> Result<Vec<Image>, Box<dyn Error>> {
+        let max = filter.limit.map(|l| l as usize);
+        let mut page_filter = filter.clone();
+        let mut images: Vec<Image> = Vec::new();
+        let mut seen = HashSet::new();
+        for pages in 1.. {
+            // Ask for one more than needed, since the marker image is returned again.
+            let want = max.map_or(MAX_PAGE_SIZE, |m| {
+                ((m - images.len()) as u32 + 1).min(MAX_PAGE_SIZE)
+            });
+            page_filter.limit = Some(want);
+            let page = self.list(Some(&page_filter))?;
+            let full = page.len() as u32 >= want;
+            let before = images.len();
+            images.extend(page.into_iter().filter(|i| seen.insert(i.uuid)));
+            on_page(pages, images.len());
+
+            if let Some(max) = max.filter(|&m| images.len() >= m) {
+                images.truncate(max);
+                break;
+            }
+            // A page with nothing new means every image left was published at the same moment
+            // as the marker, so paging can't get past them.
+            if !full || images.len() == before {
+                break;
+            }
+            page_filter.marker = images.last().map(|i| Marker::Image(i.uuid));
+        }
+        Ok(images)
+    }
+
+    /// Get a single image.
+    pub fn get(&self, uuid: &Uuid) -> Result<Image, Box<dyn Error>> {
+        self.get_in_channel(uuid, None)
+    }
+
+    /// Get a single image in `channel`, rather than the server's default channel. Images that
+    /// aren't in the channel are reported as not found.
+    pub fn get_in_channel(
+        &self,
+        uuid: &Uuid,
+        channel: Option<&Channel>,
+    ) -> Result<Image, Box<dyn Error>> {
+        self.get_image(uuid, channel)
+    }
+
+    /// Get a single image's manifest exactly as the server returned it, e.g. to see fields this
+    /// crate doesn't know about. See [`Client::get_in_channel`]. Offline, with a cached catalog,
+    /// the manifest is the catalog's, which only has the fields this crate knows about.
+    pub fn get_raw(&self, uuid: &Uuid, channel: Option<&Channel>) -> Result<Value, Box<dyn Error>> {
+        self.get_image(uuid, channel)
+    }
+
+    /// GETs image `uuid`, or offline, looks it up in the cached catalog if there is one, where an
+    /// image that's missing is reported as not found, as the server would.
+    fn get_image<T: DeserializeOwned>(
+        &self,
+        uuid: &Uuid,
+        channel: Option<&Channel>,
+    ) -> Result<T, Box<dyn Error>> {
+        let catalog = match self.offline_catalog(channel) {
+            Some(catalog) => catalog,
+            None => return self.get_json(&image_path(uuid, channel)),
+        };
+        debug!("looking up image {} in the cached catalog (offline)", uuid);
+        match catalog.images.into_iter().find(|i| i.uuid == *uuid) {
+            Some(image) => Ok(serde_json::from_value(serde_json::to_value(image)?)?),
+            None => Err(ApiError {
+                status: StatusCode::NOT_FOUND.as_u16(),
+                code: "ResourceNotFound".to_string(),
+                message: format!("image {} is not in the cached catalog", uuid),
+                ..Default::default()
+            }
+            .into()),
+        }
+    }
+
+    /// Gets several images at once, with at most `concurrency` requests in progress, returning the
+    /// result for each UUID in the same order. A failure to get one image doesn't stop the others.
+    pub fn get_many(&self, uuids: &[Uuid], concurrency: usize) -> Vec<Result<Image, String>> {
+        self.get_many_json(uuids, concurrency)
+    }
+
+    /// Like [`Client::get_many`], but with the manifests exactly as the server returned them, as
+    /// from [`Client::get_raw`].
+    pub fn get_many_raw(&self, uuids: &[Uuid], concurrency: usize) -> Vec<Result<Value, String>> {
+        self.get_many_json(uuids, concurrency)
+    }
+
+    fn get_many_json<T: DeserializeOwned + Send>(
+        &self,
+        uuids: &[Uuid],
+        concurrency: usize,
+    ) -> Vec<Result<T, String>> {
+        run_bounded(uuids, concurrency, |uuid| {
+            self.get_image(uuid, None).map_err(|e| e.to_string())
+        })
+    }
+
+    /// List the server's channels (ListChannels). Servers that don't use channels answer with a
+    /// 404, which [`ApiError::is_not_found`] recognizes.
+    pub fn list_channels(&self) -> Result<Vec<ChannelInfo>, Box<dyn Error>> {
+        self.get_json("channels")
+    }
+
+    /// Update the mutable fields of an image (UpdateImage), returning the updated image.
+    pub fn update(&self, uuid: &Uuid, update: &ImageUpdate) -> Result<Image, Box<dyn Error>> {
+        let mut url = self.url(&format!("images/{}", uuid))?;
+        url.query_pairs_mut().append_pair("action", "update");
+        let image = self.send_json(self.http.post(url).json(update))?;
+        self.invalidate(Some(uuid))?;
+        Ok(image)
+    }
+
+    /// Create a new, unactivated image from a manifest (CreateImage). Fields the server manages,
+    /// such as the UUID and files, are ignored; see [`Image::to_create_request`].
+    pub fn create(&self, manifest: &Image) -> Result<Image, Box<dyn Error>> {
+        let url = self.url("images")?;
+        let image = self.send_json(self.http.post(url).json(&manifest.to_create_request()?))?;
+        self.invalidate(None)?;
+        Ok(image)
+    }
+
+    /// Import an image with its UUID and other server-managed fields intact
+    /// (AdminImportImage). Requires admin access.
+    pub fn admin_import(&self, manifest: &Image) -> Result<Image, Box<dyn Error>> {
+        let mut url = self.url(&format!("images/{}", manifest.uuid))?;
+        url.query_pairs_mut().append_pair("action", "import");
+        let image = self.send_json(self.http.post(url).json(&manifest.to_import_request()?))?;
+        self.invalidate(Some(&manifest.uuid))?;
+        Ok(image)
+    }
+
+    /// Delete an image (DeleteImage).
+    pub fn delete(&self, uuid: &Uuid) -> Result<(), Box<dyn Error>> {
+        let url = self.url(&format!("images/{}", uuid))?;
+        self.send(self.http.delete(url))?;
+        self.invalidate(Some(uuid))
+    }
+
+    /// Clone an image that's shared with `account` into an image owned by it (CloneImage),
+    /// returning the new image. Incremental images are cloned along with their origins.
+    pub fn clone_image(&self, uuid: &Uuid, account: &Uuid) -> Result<Image, Box<dyn Error>> {
+        let mut url = self.url(&format!("images/{}/clone", uuid))?;
+        url.query_pairs_mut()
+            .append_pair("account", &account.to_string());
+        let image = self.send_json(self.http.post(url))?;
+        self.invalidate(None)?;
+        Ok(image)
+    }
+
+    /// Wait for an image to be in `state`, e.g. for an image being created asynchronously to be
+    /// active, polling it every [`WaitOptions::interval`] and returning it once it is.
+    ///
+    /// `on_poll` is called with the image every time it's fetched. The cache is bypassed. Fails
+    /// with a [`WaitError`] if the image fails, or isn't in `state` within the timeout.
+    pub fn wait_for_state<F: FnMut(&Image)>(
+        &self,
+        uuid: &Uuid,
+        state: ImageState,
+        opts: &WaitOptions,
+        mut on_poll: F,
+    ) -> Result<Image, Box<dyn Error>> {
+        let started = Instant::now();
+        loop {
+            let image: Image = self.send_json(self.http.get(self.url(&image_path(uuid, None))?))?;
+            on_poll(&image);
+            if image.state == state {
+                return Ok(image);
+            }
+            if image.state == ImageState::Failed {
+                return Err(WaitError::failed(&image).into());
+            }
+            let elapsed = started.elapsed();
+            if let Some(timeout) = opts.timeout.filter(|&t| elapsed >= t) {
+                return Err(WaitError::TimedOut {
+                    uuid: *uuid,
+                    state: image.state,
+                    after: timeout,
+                }
+                .into());
+            }
+            let left = opts.timeout.map_or(opts.interval, |t| t - elapsed);
+            thread::sleep(opts.interval.min(left));
+        }
+    }
+
+    /// Export an image's manifest and file to Manta (ExportImage). `manta_path` is a directory,
+    /// ending in `/`, or the path the files are named after, e.g. `/jill/stor/images/base` for
+    /// `base.imgmanifest` and `base.zfs.gz`.
+    ///
+    /// The path is checked before anything is sent, and so is that the image has a file.
+    pub fn export_to_manta(
+        &self,
+        uuid: &Uuid,
+        manta_path: &str,
+    ) -> Result<MantaExport, Box<dyn Error>> {
+        check_manta_path(manta_path)?;
+        if self.get(uuid)?.files.is_empty() {
+            return Err(format!("image {} has no file yet, so it can't be exported", uuid).into());
+        }
+        let mut url = self.url(&format!("images/{}", uuid))?;
+        url.query_pairs_mut()
+            .append_pair("action", "export")
+            .append_pair("manta_path", manta_path);
+        self.send_json(self.http.post(url))
+    }
+
+    /// Add an image to another channel (ChannelAddImage), returning the updated image.
+    pub fn channel_add(&self, uuid: &Uuid, channel: &Channel) -> Result<Image, Box<dyn Error>> {
+        let mut url = self.url(&format!("images/{}", uuid))?;
+        url.query_pairs_mut().append_pair("action", "channel-add");
+        let body = serde_json::json!({ "channel": channel });
+        let image = self.send_json(self.http.post(url).json(&body))?;
+        self.invalidate(Some(uuid))?;
+        Ok(image)
+    }
+
+    /// Remove an image from `channel` (DeleteImage with a channel). An image that isn't in any
+    /// other channel is deleted.
+    pub fn channel_remove(&self, uuid: &Uuid, channel: &Channel) -> Result<(), Box<dyn Error>> {
+        let url = self.url(&image_path(uuid, Some(channel)))?;
+        self.send(self.http.delete(url))?;
+        self.invalidate(Some(uuid))
+    }
+
+    /// Activate an image once its file has been added (ActivateImage).
+    pub fn activate(&self, uuid: &Uuid) -> Result<Image, Box<dyn Error>> {
+        self.image_action(uuid, "activate")
+    }
+
+    /// Enable a disabled image, so that it can be provisioned again (EnableImage).
+    pub fn enable(&self, uuid: &Uuid) -> Result<Image, Box<dyn Error>> {
+        self.image_action(uuid, "enable")
+    }
+
+    /// Disable an image, so that it can't be provisioned, while keeping it listed (DisableImage).
+    pub fn disable(&self, uuid: &Uuid) -> Result<Image, Box<dyn Error>> {
+        self.image_action(uuid, "disable")
+    }
+
+    /// POSTs `?action=<action>` for the image, returning the updated image.
+    fn image_action(&self, uuid: &Uuid, action: &str) -> Result<Image, Box<dyn Error>> {
+        let mut url = self.url(&format!("images/{}", uuid))?;
+        url.query_pairs_mut().append_pair("action", action);
+        let image = self.send_json(self.http.post(url))?;
+        self.invalidate(Some(uuid))?;
+        Ok(image)
+    }
+
+    /// Publish an image from a manifest and file on disk, e.g. as written by
+    /// [`Client::export_to_dir`] or `imgadm create`, returning the activated image.
+    ///
+    /// The manifest may be v1 or v2, and must pass [`Image::validate`]. The file is checked against
+    /// the manifest's size and SHA-1 before anything is sent. The image is then created (or, with
+    /// [`PublishOptions::admin`], imported), the file is added, and the image is activated.
+    pub fn publish_from_dir<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        manifest_path: P,
+        file_path: Q,
+        opts: &PublishOptions,
+    ) -> Result<Image, Box<dyn Error>> {
+        let (manifest_path, file_path) = (manifest_path.as_ref(), file_path.as_ref());
+        let manifest = Image::from_path(manifest_path)?;
+        if let Some(issue) = manifest
+            .validate()
+            .into_iter()
+            .find(|i| i.severity == Severity::Error)
+        {
+            return Err(format!("{}: {}", manifest_path.display(), issue).into());
+        }
+        let file = manifest
+            .files
+            .first()
+            .ok_or_else(|| format!("{}: manifest has no files", manifest_path.display()))?;
+
+        let with_path = |e: std::io::Error| format!("{}: {}", file_path.display(), e);
+        let local = fs::File::open(file_path).map_err(with_path)?;
+        let digest = file_digest(BufReader::new(local)).map_err(with_path)?;
+        digest
+            .verify(file)
+            .map_err(|e| format!("{}: {}", file_path.display(), e))?;
+
+        let image = if opts.admin {
+            self.admin_import(&manifest)?
+        } else {
+            self.create(&manifest)?
+        };
+        self.add_file_with_digest(&image.uuid, file_path, file.compression, &digest)?;
+        self.activate(&image.uuid)
+    }
+
+    /// Upload the file at `path` as the image's file (AddImageFile).
+    ///
+    /// If `compression` is [`Compression::Auto`], the compression is detected from the file's
+    /// contents. A file name suggesting a different compression is reported in
+    /// [`AddFileReport::warnings`].
+    ///
+    /// The file's digest is computed while it is uploaded, and checked against the size and SHA-1
+    /// the server reports for it.
+    pub fn add_file<P: AsRef<Path>>(
+        &self,
+        uuid: &Uuid,
+        path: P,
+        compression: Compression,
+    ) -> Result<AddFileReport, Box<dyn Error>> {
+        self.upload_file(uuid, path.as_ref(), compression, None, None)
+    }
+
+    /// Like [`Client::add_file`], calling `progress` as the file is uploaded.
+    pub fn add_file_with_progress<P: AsRef<Path>, F: FnMut(Progress) + Send + 'static>(
+        &self,
+        uuid: &Uuid,
+        path: P,
+        compression: Compression,
+        progress: F,
+    ) -> Result<AddFileReport, Box<dyn Error>> {
+        self.upload_file(
+            uuid,
+            path.as_ref(),
+            compression,
+            None,
+            Some(Box::new(progress)),
+        )
+    }
+
+    /// Like [`Client::add_file`], but with a digest of the file computed beforehand, e.g. with
+    /// [`file_digest`]. The SHA-1 is sent along for the server to verify, and the file is not
+    /// hashed again.
+    pub fn add_file_with_digest<P: AsRef<Path>>(
+        &self,
+        uuid: &Uuid,
+        path: P,
+        compression: Compression,
+        digest: &FileDigest,
+    ) -> Result<AddFileReport, Box<dyn Error>> {
+        self.upload_file(uuid, path.as_ref(), compression, Some(digest), None)
+    }
+
+    fn upload_file(
+        &self,
+        uuid: &Uuid,
+        path: &Path,
+        compression: Compression,
+        digest: Option<&FileDigest>,
+        progress: Option<Box<dyn FnMut(Progress) + Send>>,
+    ) -> Result<AddFileReport, Box<dyn Error>> {
+        let with_path = |e: std::io::Error| format!("{}: {}", path.display(), e);
+        let file = fs::File::open(path).map_err(with_path)?;
+        let size = file.metadata().map_err(with_path)?.len();
+        // Fail before streaming a file the server is just going to reject.
+        if size > MAX_FILE_SIZE {
+            return Err(format!(
+                "{}: file is {}, but the maximum image file size is {}",
+                path.display(),
+                size::format_size(size),
+                size::format_size(MAX_FILE_SIZE)
+            )
+            .into());
+        }
+        if let Some(d) = digest.filter(|d| d.size != size) {
+            return Err(ChecksumMismatch {
+                check: "size",
+                expected: d.size.to_string(),
+                actual: size.to_string(),
+            }
+            .into());
+        }
+        let mut reader = BufReader::new(file);
+
+        let mut warnings = Vec::new();
+        let compression = match compression {
+            Compression::Auto => {
+                let sniffed = Compression::sniff(&mut reader).map_err(with_path)?;
+                warnings.extend(Compression::mismatch_warning(path, sniffed));
+                sniffed
+            }
+            c => c,
+        };
+
+        let reader: Box<dyn Read + Send> = match progress {
+            Some(progress) => Box::new(ProgressReader::new(reader, Some(size), progress)),
+            None => Box::new(reader),
+        };
+        let (image, digest) = self.upload_reader(uuid, reader, size, compression, digest)?;
+        Ok(AddFileReport {
+            image,
+            compression,
+            digest,
+            warnings,
+        })
+    }
+
+    /// Uploads `size` bytes from `reader` as the image's file, returning the updated image and the
+    /// digest of what was uploaded.
+    ///
+    /// If `digest` is given, the SHA-1 is sent for the server to verify instead of hashing the
+    /// upload.
+    pub(crate) fn upload_reader<R: Read + Send + 'static>(
+        &self,
+        uuid: &Uuid,
+        reader: R,
+        size: u64,
+        compression: Compression,
+        digest: Option<&FileDigest>,
+    ) -> Result<(Image, FileDigest), Box<dyn Error>> {
+        let mut url = self.url(&format!("images/{}/file", uuid))?;
+        url.query_pairs_mut()
+            .append_pair("compression", &compression.to_string())
+            .append_pair("size", &size.to_string());
+        if let Some(d) = digest {
+            url.query_pairs_mut().append_pair("sha1", &d.sha1);
+        }
+        let (reader, hashes): (Box<dyn Read + Send>, _) = match digest {
+            Some(_) => (Box::new(reader), None),
+            None => {
+                let (reader, hashes) = DigestReader::new(reader);
+                (Box::new(reader), Some(hashes))
+            }
+        };
+        let body = Body::sized(throttle::maybe_throttle(reader, self.rate_limit), size);
+        let image: Image = self.send_json(self.http.put(url).body(body))?;
+        self.invalidate(Some(uuid))?;
+
+        let digest = match hashes {
+            Some(h) => {
+                let hashes = h.lock().expect("digest lock poisoned").clone();
+                FileDigest::from(hashes)
+            }
+            None => digest.cloned().expect("a digest was given"),
+        };
+        // Catch corruption in transit, which the server can't when it isn't given a SHA-1.
+        if let Some(file) = image.files.first() {
+            digest.verify(file)?;
+        }
+        Ok((image, digest))
+    }
+
+    /// Returns the manifest's entry for file `index` of the image.
+    fn file_entry(&self, uuid: &Uuid, index: usize) -> Result<File, Box<dyn Error>> {
+        let image = self.get(uuid)?;
+        Ok(image
+            .files
+            .get(index)
+            .cloned()
+            .ok_or_else(|| format!("image {} has no file at index {}", uuid, index))?)
+    }
+
+    /// Starts downloading file `index` of the image (GetImageFile), from byte `offset` onwards if
+    /// it's non-zero. The server may ignore the range and send the whole file.
+    pub(crate) fn get_file(
+        &self,
+        uuid: &Uuid,
+        index: usize,
+        offset: u64,
+    ) -> Result<Response, Box<dyn Error>> {
+        let mut url = self.url(&format!("images/{}/file", uuid))?;
+        if index > 0 {
+            url.query_pairs_mut()
+                .append_pair("index", &index.to_string());
+        }
+        let mut req = self.http.get(url);
+        if offset > 0 {
+            req = req.header(header::RANGE, format!("bytes={}-", offset));
+        }
+        self.send(req)
+    }
+
+    /// Download file `index` of the image to `dest`, verifying its size, SHA-1 and any Docker
+    /// digests against the manifest. With [`DownloadOptions::decompress`], the file is
+    /// decompressed as it arrives.
+    ///
+    /// The file is downloaded to `<dest>.partial` in the same directory, and only renamed to
+    /// `dest` once it has been written to disk and verified, so `dest` never has bad contents. On
+    /// failure, the partial file is removed, unless [`DownloadOptions::keep_partial`] is set and
+    /// the failure wasn't a [`ChecksumMismatch`] or [`TransportChecksumMismatch`].
+    ///
+    /// With [`DownloadOptions::resume`], an existing `<dest>.partial` is continued with a range
+    /// request. Its bytes are re-hashed, and the rest is only appended to them if the server
+    /// accepts byte ranges and sends a 206 for exactly the range asked for; otherwise the download
+    /// starts over from the first byte. An existing `dest` that matches the manifest isn't downloaded again.
+    pub fn download_file<P: AsRef<Path>>(
+        &self,
+        uuid: &Uuid,
+        index: usize,
+        dest: P,
+        opts: &DownloadOptions,
+    ) -> Result<DownloadReport, Box<dyn Error>> {
+        self.download_file_with_progress(uuid, index, dest, opts, |_| {})
+    }
+
+    /// Download file `index` of the image to `dest` as [`Client::download_file`] does, calling
+    /// `progress` as the file arrives.
+    pub fn download_file_with_progress<P: AsRef<Path>, F: FnMut(Progress)>(
+        &self,
+        uuid: &Uuid,
+        index: usize,
+        dest: P,
+        opts: &DownloadOptions,
+        mut progress: F,
+    ) -> Result<DownloadReport, Box<dyn Error>> {
+        let file = self.file_entry(uuid, index)?;
+        let hooks = Hooks {
+            progress: Some(&mut progress),
+            ..Hooks::default()
+        };
+        self.download_entry(uuid, index, &file, dest.as_ref(), opts, hooks)
+    }
+
+    /// Downloads several files at once, with at most `concurrency` downloads in progress, returning
+    /// the result of each request in the same order.
+    ///
+    /// Each file is verified and written as by [`Client::download_file`], and a failed download
+    /// doesn't stop the others. The client's rate limit, if any, applies to all the downloads
+    /// together rather than to each one. `progress` is called, one call at a time, as any of the
+    /// downloads progresses.
+    ///
+    /// Cancelling `cancel` stops the downloads in progress and removes their partial files, even
+    /// with [`DownloadOptions::keep_partial`]. Downloads that haven't started yet aren't started.
+    /// Either way, their result is a [`Cancelled`] error.
+    ///
+    /// The downloads run on threads of their own, since the client is blocking; there's no async
+    /// version.
+    pub fn download_many<F: FnMut(BatchProgress) + Send>(
+        &self,
+        requests: &[DownloadRequest],
+        concurrency: usize,
+        cancel: &CancelToken,
+        progress: F,
+    ) -> Vec<Result<DownloadReport, String>> {
+        let files = run_bounded(requests, concurrency, |r| {
+            if cancel.is_cancelled() {
+                return Err(Cancelled.to_string());
+            }
+            self.file_entry(&r.uuid, r.index).map_err(|e| e.to_string())
+        });
+        let total = files.iter().flatten().map(|f| f.size).sum();
+        let jobs: Vec<_> = requests.iter().zip(files).enumerate().collect();
+
+        let bytes = AtomicU64::new(0);
+        let progress = Mutex::new(progress);
+        let bucket = self
+            .rate_limit
+            .map(|limit| Arc::new(Mutex::new(throttle::Bucket::new(limit))));
+        run_bounded(&jobs, concurrency, |(i, (req, file))| {
+            let file = file.as_ref().map_err(Clone::clone)?;
+            let mut last = 0;
+            let mut report = |p: Progress| {
+                let delta = p.bytes.saturating_sub(last);
+                last = last.max(p.bytes);
+                let done = bytes.fetch_add(delta, Ordering::SeqCst) + delta;
+                (*progress.lock().expect("progress lock poisoned"))(BatchProgress {
+                    request: *i,
+                    file: p,
+                    bytes: done,
+                    total,
+                });
+            };
+            let hooks = Hooks {
+                bucket: bucket.clone(),
+                cancel: Some(cancel),
+                progress: Some(&mut report),
+            };
+            let result = self
+                .download_entry(&req.uuid, req.index, file, &req.dest, &req.opts, hooks)
+                .map_err(|e| e.to_string());
+            // Report each file as finished, which also accounts for one that was already there
+            // and so made no progress.
+            if let Ok(r) = &result {
+                report(Progress {
+                    bytes: r.bytes,
+                    total: Some(file.size),
+                    rate: 0.0,
+                });
+            }
+            result
+        })
+    }
+
+    /// Implements [`Client::download_file`], given the manifest's entry for the file.
+    fn download_entry(
+        &self,
+        uuid: &Uuid,
+        index: usize,
+        file: &File,
+        dest: &Path,
+        opts: &DownloadOptions,
+        hooks: Hooks<'_>,
+    ) -> Result<DownloadReport, Box<dyn Error>> {
+        let cancel = hooks.cancel;
+        if cancel.is_some_and(CancelToken::is_cancelled) {
+            return Err(Cancelled.into());
+        }
+        let partial = download::partial_path(dest)?;
+        let with_path = |e: std::io::Error| format!("{}: {}", partial.display(), e);
+        let resumable =
+            opts.resume && opts.decompress.resolve(file.compression) == Compression::None;
+        if resumable {
+            if let Ok(existing) = fs::File::open(dest) {
+                if let Ok(report) = download::verify_existing(file, BufReader::new(existing)) {
+                    return Ok(report);
+                }
+            }
+        }
+        let offset = match fs::metadata(&partial) {
+            Ok(m) if resumable && m.len() < file.size => m.len(),
+            _ => 0,
+        };
+
+        let mut resp = self.get_file(uuid, index, offset)?;
+        let range_honored = offset > 0 && range_honored(&resp, offset, file.size);
+        if offset > 0 && !range_honored && resp.status() == StatusCode::PARTIAL_CONTENT {
+            debug!("the server sent a different range than asked for; starting over");
+            resp = self.get_file(uuid, index, 0)?;
+        }
+        let url = resp.url().clone();
+        let content_md5 = content_md5(&resp);
+
+        let result = (|| -> Result<DownloadReport, Box<dyn Error>> {
+            let (out, prefix) = if range_honored {
+                let out = fs::OpenOptions::new()
+                    .read(true)
+                    .append(true)
+                    .open(&partial)
+                    .map_err(with_path)?;
+                let prefix =
+                    download::Prefix::read(file, (&out).take(offset)).map_err(with_path)?;
+                (out, prefix)
+            } else {
+                let out = fs::File::create(&partial).map_err(with_path)?;
+                (out, download::Prefix::new(file))
+            };
+            let mut out = BufWriter::new(out);
+            let reader = match hooks.bucket {
+                Some(bucket) => Box::new(throttle::Throttled::shared(&mut resp, bucket)),
+                None => throttle::maybe_throttle(&mut resp, opts.rate_limit.or(self.rate_limit)),
+            };
+            let reader: Box<dyn Read + '_> = match cancel {
+                Some(token) => Box::new(Cancellable {
+                    inner: reader,
+                    token,
+                }),
+                None => reader,
+            };
+            let mut ignore = |_| {};
+            let report = download::transfer(
+                reader,
+                &mut out,
+                file,
+                content_md5.as_deref(),
+                opts,
+                prefix,
+                hooks.progress.unwrap_or(&mut ignore),
+            )?;
+            out.into_inner()
+                .map_err(|e| with_path(e.into_error()))?
+                .sync_all()
+                .map_err(with_path)?;
+            fs::rename(&partial, dest).map_err(|e| format!("{}: {}", dest.display(), e))?;
+            Ok(report)
+        })();
+        if let Err(e) = &result {
+            let cancelled = cancel.is_some_and(CancelToken::is_cancelled);
+            let corrupt = e.is::<ChecksumMismatch>() || e.is::<TransportChecksumMismatch>();
+            if !opts.keep_partial || corrupt || cancelled {
+                let _ = fs::remove_file(&partial);
+            }
+            if cancelled {
+                return Err(Cancelled.into());
+            }
+        }
+        result.map_err(|e| self.timed_out(&url, e, false))
+    }
+
+    /// Get the image and all of its ancestors, by following origins on the server.
+    pub fn get_ancestry(&self, uuid: &Uuid) -> Result<Ancestry, Box<dyn Error>> {
+        let mut images: Vec<Image> = Vec::new();
+        let mut next = Some(*uuid);
+        while let Some(uuid) = next {
+            if images.iter().any(|i| i.uuid == uuid) {
+                return Err(AncestryError::Cycle(uuid).into());
+            }
+            let image = self.get(&uuid)?;
+            next = image.origin;
+            images.push(image);
+        }
+        images.reverse();
+        Ok(Ancestry::from_images(images)?)
+    }
+
+    /// Download the files of the image and all of its ancestors into `dest_dir`, from the base
+    /// image to the leaf, as needed to install the leaf image.
+    ///
+    /// Each image is written as `<uuid>.file` and `<uuid>.manifest.json`. Files already in
+    /// `dest_dir` that match the manifest's size and SHA-1 aren't downloaded again, and any other
+    /// file there is downloaded over.
+    ///
+    /// The images are downloaded one at a time, in that order, and the first that fails stops the
+    /// rest, so the images written are always a base and the descendants that depend on it. Use
+    /// [`Client::download_many`] to download images concurrently.
+    pub fn download_ancestry<P: AsRef<Path>>(
+        &self,
+        uuid: &Uuid,
+        dest_dir: P,
+        opts: &DownloadOptions,
+    ) -> Result<Vec<ImageDownload>, Box<dyn Error>> {
+        let dest_dir = dest_dir.as_ref();
+        self.get_ancestry(uuid)?
+            .into_iter()
+            .map(|image| {
+                let file_path = dest_dir.join(format!("{}.file", image.uuid));
+                let manifest_path = dest_dir.join(format!("{}.manifest.json", image.uuid));
+                self.download_image(&image, file_path, manifest_path, opts)
+            })
+            .collect()
+    }
+
+    /// Downloads the first file of `image` to `file_path`, unless it's already there, and writes
+    /// the manifest to `manifest_path`.
+    ///
+    /// A decompressed file isn't the one the server's manifest describes, so the manifest written
+    /// describes the file as written instead: its SHA-1 and size, without compression. That's also
+    /// the manifest a decompressed file already there is checked against.
+    fn download_image(
+        &self,
+        image: &Image,
+        file_path: PathBuf,
+        manifest_path: PathBuf,
+        opts: &DownloadOptions,
+    ) -> Result<ImageDownload, Box<dyn Error>> {
+        let file = image
+            .files
+            .first()
+            .ok_or_else(|| format!("image {} has no file", image.uuid))?;
+        let keep = opts.decompress.resolve(file.compression) == Compression::None;
+        let expected = if keep {
+            Some(file.clone())
+        } else {
+            Image::from_path(&manifest_path)
+                .ok()
+                .filter(|written| written.uuid == image.uuid)
+                .and_then(|written| written.files.into_iter().next())
+                .filter(|written| written.compression == Compression::None)
+        };
+        let existing = expected.as_ref().and_then(|expected| {
+            let f = fs::File::open(&file_path).ok()?;
+            download::verify_existing(expected, BufReader::new(f)).ok()
+        });
+        let skipped = existing.is_some();
+        let report = match existing {
+            Some(report) => report,
+            None => {
+                self.download_entry(&image.uuid, 0, file, &file_path, opts, Hooks::default())?
+            }
+        };
+        let mut manifest = image.clone();
+        if !keep {
+            manifest.files[0] = match expected.filter(|_| skipped) {
+                Some(written) => written,
+                None => {
+                    let with_path = |e: std::io::Error| format!("{}: {}", file_path.display(), e);
+                    let written = fs::File::open(&file_path).map_err(with_path)?;
+                    let digest = file_digest(BufReader::new(written)).map_err(with_path)?;
+                    File {
+                        digest: file.digest.is_some().then_some(digest.sha256),
+                        sha1: digest.sha1,
+                        size: digest.size,
+                        compression: Compression::None,
+                        ..file.clone()
+                    }
+                }
+            };
+        }
+        manifest.to_path(&manifest_path)?;
+        Ok(ImageDownload {
+            uuid: image.uuid,
+            file_path,
+            manifest_path,
+            skipped,
+            report,
+        })
+    }
+
+    /// Export the image to `dir` in the layout `imgadm install -m <manifest> -f <file>` accepts:
+    /// the manifest as `<uuid>.imgmanifest`, without administrative fields, and the file as
+    /// `<uuid>.zfs`, plus an extension for its compression (e.g. `<uuid>.zfs.gz`).
+    ///
+    /// A file decompressed while downloading is written as plain `<uuid>.zfs`, and the manifest is
+    /// rewritten to describe it, since `imgadm install` checks the file against the manifest.
+    ///
+    /// With [`ExportOptions::ancestry`], the image's ancestors are exported too, base image first.
+    /// Files already in `dir` that match the manifest aren't downloaded again.
+    pub fn export_to_dir<P: AsRef<Path>>(
+        &self,
+        uuid: &Uuid,
+        dir: P,
+        opts: &ExportOptions,
+    ) -> Result<Vec<ImageDownload>, Box<dyn Error>> {
+        let dir = dir.as_ref();
+        let images = if opts.ancestry {
+            self.get_ancestry(uuid)?.into_vec()
+        } else {
+            vec![self.get(uuid)?]
+        };
+        images
+            .into_iter()
+            .map(|mut image| {
+                image.strip_admin_fields();
+                let [file_name, manifest_name] = export_file_names(&image, &opts.download)?;
+                self.download_image(
+                    &image,
+                    dir.join(file_name),
+                    dir.join(manifest_name),
+                    &opts.download,
+                )
+            })
+            .collect()
+    }
+
+    /// Download an image's icon into `dir`, as `<uuid>.png`, `<uuid>.gif` or `<uuid>.jpg`
+    /// depending on its content type, returning the path it was written to.
+    ///
+    /// Returns `None`, without requesting the icon, if the manifest says the image has none.
+    /// Icons of any other type, or larger than [`MAX_ICON_SIZE`], are rejected.
+    pub fn download_icon<P: AsRef<Path>>(
+        &self,
+        uuid: &Uuid,
+        dir: P,
+    ) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        if self.get(uuid)?.icon != Some(true) {
+            return Ok(None);
+        }
+        let url = self.url(&format!("images/{}/icon", uuid))?;
+        let resp = self.send(self.http.get(url))?;
+        let content_type = resp
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        // Ignore parameters, e.g. `image/png; charset=binary`.
+        let ext = match content_type.split(';').next().unwrap_or_default().trim() {
+            "image/png" => "png",
+            "image/gif" => "gif",
+            "image/jpeg" => "jpg",
+            other => return Err(format!("unexpected icon content type: {:?}", other).into()),
+        };
+        let too_big = || {
+            format!(
+                "icon is larger than the maximum icon size of {}",
+                size::format_size(MAX_ICON_SIZE)
+            )
+        };
+        if resp.content_length().is_some_and(|len| len > MAX_ICON_SIZE) {
+            return Err(too_big().into());
+        }
+        // The length header may be missing or wrong, so don't read more than the limit anyway.
+        let mut icon = Vec::new();
+        resp.take(MAX_ICON_SIZE + 1).read_to_end(&mut icon)?;
+        if icon.len() as u64 > MAX_ICON_SIZE {
+            return Err(too_big().into());
+        }
+
+        let path = dir.as_ref().join(format!("{}.{}", uuid, ext));
+        fs::write(&path, icon).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Some(path))
+    }
+
+    /// Stream file `index` of the image into `writer`, e.g. straight into `zfs receive`, calling
+    /// `progress` periodically as bytes arrive.
+    ///
+    /// The file is still verified against the manifest, but since the bytes have
+    /// already been written by the time a [`ChecksumMismatch`] is detected, it's up to the caller
+    /// to discard them. A response whose `Content-Length` isn't the manifest's size is refused
+    /// before anything is written. Progress totals are the manifest's size.
+    ///
+    /// There's no async version, since the client is blocking throughout; from async code, call
+    /// this on a blocking thread, e.g. with `tokio::task::spawn_blocking`.
+    pub fn download_file_to<W: Write, F: FnMut(Progress)>(
+        &self,
+        uuid: &Uuid,
+        index: usize,
+        mut writer: W,
+        opts: &DownloadOptions,
+        mut progress: F,
+    ) -> Result<DownloadReport, Box<dyn Error>> {
+        let file = self.file_entry(uuid, index)?;
+        let mut resp = self.get_file(uuid, index, 0)?;
+        if let Some(len) = resp.content_length().filter(|&len| len != file.size) {
+            return Err(ChecksumMismatch {
+                check: "size",
+                expected: file.size.to_string(),
+                actual: len.to_string(),
+            }
+            .into());
+        }
+        let url = resp.url().clone();
+        let content_md5 = content_md5(&resp);
+        let prefix = download::Prefix::new(&file);
+        download::transfer(
+            throttle::maybe_throttle(&mut resp, opts.rate_limit.or(self.rate_limit)),
+            &mut writer,
+            &file,
+            content_md5.as_deref(),
+            opts,
+            prefix,
+            &mut progress,
+        )
+        .map_err(|e| self.timed_out(&url, e, false))
+    }
+
+    /// Add or remove accounts from the ACL of a private image, returning the updated image.
+    ///
+    /// The update is validated with [`AclUpdate::validate`] against the image's current manifest
+    /// before it is sent.
+    pub fn update_acl(&self, uuid: &Uuid, update: &AclUpdate) -> Result<Image, Box<dyn Error>> {
+        let image = self.get(uuid)?;
+        update.validate(&image)?;
+        let mut url = self.url(&format!("images/{}/acl", uuid))?;
+        url.query_pairs_mut()
+            .append_pair("action", &update.action.to_string());
+        let image = self.send_json(self.http.post(url).json(update))?;
+        self.invalidate(Some(uuid))?;
+        Ok(image)
+    }
+
+    /// Add accounts to the ACL of a private image (AddImageAcl).
+    pub fn add_acl(&self, uuid: &Uuid, accounts: &[Uuid]) -> Result<Image, Box<dyn Error>> {
+        self.update_acl(uuid, &AclUpdate::add(accounts.iter().copied()))
+    }
+
+    /// Remove accounts from the ACL of a private image (RemoveImageAcl).
+    pub fn remove_acl(&self, uuid: &Uuid, accounts: &[Uuid]) -> Result<Image, Box<dyn Error>> {
+        self.update_acl(uuid, &AclUpdate::remove(accounts.iter().copied()))
+    }
+}
+
+/// Extra state for a download, shared with other downloads by [`Client::download_many`].
+#[derive(Default)]
+struct Hooks<'a> {
+    /// A rate limit shared with other downloads, overriding the download's and the client's.
+    bucket: Option<throttle::SharedBucket>,
+    cancel: Option<&'a CancelToken>,
+    progress: Option<&'a mut dyn FnMut(Progress)>,
+}
+
+/// Returns the names [`Client::export_to_dir`] gives the image's file and manifest.
+fn export_file_names(image: &Image, opts: &DownloadOptions) -> Result<[String; 2], Box<dyn Error>> {
+    let file = image
+        .files
+        .first()
+        .ok_or_else(|| format!("image {} has no file", image.uuid))?;
+    // The file keeps its compression unless it's decompressed while downloading.
+    let compression = match opts.decompress.resolve(file.compression) {
+        Compression::None => file.compression,
+        _ => Compression::None,
+    };
+    let file_name = match compression.extension() {
+        Some(ext) => format!("{}.zfs.{}", image.uuid, ext),
+        None => format!("{}.zfs", image.uuid),
+    };
+    Ok([file_name, format!("{}.imgmanifest", image.uuid)])
+}
+
+/// Whether `resp` is the rest of a file of `size` bytes from `offset` on, as a range request for it
+/// asked: a 206 from a server that accepts byte ranges, whose `Content-Range` is exactly the range
+/// asked for.
+fn range_honored(resp: &Response, offset: u64, size: u64) -> bool {
+    let header = |name| {
+        resp.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
     };
+    let accepts_bytes = header(header::ACCEPT_RANGES)
+        .split(',')
+        .any(|unit| unit.trim().eq_ignore_ascii_case("bytes"));
+    let range = header(header::CONTENT_RANGE)
+        .strip_prefix("bytes ")
+        .and_then(|r| r.split_once('/'));
+    let range_matches = range.is_some_and(|(range, total)| {
+        range == format!("{}-{}", offset, size.saturating_sub(1))
+            && (total == "*" || total == size.to_string())
+    });
+    resp.status() == StatusCode::PARTIAL_CONTENT && accepts_bytes && range_matches
+}
 
-    println!("url: {}", url);
-    let images: Vec<Image> = reqwest::blocking::get(url)?.json()?;
-    Ok(images)
+/// Describes each homepage or EULA of `images` that isn't a valid URL: the images
+/// [`Client::list`] warns about. `img validate` reports the same, for one manifest.
+fn url_warnings(images: &[Image]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for image in images {
+        let urls = [("homepage", &image.homepage), ("eula", &image.eula)];
+        for (field, url) in urls.iter() {
+            if let Some(MaybeUrl::Invalid(u)) = url {
+                warnings.push(format!(
+                    "image {} ({}@{}) has an invalid {} URL \"{}\"",
+                    image.uuid, image.name, image.version, field, u
+                ));
+            }
+        }
+    }
+    warnings
 }
 
+/// Returns the `Content-MD5` header of a response.
+fn content_md5(resp: &Response) -> Option<String> {
+    resp.headers()
+        .get("content-md5")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// List images on the public Joyent IMGAPI server.
+pub fn list(filter: Option<&ImageFilter>) -> Result<Vec<Image>, Box<dyn Error>> {
+    Client::default().list(filter)
+}
+
+/// Get a single image from the public Joyent IMGAPI server.
 pub fn get(image_uuid: &str) -> Result<Image, Box<dyn Error>> {
-    let _ = Uuid::parse_str(image_uuid)?;
-    let base_url = Url::parse(JOYENT_IMGAPI_URL)?;
-    let img_url = base_url.join(image_uuid)?;
-    let img: Image = reqwest::blocking::get(img_url)?.json()?;
-    Ok(img)
+    let uuid = Uuid::parse_str(image_uuid)?;
+    Client::default().get(&uuid)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::testutil::{image_with_file, uuid, Imgapi, Request, Response, Server};
+
+    const FILE: &[u8] = b"the contents of an image file, as downloaded";
+
+    /// How much of [`FILE`] an interrupted download gets.
+    const HALF: usize = 20;
+
+    /// A server with image 1, whose file is [`FILE`], and a directory to download it to.
+    fn serving(change: impl FnOnce(&mut Image)) -> (Imgapi, tempfile::TempDir) {
+        let server = Imgapi::start();
+        let mut image = image_with_file(1, FILE, json!({}));
+        change(&mut image);
+        server.add(&image, FILE);
+        (server, tempfile::tempdir().expect("a temporary directory"))
+    }
+
+    fn checksum_error<'a>(e: &'a (dyn Error + 'static)) -> &'a ChecksumMismatch {
+        e.downcast_ref::<ChecksumMismatch>()
+            .unwrap_or_else(|| panic!("not a checksum mismatch: {}", e))
+    }
+
+    #[test]
+    fn downloads_and_verifies_a_file() {
+        let (server, dir) = serving(|_| {});
+        let dest = dir.path().join("file");
+        let report = server
+            .client()
+            .download_file(&uuid(1), 0, &dest, &DownloadOptions::default())
+            .expect("downloading");
+        assert_eq!(fs::read(&dest).unwrap(), FILE);
+        assert_eq!(report.bytes, FILE.len() as u64);
+        assert_eq!(report.sha1, file_digest(FILE).unwrap().sha1);
+        assert!(!dir.path().join("file.partial").exists());
+    }
+
+    #[test]
+    fn a_corrupted_file_is_a_checksum_mismatch_and_removed() {
+        let (server, dir) = serving(|_| {});
+        server.corrupt(uuid(1));
+        let dest = dir.path().join("file");
+        // Even when asked to keep a partial file, a corrupt one isn't kept.
+        let opts = DownloadOptions {
+            keep_partial: true,
+            ..DownloadOptions::default()
+        };
+        let err = server
+            .client()
+            .download_file(&uuid(1), 0, &dest, &opts)
+            .expect_err("downloading a corrupted file");
+        assert_eq!(checksum_error(&*err).check, "sha1");
+        assert!(!dest.exists());
+        assert!(!dir.path().join("file.partial").exists());
+    }
+
+    #[test]
+    fn a_file_of_the_wrong_size_is_a_checksum_mismatch_and_removed() {
+        let (server, dir) = serving(|image| image.files[0].size += 5);
+        let dest = dir.path().join("file");
+        let err = server
+            .client()
+            .download_file(&uuid(1), 0, &dest, &DownloadOptions::default())
+            .expect_err("downloading a file of the wrong size");
+        let mismatch = checksum_error(&*err);
+        assert_eq!(mismatch.check, "size");
+        assert_eq!(mismatch.expected, (FILE.len() + 5).to_string());
+        assert_eq!(mismatch.actual, FILE.len().to_string());
+        assert!(!dest.exists());
+        assert!(!dir.path().join("file.partial").exists());
+    }
+
+    #[test]
+    fn streams_with_progress_up_to_the_manifest_size() {
+        let file: Vec<u8> = (0..1_000_000u32).map(|n| n as u8).collect();
+        let server = Imgapi::start();
+        let image = image_with_file(1, &file, json!({}));
+        server.add(&image, &file);
+
+        let mut out = Vec::new();
+        let mut calls = Vec::new();
+        let report = server
+            .client()
+            .download_file_to(&uuid(1), 0, &mut out, &DownloadOptions::default(), |p| {
+                calls.push(p)
+            })
+            .expect("downloading");
+        assert_eq!(out, file);
+        assert_eq!(report.bytes, file.len() as u64);
+        assert!(!calls.is_empty());
+        assert!(calls.windows(2).all(|w| w[0].bytes <= w[1].bytes));
+        assert!(calls.iter().all(|p| p.total == Some(file.len() as u64)));
+        assert_eq!(calls.last().unwrap().bytes, file.len() as u64);
+    }
+
+    #[test]
+    fn streaming_refuses_a_response_of_the_wrong_length() {
+        let (server, _dir) = serving(|image| image.files[0].size += 5);
+        let mut out = Vec::new();
+        let mut calls = 0;
+        let err = server
+            .client()
+            .download_file_to(&uuid(1), 0, &mut out, &DownloadOptions::default(), |_| {
+                calls += 1
+            })
+            .expect_err("streaming a file of the wrong size");
+        let mismatch = checksum_error(&*err);
+        assert_eq!(mismatch.check, "size");
+        assert_eq!(mismatch.expected, (FILE.len() + 5).to_string());
+        assert_eq!(mismatch.actual, FILE.len().to_string());
+        assert!(out.is_empty());
+        assert_eq!(calls, 0);
+    }
+
+    /// A server with image 1, whose file is [`FILE`], sending its file as `respond` says to, given
+    /// the request and how many requests for the file came before it.
+    fn file_server<R>(respond: R) -> Server
+    where
+        R: Fn(&Request, usize) -> Response + Send + Sync + 'static,
+    {
+        let image = image_with_file(1, FILE, json!({}));
+        let manifest = serde_json::to_value(&image).unwrap();
+        let attempts = Mutex::new(0);
+        Server::start(move |req| {
+            if !req.path().ends_with("/file") {
+                return Response::json(200, &manifest);
+            }
+            let mut attempts = attempts.lock().unwrap();
+            *attempts += 1;
+            respond(req, *attempts - 1)
+        })
+    }
+
+    /// A response that promises the whole file but stops halfway, as a dropped connection would.
+    fn interrupted() -> Response {
+        Response::new(200, &FILE[..HALF]).with_header("Content-Length", &FILE.len().to_string())
+    }
+
+    /// The rest of the file from the byte a range request asks for.
+    fn rest(req: &Request) -> Response {
+        let from: usize = req
+            .header("range")
+            .and_then(|r| r.strip_prefix("bytes="))
+            .and_then(|r| r.strip_suffix('-'))
+            .and_then(|r| r.parse().ok())
+            .expect("a range request");
+        let range = format!("bytes {}-{}/{}", from, FILE.len() - 1, FILE.len());
+        Response::new(206, &FILE[from..])
+            .with_header("Accept-Ranges", "bytes")
+            .with_header("Content-Range", &range)
+    }
+
+    /// Downloads the file from `server` with resuming, once to be interrupted and once more.
+    fn download_twice(server: &Server) -> (tempfile::TempDir, DownloadReport) {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("file");
+        let opts = DownloadOptions {
+            resume: true,
+            keep_partial: true,
+            ..DownloadOptions::default()
+        };
+        let client = server.client();
+        client
+            .download_file(&uuid(1), 0, &dest, &opts)
+            .expect_err("an interrupted download");
+        assert_eq!(
+            fs::read(dir.path().join("file.partial")).unwrap(),
+            &FILE[..HALF]
+        );
+        let report = client
+            .download_file(&uuid(1), 0, &dest, &opts)
+            .expect("resuming the download");
+        assert_eq!(fs::read(&dest).unwrap(), FILE);
+        assert!(!dir.path().join("file.partial").exists());
+        (dir, report)
+    }
+
+    fn file_requests(server: &Server) -> Vec<Request> {
+        let requests = server.requests();
+        requests
+            .into_iter()
+            .filter(|r| r.path().ends_with("/file"))
+            .collect()
+    }
+
+    #[test]
+    fn resumes_an_interrupted_download() {
+        let server = file_server(|req, attempt| match attempt {
+            0 => interrupted(),
+            _ => rest(req),
+        });
+        let (_dir, report) = download_twice(&server);
+        assert_eq!(report.resumed_from, HALF as u64);
+        assert_eq!(report.sha1, file_digest(FILE).unwrap().sha1);
+        let requests = file_requests(&server);
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].header("range"), Some("bytes=20-"));
+    }
+
+    #[test]
+    fn starts_over_if_the_server_sends_the_whole_file() {
+        let server = file_server(|_, attempt| match attempt {
+            0 => interrupted(),
+            _ => Response::new(200, FILE).with_header("Accept-Ranges", "bytes"),
+        });
+        let (_dir, report) = download_twice(&server);
+        assert_eq!(report.resumed_from, 0);
+        assert_eq!(file_requests(&server).len(), 2);
+    }
+
+    #[test]
+    fn starts_over_if_the_server_doesnt_accept_ranges() {
+        let server = file_server(|req, attempt| match attempt {
+            0 => interrupted(),
+            1 => {
+                let mut resp = rest(req);
+                resp.headers.retain(|(k, _)| k != "Accept-Ranges");
+                resp
+            }
+            _ => Response::new(200, FILE),
+        });
+        let (_dir, report) = download_twice(&server);
+        assert_eq!(report.resumed_from, 0);
+        let requests = file_requests(&server);
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[2].header("range"), None);
+    }
+
+    #[test]
+    fn starts_over_if_the_server_sends_another_range() {
+        let server = file_server(|_, attempt| match attempt {
+            0 => interrupted(),
+            1 => {
+                let range = format!("bytes 10-{}/{}", FILE.len() - 1, FILE.len());
+                Response::new(206, &FILE[10..])
+                    .with_header("Accept-Ranges", "bytes")
+                    .with_header("Content-Range", &range)
+            }
+            _ => Response::new(200, FILE),
+        });
+        let (_dir, report) = download_twice(&server);
+        assert_eq!(report.resumed_from, 0);
+        let requests = file_requests(&server);
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[2].header("range"), None);
+    }
+
+    /// A server with images 1 to `n`, each with a file of `n * 100` bytes, that takes a moment to
+    /// start sending each file. Also returns the most files it was sending at once.
+    fn slow_server(n: u128) -> (Server, Arc<AtomicU64>) {
+        let images: HashMap<String, (Value, Vec<u8>)> = (1..=n)
+            .map(|n| {
+                let file = vec![n as u8; n as usize * 100];
+                let image = image_with_file(n, &file, json!({}));
+                let manifest = serde_json::to_value(&image).unwrap();
+                (uuid(n).to_string(), (manifest, file))
+            })
+            .collect();
+        let (sending, most) = (Arc::new(AtomicU64::new(0)), Arc::new(AtomicU64::new(0)));
+        let counted = Arc::clone(&most);
+        let server = Server::start(move |req| {
+            let segments: Vec<&str> = req.path().split('/').collect();
+            let (manifest, file) = match images.get(segments[2]) {
+                Some(image) => image,
+                None => return Response::error(404, "ResourceNotFound", "image not found"),
+            };
+            if segments.get(3) != Some(&"file") {
+                return Response::json(200, manifest);
+            }
+            let now = sending.fetch_add(1, Ordering::SeqCst) + 1;
+            counted.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(50));
+            sending.fetch_sub(1, Ordering::SeqCst);
+            Response::new(200, file.clone())
+        });
+        (server, most)
+    }
+
+    #[test]
+    fn downloads_many_at_a_bounded_concurrency() {
+        let (server, most) = slow_server(6);
+        let dir = tempfile::tempdir().unwrap();
+        let mut requests: Vec<_> = (1..=6)
+            .map(|n| DownloadRequest::new(uuid(n), dir.path().join(n.to_string())))
+            .collect();
+        // One that isn't on the server, which doesn't stop the others.
+        requests.insert(2, DownloadRequest::new(uuid(99), dir.path().join("99")));
+
+        let updates = Mutex::new(Vec::new());
+        let results =
+            server
+                .client()
+                .download_many(&requests, 2, &CancelToken::new(), |p: BatchProgress| {
+                    updates.lock().unwrap().push(p)
+                });
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+        assert_eq!(results.len(), 7);
+        assert!(results[2].as_ref().unwrap_err().contains("image not found"));
+        for (n, result) in [1, 2, 0, 3, 4, 5, 6].iter().zip(&results) {
+            if *n > 0 {
+                assert_eq!(result.as_ref().unwrap().bytes, n * 100);
+                let file = fs::read(dir.path().join(n.to_string())).unwrap();
+                assert_eq!(file, vec![*n as u8; *n as usize * 100]);
+            }
+        }
+
+        // Progress adds up to the size of the files found, and never goes backwards.
+        let updates = updates.into_inner().unwrap();
+        let total = (1..=6).map(|n| n * 100).sum::<u64>();
+        assert!(updates.iter().all(|p| p.total == total));
+        assert!(updates.windows(2).all(|w| w[0].bytes <= w[1].bytes));
+        assert_eq!(updates.last().unwrap().bytes, total);
+        assert!(updates.iter().all(|p| p.request != 2));
+    }
+
+    #[test]
+    fn cancelling_stops_downloads_that_havent_started() {
+        let (server, _) = slow_server(4);
+        let dir = tempfile::tempdir().unwrap();
+        let requests: Vec<_> = (1..=4)
+            .map(|n| DownloadRequest::new(uuid(n), dir.path().join(n.to_string())))
+            .collect();
+        let cancel = CancelToken::new();
+        // Cancel once the first file is done, while the second is still to come.
+        let results = server.client().download_many(&requests, 1, &cancel, |p| {
+            if p.file.bytes == p.file.total.unwrap_or_default() {
+                cancel.cancel()
+            }
+        });
+        assert!(results[0].is_ok());
+        for result in &results[1..] {
+            assert_eq!(result.as_ref().unwrap_err(), &Cancelled.to_string());
+        }
+        let left: Vec<_> = fs::read_dir(dir.path()).unwrap().flatten().collect();
+        assert_eq!(left.len(), 1);
+    }
+
+    #[test]
+    fn lists_without_a_query_for_no_filter() {
+        let server = Imgapi::start();
+        let client = server.client();
+        client.list(None).unwrap();
+        client.list(Some(&ImageFilter::default())).unwrap();
+        let name = ImageFilter {
+            name: Some("base".to_string()),
+            ..ImageFilter::default()
+        };
+        client.list(Some(&name)).unwrap();
+        let targets: Vec<_> = server
+            .server
+            .requests()
+            .into_iter()
+            .map(|r| r.target)
+            .collect();
+        assert_eq!(targets, ["/images", "/images", "/images?name=base"]);
+
+        let err = client
+            .with_dry_run(DryRunMode::All)
+            .list(Some(&ImageFilter::default()))
+            .expect_err("a dry run");
+        let request = err.downcast_ref::<DryRun>().expect("a dry run");
+        assert_eq!(request.url, format!("{}images", server.server.url));
+    }
+
+    /// Image 1 on a server, with [`FILE`] gzipped as its file.
+    fn serving_gzipped() -> Imgapi {
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(FILE).unwrap();
+        let gzipped = gz.finish().unwrap();
+        let server = Imgapi::start();
+        let mut image = image_with_file(1, &gzipped, json!({}));
+        image.files[0].compression = Compression::Gzip;
+        server.add(&image, &gzipped);
+        server
+    }
+
+    /// How many times the server sent an image's file.
+    fn file_downloads(server: &Imgapi) -> usize {
+        let requests = server.server.requests();
+        requests
+            .iter()
+            .filter(|r| r.path().ends_with("/file"))
+            .count()
+    }
+
+    #[test]
+    fn exporting_decompressed_describes_the_decompressed_file() {
+        let server = serving_gzipped();
+        let dir = tempfile::tempdir().unwrap();
+        let opts = ExportOptions {
+            download: DownloadOptions {
+                decompress: Decompress::Auto,
+                ..DownloadOptions::default()
+            },
+            ..ExportOptions::default()
+        };
+        let exported = server
+            .client()
+            .export_to_dir(&uuid(1), dir.path(), &opts)
+            .unwrap();
+        assert_eq!(
+            exported[0].file_path,
+            dir.path().join(format!("{}.zfs", uuid(1)))
+        );
+        assert_eq!(fs::read(&exported[0].file_path).unwrap(), FILE);
+        let manifest = Image::from_path(&exported[0].manifest_path).unwrap();
+        let digest = file_digest(FILE).unwrap();
+        assert_eq!(manifest.files[0].sha1, digest.sha1);
+        assert_eq!(manifest.files[0].size, digest.size);
+        assert_eq!(manifest.files[0].compression, Compression::None);
+
+        // The decompressed file is there, as its manifest describes it, so it's kept.
+        let again = server
+            .client()
+            .export_to_dir(&uuid(1), dir.path(), &opts)
+            .unwrap();
+        assert!(again[0].skipped);
+        assert_eq!(file_downloads(&server), 1);
+        let rewritten = Image::from_path(&again[0].manifest_path).unwrap();
+        assert_eq!(rewritten.files[0].sha1, digest.sha1);
+        assert_eq!(rewritten.files[0].compression, Compression::None);
+
+        // Unless it's changed since.
+        fs::write(&exported[0].file_path, "something else").unwrap();
+        let fixed = server
+            .client()
+            .export_to_dir(&uuid(1), dir.path(), &opts)
+            .unwrap();
+        assert!(!fixed[0].skipped);
+        assert_eq!(file_downloads(&server), 2);
+        assert_eq!(fs::read(&exported[0].file_path).unwrap(), FILE);
+    }
+
+    #[test]
+    fn exporting_compressed_keeps_the_servers_file() {
+        let server = serving_gzipped();
+        let dir = tempfile::tempdir().unwrap();
+        let exported = server
+            .client()
+            .export_to_dir(&uuid(1), dir.path(), &ExportOptions::default())
+            .unwrap();
+        let name = format!("{}.zfs.gz", uuid(1));
+        assert_eq!(exported[0].file_path, dir.path().join(name));
+        let manifest = Image::from_path(&exported[0].manifest_path).unwrap();
+        let stored = server.image(uuid(1)).unwrap();
+        assert_eq!(manifest.files[0].sha1, stored.files[0].sha1);
+        assert_eq!(manifest.files[0].size, stored.files[0].size);
+        assert_eq!(manifest.files[0].compression, Compression::Gzip);
+    }
+
+    #[test]
+    fn an_update_sends_null_to_clear_a_field() {
+        let manifest = serde_json::to_value(image_with_file(1, FILE, json!({}))).unwrap();
+        let server = Server::start(move |_| Response::json(200, &manifest));
+        let update = ImageUpdate {
+            description: crate::Patch::Clear,
+            ..ImageUpdate::default()
+        };
+        server.client().update(&uuid(1), &update).unwrap();
+        let requests = server.requests();
+        assert_eq!(requests[0].query("action").as_deref(), Some("update"));
+        let body: Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body, json!({"description": null}));
+    }
+
+    #[test]
+    fn lists_images_with_invalid_urls_and_describes_them() {
+        let server = Imgapi::start();
+        let bad = image_with_file(1, FILE, json!({"homepage": "/relative", "eula": "nope"}));
+        server.add(&bad, FILE);
+        server.add(
+            &image_with_file(2, FILE, json!({"homepage": "https://example.com"})),
+            FILE,
+        );
+        let images = server.client().list(None).unwrap();
+        assert_eq!(images.len(), 2);
+        let warnings = url_warnings(&images);
+        assert_eq!(
+            warnings,
+            [
+                format!(
+                    "image {} (base@1.0.0) has an invalid homepage URL \"/relative\"",
+                    uuid(1)
+                ),
+                format!(
+                    "image {} (base@1.0.0) has an invalid eula URL \"nope\"",
+                    uuid(1)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn downloads_an_ancestry_skipping_the_files_already_there() {
+        let server = Imgapi::start();
+        let base = image_with_file(1, b"base", json!({}));
+        let leaf = image_with_file(2, b"leaf", json!({ "origin": uuid(1) }));
+        server.add(&base, b"base");
+        server.add(&leaf, b"leaf");
+        let dir = tempfile::tempdir().unwrap();
+        let file = |n| dir.path().join(format!("{}.file", uuid(n)));
+        fs::write(file(1), b"base").unwrap();
+        fs::write(file(2), b"stale").unwrap();
+
+        let downloads = server
+            .client()
+            .download_ancestry(&uuid(2), dir.path(), &DownloadOptions::default())
+            .unwrap();
+        let skipped: Vec<_> = downloads.iter().map(|d| (d.uuid, d.skipped)).collect();
+        assert_eq!(skipped, [(uuid(1), true), (uuid(2), false)]);
+        assert_eq!(fs::read(file(2)).unwrap(), b"leaf");
+        let requests = server.server.requests();
+        let sent: Vec<_> = requests
+            .iter()
+            .map(|r| r.path())
+            .filter(|p| p.ends_with("/file"))
+            .collect();
+        assert_eq!(sent, [format!("/images/{}/file", uuid(2))]);
+        for n in 1..=2 {
+            let manifest = dir.path().join(format!("{}.manifest.json", uuid(n)));
+            assert_eq!(Image::from_path(manifest).unwrap().uuid, uuid(n));
+        }
+    }
 }
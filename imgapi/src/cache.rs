@@ -0,0 +1,342 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+
+use super::{Channel, DateTime, Image, Uuid};
+
+const INDEX_FILE: &str = "index.json";
+const LOCK_FILE: &str = ".lock";
+
+/// How a [`blocking::Client`](crate::blocking::Client) uses its [`CatalogCache`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CacheMode {
+    /// Always ask the server, and leave the cache alone.
+    #[default]
+    Off,
+
+    /// Answer from the cache while an entry is younger than `ttl`. Older entries are fetched
+    /// again, revalidated with their ETag if the server sent one.
+    ReadThrough { ttl: Duration },
+
+    /// Only answer from the cache, however old the entries are, and send nothing. Lists and
+    /// images are looked up in the catalog saved by
+    /// [`Client::refresh_catalog`](crate::blocking::Client::refresh_catalog) if there is one,
+    /// and otherwise must have been cached exactly; anything else is a [`NotCached`] error, and
+    /// requests that can't be cached, such as downloads and changes, are [`Offline`] errors.
+    OfflineOnly,
+}
+
+/// Counters for how a [`CatalogCache`] has been used, since it was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Requests answered from the cache without asking the server.
+    pub hits: u64,
+
+    /// Requests that had to be fetched from the server.
+    pub misses: u64,
+
+    /// Stale entries the server confirmed were still current (HTTP 304).
+    pub revalidated: u64,
+
+    /// Unreadable index or entry files, which were treated as missing.
+    pub corrupt: u64,
+}
+
+/// A request in [`CacheMode::OfflineOnly`] that isn't in the cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotCached {
+    /// The request path, relative to the server, e.g. `images/<uuid>`.
+    pub key: String,
+}
+
+impl fmt::Display for NotCached {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} is not cached, and the cache is offline-only",
+            self.key
+        )
+    }
+}
+
+impl Error for NotCached {}
+
+/// A request that a client in [`CacheMode::OfflineOnly`] would have had to send to the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Offline {
+    pub method: String,
+
+    /// The URL, with any password redacted.
+    pub url: String,
+}
+
+impl fmt::Display for Offline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "can't {} {}: the client is offline, and only answers from the cache",
+            self.method, self.url
+        )
+    }
+}
+
+impl Error for Offline {}
+
+/// A copy of every image in a channel, saved in a [`CatalogCache`] so that lists and images can
+/// be looked up in it offline.
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    /// The images, in any state, in the order the server lists them.
+    pub images: Vec<Image>,
+
+    /// How long ago the catalog was fetched.
+    pub age: Duration,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    /// The name of the entry's file in the cache directory.
+    file: String,
+    fetched_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+}
+
+type Index = BTreeMap<String, IndexEntry>;
+
+/// A cached response body.
+pub(crate) struct Cached {
+    pub(crate) value: Value,
+    pub(crate) etag: Option<String>,
+    pub(crate) age: Duration,
+}
+
+/// A directory of cached IMGAPI responses: one JSON file per request, plus an index with the time
+/// each was fetched and its ETag.
+///
+/// Several processes may share a cache directory; reads and writes are serialized with a lock
+/// file. A damaged index or entry is treated as missing, and replaced on the next fetch.
+#[derive(Debug, Clone)]
+pub struct CatalogCache {
+    dir: PathBuf,
+    stats: Arc<Mutex<CacheStats>>,
+}
+
+impl CatalogCache {
+    /// Opens the cache in `dir`, creating the directory if needed.
+    pub fn new<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            stats: Arc::default(),
+        })
+    }
+
+    /// The cache directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Counters for how the cache has been used, shared between clones of the cache.
+    pub fn stats(&self) -> CacheStats {
+        *self.stats.lock().expect("cache stats lock poisoned")
+    }
+
+    /// The request paths that are cached, e.g. `images/<uuid>` and `images?os=linux`.
+    pub fn keys(&self) -> io::Result<Vec<String>> {
+        let _lock = self.lock(false)?;
+        Ok(self.read_index().into_keys().collect())
+    }
+
+    /// Removes every entry.
+    pub fn clear(&self) -> io::Result<()> {
+        let _lock = self.lock(true)?;
+        for entry in self.read_index().values() {
+            remove_if_exists(&self.dir.join(&entry.file))?;
+        }
+        remove_if_exists(&self.dir.join(INDEX_FILE))
+    }
+
+    /// The catalog of `channel`, or of the server's default channel, as last saved by
+    /// [`Client::refresh_catalog`](crate::blocking::Client::refresh_catalog), if there is one.
+    pub fn catalog(&self, channel: Option<&Channel>) -> Option<Catalog> {
+        let cached = self.lookup(&catalog_key(channel))?;
+        match serde_json::from_value(cached.value) {
+            Ok(images) => Some(Catalog {
+                images,
+                age: cached.age,
+            }),
+            Err(_) => {
+                self.record(|s| s.corrupt += 1);
+                None
+            }
+        }
+    }
+
+    /// Removes the entries a change to an image could have made stale: the image itself, and
+    /// every list. With no UUID, e.g. for a newly created image, only the lists are removed.
+    /// Catalogs are kept, since they're meant to be out of date until they're refreshed.
+    pub fn invalidate(&self, uuid: Option<&Uuid>) -> io::Result<()> {
+        let image_key = uuid.map(|u| format!("images/{}", u));
+        let _lock = self.lock(true)?;
+        let mut index = self.read_index();
+        let stale: Vec<String> = index
+            .keys()
+            .filter(|k| is_list_key(k) || image_key.as_deref().is_some_and(|i| is_image_key(k, i)))
+            .cloned()
+            .collect();
+        if stale.is_empty() {
+            return Ok(());
+        }
+        for key in stale {
+            if let Some(entry) = index.remove(&key) {
+                remove_if_exists(&self.dir.join(entry.file))?;
+            }
+        }
+        self.write_index(&index)
+    }
+
+    pub(crate) fn lookup(&self, key: &str) -> Option<Cached> {
+        let _lock = self.lock(false).ok()?;
+        let entry = self.read_index().remove(key)?;
+        let value = fs::read(self.dir.join(&entry.file))
+            .ok()
+            .and_then(|b| serde_json::from_slice(&b).ok());
+        let Some(value) = value else {
+            self.record(|s| s.corrupt += 1);
+            return None;
+        };
+        let age = Utc::now()
+            .signed_duration_since(entry.fetched_at)
+            .to_std()
+            .unwrap_or_default();
+        Some(Cached {
+            value,
+            etag: entry.etag,
+            age,
+        })
+    }
+
+    pub(crate) fn store(&self, key: &str, etag: Option<String>, value: &Value) -> io::Result<()> {
+        let file = format!("{}.json", hex_sha1(key));
+        let _lock = self.lock(true)?;
+        write_atomic(
+            &self.dir,
+            &self.dir.join(&file),
+            &serde_json::to_vec(value)?,
+        )?;
+        let mut index = self.read_index();
+        index.insert(
+            key.to_string(),
+            IndexEntry {
+                file,
+                fetched_at: Utc::now(),
+                etag,
+            },
+        );
+        self.write_index(&index)
+    }
+
+    /// Marks an entry as fetched now, after the server confirmed it's still current.
+    pub(crate) fn touch(&self, key: &str) -> io::Result<()> {
+        let _lock = self.lock(true)?;
+        let mut index = self.read_index();
+        match index.get_mut(key) {
+            Some(entry) => entry.fetched_at = Utc::now(),
+            None => return Ok(()),
+        }
+        self.write_index(&index)
+    }
+
+    pub(crate) fn record<F: FnOnce(&mut CacheStats)>(&self, f: F) {
+        f(&mut self.stats.lock().expect("cache stats lock poisoned"))
+    }
+
+    /// Takes the cache's lock file, which is released when the returned file is dropped.
+    fn lock(&self, exclusive: bool) -> io::Result<File> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.dir.join(LOCK_FILE))?;
+        if exclusive {
+            file.lock()?;
+        } else {
+            file.lock_shared()?;
+        }
+        Ok(file)
+    }
+
+    fn read_index(&self) -> Index {
+        match fs::read(self.dir.join(INDEX_FILE)) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|_| {
+                self.record(|s| s.corrupt += 1);
+                Index::new()
+            }),
+            Err(_) => Index::new(),
+        }
+    }
+
+    fn write_index(&self, index: &Index) -> io::Result<()> {
+        let path = self.dir.join(INDEX_FILE);
+        write_atomic(&self.dir, &path, &serde_json::to_vec_pretty(index)?)
+    }
+}
+
+/// The cache key of the catalog of `channel`.
+pub(crate) fn catalog_key(channel: Option<&Channel>) -> String {
+    match channel {
+        Some(channel) => format!("catalog?channel={}", channel),
+        None => "catalog".to_string(),
+    }
+}
+
+/// The channel of the catalog with cache key `key`, or `None` if the key isn't a catalog's. The
+/// inner `None` is the server's default channel.
+pub(crate) fn catalog_channel(key: &str) -> Option<Option<Channel>> {
+    match key.strip_prefix("catalog")? {
+        "" => Some(None),
+        rest => rest.strip_prefix("?channel=")?.parse().ok().map(Some),
+    }
+}
+
+/// Whether the cache key is for ListImages rather than a single image.
+fn is_list_key(key: &str) -> bool {
+    key == "images" || key.starts_with("images?")
+}
+
+/// Whether the cache key is for GetImage of the image whose key is `image_key`, in any channel.
+fn is_image_key(key: &str, image_key: &str) -> bool {
+    key.strip_prefix(image_key)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('?'))
+}
+
+fn hex_sha1(s: &str) -> String {
+    format!("{:x}", Sha1::digest(s.as_bytes()))
+}
+
+/// Writes `path` by renaming a temporary file into place, so readers never see a partial write.
+fn write_atomic(dir: &Path, path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+    tmp.write_all(bytes)?;
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
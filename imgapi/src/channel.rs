@@ -0,0 +1,141 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// The name of an image channel, e.g. `release` or `experimental`.
+///
+/// Channel names given by the user, with [`FromStr`] or [`TryFrom`], must be non-empty and consist
+/// only of ASCII letters, digits, `-`, `_` and `.`. The special name `*` means "all channels" and is
+/// only meaningful in [`ImageFilter::channel`]. Names from the server are taken as they are, so
+/// that one image in a channel named otherwise doesn't stop the rest of a listing from parsing.
+///
+/// [`ImageFilter::channel`]: crate::ImageFilter::channel
+#[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Hash, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Channel(String);
+
+impl Channel {
+    /// The special channel name matching all channels.
+    pub const ALL: &'static str = "*";
+
+    /// Returns the special channel matching all channels.
+    pub fn all() -> Self {
+        Self(Self::ALL.to_string())
+    }
+
+    /// Whether this is the special channel matching all channels.
+    pub fn is_all(&self) -> bool {
+        self.0 == Self::ALL
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParseChannelError {
+    name: String,
+}
+
+impl fmt::Display for ParseChannelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid channel name \"{}\": must be non-empty and contain only letters, digits, '-', '_' and '.'",
+            self.name
+        )
+    }
+}
+
+impl Error for ParseChannelError {}
+
+impl TryFrom<String> for Channel {
+    type Error = ParseChannelError;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let valid = s == Self::ALL
+            || (!s.is_empty()
+                && s.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)));
+        if valid {
+            Ok(Self(s))
+        } else {
+            Err(ParseChannelError { name: s })
+        }
+    }
+}
+
+impl TryFrom<&str> for Channel {
+    type Error = ParseChannelError;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Self::try_from(s.to_string())
+    }
+}
+
+impl FromStr for Channel {
+    type Err = ParseChannelError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(s)
+    }
+}
+
+impl From<Channel> for String {
+    fn from(c: Channel) -> Self {
+        c.0
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A channel as listed by the server (ListChannels).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelInfo {
+    pub name: Channel,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Whether this is the server's default channel, used when a request doesn't name one.
+    #[serde(default)]
+    pub default: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testutil::image;
+
+    #[test]
+    fn user_names_are_checked() {
+        for name in ["release", "v1.2_test-3", "*"] {
+            assert_eq!(name.parse::<Channel>().unwrap().as_str(), name);
+        }
+        for name in ["", "odd name", "dev/1", "ünïcode"] {
+            let err = name.parse::<Channel>().unwrap_err();
+            assert!(err.to_string().contains("invalid channel name"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn server_names_are_taken_as_they_are() {
+        let image = image(1, json!({ "channels": ["release", "odd name!"] }));
+        let channels = image.channels.as_deref().unwrap();
+        assert_eq!(channels[1].as_str(), "odd name!");
+        assert_eq!(
+            image.to_json().unwrap()["channels"],
+            json!(["release", "odd name!"])
+        );
+
+        let info: ChannelInfo = serde_json::from_value(json!({ "name": "" })).unwrap();
+        assert_eq!(info.name.as_str(), "");
+    }
+}
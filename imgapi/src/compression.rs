@@ -0,0 +1,175 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use super::Compression;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// The result of [`Compression::detect`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetectedCompression {
+    /// The compression the file is actually compressed with, according to its contents.
+    pub compression: Compression,
+
+    /// Set if the file name suggested a different compression than its contents.
+    pub warning: Option<String>,
+}
+
+impl Compression {
+    /// Infers the compression from a file name, e.g. `foo.zfs.gz`.
+    ///
+    /// Files without a recognized extension are assumed to be uncompressed.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("gz") | Some("tgz") => Self::Gzip,
+            Some("bz2") | Some("tbz2") => Self::Bzip2,
+            Some("xz") | Some("txz") => Self::Xz,
+            _ => Self::None,
+        }
+    }
+
+    /// The file name extension for files with this compression, e.g. `gz`.
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Self::Gzip => Some("gz"),
+            Self::Bzip2 => Some("bz2"),
+            Self::Xz => Some("xz"),
+            Self::None | Self::Auto => None,
+        }
+    }
+
+    /// Infers the compression from the magic bytes at the start of `reader`.
+    ///
+    /// The bytes are only peeked at, not consumed, so the same reader can be used to read the
+    /// whole file afterwards.
+    pub fn sniff<R: BufRead>(reader: &mut R) -> io::Result<Self> {
+        let buf = reader.fill_buf()?;
+        Ok(if buf.starts_with(GZIP_MAGIC) {
+            Self::Gzip
+        } else if buf.starts_with(BZIP2_MAGIC) {
+            Self::Bzip2
+        } else if buf.starts_with(XZ_MAGIC) {
+            Self::Xz
+        } else {
+            Self::None
+        })
+    }
+
+    /// Returns a warning if the name of the file at `path` suggests a different compression than
+    /// `actual`.
+    pub(crate) fn mismatch_warning(path: &Path, actual: Self) -> Option<String> {
+        let by_name = Self::from_path(path);
+        if by_name == actual {
+            return None;
+        }
+        Some(format!(
+            "{}: file name suggests {} compression, but contents are {}",
+            path.display(),
+            by_name,
+            actual
+        ))
+    }
+
+    /// Determines the compression of the file at `path` from its contents, warning if its name
+    /// suggests otherwise.
+    pub fn detect<P: AsRef<Path>>(path: P) -> io::Result<DetectedCompression> {
+        let path = path.as_ref();
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let compression = Self::sniff(&mut reader)?;
+        let warning = Self::mismatch_warning(path, compression);
+        Ok(DetectedCompression {
+            compression,
+            warning,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    const GZIP: &[u8] = &[0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00];
+    const BZIP2: &[u8] = b"BZh91AY&SY";
+    const XZ: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00, 0x00, 0x04];
+    const PLAIN: &[u8] = b"not compressed at all";
+
+    #[test]
+    fn reads_the_compression_from_a_file_name() {
+        assert_eq!(Compression::from_path("a.zfs.gz"), Compression::Gzip);
+        assert_eq!(Compression::from_path("a.tgz"), Compression::Gzip);
+        assert_eq!(Compression::from_path("a.zfs.bz2"), Compression::Bzip2);
+        assert_eq!(Compression::from_path("a.txz"), Compression::Xz);
+        assert_eq!(Compression::from_path("a.zfs"), Compression::None);
+        assert_eq!(Compression::from_path("gz"), Compression::None);
+    }
+
+    #[test]
+    fn sniffs_magic_bytes_without_consuming_them() {
+        for (bytes, expected) in [
+            (GZIP, Compression::Gzip),
+            (BZIP2, Compression::Bzip2),
+            (XZ, Compression::Xz),
+            (PLAIN, Compression::None),
+            (&[][..], Compression::None),
+        ] {
+            let mut reader = BufReader::new(bytes);
+            assert_eq!(Compression::sniff(&mut reader).unwrap(), expected);
+            let mut read = Vec::new();
+            reader.read_to_end(&mut read).unwrap();
+            assert_eq!(read, bytes);
+        }
+    }
+
+    #[test]
+    fn detects_from_contents_and_warns_if_the_name_disagrees() {
+        let dir = tempfile::tempdir().unwrap();
+        let detect = |name: &str, contents: &[u8]| {
+            let path = dir.path().join(name);
+            fs::write(&path, contents).unwrap();
+            Compression::detect(&path).unwrap()
+        };
+
+        let agrees = detect("a.zfs.gz", GZIP);
+        assert_eq!(agrees.compression, Compression::Gzip);
+        assert_eq!(agrees.warning, None);
+        assert_eq!(detect("a.zfs", PLAIN).warning, None);
+
+        let disagrees = detect("b.zfs.gz", XZ);
+        assert_eq!(disagrees.compression, Compression::Xz);
+        let warning = disagrees.warning.unwrap();
+        assert!(
+            warning.ends_with("b.zfs.gz: file name suggests gzip compression, but contents are xz"),
+            "{}",
+            warning
+        );
+        let unnamed = detect("c.zfs", BZIP2);
+        assert_eq!(unnamed.compression, Compression::Bzip2);
+        assert!(unnamed
+            .warning
+            .unwrap()
+            .contains("suggests none compression"));
+    }
+
+    #[test]
+    fn auto_isnt_serialized() {
+        for c in [
+            Compression::Bzip2,
+            Compression::Gzip,
+            Compression::Xz,
+            Compression::None,
+        ] {
+            let json = serde_json::to_string(&c).unwrap();
+            assert_eq!(json, format!("\"{}\"", c));
+            assert_eq!(serde_json::from_str::<Compression>(&json).unwrap(), c);
+        }
+        assert!(serde_json::to_string(&Compression::Auto).is_err());
+        assert!(serde_json::from_str::<Compression>("\"auto\"").is_err());
+        // It's still an option on the command line.
+        assert_eq!("auto".parse(), Ok(Compression::Auto));
+    }
+}
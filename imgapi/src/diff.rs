@@ -0,0 +1,278 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::Image;
+
+/// Top-level arrays whose elements are compared as a set of values rather than by position.
+const SET_FIELDS: &[&str] = &["acl", "channels", "billing_tags", "inherited_directories"];
+
+/// A single changed field between two manifests.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    /// The path of the field, e.g. `name`, `tags.role`, `acl[<uuid>]` or `files[<sha1>].size`.
+    pub path: String,
+
+    /// The old value, or `None` if the field was added.
+    pub old: Option<Value>,
+
+    /// The new value, or `None` if the field was removed.
+    pub new: Option<Value>,
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn show(v: &Option<Value>) -> String {
+            match v {
+                Some(v) => v.to_string(),
+                None => "(none)".to_string(),
+            }
+        }
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.path,
+            show(&self.old),
+            show(&self.new)
+        )
+    }
+}
+
+/// The differences between two manifests, as produced by [`diff`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ManifestDiff {
+    pub changes: Vec<FieldChange>,
+}
+
+impl ManifestDiff {
+    /// Whether the two manifests were identical.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, FieldChange> {
+        self.changes.iter()
+    }
+}
+
+/// Renders one `path: old -> new` line per change.
+impl fmt::Display for ManifestDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in &self.changes {
+            writeln!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+/// Computes the fields that differ between the manifests `a` and `b`.
+///
+/// Maps such as `tags`, `traits` and `requirements.min_platform` are compared key by key. `acl`,
+/// `channels`, `billing_tags` and `inherited_directories` are compared as sets, and `files` are
+/// matched up by their sha1. Any other arrays are compared element-wise by position. Fields that
+/// are `null` are treated as absent.
+pub fn diff(a: &Image, b: &Image) -> ManifestDiff {
+    let mut changes = Vec::new();
+    let a = serde_json::to_value(a).unwrap_or(Value::Null);
+    let b = serde_json::to_value(b).unwrap_or(Value::Null);
+    diff_values("", &a, &b, &mut changes);
+    ManifestDiff { changes }
+}
+
+fn non_null(v: Option<&Value>) -> Option<&Value> {
+    v.filter(|v| !v.is_null())
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+fn change(path: String, old: Option<&Value>, new: Option<&Value>, out: &mut Vec<FieldChange>) {
+    out.push(FieldChange {
+        path,
+        old: old.cloned(),
+        new: new.cloned(),
+    });
+}
+
+fn diff_values(path: &str, a: &Value, b: &Value, out: &mut Vec<FieldChange>) {
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => diff_maps(path, a, b, out),
+        (Value::Array(a), Value::Array(b)) => {
+            if path == "files" {
+                diff_files(a, b, out)
+            } else if SET_FIELDS.contains(&path) {
+                diff_sets(path, a, b, out)
+            } else {
+                diff_arrays(path, a, b, out)
+            }
+        }
+        (a, b) if a != b => change(path.to_string(), Some(a), Some(b), out),
+        _ => {}
+    }
+}
+
+fn diff_maps(
+    path: &str,
+    a: &Map<String, Value>,
+    b: &Map<String, Value>,
+    out: &mut Vec<FieldChange>,
+) {
+    let keys: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+    for key in keys {
+        let path = join(path, key);
+        match (non_null(a.get(key)), non_null(b.get(key))) {
+            (Some(a), Some(b)) => diff_values(&path, a, b, out),
+            (None, None) => {}
+            (old, new) => change(path, old, new, out),
+        }
+    }
+}
+
+fn diff_arrays(path: &str, a: &[Value], b: &[Value], out: &mut Vec<FieldChange>) {
+    for i in 0..a.len().max(b.len()) {
+        let path = format!("{}[{}]", path, i);
+        match (a.get(i), b.get(i)) {
+            (Some(a), Some(b)) => diff_values(&path, a, b, out),
+            (old, new) => change(path, old, new, out),
+        }
+    }
+}
+
+fn element_key(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+fn diff_sets(path: &str, a: &[Value], b: &[Value], out: &mut Vec<FieldChange>) {
+    for v in a.iter().filter(|v| !b.contains(v)) {
+        change(format!("{}[{}]", path, element_key(v)), Some(v), None, out);
+    }
+    for v in b.iter().filter(|v| !a.contains(v)) {
+        change(format!("{}[{}]", path, element_key(v)), None, Some(v), out);
+    }
+}
+
+fn diff_files(a: &[Value], b: &[Value], out: &mut Vec<FieldChange>) {
+    let sha1 = |v: &Value| v.get("sha1").map(element_key).unwrap_or_default();
+    let find = |files: &[Value], key: &str| files.iter().find(|f| sha1(f) == key).cloned();
+
+    let mut keys: Vec<String> = a.iter().map(sha1).collect();
+    keys.extend(
+        b.iter()
+            .map(sha1)
+            .filter(|k| !a.iter().any(|f| sha1(f) == *k)),
+    );
+    for key in keys {
+        let path = format!("files[{}]", key);
+        match (find(a, &key), find(b, &key)) {
+            (Some(a), Some(b)) => diff_values(&path, &a, &b, out),
+            (old, new) => change(path, old.as_ref(), new.as_ref(), out),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testutil::{image, uuid};
+
+    fn paths(d: &ManifestDiff) -> Vec<&str> {
+        d.iter().map(|c| c.path.as_str()).collect()
+    }
+
+    fn files(files: &[(&str, u64)]) -> Value {
+        let files: Vec<_> = files
+            .iter()
+            .map(|(sha1, size)| json!({"sha1": sha1, "size": size, "compression": "gzip"}))
+            .collect();
+        json!({ "files": files })
+    }
+
+    #[test]
+    fn the_same_manifest_has_no_diff() {
+        let a = image(1, json!({"tags": {"role": "db"}}));
+        assert!(diff(&a, &a.clone()).is_empty());
+    }
+
+    #[test]
+    fn reordered_sets_have_no_diff() {
+        let a = image(
+            1,
+            json!({
+                "acl": [uuid(2), uuid(3)],
+                "channels": ["dev", "release"],
+                "billing_tags": ["a", "b"],
+                "inherited_directories": ["/opt", "/var"],
+            }),
+        );
+        let b = image(
+            1,
+            json!({
+                "acl": [uuid(3), uuid(2)],
+                "channels": ["release", "dev"],
+                "billing_tags": ["b", "a"],
+                "inherited_directories": ["/var", "/opt"],
+            }),
+        );
+        assert_eq!(diff(&a, &b), ManifestDiff::default());
+    }
+
+    #[test]
+    fn set_changes_are_keyed_by_element() {
+        let a = image(1, json!({"acl": [uuid(2), uuid(3)]}));
+        let b = image(1, json!({"acl": [uuid(3), uuid(4)]}));
+        let d = diff(&a, &b);
+        assert_eq!(
+            paths(&d),
+            [format!("acl[{}]", uuid(2)), format!("acl[{}]", uuid(4))]
+        );
+        assert_eq!(d.changes[0].new, None);
+        assert_eq!(d.changes[1].old, None);
+    }
+
+    #[test]
+    fn files_are_matched_by_sha1() {
+        let a = image(1, files(&[("aaa", 1), ("bbb", 2)]));
+        let reordered = image(1, files(&[("bbb", 2), ("aaa", 1)]));
+        assert!(diff(&a, &reordered).is_empty());
+
+        let b = image(1, files(&[("bbb", 3), ("ccc", 4)]));
+        let d = diff(&a, &b);
+        assert_eq!(paths(&d), ["files[aaa]", "files[bbb].size", "files[ccc]"]);
+        assert_eq!(d.changes[0].new, None);
+        assert_eq!(d.changes[1].old, Some(json!(2)));
+        assert_eq!(d.changes[1].new, Some(json!(3)));
+        assert_eq!(d.changes[2].old, None);
+    }
+
+    #[test]
+    fn maps_are_compared_key_by_key_and_nulls_are_absent() {
+        let a = image(
+            1,
+            json!({"tags": {"role": "db", "tier": 1}, "description": null}),
+        );
+        let b = image(1, json!({"tags": {"role": "web", "zone": "east"}}));
+        let d = diff(&a, &b);
+        assert_eq!(paths(&d), ["tags.role", "tags.tier", "tags.zone"]);
+        assert_eq!(
+            d.to_string(),
+            "tags.role: \"db\" -> \"web\"\ntags.tier: 1 -> (none)\ntags.zone: (none) -> \"east\"\n"
+        );
+    }
+}
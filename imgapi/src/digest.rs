@@ -0,0 +1,82 @@
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+
+use super::download::Prefix;
+use super::{ChecksumMismatch, DownloadReport, File};
+
+/// The size and digests of a file's contents, as needed to describe it in a manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDigest {
+    /// SHA-1 hex digest.
+    pub sha1: String,
+
+    /// SHA-256 digest, in the `sha256:` prefixed form used for Docker digests.
+    pub sha256: String,
+
+    /// Number of bytes.
+    pub size: u64,
+}
+
+impl FileDigest {
+    /// Checks the digest against a manifest's [`File`], e.g. to verify a file that has already been
+    /// downloaded.
+    pub fn verify(&self, file: &File) -> Result<(), ChecksumMismatch> {
+        super::verify::check(
+            file,
+            &DownloadReport {
+                bytes: self.size,
+                written: self.size,
+                sha1: self.sha1.clone(),
+                digest: Some(self.sha256.clone()),
+                uncompressed_digest: None,
+                resumed_from: 0,
+                elapsed: Default::default(),
+            },
+        )
+    }
+}
+
+impl From<Prefix> for FileDigest {
+    fn from(hashes: Prefix) -> Self {
+        let (size, sha1, sha256) = hashes.finish();
+        Self {
+            sha1,
+            sha256: sha256.unwrap_or_default(),
+            size,
+        }
+    }
+}
+
+/// Computes the size, SHA-1 and SHA-256 of everything `reader` produces, reading it only once.
+pub fn file_digest<R: Read>(mut reader: R) -> io::Result<FileDigest> {
+    let mut hashes = Prefix::with_sha256(true);
+    io::copy(&mut reader, &mut hashes)?;
+    Ok(hashes.into())
+}
+
+/// A reader that computes a [`FileDigest`] of the bytes read through it. The digest is shared, so
+/// it can be read after the reader has been handed off, e.g. as a request body.
+pub(crate) struct DigestReader<R> {
+    inner: R,
+    hashes: Arc<Mutex<Prefix>>,
+}
+
+impl<R: Read> DigestReader<R> {
+    pub(crate) fn new(inner: R) -> (Self, Arc<Mutex<Prefix>>) {
+        let hashes = Arc::new(Mutex::new(Prefix::with_sha256(true)));
+        let reader = Self {
+            inner,
+            hashes: Arc::clone(&hashes),
+        };
+        (reader, hashes)
+    }
+}
+
+impl<R: Read> Read for DigestReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let mut hashes = self.hashes.lock().expect("digest lock poisoned");
+        io::Write::write_all(&mut *hashes, &buf[..n])?;
+        Ok(n)
+    }
+}
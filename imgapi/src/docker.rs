@@ -0,0 +1,145 @@
+use super::Image;
+
+/// The tag holding the Docker repository of a docker-type image.
+const REPO_TAG: &str = "docker:repo";
+
+/// The prefix of the boolean tags naming each Docker tag of the image, e.g. `docker:tag:latest`.
+const TAG_PREFIX: &str = "docker:tag:";
+
+impl Image {
+    /// Indicates whether this is a Docker image, i.e. its type is `docker`.
+    pub fn is_docker(&self) -> bool {
+        self.image_type == "docker"
+    }
+
+    /// The Docker repository this image belongs to, e.g. `library/busybox`.
+    pub fn docker_repo(&self) -> Option<&str> {
+        self.tag_str(REPO_TAG)
+    }
+
+    /// The Docker tags (e.g. `latest`) currently pointing at this image.
+    pub fn docker_tags(&self) -> Vec<&str> {
+        self.tags_matching(TAG_PREFIX)
+            .filter(|(_, v)| v.as_bool().unwrap_or(false))
+            .map(|(k, _)| &k[TAG_PREFIX.len()..])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::testutil::image;
+    use crate::ValidationIssue;
+
+    const ID: &str = "sha256:a3ed95caeb02ffe68cdd9fd84406680ae93d633cb16422d00e8a7c22955b46d4";
+
+    fn layer(n: u8) -> Value {
+        let hex = |c: u8| format!("sha256:{}", format!("{:x}", c % 16).repeat(64));
+        json!({
+            "sha1": format!("{:x}", n % 16).repeat(40),
+            "size": 1000 * n as u64,
+            "compression": "gzip",
+            "digest": hex(n),
+            "uncompressedDigest": hex(n + 8),
+        })
+    }
+
+    /// A docker image as Triton's IMGAPI has them, with a layer file per entry in `files`.
+    fn docker(files: Vec<Value>) -> Image {
+        image(
+            1,
+            json!({
+                "name": "docker-layer",
+                "version": "a3ed95caeb02",
+                "type": "docker",
+                "os": "linux",
+                "public": false,
+                "tags": {
+                    "docker:repo": "library/busybox",
+                    "docker:id": ID,
+                    "docker:architecture": "amd64",
+                    "docker:tag:latest": true,
+                    "docker:tag:1.36": true,
+                    "docker:tag:1.35": false,
+                    "docker:tag:odd": "yes",
+                },
+                "files": files,
+            }),
+        )
+    }
+
+    fn file_issues(image: &Image) -> Vec<ValidationIssue> {
+        image
+            .validate()
+            .into_iter()
+            .filter(|i| i.field.starts_with("files["))
+            .collect()
+    }
+
+    #[test]
+    fn every_layer_has_sha256_digests() {
+        let image = docker(vec![layer(1), layer(2), layer(3)]);
+        assert!(image.is_docker());
+        assert_eq!(file_issues(&image), []);
+    }
+
+    #[test]
+    fn a_layer_without_digests_is_an_error() {
+        let mut second = layer(2);
+        second.as_object_mut().unwrap().remove("digest");
+        second["uncompressedDigest"] = Value::Null;
+        let image = docker(vec![layer(1), second, layer(3)]);
+        assert_eq!(
+            file_issues(&image),
+            [
+                ValidationIssue::error("files[1].digest", "required for docker images"),
+                ValidationIssue::error("files[1].uncompressedDigest", "required for docker images"),
+            ]
+        );
+    }
+
+    #[test]
+    fn digests_must_be_sha256() {
+        let mut third = layer(3);
+        third["uncompressedDigest"] = json!("md5:0cc175b9c0f1b6a831c399e269772661");
+        let mut first = layer(1);
+        first["digest"] = json!("0".repeat(64));
+        let image = docker(vec![first, layer(2), third]);
+        assert_eq!(
+            file_issues(&image),
+            [
+                ValidationIssue::error("files[0].digest", "must start with \"sha256:\""),
+                ValidationIssue::error(
+                    "files[2].uncompressedDigest",
+                    "must start with \"sha256:\""
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn other_images_dont_need_digests() {
+        let image = image(1, json!({ "type": "zone-dataset" }));
+        assert!(!image.is_docker());
+        assert_eq!(file_issues(&image), []);
+    }
+
+    #[test]
+    fn reads_the_docker_tags() {
+        let image = docker(vec![layer(1), layer(2)]);
+        assert_eq!(image.docker_repo(), Some("library/busybox"));
+        // Only tags set to true point at the image.
+        assert_eq!(image.docker_tags(), ["1.36", "latest"]);
+    }
+
+    #[test]
+    fn an_untagged_image_has_no_docker_tags() {
+        let mut image = docker(vec![layer(1)]);
+        image.tags = None;
+        assert_eq!(image.docker_repo(), None);
+        assert!(image.docker_tags().is_empty());
+    }
+}
@@ -0,0 +1,592 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use md5::Md5;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use super::verify;
+use super::{BytesPerSec, Compression, File, Uuid};
+
+/// The result of a successful, verified download.
+#[derive(Debug, Clone)]
+pub struct DownloadReport {
+    /// The number of bytes downloaded.
+    pub bytes: u64,
+
+    /// The number of bytes written to the destination, which differs from `bytes` if the file was
+    /// decompressed.
+    pub written: u64,
+
+    /// SHA-1 hex digest of the downloaded (not decompressed) bytes.
+    pub sha1: String,
+
+    /// The `sha256:` Docker digest of the downloaded bytes, if the manifest has one to compare
+    /// against.
+    pub digest: Option<String>,
+
+    /// The `sha256:` Docker digest of the decompressed bytes, if the file was decompressed and the
+    /// manifest has an `uncompressedDigest` to compare against.
+    pub uncompressed_digest: Option<String>,
+
+    /// The number of bytes that were already on disk from a previous attempt. These are included
+    /// in `bytes` and `written`.
+    pub resumed_from: u64,
+
+    /// How long the transfer took.
+    pub elapsed: Duration,
+}
+
+/// A download whose `Content-MD5` header doesn't match the bytes received, meaning they were
+/// corrupted in transit.
+///
+/// This is distinct from a [`ChecksumMismatch`](super::ChecksumMismatch), where the bytes arrived
+/// intact but don't match the manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportChecksumMismatch {
+    /// The base64-encoded MD5 digest from the header.
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for TransportChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Content-MD5 mismatch: expected {}, got {}",
+            self.expected, self.actual
+        )
+    }
+}
+
+impl Error for TransportChecksumMismatch {}
+
+/// The result of downloading one image of an ancestry or export, see
+/// [`Client::download_ancestry`](super::blocking::Client::download_ancestry).
+#[derive(Debug, Clone)]
+pub struct ImageDownload {
+    pub uuid: Uuid,
+
+    /// Where the image's file was written.
+    pub file_path: PathBuf,
+
+    /// Where the image's manifest was written.
+    pub manifest_path: PathBuf,
+
+    /// Whether the file was already there, and so wasn't downloaded again.
+    pub skipped: bool,
+
+    pub report: DownloadReport,
+}
+
+/// How often progress callbacks are invoked, at most.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A snapshot of a transfer in progress, passed to progress callbacks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// The number of bytes transferred so far.
+    pub bytes: u64,
+
+    /// The total number of bytes expected, if known.
+    pub total: Option<u64>,
+
+    /// The transfer rate since the previous callback, in bytes per second.
+    pub rate: f64,
+}
+
+impl Progress {
+    /// The fraction of the transfer completed, between 0 and 1, if the total is known.
+    pub fn fraction(&self) -> Option<f64> {
+        self.total.map(|t| {
+            if t == 0 {
+                1.0
+            } else {
+                (self.bytes as f64 / t as f64).min(1.0)
+            }
+        })
+    }
+}
+
+/// Whether, and how, to decompress a file while downloading it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Decompress {
+    /// Decompress according to the file's [`File::compression`](super::File::compression).
+    Auto,
+
+    /// Write the file exactly as stored on the server.
+    #[default]
+    Keep,
+
+    /// Decompress as the given compression, regardless of what the manifest says.
+    Force(Compression),
+}
+
+impl Decompress {
+    /// The compression to decode, given the file's compression in the manifest.
+    pub(crate) fn resolve(self, stored: Compression) -> Compression {
+        match self {
+            Self::Auto => stored,
+            Self::Keep => Compression::None,
+            Self::Force(c) => c,
+        }
+    }
+}
+
+/// Options for the download APIs, e.g.
+/// [`Client::download_file`](super::blocking::Client::download_file).
+#[derive(Debug, Clone, Default)]
+pub struct DownloadOptions {
+    pub decompress: Decompress,
+
+    /// Continue a previous, interrupted download to the same destination instead of starting
+    /// over, if the server supports range requests. Ignored when decompressing, since the bytes
+    /// already on disk can't be hashed as downloaded.
+    pub resume: bool,
+
+    /// Keep the partially-downloaded file when a download fails, so that it can be resumed later.
+    pub keep_partial: bool,
+
+    /// Don't compute the MD5 of the download to check the server's `Content-MD5` header, saving
+    /// CPU time. The manifest's digests are still checked.
+    pub skip_md5: bool,
+
+    /// Limits the bandwidth used by the download, overriding the client's default set with
+    /// [`Client::with_rate_limit`](super::blocking::Client::with_rate_limit).
+    pub rate_limit: Option<BytesPerSec>,
+}
+
+/// One of the downloads for
+/// [`Client::download_many`](super::blocking::Client::download_many).
+#[derive(Debug, Clone)]
+pub struct DownloadRequest {
+    pub uuid: Uuid,
+
+    /// Which of the image's files to download.
+    pub index: usize,
+
+    /// Where to write the file.
+    pub dest: PathBuf,
+
+    /// The options for this download. Its rate limit is ignored in favor of the one shared by all
+    /// the downloads.
+    pub opts: DownloadOptions,
+}
+
+impl DownloadRequest {
+    /// A request for the first file of an image, with the default options.
+    pub fn new<P: AsRef<Path>>(uuid: Uuid, dest: P) -> Self {
+        Self {
+            uuid,
+            index: 0,
+            dest: dest.as_ref().to_path_buf(),
+            opts: DownloadOptions::default(),
+        }
+    }
+}
+
+/// Progress of one of several concurrent downloads, see
+/// [`Client::download_many`](super::blocking::Client::download_many).
+#[derive(Debug, Clone, Copy)]
+pub struct BatchProgress {
+    /// The position of the download among the requests.
+    pub request: usize,
+
+    /// The progress of that download.
+    pub file: Progress,
+
+    /// Bytes transferred so far, across all downloads.
+    pub bytes: u64,
+
+    /// The combined size of all the files being downloaded.
+    pub total: u64,
+}
+
+impl BatchProgress {
+    /// The fraction of all the downloads completed, between 0 and 1.
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            1.0
+        } else {
+            (self.bytes as f64 / self.total as f64).min(1.0)
+        }
+    }
+}
+
+/// Cancels downloads in progress from another thread. Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stops the downloads using this token at the next read.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// A download that was stopped with a [`CancelToken`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "download cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+/// A reader that fails once its token is cancelled.
+pub(crate) struct Cancellable<'a, R> {
+    pub(crate) inner: R,
+    pub(crate) token: &'a CancelToken,
+}
+
+impl<R: Read> Read for Cancellable<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.token.is_cancelled() {
+            return Err(io::Error::other(Cancelled));
+        }
+        self.inner.read(buf)
+    }
+}
+
+/// Options for [`Client::export_to_dir`](super::blocking::Client::export_to_dir).
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// Export the image's ancestors as well.
+    pub ancestry: bool,
+
+    pub download: DownloadOptions,
+}
+
+/// Running digests of a file's bytes, e.g. the part already downloaded when resuming.
+///
+/// SHA-256 is only computed if the manifest has a Docker digest to compare it against.
+#[derive(Clone)]
+pub(crate) struct Prefix {
+    sha1: Sha1,
+    sha256: Option<Sha256>,
+    bytes: u64,
+}
+
+impl Prefix {
+    /// An empty prefix for downloading `file`.
+    pub(crate) fn new(file: &File) -> Self {
+        Self::with_sha256(file.digest.is_some())
+    }
+
+    pub(crate) fn with_sha256(sha256: bool) -> Self {
+        Self {
+            sha1: Sha1::new(),
+            sha256: if sha256 { Some(Sha256::new()) } else { None },
+            bytes: 0,
+        }
+    }
+
+    /// Hashes the bytes of `file` already downloaded.
+    pub(crate) fn read<R: Read>(file: &File, mut reader: R) -> io::Result<Self> {
+        let mut prefix = Self::new(file);
+        io::copy(&mut reader, &mut prefix)?;
+        Ok(prefix)
+    }
+
+    /// Returns the number of bytes, their SHA-1 hex digest, and their `sha256:` digest, if
+    /// computed.
+    pub(crate) fn finish(self) -> (u64, String, Option<String>) {
+        (
+            self.bytes,
+            format!("{:x}", self.sha1.finalize()),
+            self.sha256.map(sha256_digest),
+        )
+    }
+}
+
+impl Write for Prefix {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.sha1.update(buf);
+        if let Some(h) = &mut self.sha256 {
+            h.update(buf);
+        }
+        self.bytes += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub(crate) fn sha256_digest(h: Sha256) -> String {
+    format!("sha256:{:x}", h.finalize())
+}
+
+/// A reader that hashes and counts the bytes read through it, reporting progress as it goes.
+///
+/// `progress` is called at most every [`PROGRESS_INTERVAL`], and once more by
+/// [`HashingReader::finish`].
+struct HashingReader<'a, R> {
+    inner: R,
+    hashes: Prefix,
+    /// The MD5 of only the bytes read through this reader, i.e. excluding the prefix.
+    md5: Option<Md5>,
+    total: Option<u64>,
+    progress: &'a mut dyn FnMut(Progress),
+    last_at: Instant,
+    last_bytes: u64,
+}
+
+impl<'a, R: Read> HashingReader<'a, R> {
+    fn new(
+        inner: R,
+        total: Option<u64>,
+        prefix: Prefix,
+        progress: &'a mut dyn FnMut(Progress),
+    ) -> Self {
+        Self {
+            inner,
+            last_bytes: prefix.bytes,
+            hashes: prefix,
+            md5: None,
+            total,
+            progress,
+            last_at: Instant::now(),
+        }
+    }
+
+    fn report(&mut self, force: bool) {
+        let elapsed = self.last_at.elapsed();
+        if !force && elapsed < PROGRESS_INTERVAL {
+            return;
+        }
+        let bytes = self.hashes.bytes;
+        let secs = elapsed.as_secs_f64();
+        let rate = if secs > 0.0 {
+            (bytes - self.last_bytes) as f64 / secs
+        } else {
+            0.0
+        };
+        (self.progress)(Progress {
+            bytes,
+            total: self.total,
+            rate,
+        });
+        self.last_at = Instant::now();
+        self.last_bytes = bytes;
+    }
+
+    fn finish(mut self) -> (u64, String, Option<String>) {
+        self.report(true);
+        self.hashes.finish()
+    }
+}
+
+impl<R: Read> Read for HashingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hashes.write_all(&buf[..n])?;
+        if let Some(h) = &mut self.md5 {
+            h.update(&buf[..n]);
+        }
+        if n > 0 {
+            self.report(false);
+        }
+        Ok(n)
+    }
+}
+
+/// A reader that reports how much has been read through it to `progress`, e.g. as an upload's
+/// body is sent. `progress` is called at most every [`PROGRESS_INTERVAL`], and once more at the end.
+pub(crate) struct ProgressReader<R, F> {
+    inner: R,
+    total: Option<u64>,
+    progress: F,
+    bytes: u64,
+    last_at: Instant,
+    last_bytes: u64,
+}
+
+impl<R: Read, F: FnMut(Progress)> ProgressReader<R, F> {
+    pub(crate) fn new(inner: R, total: Option<u64>, progress: F) -> Self {
+        Self {
+            inner,
+            total,
+            progress,
+            bytes: 0,
+            last_at: Instant::now(),
+            last_bytes: 0,
+        }
+    }
+}
+
+impl<R: Read, F: FnMut(Progress)> Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes += n as u64;
+        let elapsed = self.last_at.elapsed();
+        if n == 0 || elapsed >= PROGRESS_INTERVAL {
+            let secs = elapsed.as_secs_f64();
+            let rate = if secs > 0.0 {
+                (self.bytes - self.last_bytes) as f64 / secs
+            } else {
+                0.0
+            };
+            (self.progress)(Progress {
+                bytes: self.bytes,
+                total: self.total,
+                rate,
+            });
+            self.last_at = Instant::now();
+            self.last_bytes = self.bytes;
+        }
+        Ok(n)
+    }
+}
+
+/// A writer that computes the SHA-256 digest of the bytes written through it, if asked to.
+struct HashingWriter<W> {
+    inner: W,
+    sha256: Option<Sha256>,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if let Some(h) = &mut self.sha256 {
+            h.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The path a file being downloaded to `dest` is written to until it's complete.
+pub(crate) fn partial_path(dest: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let name = dest
+        .file_name()
+        .ok_or_else(|| format!("{}: not a file name", dest.display()))?;
+    let mut partial = name.to_os_string();
+    partial.push(".partial");
+    Ok(dest.with_file_name(partial))
+}
+
+/// Wraps `reader` in a decoder for `compression`.
+pub(crate) fn decoder<'a, R: Read + 'a>(
+    compression: Compression,
+    reader: R,
+) -> Result<Box<dyn Read + 'a>, Box<dyn Error>> {
+    Ok(match compression {
+        Compression::None | Compression::Auto => Box::new(reader),
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+        #[cfg(feature = "xz")]
+        Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+        #[allow(unreachable_patterns)]
+        c => return Err(format!("decompressing {} requires the imgapi `{}` feature", c, c).into()),
+    })
+}
+
+/// Copies a file's contents from `reader` into `writer`, verifying them against `file`.
+///
+/// The size, SHA-1 and Docker digest are checked against the bytes as downloaded, i.e. before any
+/// decompression, as the manifest specifies. The Docker `uncompressedDigest` is checked against
+/// the decompressed bytes, if decompressing. When resuming, `reader` provides the rest of the file
+/// after `prefix`, which `writer` already contains.
+///
+/// `content_md5` is the response's `Content-MD5` header, if any, which is checked against the bytes
+/// from `reader` before anything else.
+pub(crate) fn transfer<R: Read, W: Write>(
+    reader: R,
+    writer: &mut W,
+    file: &File,
+    content_md5: Option<&str>,
+    opts: &DownloadOptions,
+    prefix: Prefix,
+    progress: &mut dyn FnMut(Progress),
+) -> Result<DownloadReport, Box<dyn Error>> {
+    let start = Instant::now();
+    let resumed_from = prefix.bytes;
+    let compression = opts.decompress.resolve(file.compression);
+    let decompressing = !matches!(compression, Compression::None | Compression::Auto);
+
+    let mut hashing = HashingReader::new(reader, Some(file.size), prefix, progress);
+    let content_md5 = content_md5.filter(|_| !opts.skip_md5);
+    if content_md5.is_some() {
+        hashing.md5 = Some(Md5::new());
+    }
+    let mut out = HashingWriter {
+        inner: writer,
+        sha256: file
+            .uncompressed_digest
+            .as_ref()
+            .filter(|_| decompressing)
+            .map(|_| Sha256::new()),
+    };
+    let written = io::copy(&mut decoder(compression, &mut hashing)?, &mut out)?;
+    // A decoder stops at the end of the compressed stream, but anything after it still counts
+    // toward the size and digest.
+    io::copy(&mut hashing, &mut io::sink())?;
+    out.flush()?;
+
+    if let (Some(expected), Some(md5)) = (content_md5, hashing.md5.take()) {
+        let actual = BASE64.encode(md5.finalize());
+        if actual != expected.trim() {
+            return Err(TransportChecksumMismatch {
+                expected: expected.to_string(),
+                actual,
+            }
+            .into());
+        }
+    }
+    let (bytes, sha1, digest) = hashing.finish();
+    let report = DownloadReport {
+        bytes,
+        written: resumed_from + written,
+        sha1,
+        digest,
+        uncompressed_digest: out.sha256.map(sha256_digest),
+        resumed_from,
+        elapsed: start.elapsed(),
+    };
+    verify::check(file, &report)?;
+    Ok(report)
+}
+
+/// Hashes a file that has already been completely downloaded, and verifies it against `file`.
+pub(crate) fn verify_existing<R: Read>(
+    file: &File,
+    reader: R,
+) -> Result<DownloadReport, Box<dyn Error>> {
+    let start = Instant::now();
+    let (bytes, sha1, digest) = Prefix::read(file, reader)?.finish();
+    let report = DownloadReport {
+        bytes,
+        written: bytes,
+        sha1,
+        digest,
+        uncompressed_digest: None,
+        resumed_from: bytes,
+        elapsed: start.elapsed(),
+    };
+    verify::check(file, &report)?;
+    Ok(report)
+}
@@ -0,0 +1,154 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// An error response from an IMGAPI server.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct ApiError {
+    /// The HTTP status code of the response.
+    #[serde(skip)]
+    pub status: u16,
+
+    /// A "CamelCase" error code, e.g. `ResourceNotFound`.
+    #[serde(default)]
+    pub code: String,
+
+    #[serde(default)]
+    pub message: String,
+
+    /// Problems with individual fields of the request, e.g. for a `ValidationFailed` error.
+    #[serde(default)]
+    pub errors: Vec<FieldError>,
+
+    /// The `keyId` the request was signed with, if it was signed. Only set for 401 and 403
+    /// responses, where it's shown to help work out why the server refused.
+    #[serde(skip)]
+    pub identity: Option<String>,
+}
+
+/// A problem with one field of a request, as listed in [`ApiError::errors`].
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+
+    /// E.g. `Invalid` or `MissingParameter`.
+    #[serde(default)]
+    pub code: String,
+
+    #[serde(default)]
+    pub message: String,
+}
+
+impl ApiError {
+    /// Whether the server reported that the requested resource does not exist.
+    pub fn is_not_found(&self) -> bool {
+        self.status == 404
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.code.is_empty(), self.message.is_empty()) {
+            (true, true) => write!(f, "server returned HTTP {}", self.status),
+            (false, true) => write!(f, "{} (HTTP {})", self.code, self.status),
+            (true, false) => write!(f, "{} (HTTP {})", self.message, self.status),
+            (false, false) => write!(f, "{}: {} (HTTP {})", self.code, self.message, self.status),
+        }?;
+        match (self.status, &self.identity) {
+            (401 | 403, Some(key_id)) => write!(f, "; the request was signed with {}", key_id),
+            (401 | 403, None) => write!(f, "; the request wasn't signed"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Error for ApiError {}
+
+/// A request that the server didn't answer, or stopped sending the response to, within the
+/// client's timeout. See [`Client::with_timeouts`](super::blocking::Client::with_timeouts).
+#[derive(Debug, Clone)]
+pub struct RequestTimeout {
+    /// The URL that was requested.
+    pub url: String,
+
+    /// The timeout that ran out.
+    pub after: Duration,
+
+    /// Whether it ran out while connecting, rather than while waiting for or reading the
+    /// response.
+    pub connecting: bool,
+}
+
+impl RequestTimeout {
+    /// Whether `e` is a timeout from the HTTP client, either directly or from reading a response
+    /// body.
+    pub(crate) fn is_timeout(e: &(dyn Error + 'static)) -> bool {
+        let e = match e.downcast_ref::<io::Error>() {
+            Some(io) if io.kind() == io::ErrorKind::TimedOut => return true,
+            Some(io) => match io.get_ref() {
+                Some(inner) => inner,
+                None => return false,
+            },
+            None => e,
+        };
+        e.downcast_ref::<reqwest::Error>()
+            .is_some_and(reqwest::Error::is_timeout)
+    }
+}
+
+/// E.g. `30s`, `5m` or `1.5s`.
+fn format_timeout(d: Duration) -> String {
+    match (d.subsec_nanos(), d.as_secs()) {
+        (0, secs) if secs >= 60 && secs % 60 == 0 => format!("{}m", secs / 60),
+        (0, secs) => format!("{}s", secs),
+        _ => format!("{:?}", d),
+    }
+}
+
+impl fmt::Display for RequestTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "timed out after {} {} {}",
+            format_timeout(self.after),
+            if self.connecting {
+                "connecting to"
+            } else {
+                "talking to"
+            },
+            self.url
+        )
+    }
+}
+
+impl Error for RequestTimeout {}
+
+/// A request that a client in dry-run mode built but didn't send, as it would have been sent. See
+/// [`Client::with_dry_run`](super::blocking::Client::with_dry_run).
+#[derive(Debug, Clone)]
+pub struct DryRun {
+    pub method: String,
+
+    /// The full URL, query string included, with any password redacted.
+    pub url: String,
+
+    /// The request's headers, with the signature in `Authorization` redacted.
+    pub headers: Vec<(String, String)>,
+
+    /// The request body, if it has one that's held in memory.
+    pub body: Option<Vec<u8>>,
+
+    /// Whether the request has a body that's streamed instead, such as an image file.
+    pub streamed_body: bool,
+}
+
+impl fmt::Display for DryRun {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "dry run: not sending {} {}", self.method, self.url)
+    }
+}
+
+impl Error for DryRun {}
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::iter::FromIterator;
+
+use super::version::version_order;
+use super::{Image, ImageFilter, Uuid};
+
+/// Something [`ImageSet::retain`] can select images with: either a closure or an
+/// [`ImageFilter`], which is evaluated locally with [`ImageFilter::matches`].
+pub trait ImagePredicate {
+    fn test(&mut self, image: &Image) -> bool;
+}
+
+impl<F: FnMut(&Image) -> bool> ImagePredicate for F {
+    fn test(&mut self, image: &Image) -> bool {
+        self(image)
+    }
+}
+
+impl ImagePredicate for &ImageFilter {
+    fn test(&mut self, image: &Image) -> bool {
+        self.matches(image)
+    }
+}
+
+/// A collection of images indexed by uuid and by name.
+///
+/// Each uuid is present at most once. Iteration yields images in insertion order.
+#[derive(Debug, Default, Clone)]
+pub struct ImageSet {
+    images: Vec<Image>,
+    by_uuid: HashMap<Uuid, usize>,
+    by_name: HashMap<String, Vec<usize>>,
+}
+
+impl ImageSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    /// Adds an image to the set, returning the image it replaced if one with the same uuid was
+    /// already present.
+    pub fn insert(&mut self, image: Image) -> Option<Image> {
+        match self.by_uuid.get(&image.uuid) {
+            Some(&i) => {
+                let old = std::mem::replace(&mut self.images[i], image);
+                if old.name != self.images[i].name {
+                    self.reindex();
+                }
+                Some(old)
+            }
+            None => {
+                let i = self.images.len();
+                self.by_uuid.insert(image.uuid, i);
+                self.by_name.entry(image.name.clone()).or_default().push(i);
+                self.images.push(image);
+                None
+            }
+        }
+    }
+
+    /// Removes the image with the given uuid from the set, returning it if it was present.
+    pub fn remove(&mut self, uuid: &Uuid) -> Option<Image> {
+        let i = self.by_uuid.get(uuid).copied()?;
+        let image = self.images.remove(i);
+        self.reindex();
+        Some(image)
+    }
+
+    pub fn get(&self, uuid: &Uuid) -> Option<&Image> {
+        self.by_uuid.get(uuid).map(|&i| &self.images[i])
+    }
+
+    pub fn contains(&self, uuid: &Uuid) -> bool {
+        self.by_uuid.contains_key(uuid)
+    }
+
+    /// Returns all images named `name`, in insertion order.
+    pub fn by_name(&self, name: &str) -> Vec<&Image> {
+        self.by_name
+            .get(name)
+            .map(|idx| idx.iter().map(|&i| &self.images[i]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the newest provisionable image named `name`. See [`latest_by_name`].
+    ///
+    /// [`latest_by_name`]: crate::latest_by_name
+    pub fn latest(&self, name: &str) -> Option<&Image> {
+        self.by_name(name)
+            .into_iter()
+            .filter(|i| i.is_provisionable())
+            .max_by_key(|i| version_order(i))
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Image> {
+        self.images.iter()
+    }
+
+    /// Returns the images in this set whose uuid is not in `other`.
+    pub fn difference(&self, other: &ImageSet) -> ImageSet {
+        self.iter()
+            .filter(|i| !other.contains(&i.uuid))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the images in this set whose uuid is also in `other`.
+    pub fn intersection(&self, other: &ImageSet) -> ImageSet {
+        self.iter()
+            .filter(|i| other.contains(&i.uuid))
+            .cloned()
+            .collect()
+    }
+
+    /// Keeps only the images selected by `predicate`, which is either a closure or an
+    /// `&ImageFilter`.
+    pub fn retain<P: ImagePredicate>(&mut self, mut predicate: P) {
+        self.images.retain(|i| predicate.test(i));
+        self.reindex();
+    }
+
+    fn reindex(&mut self) {
+        self.by_uuid.clear();
+        self.by_name.clear();
+        for (i, image) in self.images.iter().enumerate() {
+            self.by_uuid.insert(image.uuid, i);
+            self.by_name.entry(image.name.clone()).or_default().push(i);
+        }
+    }
+}
+
+impl FromIterator<Image> for ImageSet {
+    fn from_iter<I: IntoIterator<Item = Image>>(iter: I) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl Extend<Image> for ImageSet {
+    fn extend<I: IntoIterator<Item = Image>>(&mut self, iter: I) {
+        for image in iter {
+            self.insert(image);
+        }
+    }
+}
+
+impl IntoIterator for ImageSet {
+    type Item = Image;
+    type IntoIter = std::vec::IntoIter<Image>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.images.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ImageSet {
+    type Item = &'a Image;
+    type IntoIter = std::slice::Iter<'a, Image>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.images.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testutil::{image, permutations, uuid};
+
+    fn uuids<'a>(images: impl IntoIterator<Item = &'a Image>) -> Vec<Uuid> {
+        images.into_iter().map(|i| i.uuid).collect()
+    }
+
+    #[test]
+    fn indexes_by_uuid_and_name() {
+        let mut set: ImageSet = vec![
+            image(1, json!({"name": "base"})),
+            image(2, json!({"name": "minimal"})),
+            image(3, json!({"name": "base"})),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(set.len(), 3);
+        assert_eq!(set.get(&uuid(2)).map(|i| i.name.as_str()), Some("minimal"));
+        assert!(!set.contains(&uuid(4)));
+        assert_eq!(uuids(set.by_name("base")), [uuid(1), uuid(3)]);
+        assert!(set.by_name("other").is_empty());
+
+        // Replacing an image keeps its place, and renaming it moves it to its new name.
+        let old = set.insert(image(1, json!({"name": "minimal"})));
+        assert_eq!(old.map(|i| i.name), Some("base".to_string()));
+        assert_eq!(uuids(&set), [uuid(1), uuid(2), uuid(3)]);
+        assert_eq!(uuids(set.by_name("base")), [uuid(3)]);
+        assert_eq!(uuids(set.by_name("minimal")), [uuid(1), uuid(2)]);
+
+        assert_eq!(set.remove(&uuid(2)).map(|i| i.uuid), Some(uuid(2)));
+        assert_eq!(set.remove(&uuid(2)).map(|i| i.uuid), None);
+        assert_eq!(uuids(set.by_name("minimal")), [uuid(1)]);
+        assert_eq!(set.get(&uuid(3)).map(|i| i.uuid), Some(uuid(3)));
+    }
+
+    #[test]
+    fn latest_is_the_newest_provisionable_whatever_the_order() {
+        let images = vec![
+            image(1, json!({"version": "1.9.0"})),
+            image(2, json!({"version": "1.10.0"})),
+            image(3, json!({"version": "1.11.0", "disabled": true})),
+            image(4, json!({"version": "2.0.0", "state": "unactivated"})),
+            image(5, json!({"version": "9.0.0", "name": "other"})),
+        ];
+        for order in permutations(&images) {
+            let set: ImageSet = order.into_iter().collect();
+            assert_eq!(set.latest("base").map(|i| i.uuid), Some(uuid(2)));
+        }
+        let set: ImageSet = images.into_iter().collect();
+        assert_eq!(set.latest("none").map(|i| i.uuid), None);
+    }
+
+    #[test]
+    fn retains_by_closure_or_filter() {
+        let all: ImageSet = vec![
+            image(1, json!({"os": "smartos"})),
+            image(2, json!({"os": "linux"})),
+            image(3, json!({"os": "linux", "name": "other"})),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut set = all.clone();
+        set.retain(|i: &Image| i.uuid != uuid(2));
+        assert_eq!(uuids(&set), [uuid(1), uuid(3)]);
+        assert_eq!(uuids(set.by_name("base")), [uuid(1)]);
+
+        let mut set = all.clone();
+        let filter = ImageFilter {
+            os: Some("linux".parse().unwrap()),
+            ..ImageFilter::default()
+        };
+        set.retain(&filter);
+        assert_eq!(uuids(&set), [uuid(2), uuid(3)]);
+        assert_eq!(set.get(&uuid(3)).map(|i| i.uuid), Some(uuid(3)));
+        assert!(!set.contains(&uuid(1)));
+    }
+
+    #[test]
+    fn compares_sets_by_uuid() {
+        let a: ImageSet = (1..=3).map(|n| image(n, json!({}))).collect();
+        let b: ImageSet = (2..=4).map(|n| image(n, json!({}))).collect();
+        assert_eq!(uuids(&a.difference(&b)), [uuid(1)]);
+        assert_eq!(uuids(&a.intersection(&b)), [uuid(2), uuid(3)]);
+    }
+}
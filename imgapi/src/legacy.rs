@@ -0,0 +1,289 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+    Brand, Compression, CpuType, DateTime, DiskDriver, File, Image, ImageState, Network, NicDriver,
+    PlatformConstraint, Requirements, User, Utc, Uuid,
+};
+
+/// The owner given to upgraded manifests that don't record a creator, as imgadm does.
+const UNSET_OWNER_UUID: Uuid = Uuid::nil();
+
+/// A v1 ("dsmanifest") image manifest, as served by the old datasets.joyent.com DSAPI.
+///
+/// These can still be found on disk on older SmartOS installs. Use [`upgrade_to_v2`] to turn one
+/// into an [`Image`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegacyManifest {
+    pub uuid: Uuid,
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+    #[serde(default, with = "crate::timestamp::option")]
+    pub published_at: Option<DateTime<Utc>>,
+
+    /// The dataset type, e.g. `zone-dataset` or `zvol`.
+    #[serde(rename = "type")]
+    pub image_type: Option<String>,
+    pub os: Option<String>,
+
+    /// The platform the dataset runs on. Used as the OS if `os` is missing.
+    pub platform_type: Option<String>,
+
+    /// The account that created the dataset. Becomes [`Image::owner`].
+    pub creator_uuid: Option<Uuid>,
+    pub creator_name: Option<String>,
+    pub vendor_uuid: Option<Uuid>,
+    pub cloud_name: Option<String>,
+    pub urn: Option<String>,
+
+    /// If set, the dataset was private to this account.
+    pub restricted_to_uuid: Option<Uuid>,
+    pub disabled: Option<bool>,
+
+    #[serde(default)]
+    pub files: Vec<LegacyFile>,
+    pub requirements: Option<LegacyRequirements>,
+    pub users: Option<Vec<User>>,
+    pub generate_passwords: Option<bool>,
+    pub inherited_directories: Option<Vec<String>>,
+    pub tags: Option<BTreeMap<String, Value>>,
+    pub nic_driver: Option<NicDriver>,
+    pub disk_driver: Option<DiskDriver>,
+    pub cpu_type: Option<CpuType>,
+    pub image_size: Option<u32>,
+}
+
+/// A file entry in a v1 manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegacyFile {
+    /// The file name, e.g. `smartos64-1.6.3.zfs.bz2`. The compression is inferred from it.
+    pub path: Option<String>,
+    pub sha1: String,
+    pub size: u64,
+    pub url: Option<String>,
+}
+
+/// The requirements of a v1 manifest, which used `min_memory`/`max_memory` for RAM.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegacyRequirements {
+    #[serde(default)]
+    pub networks: Vec<Network>,
+    pub brand: Option<Brand>,
+    pub ssh_key: Option<bool>,
+    #[serde(alias = "min_memory")]
+    pub min_ram: Option<u32>,
+    #[serde(alias = "max_memory")]
+    pub max_ram: Option<u32>,
+    pub min_platform: Option<PlatformConstraint>,
+    pub max_platform: Option<PlatformConstraint>,
+}
+
+impl LegacyFile {
+    fn compression(&self) -> Compression {
+        match &self.path {
+            Some(p) => Compression::from_path(p),
+            None => Compression::None,
+        }
+    }
+}
+
+/// Converts a v1 manifest into a v2 [`Image`], using the same mapping rules as imgadm.
+///
+/// * `creator_uuid` becomes the owner (or the nil uuid if unset).
+/// * `restricted_to_uuid` makes the image private with that account on the ACL; otherwise the
+///   image is public.
+/// * The image is considered active. v1 had no notion of activation.
+/// * File compression is inferred from the `path` extension; `path` and `url` are dropped.
+/// * `min_memory`/`max_memory` requirements become `min_ram`/`max_ram`.
+/// * `urn`, `vendor_uuid`, `creator_name`, `cloud_name` and `platform_type` are dropped.
+pub fn upgrade_to_v2(m: LegacyManifest) -> Image {
+    let files = m
+        .files
+        .iter()
+        .map(|f| File {
+            sha1: f.sha1.clone(),
+            size: f.size,
+            compression: f.compression(),
+            dataset_guid: None,
+            stor: None,
+            digest: None,
+            uncompressed_digest: None,
+        })
+        .collect();
+
+    let requirements = m.requirements.map(|r| Requirements {
+        networks: r.networks,
+        brand: r.brand,
+        ssh_key: r.ssh_key,
+        min_ram: r.min_ram,
+        max_ram: r.max_ram,
+        min_platform: r.min_platform,
+        max_platform: r.max_platform,
+        boot_rom: None,
+    });
+
+    Image {
+        v: 2,
+        uuid: m.uuid,
+        owner: m.creator_uuid.unwrap_or(UNSET_OWNER_UUID),
+        name: m.name,
+        version: m.version,
+        description: m.description,
+        homepage: None,
+        eula: None,
+        icon: None,
+        state: ImageState::Active,
+        error: None,
+        disabled: m.disabled.unwrap_or(false),
+        public: m.restricted_to_uuid.is_none(),
+        published_at: m.published_at,
+        image_type: m.image_type.unwrap_or_else(|| "zone-dataset".to_string()),
+        os: m
+            .os
+            .or(m.platform_type)
+            .unwrap_or_else(|| "other".to_string()),
+        origin: None,
+        files,
+        acl: m.restricted_to_uuid.map(|u| vec![u]),
+        requirements,
+        users: m.users,
+        billing_tags: None,
+        traits: None,
+        tags: m.tags,
+        generate_passwords: m.generate_passwords,
+        inherited_directories: m.inherited_directories,
+        nic_driver: m.nic_driver,
+        disk_driver: m.disk_driver,
+        cpu_type: m.cpu_type,
+        image_size: m.image_size,
+        channels: None,
+    }
+}
+
+/// Parses a manifest of any supported version.
+///
+/// Manifests with `"v": 2` are parsed directly. Manifests with `"v": 1`, or with no `v` field at
+/// all as was common for dsmanifests, are parsed as a [`LegacyManifest`] and upgraded.
+pub fn parse_any_manifest(value: Value) -> Result<Image, Box<dyn Error>> {
+    match value.get("v").map(|v| v.as_u64()) {
+        None | Some(Some(1)) => {
+            let legacy: LegacyManifest = serde_json::from_value(value)?;
+            Ok(upgrade_to_v2(legacy))
+        }
+        Some(Some(2)) => Ok(serde_json::from_value(value)?),
+        Some(_) => Err(format!("unsupported manifest version: {}", value["v"]).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::uuid;
+    use serde_json::json;
+
+    /// A dsmanifest as DSAPI served them, with `v` set to `v`, or left out if it's `None`.
+    fn dsmanifest(v: Option<u64>) -> Value {
+        let mut manifest = json!({
+            "name": "smartos64",
+            "version": "1.6.3",
+            "type": "zone-dataset",
+            "description": "Base template to build other templates on",
+            "published_at": "2012-05-02T15:15:24.139Z",
+            "platform_type": "smartos",
+            "cloud_name": "sdc",
+            "urn": "sdc:sdc:smartos64:1.6.3",
+            "uuid": uuid(1),
+            "creator_uuid": uuid(2),
+            "creator_name": "sdc",
+            "vendor_uuid": uuid(2),
+            "restricted_to_uuid": uuid(3),
+            "files": [{
+                "path": "smartos64-1.6.3.zfs.bz2",
+                "sha1": "97f20b32c2016782257176fb58a35e5044f05840",
+                "size": 48672854,
+                "url": "https://datasets.joyent.com/datasets/1/smartos64-1.6.3.zfs.bz2",
+            }],
+            "requirements": {"min_memory": 256, "max_memory": 1024, "networks": []},
+        });
+        if let Some(v) = v {
+            manifest["v"] = json!(v);
+        }
+        manifest
+    }
+
+    #[test]
+    fn upgrades_a_v1_manifest_as_imgadm_does() {
+        let image = parse_any_manifest(dsmanifest(Some(1))).unwrap();
+        assert_eq!(image.v, 2);
+        assert_eq!(
+            (image.name.as_str(), image.version.as_str()),
+            ("smartos64", "1.6.3")
+        );
+        assert_eq!(image.owner, uuid(2));
+        assert_eq!(image.os, "smartos");
+        assert_eq!(image.state, ImageState::Active);
+        assert!(!image.public);
+        assert_eq!(image.acl, Some(vec![uuid(3)]));
+        assert_eq!(image.files.len(), 1);
+        assert_eq!(image.files[0].compression, Compression::Bzip2);
+        assert_eq!(image.files[0].size, 48672854);
+        let requirements = image.requirements.unwrap();
+        assert_eq!(
+            (requirements.min_ram, requirements.max_ram),
+            (Some(256), Some(1024))
+        );
+        assert_eq!(
+            image.published_at.map(|p| crate::timestamp::format(&p)),
+            Some("2012-05-02T15:15:24.139Z".to_string())
+        );
+    }
+
+    #[test]
+    fn a_manifest_without_v_is_v1() {
+        let unversioned = parse_any_manifest(dsmanifest(None)).unwrap();
+        let v1 = parse_any_manifest(dsmanifest(Some(1))).unwrap();
+        assert_eq!(
+            serde_json::to_value(&unversioned).unwrap(),
+            serde_json::to_value(&v1).unwrap()
+        );
+    }
+
+    #[test]
+    fn fills_in_what_a_minimal_v1_manifest_lacks() {
+        let image = parse_any_manifest(json!({
+            "uuid": uuid(1),
+            "name": "old",
+            "version": "1",
+            "files": [{"sha1": "", "size": 0}],
+        }))
+        .unwrap();
+        assert_eq!(image.owner, Uuid::nil());
+        assert_eq!(image.image_type, "zone-dataset");
+        assert_eq!(image.os, "other");
+        assert!(image.public);
+        assert_eq!(image.acl, None);
+        assert_eq!(image.files[0].compression, Compression::None);
+    }
+
+    #[test]
+    fn parses_a_v2_manifest_as_it_is() {
+        let manifest = serde_json::to_value(crate::testutil::image(1, json!({}))).unwrap();
+        let image = parse_any_manifest(manifest.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&image).unwrap(), manifest);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        for v in &[json!(3), json!("2")] {
+            let err = parse_any_manifest(json!({"v": v})).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("unsupported manifest version: {}", v)
+            );
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fmt;
 use std::str::FromStr;
@@ -12,7 +12,81 @@ pub use uuid::Uuid;
 pub use chrono::DateTime;
 use chrono::Utc;
 
+mod acl;
+mod ancestry;
+mod auth;
 pub mod blocking;
+mod cache;
+mod channel;
+mod compression;
+mod diff;
+mod digest;
+mod docker;
+mod download;
+mod error;
+mod image_set;
+mod legacy;
+mod manifest;
+mod mirror;
+mod platform;
+mod pool;
+mod publish;
+mod requirements;
+#[cfg(feature = "schema")]
+mod schema;
+pub mod size;
+mod sort;
+mod source;
+mod summary;
+mod tags;
+#[cfg(test)]
+mod testutil;
+mod throttle;
+pub mod timestamp;
+mod traits;
+mod update;
+mod validate;
+mod verify;
+mod version;
+mod wait;
+
+pub use acl::{AclAction, AclUpdate, AclUpdateError};
+pub use ancestry::{Ancestry, AncestryError};
+pub use auth::{KeyError, RequestSigner};
+pub use cache::{CacheMode, CacheStats, Catalog, CatalogCache, NotCached, Offline};
+pub use channel::{Channel, ChannelInfo, ParseChannelError};
+pub use compression::DetectedCompression;
+pub use diff::{diff, FieldChange, ManifestDiff};
+pub use digest::{file_digest, FileDigest};
+pub use download::{
+    BatchProgress, CancelToken, Cancelled, Decompress, DownloadOptions, DownloadReport,
+    DownloadRequest, ExportOptions, ImageDownload, Progress, TransportChecksumMismatch,
+};
+pub use error::{ApiError, DryRun, FieldError, RequestTimeout};
+pub use image_set::{ImagePredicate, ImageSet};
+pub use legacy::{parse_any_manifest, upgrade_to_v2, LegacyFile, LegacyManifest};
+pub use mirror::{
+    copy_image, copy_image_with_progress, mirror, CopyOptions, MirrorOptions, MirrorReport,
+};
+pub use platform::{is_platform_timestamp, PlatformBound, PlatformConstraint};
+pub use publish::PublishOptions;
+pub use requirements::{ProvisionSpec, RequirementViolation};
+#[cfg(feature = "schema")]
+pub use schema::validate_schema;
+pub use sort::{sort_images, sort_images_by, ParseSortKeyError, SortKey, SortOrder};
+pub use source::{open_source, ImageSource, LocalSource};
+pub use summary::ImageSummary;
+pub use tags::TagValue;
+pub use throttle::BytesPerSec;
+pub use traits::{ServerTraits, TraitValue, Traits};
+pub use update::{ImageUpdate, Patch};
+pub use validate::{Severity, ValidationIssue};
+pub use verify::{verify, ChecksumMismatch, Discrepancy, VerifyReport};
+pub use version::{cmp_version_strings, cmp_versions, latest_by_name};
+pub use wait::{WaitError, WaitOptions};
+
+/// The public Joyent IMGAPI server.
+pub const JOYENT_IMGAPI_SERVER: &str = "https://images.joyent.com";
 
 pub const JOYENT_IMGAPI_URL: &str = "https://images.joyent.com/images";
 
@@ -27,8 +101,8 @@ pub struct ImageFilter {
 
     /// The image channel to use. If not provided the server-side default channel is used.
     ///
-    /// Use '*' to list in all channels.
-    pub channel: Option<String>,
+    /// Use '*' ([`Channel::all`]) to list in all channels.
+    pub channel: Option<Channel>,
 
     /// Whether to include administrative fields (e.g. files.*.stor) in the returned image objects.
     ///
@@ -38,8 +112,9 @@ pub struct ImageFilter {
     /// Only list images owned by this account.
     pub owner: Option<Uuid>,
 
-    /// List images with the given state.
-    pub state: Option<ImageState>,
+    /// List images with the given state, or in any state with [`StateFilter::All`]. The default
+    /// is to list only active images.
+    pub state: Option<StateFilter>,
 
     /// List images with the given name.
     ///
@@ -67,15 +142,15 @@ pub struct ImageFilter {
     /// tagged as `cloud=private`, then the filter to be added would look like this:
     ///
     /// ```
-    /// use std::collections::HashMap;
-    /// let mut tags: HashMap<String, String> = HashMap::new();
+    /// use std::collections::BTreeMap;
+    /// let mut tags: BTreeMap<String, String> = BTreeMap::new();
     /// tags.insert("cloud".to_string(), "private".to_string());
     /// ```
     ///
     /// More than one tag can be specified for the same search. Multiple tags are interpreted as a
     /// logical AND, meaning that each of the images returned is tagged with each of the values
     /// provided.
-    pub tag: Option<HashMap<String, String>>,
+    pub tag: Option<BTreeMap<String, String>>,
 
     pub billing_tag: Option<Vec<String>>,
 
@@ -84,18 +159,111 @@ pub struct ImageFilter {
     /// Images are sorted by creation date (ASC) by default. The default (and maximum) limit value
     /// is 1000.
     pub limit: Option<u32>,
-    // XXX: handle markers for pagination
-    // pub marker: Option<???>,
+
+    /// Only list images published at or after the marker, to page through more images than the
+    /// limit allows. See [`blocking::Client::list_all`].
+    pub marker: Option<Marker>,
+}
+
+/// Where a page of [`ImageFilter`] results starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Marker {
+    /// Images published at or after the image with this UUID, including that image.
+    Image(Uuid),
+
+    /// Images published at or after this time.
+    PublishedAt(DateTime<Utc>),
 }
 
-impl std::string::ToString for ImageFilter {
-    fn to_string(&self) -> String {
+impl fmt::Display for Marker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Image(uuid) => uuid.fmt(f),
+            Self::PublishedAt(dt) => timestamp::format(dt).fmt(f),
+        }
+    }
+}
+
+impl FromStr for Marker {
+    type Err = String;
+
+    /// Parses a UUID or a timestamp, in any format [`timestamp::parse`] accepts.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Uuid::parse_str(s) {
+            Ok(uuid) => Ok(Self::Image(uuid)),
+            Err(_) => timestamp::parse(s)
+                .map(Self::PublishedAt)
+                .map_err(|_| format!("invalid marker: {:?} is neither a UUID nor a timestamp", s)),
+        }
+    }
+}
+
+impl ImageFilter {
+    /// Evaluates the filter locally, mirroring how the IMGAPI server applies it.
+    ///
+    /// As on the server, only active images match if [`ImageFilter::state`] is unset. The `limit`,
+    /// `marker` and `include_admin_fields` fields don't affect matching.
+    pub fn matches(&self, image: &Image) -> bool {
+        fn str_matches(pattern: &str, value: &str) -> bool {
+            match pattern.strip_prefix('~') {
+                Some(substr) => value.contains(substr),
+                None => pattern == value,
+            }
+        }
+
+        fn tag_matches(value: Option<&Value>, wanted: &str) -> bool {
+            match value {
+                Some(Value::String(s)) => s == wanted,
+                Some(v) => wanted.parse::<Value>().is_ok_and(|w| w == *v),
+                None => false,
+            }
+        }
+
+        let state = self.state.unwrap_or(StateFilter::Only(ImageState::Active));
+
+        self.account.is_none_or(|a| image.has_access(&a))
+            && self
+                .channel
+                .as_ref()
+                .is_none_or(|c| c.is_all() || image.in_channel(c.as_str()))
+            && self.owner.is_none_or(|o| image.owner == o)
+            && state.matches(image.state)
+            && self
+                .name
+                .as_ref()
+                .is_none_or(|n| str_matches(n, &image.name))
+            && self
+                .version
+                .as_ref()
+                .is_none_or(|v| str_matches(v, &image.version))
+            && self.public.is_none_or(|p| image.public == p)
+            && self.os.is_none_or(|os| image.os == os.as_param())
+            && self
+                .image_type
+                .as_ref()
+                .is_none_or(|t| match t.strip_prefix('!') {
+                    Some(excluded) => image.image_type != excluded,
+                    None => image.image_type == *t,
+                })
+            && self
+                .tag
+                .as_ref()
+                .is_none_or(|tags| tags.iter().all(|(k, v)| tag_matches(image.tag(k), v)))
+            && self.billing_tag.as_ref().is_none_or(|wanted| {
+                let tags = image.billing_tags.as_deref().unwrap_or_default();
+                wanted.iter().all(|t| tags.contains(t))
+            })
+    }
+}
+
+impl fmt::Display for ImageFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         macro_rules! add_param {
             ($param:ident, $collection:ident) => {
                 add_param!($param, stringify!($param), $collection);
             };
             ($param:ident, $query_name:expr, $collection:ident) => {
-                add_param!($param, stringify!($param), to_string, $collection);
+                add_param!($param, $query_name, to_string, $collection);
             };
             ($param:ident, $query_name:expr, $val_func:ident, $collection:ident) => {
                 if let Some(v) = &self.$param {
@@ -115,8 +283,9 @@ impl std::string::ToString for ImageFilter {
         add_param!(version, qp);
         add_param!(public, qp);
         add_param!(os, "os", as_param, qp);
-        add_param!(image_type, qp);
+        add_param!(image_type, "type", qp);
         add_param!(limit, qp);
+        add_param!(marker, qp);
 
         if let Some(val) = &self.tag {
             for (k, v) in val.iter() {
@@ -130,7 +299,7 @@ impl std::string::ToString for ImageFilter {
             }
         }
 
-        qp.finish()
+        qp.finish().fmt(f)
     }
 }
 
@@ -157,15 +326,19 @@ pub struct Image {
     pub version: String,
 
     /// A short description of the image.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
 
     /// Homepage URL where users can find more information about the image.
-    pub homepage: Option<Url>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<MaybeUrl>,
 
     /// URL of the End User License Agreement (EULA) for the image.
-    pub eula: Option<Url>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eula: Option<MaybeUrl>,
 
     /// Indicates if the image has an icon file. If not present, then no icon is present.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub icon: Option<bool>,
 
     /// The current state of the image. One of 'active', 'unactivated', 'disabled', 'creating',
@@ -175,6 +348,7 @@ pub struct Image {
     /// An object with details on image creation failure.
     ///
     /// This only set when state is [`State::Failed`].
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<ImageError>,
 
     /// Indicates if this image is available for provisioning.
@@ -184,6 +358,11 @@ pub struct Image {
     pub public: bool,
 
     /// The date at which the image is activated.
+    #[serde(
+        default,
+        with = "timestamp::option",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub published_at: Option<DateTime<Utc>>,
 
     #[serde(rename = "type")]
@@ -194,6 +373,7 @@ pub struct Image {
     pub os: String,
 
     /// The origin image UUID if this is an incremental image.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub origin: Option<Uuid>,
 
     /// An array with a single object describing the image file.
@@ -201,42 +381,215 @@ pub struct Image {
 
     /// An array of account UUIDs given access to a private image. The field is only relevant to
     /// private images.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub acl: Option<Vec<Uuid>>,
 
+    /// A set of named requirements for provisioning a VM with this image.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requirements: Option<Requirements>,
+
     /// A list of users for which passwords should be generated for provisioning.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub users: Option<Vec<User>>,
 
     /// A list of tags that can be used by operators for additional billing processing.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub billing_tags: Option<Vec<String>>,
 
     /// An object that defines a collection of properties that is used by other APIs to evaluate
     /// where should customer VMs be placed.
-    pub traits: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub traits: Option<Traits>,
 
     /// An object of key/value pairs that allows clients to categorize images by any given criteria.
-    pub tags: Option<HashMap<String, Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<BTreeMap<String, Value>>,
 
     /// Indicates whether to generate passwords for the users in the [`users`] field.  If `None`,
     /// the field should be assumed to mean `true`.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub generate_passwords: Option<bool>,
 
     /// A list of inherited directories (other than the defaults for the brand).
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub inherited_directories: Option<Vec<String>>,
 
     /// NIC driver used by this VM image. Only required for [`ImageType::Zvol`] images.
-    pub nic_driver: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nic_driver: Option<NicDriver>,
 
     /// Disk driver used by this VM image. Only required for [`ImageType::Zvol`] images.
-    pub disk_driver: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk_driver: Option<DiskDriver>,
 
     /// The QEMU CPU model used by this VM image. Only required for [`ImageType::Zvol`] images.
-    pub cpu_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_type: Option<CpuType>,
 
     /// The size (in MiB) of this VM image's disk. Only required for [`ImageType::Zvol`] images.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub image_size: Option<u32>,
 
     /// Array of channel names to which this image belongs.
-    pub channels: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<Vec<Channel>>,
+}
+
+/// Images are compared by identity: two images are equal if they have the same uuid, regardless
+/// of the rest of their content. Use [`Image::content_eq`] to compare manifests field by field.
+impl PartialEq for Image {
+    fn eq(&self, other: &Self) -> bool {
+        self.uuid == other.uuid
+    }
+}
+
+impl Eq for Image {}
+
+impl std::hash::Hash for Image {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.uuid.hash(state)
+    }
+}
+
+impl Image {
+    /// Whether the two manifests are identical in every field, as opposed to `==`, which only
+    /// compares uuids. This is useful to detect drift between two copies of the same image.
+    pub fn content_eq(&self, other: &Self) -> bool {
+        diff(self, other).is_empty()
+    }
+
+    /// Indicates whether VMs can be provisioned from this image.
+    ///
+    /// This requires the image to be [`ImageState::Active`] *and* not disabled. Note that
+    /// [`ImageState::Disabled`] is only reported for activated images, so checking
+    /// [`Image::disabled`] is still necessary.
+    pub fn is_provisionable(&self) -> bool {
+        self.state == ImageState::Active && !self.disabled
+    }
+
+    /// Indicates whether this is an incremental image, i.e. it has an origin image.
+    pub fn is_incremental(&self) -> bool {
+        self.origin.is_some()
+    }
+
+    /// Indicates whether this image is publicly available.
+    pub fn is_public(&self) -> bool {
+        self.public
+    }
+
+    /// Indicates whether `account` can see this image.
+    ///
+    /// An account can see its own images, all public images, and private images on whose ACL it
+    /// appears. The ACL is ignored for public images.
+    pub fn has_access(&self, account: &Uuid) -> bool {
+        self.owner == *account
+            || self.public
+            || self.acl.as_ref().is_some_and(|acl| acl.contains(account))
+    }
+
+    /// Indicates whether this image belongs to the channel `name`.
+    ///
+    /// Images from servers that don't support channels never belong to any channel.
+    pub fn in_channel(&self, name: &str) -> bool {
+        self.channels
+            .as_ref()
+            .is_some_and(|channels| channels.iter().any(|c| c.as_str() == name))
+    }
+
+    /// Returns the users for which passwords must be generated when provisioning.
+    ///
+    /// This honors [`Image::generate_passwords`], where `None` means `true`: if it is explicitly
+    /// `false`, no users are returned.
+    pub fn provisioning_users(&self) -> Vec<&User> {
+        match (self.generate_passwords, &self.users) {
+            (Some(false), _) | (_, None) => Vec::new(),
+            (_, Some(users)) => users.iter().collect(),
+        }
+    }
+
+    /// Returns the metadata keys (`<user>_pw`) a provisioner must supply passwords in.
+    pub fn password_metadata_keys(&self) -> Vec<String> {
+        self.provisioning_users()
+            .iter()
+            .map(|u| format!("{}_pw", u.name))
+            .collect()
+    }
+
+    /// The sum of the sizes of all of the image's files, in bytes.
+    pub fn total_file_size(&self) -> u64 {
+        self.files
+            .iter()
+            .fold(0u64, |acc, f| acc.saturating_add(f.size))
+    }
+
+    /// Whether any administrative fields (see [`ImageFilter::include_admin_fields`]) are set.
+    pub fn has_admin_fields(&self) -> bool {
+        self.files.iter().any(|f| f.stor.is_some())
+    }
+
+    /// Clears administrative fields, e.g. before writing a manifest that is meant to be imported
+    /// into a different IMGAPI server.
+    pub fn strip_admin_fields(&mut self) {
+        for f in &mut self.files {
+            f.stor = None;
+        }
+    }
+}
+
+/// A URL field that may not actually contain a valid URL.
+///
+/// Some images in the public catalog have malformed or relative homepage URLs. Rather than failing
+/// to parse the whole manifest (and with it, entire listings), the raw string is kept and
+/// [`Image::validate`] reports a warning.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum MaybeUrl {
+    Valid(Url),
+    Invalid(String),
+}
+
+impl MaybeUrl {
+    /// Returns the URL, if it is valid.
+    pub fn url(&self) -> Option<&Url> {
+        match self {
+            Self::Valid(u) => Some(u),
+            Self::Invalid(_) => None,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        matches!(self, Self::Valid(_))
+    }
+}
+
+impl From<String> for MaybeUrl {
+    fn from(s: String) -> Self {
+        match Url::parse(&s) {
+            Ok(u) => Self::Valid(u),
+            Err(_) => Self::Invalid(s),
+        }
+    }
+}
+
+impl From<Url> for MaybeUrl {
+    fn from(u: Url) -> Self {
+        Self::Valid(u)
+    }
+}
+
+impl From<MaybeUrl> for String {
+    fn from(u: MaybeUrl) -> Self {
+        u.to_string()
+    }
+}
+
+impl fmt::Display for MaybeUrl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Valid(u) => u.as_str().fmt(f),
+            Self::Invalid(s) => s.fmt(f),
+        }
+    }
 }
 
 #[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
@@ -274,6 +627,91 @@ impl fmt::Display for ImageState {
     }
 }
 
+/// The error returned when parsing an [`ImageState`] or [`StateFilter`] fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseStateError {
+    input: String,
+    all: bool,
+}
+
+impl fmt::Display for ParseStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid image state {:?}, expected one of: active, unactivated, disabled, creating, \
+             failed{}",
+            self.input,
+            if self.all { ", all" } else { "" }
+        )
+    }
+}
+
+impl Error for ParseStateError {}
+
+impl FromStr for ImageState {
+    type Err = ParseStateError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "active" => Ok(Self::Active),
+            "unactivated" => Ok(Self::Unactivated),
+            "disabled" => Ok(Self::Disabled),
+            "creating" => Ok(Self::Creating),
+            "failed" => Ok(Self::Failed),
+            _ => Err(ParseStateError {
+                input: s.to_string(),
+                all: false,
+            }),
+        }
+    }
+}
+
+/// The states of the images to list, see [`ImageFilter::state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateFilter {
+    /// Only images in the given state.
+    Only(ImageState),
+
+    /// Images in any state, including unactivated, disabled and failed images.
+    All,
+}
+
+impl StateFilter {
+    /// Whether an image in `state` is selected.
+    pub fn matches(&self, state: ImageState) -> bool {
+        match self {
+            Self::Only(s) => *s == state,
+            Self::All => true,
+        }
+    }
+}
+
+impl From<ImageState> for StateFilter {
+    fn from(state: ImageState) -> Self {
+        Self::Only(state)
+    }
+}
+
+impl fmt::Display for StateFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Only(s) => s.fmt(f),
+            Self::All => "all".fmt(f),
+        }
+    }
+}
+
+impl FromStr for StateFilter {
+    type Err = ParseStateError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Self::All),
+            _ => ImageState::from_str(s)
+                .map(Self::Only)
+                .map_err(|e| ParseStateError { all: true, ..e }),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// An object providing details on failure of some asynchronous image action.
 pub struct ImageError {
@@ -281,12 +719,14 @@ pub struct ImageError {
     pub message: String,
 
     /// A "CamelCase" string error code.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub code: Option<String>,
 
     /// A stack trace giving context for the error.
     ///
     /// This is generally considered internal implementation detail, only there to assist with
     /// debugging and error classification.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub stack: Option<String>,
 }
 
@@ -331,13 +771,22 @@ impl fmt::Display for ImageErrorCode {
     }
 }
 
+/// The largest [`ImageFilter::limit`] IMGAPI accepts, and the default.
+pub const MAX_PAGE_SIZE: u32 = 1000;
+
+/// The largest image file IMGAPI accepts, in bytes (20 GiB).
+pub const MAX_FILE_SIZE: u64 = 20 * 1024 * 1024 * 1024;
+
+/// The largest image icon IMGAPI accepts, in bytes (128 KiB).
+pub const MAX_ICON_SIZE: u64 = 128 * 1024;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// An image file that makes up part or all of an image.
 pub struct File {
     /// SHA-1 hex digest of the file content. Used for upload/download corruption checking.
     pub sha1: String,
 
-    /// Number of bytes. Maximum 20GiB.
+    /// Number of bytes. Maximum 20GiB ([`MAX_FILE_SIZE`]).
     pub size: u64,
 
     /// The type of file compression used by the file.
@@ -347,16 +796,22 @@ pub struct File {
     ///
     /// This identifier is available via `zfs get guid SNAPSHOT`, e.g. `zfs get guid
     /// zones/f669428c-a939-11e2-a485-b790efc0f0c1@final`.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub dataset_guid: Option<Uuid>,
 
-    #[serde(skip)]
+    /// The storage backend holding the file, e.g. `manta` or `local`. This is an administrative
+    /// field: it is only returned when [`ImageFilter::include_admin_fields`] is set (which may
+    /// require auth), and is left out when serializing a manifest that doesn't have it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub stor: Option<String>,
 
     /// Docker digest of the file contents. Only used when [`Image::image_type`] is 'docker'.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub digest: Option<String>,
 
     #[serde(rename = "uncompressedDigest")]
     /// Docker digest of the uncompressed file contents. Only used when [`Image::image_type`] is 'docker'.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub uncompressed_digest: Option<String>,
 }
 
@@ -366,7 +821,14 @@ pub struct File {
 pub enum Compression {
     Bzip2,
     Gzip,
+    Xz,
     None,
+
+    /// Detect the compression from the file name and contents when uploading a file. See
+    /// [`Compression::detect`]. It's resolved before anything is sent, and isn't part of the
+    /// serialized form, so a manifest can neither be written nor read with it.
+    #[serde(skip)]
+    Auto,
 }
 
 impl fmt::Display for Compression {
@@ -374,12 +836,31 @@ impl fmt::Display for Compression {
         match self {
             Self::Bzip2 => "bzip2",
             Self::Gzip => "gzip",
+            Self::Xz => "xz",
             Self::None => "none",
+            Self::Auto => "auto",
         }
         .fmt(f)
     }
 }
 
+impl FromStr for Compression {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bzip2" => Ok(Self::Bzip2),
+            "gzip" => Ok(Self::Gzip),
+            "xz" => Ok(Self::Xz),
+            "none" => Ok(Self::None),
+            "auto" => Ok(Self::Auto),
+            _ => Err(format!(
+                "unknown compression {:?} (expected one of: bzip2, gzip, xz, none, auto)",
+                s
+            )),
+        }
+    }
+}
+
 #[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum ImageType {
     #[serde(rename = "zone-dataset")]
@@ -394,6 +875,10 @@ pub enum ImageType {
     /// A virtual machine image for use by KVM or Bhyve.
     Zvol,
 
+    #[serde(rename = "docker")]
+    /// A Docker image layer.
+    Docker,
+
     #[serde(rename = "other")]
     /// An image that serves any other specific purpose.
     Other,
@@ -405,6 +890,7 @@ impl fmt::Display for ImageType {
             Self::ZoneDataset => "SmartOS zone dataset",
             Self::LxDataset => "Lx-brand dataset",
             Self::Zvol => "zvol",
+            Self::Docker => "Docker",
             Self::Other => "Other",
         }
         .fmt(f)
@@ -473,37 +959,269 @@ impl fmt::Display for OperatingSystem {
     }
 }
 
+/// The NIC driver used by a VM image.
+#[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum NicDriver {
+    Virtio,
+    E1000,
+    Rtl8139,
+
+    /// A driver this crate doesn't know about. It is passed through unchanged, but
+    /// [`Image::validate`] will warn about it.
+    Other(String),
+}
+
+impl fmt::Display for NicDriver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Virtio => "virtio",
+            Self::E1000 => "e1000",
+            Self::Rtl8139 => "rtl8139",
+            Self::Other(s) => s,
+        }
+        .fmt(f)
+    }
+}
+
+impl FromStr for NicDriver {
+    type Err = std::convert::Infallible;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from(s.to_string()))
+    }
+}
+
+impl From<String> for NicDriver {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "virtio" => Self::Virtio,
+            "e1000" => Self::E1000,
+            "rtl8139" => Self::Rtl8139,
+            _ => Self::Other(s),
+        }
+    }
+}
+
+impl From<NicDriver> for String {
+    fn from(d: NicDriver) -> Self {
+        d.to_string()
+    }
+}
+
+/// The disk driver used by a VM image.
+#[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum DiskDriver {
+    Virtio,
+    Ide,
+    Scsi,
+
+    /// A driver this crate doesn't know about. It is passed through unchanged, but
+    /// [`Image::validate`] will warn about it.
+    Other(String),
+}
+
+impl fmt::Display for DiskDriver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Virtio => "virtio",
+            Self::Ide => "ide",
+            Self::Scsi => "scsi",
+            Self::Other(s) => s,
+        }
+        .fmt(f)
+    }
+}
+
+impl FromStr for DiskDriver {
+    type Err = std::convert::Infallible;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from(s.to_string()))
+    }
+}
+
+impl From<String> for DiskDriver {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "virtio" => Self::Virtio,
+            "ide" => Self::Ide,
+            "scsi" => Self::Scsi,
+            _ => Self::Other(s),
+        }
+    }
+}
+
+impl From<DiskDriver> for String {
+    fn from(d: DiskDriver) -> Self {
+        d.to_string()
+    }
+}
+
+/// The QEMU CPU model used by a VM image.
+#[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum CpuType {
+    Qemu64,
+
+    /// Pass the host CPU through to the guest.
+    Host,
+
+    /// Any other QEMU CPU model name. It is passed through unchanged, but [`Image::validate`]
+    /// will warn about it.
+    Other(String),
+}
+
+impl fmt::Display for CpuType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Qemu64 => "qemu64",
+            Self::Host => "host",
+            Self::Other(s) => s,
+        }
+        .fmt(f)
+    }
+}
+
+impl FromStr for CpuType {
+    type Err = std::convert::Infallible;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from(s.to_string()))
+    }
+}
+
+impl From<String> for CpuType {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "qemu64" => Self::Qemu64,
+            "host" => Self::Host,
+            _ => Self::Other(s),
+        }
+    }
+}
+
+impl From<CpuType> for String {
+    fn from(c: CpuType) -> Self {
+        c.to_string()
+    }
+}
+
+/// A SmartOS zone brand.
+#[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Brand {
+    Joyent,
+    JoyentMinimal,
+    Lx,
+    Kvm,
+    Bhyve,
+    Builder,
+
+    /// A brand this crate doesn't know about. It is passed through unchanged, but
+    /// [`Image::validate`] will warn about it.
+    Other(String),
+}
+
+impl Brand {
+    /// Whether this brand runs hardware virtual machines (i.e. zvol images).
+    pub fn is_hvm(&self) -> bool {
+        matches!(self, Self::Kvm | Self::Bhyve)
+    }
+}
+
+impl fmt::Display for Brand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Joyent => "joyent",
+            Self::JoyentMinimal => "joyent-minimal",
+            Self::Lx => "lx",
+            Self::Kvm => "kvm",
+            Self::Bhyve => "bhyve",
+            Self::Builder => "builder",
+            Self::Other(s) => s,
+        }
+        .fmt(f)
+    }
+}
+
+impl FromStr for Brand {
+    type Err = std::convert::Infallible;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from(s.to_string()))
+    }
+}
+
+impl From<String> for Brand {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "joyent" => Self::Joyent,
+            "joyent-minimal" => Self::JoyentMinimal,
+            "lx" => Self::Lx,
+            "kvm" => Self::Kvm,
+            "bhyve" => Self::Bhyve,
+            "builder" => Self::Builder,
+            _ => Self::Other(s),
+        }
+    }
+}
+
+impl From<Brand> for String {
+    fn from(b: Brand) -> Self {
+        b.to_string()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Requirements {
     /// An array describing the minimum number of network interfaces.
+    #[serde(default)]
     pub networks: Vec<Network>,
 
     /// Defines the SmartOS "brand" that is required to provision with this image.
-    pub brand: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brand: Option<Brand>,
 
     /// Indicates that provisioning with this image requires that an SSH public key be provided.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub ssh_key: Option<bool>,
 
     /// The minimum RAM (in MiB) required to provision the image.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub min_ram: Option<u32>,
 
     /// The maximum RAM (in MiB) the image may be provisioned with.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub max_ram: Option<u32>,
 
     /// The minimum required SmartOS platform on which this image can be used.
     ///
     /// It is a mapping of major "SDC Version" to the SmartOS platform timestamp.
-    pub min_platform: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_platform: Option<PlatformConstraint>,
 
     /// The maximum required SmartOS platform on which this image can be used.
     ///
     /// It is a mapping of major "SDC Version" to the SmartOS platform timestamp.
-    pub max_platform: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_platform: Option<PlatformConstraint>,
 
     /// The boot ROM image to use.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub boot_rom: Option<String>,
 }
 
+impl Requirements {
+    /// Whether a compute node running `platform` under SDC version `sdc_version` satisfies both
+    /// the `min_platform` and `max_platform` requirements.
+    pub fn platform_satisfied(&self, sdc_version: &str, platform: &str) -> bool {
+        let check = |c: &Option<PlatformConstraint>, bound| {
+            c.as_ref()
+                .is_none_or(|c| c.satisfied_by(bound, sdc_version, platform))
+        };
+        check(&self.min_platform, PlatformBound::Min)
+            && check(&self.max_platform, PlatformBound::Max)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Network {
     pub name: String,
@@ -531,4 +1249,103 @@ impl fmt::Display for BootRom {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub name: String,
+
+    /// Any other fields included for this user. These are preserved when re-serializing.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A manifest with every map-valued field, keys out of order, and fields only the users'
+    /// catch-all knows about.
+    const MANIFEST: &str = r#"{
+        "v": 2,
+        "uuid": "00000000-0000-0000-0000-000000000001",
+        "owner": "00000000-0000-0000-0000-000000000000",
+        "name": "base",
+        "version": "1.0.0",
+        "state": "active",
+        "disabled": false,
+        "public": true,
+        "published_at": "2024-01-01T00:00:00Z",
+        "type": "zone-dataset",
+        "os": "smartos",
+        "files": [{"sha1": "da39a3ee5e6b4b0d3255bfef95601890afd80709", "size": 0,
+                   "compression": "gzip"}],
+        "requirements": {
+            "min_platform": {"7.0": "20130122T122401Z", "6.5": "20120614T001014Z",
+                             "10.0": "20200101T000000Z"},
+            "max_platform": {"7.1": "20140101T000000Z", "7.0": "20131231T000000Z"}
+        },
+        "users": [{"name": "root", "uid": 0, "shell": "/bin/bash", "groups": ["wheel"],
+                   "home": {"path": "/root", "mode": "0700"}}],
+        "traits": {"ssd": true, "hw": ["a", "b"], "zone": "east", "weird": {"z": 1, "a": 2}},
+        "tags": {"zeta": 1, "alpha": "a", "mid": true, "docker:repo": "busybox", "Beta": 2.5}
+    }"#;
+
+    #[test]
+    fn serializing_a_manifest_is_byte_stable() {
+        let bytes = |image: &Image| {
+            let mut pretty = Vec::new();
+            image.to_writer_pretty(&mut pretty).unwrap();
+            (serde_json::to_vec(image).unwrap(), pretty)
+        };
+        let first: Image = serde_json::from_str(MANIFEST).unwrap();
+        let expected = bytes(&first);
+        for _ in 0..100 {
+            // Each parse builds its maps afresh, as a hashed map would with a new seed.
+            let image: Image = serde_json::from_str(MANIFEST).unwrap();
+            assert_eq!(bytes(&image), expected);
+        }
+
+        // The output reads back to the same manifest, and serializes the same again.
+        let again: Image = serde_json::from_slice(&expected.0).unwrap();
+        assert_eq!(bytes(&again), expected);
+
+        // Map keys come out sorted.
+        let compact = String::from_utf8(expected.0).unwrap();
+        let at = |key: &str| compact.find(key).unwrap();
+        assert!(at(r#""Beta""#) < at(r#""alpha""#) && at(r#""mid""#) < at(r#""zeta""#));
+        assert!(at(r#""10.0""#) < at(r#""6.5""#) && at(r#""6.5""#) < at(r#""7.0""#));
+        // A user's name comes first, then the fields kept in its catch-all.
+        let user = [
+            r#""name":"root""#,
+            r#""groups""#,
+            r#""home""#,
+            r#""shell""#,
+            r#""uid""#,
+        ];
+        assert!(user.windows(2).all(|w| at(w[0]) < at(w[1])));
+    }
+
+    #[test]
+    fn unknown_user_fields_are_kept() {
+        let image: Image = serde_json::from_str(MANIFEST).unwrap();
+        let user = &image.users.as_ref().unwrap()[0];
+        assert_eq!(user.name, "root");
+        assert_eq!(user.extra["shell"], "/bin/bash");
+        assert_eq!(user.extra["home"]["mode"], "0700");
+        assert!(!user.extra.contains_key("name"));
+
+        let written = image.to_json().unwrap();
+        assert_eq!(
+            written["users"][0],
+            serde_json::json!({
+                "name": "root",
+                "uid": 0,
+                "shell": "/bin/bash",
+                "groups": ["wheel"],
+                "home": {"path": "/root", "mode": "0700"},
+            })
+        );
+        assert_eq!(written["tags"]["zeta"], 1);
+        assert_eq!(written["traits"]["weird"]["a"], 2);
+        assert_eq!(
+            written["requirements"]["min_platform"]["10.0"],
+            "20200101T000000Z"
+        );
+    }
 }
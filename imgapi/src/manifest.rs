@@ -0,0 +1,182 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use serde_json::Value;
+use tempfile::NamedTempFile;
+
+use super::{parse_any_manifest, Image};
+
+impl Image {
+    /// Reads a manifest from `reader`.
+    ///
+    /// Both a bare manifest and the `{"manifest": {...}}` wrapper imgadm sometimes writes are
+    /// accepted, as are v1 manifests (see [`parse_any_manifest`]).
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, Box<dyn Error>> {
+        let mut value: Value = serde_json::from_reader(reader)?;
+        if value.get("uuid").is_none() {
+            if let Some(inner) = value.get_mut("manifest") {
+                value = inner.take();
+            }
+        }
+        parse_any_manifest(value)
+    }
+
+    /// Reads a manifest from the file at `path`. See [`Image::from_reader`].
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let with_path = |e: Box<dyn Error>| format!("{}: {}", path.display(), e);
+        let file = fs::File::open(path).map_err(|e| with_path(e.into()))?;
+        Ok(Self::from_reader(io::BufReader::new(file)).map_err(with_path)?)
+    }
+
+    /// The manifest as a JSON value, with unset fields left out rather than set to `null`, as
+    /// IMGAPI and imgadm leave them out. A `null` the manifest holds, e.g. as a tag's value, is
+    /// kept.
+    pub fn to_json(&self) -> Result<Value, serde_json::Error> {
+        serde_json::to_value(self)
+    }
+
+    /// Writes the manifest to `writer` as pretty-printed JSON, followed by a newline. Unset fields
+    /// are left out rather than written as `null`.
+    pub fn to_writer_pretty<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer_pretty(&mut writer, &self.to_json()?)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Writes the manifest to the file at `path` as pretty-printed JSON.
+    ///
+    /// The manifest is written to a temporary file in the same directory which is then renamed into
+    /// place, so `path` never contains a partially-written manifest.
+    pub fn to_path<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let with_path = |e: Box<dyn Error>| format!("{}: {}", path.display(), e);
+        let dir = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+
+        let mut tmp = NamedTempFile::new_in(dir).map_err(|e| with_path(e.into()))?;
+        let mut writer = io::BufWriter::new(&mut tmp);
+        self.to_writer_pretty(&mut writer).map_err(with_path)?;
+        writer.flush().map_err(|e| with_path(e.into()))?;
+        drop(writer);
+        tmp.as_file().sync_all().map_err(|e| with_path(e.into()))?;
+        tmp.persist(path).map_err(|e| with_path(e.into()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testutil::image;
+
+    /// The paths of the `null`s in `value`.
+    fn nulls(value: &Value, path: &str) -> Vec<String> {
+        match value {
+            Value::Null => vec![path.to_string()],
+            Value::Object(map) => map
+                .iter()
+                .flat_map(|(k, v)| nulls(v, &format!("{}/{}", path, k)))
+                .collect(),
+            Value::Array(a) => a
+                .iter()
+                .enumerate()
+                .flat_map(|(i, v)| nulls(v, &format!("{}/{}", path, i)))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn leaves_out_unset_fields() {
+        let image = image(
+            1,
+            json!({ "requirements": {}, "error": { "message": "failed" } }),
+        );
+        let value = serde_json::to_value(&image).unwrap();
+        assert_eq!(nulls(&value, ""), Vec::<String>::new());
+        assert_eq!(value["requirements"], json!({ "networks": [] }));
+        assert!(value.get("description").is_none());
+        assert!(value["files"][0].get("digest").is_none());
+    }
+
+    #[test]
+    fn keeps_nulls_the_manifest_holds() {
+        let fields = json!({
+            "tags": { "k": null },
+            "traits": { "t": null },
+            "users": [{ "name": "root", "shell": null }],
+        });
+        let image = image(1, fields.clone());
+        let value = image.to_json().unwrap();
+        for (key, expected) in fields.as_object().unwrap() {
+            assert_eq!(&value[key], expected, "{}", key);
+        }
+        let again = Image::from_reader(value.to_string().as_bytes()).unwrap();
+        assert_eq!(again.to_json().unwrap(), value);
+    }
+
+    #[test]
+    fn round_trips_through_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("base.json");
+        let manifest = image(1, json!({"description": "a base", "tags": {"role": "db"}}));
+        manifest.to_path(&path).unwrap();
+
+        let written = fs::read_to_string(&path).unwrap();
+        assert!(written.ends_with("}\n"), "{:?}", written);
+        let again = Image::from_path(&path).unwrap();
+        assert_eq!(again.to_json().unwrap(), manifest.to_json().unwrap());
+
+        // Writing again replaces the file, and leaves no temporary file behind.
+        let changed = image(1, json!({"description": "changed"}));
+        changed.to_path(&path).unwrap();
+        assert_eq!(
+            Image::from_path(&path).unwrap().description.as_deref(),
+            Some("changed")
+        );
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn reads_a_manifest_in_a_wrapper() {
+        let image = image(1, json!({}));
+        let wrapped = json!({ "manifest": image.to_json().unwrap(), "zpool": "zones" });
+        let read = Image::from_reader(wrapped.to_string().as_bytes()).unwrap();
+        assert_eq!(read.to_json().unwrap(), image.to_json().unwrap());
+    }
+
+    #[test]
+    fn names_the_file_it_cant_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("broken.json");
+        fs::write(&path, "{").unwrap();
+        let err = Image::from_path(&path).unwrap_err().to_string();
+        assert!(err.starts_with(&format!("{}: ", path.display())), "{}", err);
+
+        let missing = dir.path().join("missing.json");
+        let err = Image::from_path(&missing).unwrap_err().to_string();
+        assert!(
+            err.starts_with(&format!("{}: ", missing.display())),
+            "{}",
+            err
+        );
+
+        let nowhere = dir.path().join("missing").join("base.json");
+        let err = image(1, json!({}))
+            .to_path(&nowhere)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.starts_with(&format!("{}: ", nowhere.display())),
+            "{}",
+            err
+        );
+    }
+}
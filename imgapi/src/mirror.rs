@@ -0,0 +1,462 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::io::Read;
+
+use super::blocking::Client;
+use super::download::ProgressReader;
+use super::pool::run_bounded;
+use super::{Image, ImageFilter, Progress, StateFilter, Uuid};
+
+/// Options for [`copy_image_with_progress`].
+#[derive(Debug, Clone)]
+pub struct CopyOptions {
+    /// Activate the copy once its file has been uploaded. Otherwise it's left unactivated, e.g. to
+    /// be checked before it's activated by hand.
+    pub activate: bool,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        Self { activate: true }
+    }
+}
+
+/// Options for [`mirror`].
+#[derive(Debug, Clone, Default)]
+pub struct MirrorOptions {
+    /// The maximum number of images copied at once. `0` is treated as `1`.
+    pub concurrency: usize,
+
+    /// Delete images on the destination that match the filter but aren't on the source.
+    pub delete: bool,
+
+    /// Work out what would be copied and deleted, without changing the destination.
+    pub dry_run: bool,
+}
+
+/// What [`mirror`] did, or would do in a dry run.
+#[derive(Debug, Clone, Default)]
+pub struct MirrorReport {
+    /// Images copied to the destination, in the order they were copied. This includes ancestors of
+    /// matching images that the destination was missing.
+    pub copied: Vec<Uuid>,
+
+    /// Matching images the destination already had.
+    pub skipped: Vec<Uuid>,
+
+    /// Images deleted from the destination.
+    pub deleted: Vec<Uuid>,
+
+    /// Images that couldn't be copied or deleted, and why.
+    pub failed: Vec<(Uuid, String)>,
+}
+
+impl MirrorReport {
+    /// Whether every image was copied or deleted successfully.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Copies an image from `src` to `dst`, keeping its UUID: the manifest is imported with
+/// AdminImportImage, the file is streamed from one server to the other and verified against the
+/// manifest, and the image is activated.
+///
+/// The image's origin, if it has one, must already be on `dst`, and the image must have exactly one
+/// file. If the copy fails after the manifest was imported, the incomplete image is deleted from
+/// `dst` again.
+pub fn copy_image(src: &Client, dst: &Client, uuid: &Uuid) -> Result<Image, Box<dyn Error>> {
+    copy_manifest(src, dst, &src.get(uuid)?, &CopyOptions::default(), None)
+}
+
+/// Like [`copy_image`], for an image already looked up on `src`, calling `progress` as its file
+/// is copied, and only activating the copy if [`CopyOptions::activate`] says to.
+pub fn copy_image_with_progress<F: FnMut(Progress) + Send + 'static>(
+    src: &Client,
+    dst: &Client,
+    image: &Image,
+    opts: &CopyOptions,
+    progress: F,
+) -> Result<Image, Box<dyn Error>> {
+    copy_manifest(src, dst, image, opts, Some(Box::new(progress)))
+}
+
+fn copy_manifest(
+    src: &Client,
+    dst: &Client,
+    image: &Image,
+    opts: &CopyOptions,
+    progress: Option<Box<dyn FnMut(Progress) + Send>>,
+) -> Result<Image, Box<dyn Error>> {
+    let file = match image.files.as_slice() {
+        [file] => file,
+        [] => return Err(format!("image {} has no file", image.uuid).into()),
+        files => {
+            return Err(format!(
+                "image {} has {} files, and only images with one file can be copied",
+                image.uuid,
+                files.len()
+            )
+            .into())
+        }
+    };
+    dst.admin_import(image)?;
+
+    let result = (|| {
+        let resp = src.get_file(&image.uuid, 0, 0)?;
+        let reader: Box<dyn Read + Send> = match progress {
+            Some(progress) => Box::new(ProgressReader::new(resp, Some(file.size), progress)),
+            None => Box::new(resp),
+        };
+        let (copy, digest) =
+            dst.upload_reader(&image.uuid, reader, file.size, file.compression, None)?;
+        digest.verify(file)?;
+        if opts.activate {
+            dst.activate(&image.uuid)
+        } else {
+            Ok(copy)
+        }
+    })();
+    if result.is_err() {
+        let _ = dst.delete(&image.uuid);
+    }
+    result
+}
+
+/// How many of the image's ancestors are also in `planned`.
+fn depth(uuid: &Uuid, planned: &HashMap<Uuid, Image>, memo: &mut HashMap<Uuid, usize>) -> usize {
+    if let Some(d) = memo.get(uuid) {
+        return *d;
+    }
+    let d = match planned[uuid].origin.filter(|o| planned.contains_key(o)) {
+        Some(origin) => depth(&origin, planned, memo) + 1,
+        None => 0,
+    };
+    memo.insert(*uuid, d);
+    d
+}
+
+/// Copies the images on `src` matching `filter` to `dst`, along with any of their ancestors `dst`
+/// is missing, keeping their UUIDs.
+///
+/// Images are compared by UUID only; an image already on `dst` is skipped, even if its manifest
+/// differs. Ancestors are always copied before their descendants, and independent images are
+/// copied concurrently, up to [`MirrorOptions::concurrency`]. A failure to copy one image doesn't
+/// stop the others, except for its descendants, and is reported in [`MirrorReport::failed`].
+///
+/// Both servers are listed in full, however many pages that takes: `dst` once for the images it
+/// has, in any state, and again for those matching `filter` with [`MirrorOptions::delete`], which
+/// can't be used with a filter that has a limit or a marker. Copying requires admin access to
+/// `dst`, see [`Client::admin_import`]. Errors listing images are returned immediately.
+pub fn mirror(
+    src: &Client,
+    dst: &Client,
+    filter: &ImageFilter,
+    opts: &MirrorOptions,
+) -> Result<MirrorReport, Box<dyn Error>> {
+    if opts.delete && (filter.limit.is_some() || filter.marker.is_some()) {
+        return Err(
+            "mirroring with delete needs every matching image, so the filter can't have \
+                    a limit or a marker"
+                .into(),
+        );
+    }
+    let mut report = MirrorReport::default();
+    let wanted = src.list_all(filter, |_, _| ())?;
+    let wanted_uuids: HashSet<Uuid> = wanted.iter().map(|i| i.uuid).collect();
+
+    // Every image on `dst`, in every state and whether or not it matches, to tell which images
+    // and ancestors it already has.
+    let everything = ImageFilter {
+        state: Some(StateFilter::All),
+        channel: filter.channel.clone(),
+        ..ImageFilter::default()
+    };
+    let on_dst: HashSet<Uuid> = dst
+        .list_all(&everything, |_, _| ())?
+        .into_iter()
+        .map(|i| i.uuid)
+        .collect();
+
+    // Work out everything that needs copying, ancestors included.
+    let mut planned: HashMap<Uuid, Image> = HashMap::new();
+    for image in wanted {
+        if on_dst.contains(&image.uuid) {
+            report.skipped.push(image.uuid);
+            continue;
+        }
+        let mut next = image.origin;
+        planned.insert(image.uuid, image);
+        while let Some(origin) = next.filter(|o| !planned.contains_key(o)) {
+            if on_dst.contains(&origin) {
+                break;
+            }
+            match src.get(&origin) {
+                Ok(ancestor) => {
+                    next = ancestor.origin;
+                    planned.insert(origin, ancestor);
+                }
+                Err(e) => {
+                    report.failed.push((origin, e.to_string()));
+                    break;
+                }
+            }
+        }
+    }
+
+    // Group the images into waves by how many of their ancestors are also being copied, so each
+    // wave only depends on earlier ones.
+    let mut memo = HashMap::new();
+    let mut waves: BTreeMap<usize, Vec<&Image>> = BTreeMap::new();
+    for uuid in planned.keys() {
+        waves
+            .entry(depth(uuid, &planned, &mut memo))
+            .or_default()
+            .push(&planned[uuid]);
+    }
+
+    let mut failed: HashSet<Uuid> = report.failed.iter().map(|(u, _)| *u).collect();
+    for (_, mut wave) in waves {
+        wave.sort_by_key(|i| (i.published_at, i.uuid));
+        let (ready, blocked): (Vec<&Image>, Vec<&Image>) = wave
+            .into_iter()
+            .partition(|i| i.origin.is_none_or(|o| !failed.contains(&o)));
+        for image in blocked {
+            let origin = image.origin.expect("blocked images have an origin");
+            failed.insert(image.uuid);
+            report
+                .failed
+                .push((image.uuid, format!("origin {} could not be copied", origin)));
+        }
+        if opts.dry_run {
+            report.copied.extend(ready.iter().map(|i| i.uuid));
+            continue;
+        }
+        let results = run_bounded(&ready, opts.concurrency, |image| {
+            copy_manifest(src, dst, image, &CopyOptions::default(), None).map_err(|e| e.to_string())
+        });
+        for (image, result) in ready.iter().zip(results) {
+            match result {
+                Ok(_) => report.copied.push(image.uuid),
+                Err(e) => {
+                    failed.insert(image.uuid);
+                    report.failed.push((image.uuid, e));
+                }
+            }
+        }
+    }
+
+    if opts.delete {
+        // Newest first, so incremental images go before their origins.
+        let mut extra: Vec<Image> = dst
+            .list_all(filter, |_, _| ())?
+            .into_iter()
+            .filter(|i| !wanted_uuids.contains(&i.uuid))
+            .collect();
+        extra.sort_by_key(|i| std::cmp::Reverse((i.published_at, i.uuid)));
+        for image in extra {
+            if opts.dry_run {
+                report.deleted.push(image.uuid);
+                continue;
+            }
+            match dst.delete(&image.uuid) {
+                Ok(()) => report.deleted.push(image.uuid),
+                Err(e) => report.failed.push((image.uuid, e.to_string())),
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use serde_json::json;
+
+    use super::*;
+    use crate::testutil::{image_with_file, uuid, Imgapi};
+    use crate::timestamp;
+
+    /// Image `n`, named `name`, published `n` seconds into 2024, with a file of its own.
+    fn add(server: &Imgapi, n: u128, name: &str, origin: Option<u128>) -> Image {
+        let at = Utc.ymd(2024, 1, 1).and_hms(0, 0, 0) + Duration::seconds(n as i64);
+        let file = format!("the file of image {}", n);
+        let image = image_with_file(
+            n,
+            file.as_bytes(),
+            json!({
+                "name": name,
+                "origin": origin.map(uuid),
+                "published_at": timestamp::format(&at),
+            }),
+        );
+        server.add(&image, file.as_bytes());
+        image
+    }
+
+    fn named(name: &str) -> ImageFilter {
+        ImageFilter {
+            name: Some(name.to_string()),
+            ..ImageFilter::default()
+        }
+    }
+
+    fn opts(delete: bool, dry_run: bool) -> MirrorOptions {
+        MirrorOptions {
+            concurrency: 2,
+            delete,
+            dry_run,
+        }
+    }
+
+    #[test]
+    fn copies_missing_images_and_their_ancestors() {
+        let (src, dst) = (Imgapi::start(), Imgapi::start());
+        add(&src, 1, "base", None);
+        add(&src, 2, "app", Some(1));
+        add(&src, 3, "app", None);
+        add(&dst, 3, "app", None);
+
+        let report = mirror(
+            &src.client(),
+            &dst.client(),
+            &named("app"),
+            &opts(false, false),
+        )
+        .expect("mirroring");
+        assert_eq!(report.copied, [uuid(1), uuid(2)]);
+        assert_eq!(report.skipped, [uuid(3)]);
+        assert!(report.is_success(), "{:?}", report.failed);
+        assert_eq!(dst.uuids(), [uuid(1), uuid(2), uuid(3)]);
+        let copy = dst.image(uuid(2)).expect("the copy");
+        assert_eq!(copy.state, crate::ImageState::Active);
+        assert_eq!(
+            copy.files[0].sha1,
+            src.image(uuid(2)).unwrap().files[0].sha1
+        );
+    }
+
+    #[test]
+    fn deletes_only_images_missing_from_the_source() {
+        let (src, dst) = (Imgapi::start(), Imgapi::start());
+        add(&src, 1, "app", None);
+        add(&dst, 1, "app", None);
+        add(&dst, 2, "app", None);
+        add(&dst, 3, "other", None);
+
+        let report = mirror(
+            &src.client(),
+            &dst.client(),
+            &named("app"),
+            &opts(true, false),
+        )
+        .expect("mirroring");
+        assert_eq!(report.skipped, [uuid(1)]);
+        assert_eq!(report.deleted, [uuid(2)]);
+        assert_eq!(dst.uuids(), [uuid(1), uuid(3)]);
+    }
+
+    #[test]
+    fn lists_past_the_first_page() {
+        let (src, dst) = (Imgapi::start(), Imgapi::start());
+        // The destination only has images past the first page of the source's.
+        for n in 1..=1200 {
+            add(&src, n, "app", None);
+            if n > 1000 {
+                add(&dst, n, "app", None);
+            }
+        }
+        add(&dst, 2000, "app", None);
+
+        let report = mirror(
+            &src.client(),
+            &dst.client(),
+            &named("app"),
+            &opts(true, true),
+        )
+        .expect("mirroring");
+        assert_eq!(report.copied.len(), 1000);
+        assert_eq!(report.skipped.len(), 200);
+        assert_eq!(report.deleted, [uuid(2000)]);
+    }
+
+    #[test]
+    fn a_dry_run_changes_nothing() {
+        let (src, dst) = (Imgapi::start(), Imgapi::start());
+        add(&src, 1, "base", None);
+        add(&src, 2, "app", Some(1));
+        add(&dst, 3, "app", None);
+
+        let report = mirror(
+            &src.client(),
+            &dst.client(),
+            &named("app"),
+            &opts(true, true),
+        )
+        .expect("mirroring");
+        assert_eq!(report.copied, [uuid(1), uuid(2)]);
+        assert_eq!(report.deleted, [uuid(3)]);
+        assert_eq!(dst.uuids(), [uuid(3)]);
+        assert!(dst.server.requests().iter().all(|r| r.method == "GET"));
+    }
+
+    #[test]
+    fn an_ancestor_that_fails_stops_its_descendants() {
+        let (src, dst) = (Imgapi::start(), Imgapi::start());
+        add(&src, 1, "base", None);
+        add(&src, 2, "app", Some(1));
+        add(&src, 3, "app", None);
+        src.corrupt(uuid(1));
+
+        let report = mirror(
+            &src.client(),
+            &dst.client(),
+            &named("app"),
+            &opts(false, false),
+        )
+        .expect("mirroring");
+        assert_eq!(report.copied, [uuid(3)]);
+        let failed: Vec<_> = report.failed.iter().map(|(u, _)| *u).collect();
+        assert_eq!(failed, [uuid(1), uuid(2)]);
+        assert!(
+            report.failed[0].1.contains("sha1 mismatch"),
+            "{}",
+            report.failed[0].1
+        );
+        assert_eq!(
+            report.failed[1].1,
+            format!("origin {} could not be copied", uuid(1))
+        );
+        // The base image's incomplete copy was deleted again.
+        assert_eq!(dst.uuids(), [uuid(3)]);
+    }
+
+    #[test]
+    fn images_with_several_files_arent_copied() {
+        let (src, dst) = (Imgapi::start(), Imgapi::start());
+        let mut image = add(&src, 1, "app", None);
+        image.files.push(image.files[0].clone());
+
+        let err = copy_image_with_progress(
+            &src.client(),
+            &dst.client(),
+            &image,
+            &CopyOptions::default(),
+            |_| {},
+        )
+        .expect_err("copying an image with two files");
+        assert!(err.to_string().contains("has 2 files"), "{}", err);
+        assert!(dst.server.requests().is_empty());
+    }
+
+    #[test]
+    fn deleting_needs_every_matching_image() {
+        let (src, dst) = (Imgapi::start(), Imgapi::start());
+        let filter = ImageFilter {
+            limit: Some(10),
+            ..named("app")
+        };
+        assert!(mirror(&src.client(), &dst.client(), &filter, &opts(true, false)).is_err());
+        assert!(src.server.requests().is_empty());
+    }
+}
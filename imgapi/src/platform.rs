@@ -0,0 +1,109 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Whether a [`PlatformConstraint`] is a `min_platform` or a `max_platform` requirement.
+#[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone, Copy)]
+pub enum PlatformBound {
+    Min,
+    Max,
+}
+
+/// A `min_platform` or `max_platform` requirement: a mapping of SDC version (e.g. `7.0`) to a
+/// SmartOS platform timestamp (e.g. `20130122T122401Z`).
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PlatformConstraint(pub BTreeMap<String, String>);
+
+/// Whether `s` looks like a platform timestamp, i.e. `YYYYMMDDTHHMMSSZ`.
+pub fn is_platform_timestamp(s: &str) -> bool {
+    let b = s.as_bytes();
+    b.len() == 16
+        && b[8] == b'T'
+        && b[15] == b'Z'
+        && b[..8].iter().chain(&b[9..15]).all(u8::is_ascii_digit)
+}
+
+/// Compares two dotted SDC versions such as `6.5` and `7.0` numerically.
+fn cmp_sdc_versions(a: &str, b: &str) -> Ordering {
+    let parse = |s: &str| -> Vec<u64> { s.split('.').map(|p| p.parse().unwrap_or(0)).collect() };
+    let (a, b) = (parse(a), parse(b));
+    let len = a.len().max(b.len());
+    (0..len)
+        .map(|i| {
+            let x = a.get(i).copied().unwrap_or(0);
+            let y = b.get(i).copied().unwrap_or(0);
+            x.cmp(&y)
+        })
+        .find(|o| *o != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+impl PlatformConstraint {
+    /// Whether a compute node running `platform` (a platform timestamp) under SDC version
+    /// `sdc_version` satisfies this constraint.
+    ///
+    /// Platform timestamps are compared lexicographically. As documented for IMGAPI:
+    ///
+    /// * For `min_platform`, if the SDC version is listed, the platform must be at least the given
+    ///   timestamp. Newer SDC versions than the closest listed one are allowed; SDC versions older
+    ///   than every listed one are not.
+    /// * For `max_platform`, if the SDC version is listed, the platform must be at most the given
+    ///   timestamp. Older SDC versions than the closest listed one are allowed; SDC versions newer
+    ///   than every listed one are not.
+    ///
+    /// An empty constraint is always satisfied.
+    pub fn satisfied_by(&self, bound: PlatformBound, sdc_version: &str, platform: &str) -> bool {
+        if self.0.is_empty() {
+            return true;
+        }
+        let mut keys: Vec<&String> = self.0.keys().collect();
+        keys.sort_by(|a, b| cmp_sdc_versions(a, b));
+
+        match bound {
+            PlatformBound::Min => {
+                // The newest listed SDC version that isn't newer than the node's.
+                match keys
+                    .iter()
+                    .rev()
+                    .find(|k| cmp_sdc_versions(k, sdc_version) != Ordering::Greater)
+                {
+                    None => false,
+                    Some(k) if cmp_sdc_versions(k, sdc_version) == Ordering::Equal => {
+                        platform >= self.0[*k].as_str()
+                    }
+                    Some(_) => true,
+                }
+            }
+            PlatformBound::Max => {
+                // The oldest listed SDC version that isn't older than the node's.
+                match keys
+                    .iter()
+                    .find(|k| cmp_sdc_versions(k, sdc_version) != Ordering::Less)
+                {
+                    None => false,
+                    Some(k) if cmp_sdc_versions(k, sdc_version) == Ordering::Equal => {
+                        platform <= self.0[*k].as_str()
+                    }
+                    Some(_) => true,
+                }
+            }
+        }
+    }
+
+    /// Returns the entries whose SDC version or platform timestamp is malformed.
+    pub fn invalid_entries(&self) -> Vec<(&str, &str)> {
+        self.0
+            .iter()
+            .filter(|(sdc, plat)| {
+                let sdc_ok = !sdc.is_empty()
+                    && sdc
+                        .split('.')
+                        .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()));
+                !sdc_ok || !is_platform_timestamp(plat)
+            })
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect()
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Runs `f` on every item, with at most `concurrency` running at once, returning the results in
+/// the same order as `items`.
+pub(crate) fn run_bounded<T, R, F>(items: &[T], concurrency: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..items.len()).map(|_| None).collect::<Vec<_>>());
+    thread::scope(|s| {
+        for _ in 0..concurrency.clamp(1, items.len().max(1)) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(item) = items.get(i) else { break };
+                let r = f(item);
+                results.lock().expect("results lock poisoned")[i] = Some(r);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .expect("results lock poisoned")
+        .into_iter()
+        .map(|r| r.expect("every item was processed"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    /// Runs `items` items with `concurrency`, each taking a moment, returning the most that ran at
+    /// once and the results.
+    fn run(items: usize, concurrency: usize) -> (usize, Vec<usize>) {
+        let (running, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let items: Vec<usize> = (0..items).collect();
+        let results = run_bounded(&items, concurrency, |&i| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            running.fetch_sub(1, Ordering::SeqCst);
+            i * 2
+        });
+        (most.into_inner(), results)
+    }
+
+    #[test]
+    fn runs_at_most_concurrency_at_once() {
+        let (most, results) = run(10, 3);
+        assert_eq!(most, 3);
+        assert_eq!(results, (0..10).map(|i| i * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn zero_concurrency_runs_one_at_a_time() {
+        let (most, results) = run(3, 0);
+        assert_eq!(most, 1);
+        assert_eq!(results, [0, 2, 4]);
+    }
+
+    #[test]
+    fn no_items() {
+        let (most, results) = run(0, 4);
+        assert_eq!(most, 0);
+        assert!(results.is_empty());
+    }
+}
@@ -0,0 +1,38 @@
+use serde_json::Value;
+
+use super::Image;
+
+/// Manifest fields the server sets itself, which a CreateImage request must not include.
+const SERVER_MANAGED_FIELDS: &[&str] = &["v", "uuid", "state", "files", "published_at", "icon"];
+
+/// Options for [`Client::publish_from_dir`](super::blocking::Client::publish_from_dir).
+#[derive(Debug, Clone, Default)]
+pub struct PublishOptions {
+    /// Import the manifest as-is with AdminImportImage, keeping its UUID and other server-managed
+    /// fields, instead of creating a new image with CreateImage. Requires admin access.
+    pub admin: bool,
+}
+
+impl Image {
+    /// Returns the manifest as the body of a CreateImage request: without the fields the server
+    /// manages, and without unset fields.
+    pub fn to_create_request(&self) -> serde_json::Result<Value> {
+        let mut value = self.to_json()?;
+        if let Value::Object(map) = &mut value {
+            for field in SERVER_MANAGED_FIELDS {
+                map.remove(*field);
+            }
+        }
+        Ok(value)
+    }
+
+    /// Returns the manifest as the body of an AdminImportImage request: everything but the files,
+    /// which are added separately, and unset fields.
+    pub fn to_import_request(&self) -> serde_json::Result<Value> {
+        let mut value = self.to_json()?;
+        if let Value::Object(map) = &mut value {
+            map.remove("files");
+        }
+        Ok(value)
+    }
+}
@@ -0,0 +1,126 @@
+use std::fmt;
+
+use super::{BootRom, Brand, Image, PlatformBound, Requirements};
+
+/// A prospective VM to check an image's [`Requirements`] against.
+///
+/// Aspects left as `None` are not checked.
+#[derive(Debug, Default, Clone)]
+pub struct ProvisionSpec {
+    /// The VM's RAM, in MiB.
+    pub ram: Option<u32>,
+
+    pub brand: Option<Brand>,
+
+    /// Whether an SSH public key will be provided.
+    pub has_ssh_key: bool,
+
+    /// The SDC version of the compute node, e.g. `7.0`. Only checked along with `platform`.
+    pub sdc_version: Option<String>,
+
+    /// The platform timestamp of the compute node, e.g. `20130122T122401Z`.
+    pub platform: Option<String>,
+
+    pub boot_rom: Option<BootRom>,
+}
+
+/// A requirement that a [`ProvisionSpec`] doesn't meet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequirementViolation {
+    /// The name of the requirement, e.g. `min_ram`.
+    pub requirement: &'static str,
+
+    /// A human-readable explanation.
+    pub reason: String,
+}
+
+impl fmt::Display for RequirementViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.requirement, self.reason)
+    }
+}
+
+impl Requirements {
+    /// Checks whether a VM matching `spec` may be provisioned, returning every requirement it
+    /// violates.
+    pub fn check(&self, spec: &ProvisionSpec) -> Result<(), Vec<RequirementViolation>> {
+        let mut violations = Vec::new();
+        let mut violation = |requirement, reason: String| {
+            violations.push(RequirementViolation {
+                requirement,
+                reason,
+            })
+        };
+
+        if let Some(ram) = spec.ram {
+            if let Some(min) = self.min_ram.filter(|min| ram < *min) {
+                violation(
+                    "min_ram",
+                    format!("{} MiB of RAM is less than the minimum of {} MiB", ram, min),
+                );
+            }
+            if let Some(max) = self.max_ram.filter(|max| ram > *max) {
+                violation(
+                    "max_ram",
+                    format!("{} MiB of RAM is more than the maximum of {} MiB", ram, max),
+                );
+            }
+        }
+
+        if let (Some(required), Some(brand)) = (&self.brand, &spec.brand) {
+            if required != brand {
+                violation(
+                    "brand",
+                    format!("brand {} is required, not {}", required, brand),
+                );
+            }
+        }
+
+        if self.ssh_key == Some(true) && !spec.has_ssh_key {
+            violation("ssh_key", "an SSH public key is required".to_string());
+        }
+
+        if let (Some(sdc), Some(plat)) = (&spec.sdc_version, &spec.platform) {
+            let constraints = [
+                ("min_platform", PlatformBound::Min, &self.min_platform),
+                ("max_platform", PlatformBound::Max, &self.max_platform),
+            ];
+            for (name, bound, constraint) in constraints.iter() {
+                if let Some(c) = constraint {
+                    if !c.satisfied_by(*bound, sdc, plat) {
+                        violation(
+                            name,
+                            format!("platform {} on SDC {} is not supported", plat, sdc),
+                        );
+                    }
+                }
+            }
+        }
+
+        if let (Some(required), Some(boot_rom)) = (&self.boot_rom, spec.boot_rom) {
+            if !required.eq_ignore_ascii_case(&boot_rom.to_string()) {
+                violation(
+                    "boot_rom",
+                    format!("boot ROM {} is required, not {}", required, boot_rom),
+                );
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+impl Image {
+    /// Checks whether a VM matching `spec` may be provisioned from this image. See
+    /// [`Requirements::check`]. Images without requirements can always be provisioned.
+    pub fn can_provision(&self, spec: &ProvisionSpec) -> Result<(), Vec<RequirementViolation>> {
+        match &self.requirements {
+            Some(r) => r.check(spec),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::sync::OnceLock;
+
+use jsonschema::Validator;
+use serde_json::Value;
+
+use super::{Image, ValidationIssue};
+
+/// The IMGAPI v2 image manifest schema.
+const MANIFEST_SCHEMA: &str = include_str!("../schema/manifest.json");
+
+fn validator() -> &'static Validator {
+    static VALIDATOR: OnceLock<Validator> = OnceLock::new();
+    VALIDATOR.get_or_init(|| {
+        let schema: Value =
+            serde_json::from_str(MANIFEST_SCHEMA).expect("embedded manifest schema is valid JSON");
+        jsonschema::validator_for(&schema).expect("embedded manifest schema is a valid schema")
+    })
+}
+
+/// Validates a raw manifest against the IMGAPI image manifest JSON schema.
+///
+/// Every schema violation is returned as an error-level [`ValidationIssue`] whose field is the JSON
+/// pointer to the offending value, e.g. `/files/0/sha1`.
+pub fn validate_schema(manifest: &Value) -> Vec<ValidationIssue> {
+    validator()
+        .iter_errors(manifest)
+        .map(|e| {
+            let path = e.instance_path.to_string();
+            let field = if path.is_empty() { "/" } else { &path };
+            ValidationIssue::error(field, e.to_string())
+        })
+        .collect()
+}
+
+impl Image {
+    /// Validates this manifest against the IMGAPI image manifest JSON schema.
+    ///
+    /// This complements [`Image::validate`], which checks rules that can't be expressed in the
+    /// schema. See [`validate_schema`].
+    pub fn validate_schema(&self) -> Vec<ValidationIssue> {
+        match self.to_json() {
+            Ok(v) => validate_schema(&v),
+            Err(e) => vec![ValidationIssue::error("/", e.to_string())],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::image_with_file;
+    use serde_json::json;
+
+    /// A manifest the schema accepts, as JSON.
+    fn valid() -> Value {
+        serde_json::to_value(image_with_file(1, b"file", json!({}))).unwrap()
+    }
+
+    fn fields(manifest: &Value) -> Vec<String> {
+        validate_schema(manifest)
+            .into_iter()
+            .map(|i| i.field)
+            .collect()
+    }
+
+    #[test]
+    fn accepts_a_valid_manifest() {
+        assert_eq!(fields(&valid()), Vec::<String>::new());
+        assert_eq!(image_with_file(1, b"file", json!({})).validate_schema(), []);
+    }
+
+    /// Sets the value at the JSON pointer `pointer`, adding it if it's missing.
+    fn set(manifest: &mut Value, pointer: &str, value: Value) {
+        let (parent, key) = pointer.rsplit_once('/').expect("a JSON pointer");
+        manifest.pointer_mut(parent).expect("an existing parent")[key] = value;
+    }
+
+    #[test]
+    fn points_at_each_invalid_value() {
+        let cases = [
+            ("/uuid", json!("not a uuid")),
+            ("/state", json!("gone")),
+            ("/public", json!("yes")),
+            ("/files/0/sha1", json!("abc")),
+            ("/files/0/size", json!(-1)),
+            ("/files/0/size", json!(20 * 1024 * 1024 * 1024u64 + 1)),
+            ("/files/0/compression", json!("zip")),
+            ("/files/0/digest", json!("md5:0")),
+        ];
+        for (pointer, value) in &cases {
+            let mut manifest = valid();
+            set(&mut manifest, pointer, value.clone());
+            assert_eq!(fields(&manifest), [*pointer], "{} = {}", pointer, value);
+        }
+    }
+
+    #[test]
+    fn reports_a_missing_field_at_its_parent() {
+        let mut manifest = valid();
+        manifest.as_object_mut().unwrap().remove("owner");
+        assert_eq!(fields(&manifest), ["/"]);
+
+        let mut manifest = valid();
+        manifest["files"][0].as_object_mut().unwrap().remove("sha1");
+        assert_eq!(fields(&manifest), ["/files/0"]);
+    }
+
+    #[test]
+    fn reports_every_violation() {
+        let mut manifest = valid();
+        manifest["state"] = json!("gone");
+        manifest["files"][0]["sha1"] = json!("abc");
+        let mut found = fields(&manifest);
+        found.sort();
+        assert_eq!(found, ["/files/0/sha1", "/state"]);
+    }
+}
@@ -0,0 +1,46 @@
+/// Formats a byte count the way imgadm does, e.g. `245.1M` or `1.9G`.
+///
+/// Counts below 1 KiB are printed exactly with a `B` suffix. Larger counts are scaled to the
+/// largest binary unit that keeps the value below 1024 and printed with one decimal place.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    // Compare against the rounded value so that e.g. 1023.96M is shown as 1.0G, not 1024.0M.
+    while (value * 10.0).round() / 10.0 >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_sizes_like_imgadm() {
+        const KIB: u64 = 1024;
+        const MIB: u64 = 1024 * KIB;
+        let cases = [
+            (0, "0B"),
+            (1023, "1023B"),
+            (KIB, "1.0K"),
+            (KIB + KIB / 2, "1.5K"),
+            (MIB + MIB / 2, "1.5M"),
+            (MIB - 1, "1.0M"),
+            (1024 * MIB - MIB / 100, "1.0G"),
+            (1024 * MIB - MIB, "1023.0M"),
+            (20 * 1024 * MIB, "20.0G"),
+            (u64::MAX, "16.0E"),
+        ];
+        for (bytes, expected) in &cases {
+            assert_eq!(format_size(*bytes), *expected, "{} bytes", bytes);
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::cmp::Reverse;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::Utc;
+
+use super::version::VersionKey;
+use super::{DateTime, Image, ImageState};
+
+/// The field to sort images by with [`sort_images`].
+#[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone, Copy)]
+pub enum SortKey {
+    /// Sort by [`Image::published_at`]. Unpublished images always sort last.
+    PublishedAt,
+
+    /// Sort by name, then by version as for [`SortKey::Version`].
+    NameVersion,
+
+    /// Sort by version alone. Versions in the same scheme compare as [`cmp_version_strings`]
+    /// compares them, and otherwise semver versions sort before dates, and dates before versions
+    /// in no scheme, which compare as plain strings.
+    ///
+    /// [`cmp_version_strings`]: crate::cmp_version_strings
+    Version,
+
+    /// Sort by the total size of the image's files.
+    Size,
+
+    /// Sort by [`Image::state`].
+    State,
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::PublishedAt => "published_at",
+            Self::NameVersion => "name",
+            Self::Version => "version",
+            Self::Size => "size",
+            Self::State => "state",
+        }
+        .fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParseSortKeyError {}
+
+impl fmt::Display for ParseSortKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid sort key")
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = ParseSortKeyError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "published_at" | "published" => Ok(Self::PublishedAt),
+            "name" => Ok(Self::NameVersion),
+            "version" => Ok(Self::Version),
+            "size" => Ok(Self::Size),
+            "state" => Ok(Self::State),
+            _ => Err(ParseSortKeyError {}),
+        }
+    }
+}
+
+/// The direction to sort in.
+#[derive(Debug, Default, Ord, PartialOrd, Eq, PartialEq, Clone, Copy)]
+pub enum SortOrder {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+/// An image's value for one [`SortKey`], computed once per image so that images are compared
+/// consistently however often they're compared.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Field {
+    PublishedAt(Option<DateTime<Utc>>),
+    NameVersion(String, VersionKey),
+    Version(VersionKey),
+    Size(u64),
+    State(ImageState),
+}
+
+impl Field {
+    fn new(image: &Image, key: SortKey) -> Self {
+        match key {
+            SortKey::PublishedAt => Self::PublishedAt(image.published_at),
+            SortKey::NameVersion => {
+                Self::NameVersion(image.name.clone(), VersionKey::sort_key(&image.version))
+            }
+            SortKey::Version => Self::Version(VersionKey::sort_key(&image.version)),
+            SortKey::Size => Self::Size(image.total_file_size()),
+            SortKey::State => Self::State(image.state),
+        }
+    }
+}
+
+/// A [`Field`] in the order it's sorted in.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Directed {
+    Ascending(Field),
+    Descending(Reverse<Field>),
+}
+
+/// The sort key of an image for `key` in `order`: whether it's unpublished, when sorting by
+/// [`SortKey::PublishedAt`], so that unpublished images sort last in either order, then its field.
+fn sort_key(image: &Image, key: SortKey, order: SortOrder) -> (bool, Directed) {
+    let unpublished = key == SortKey::PublishedAt && image.published_at.is_none();
+    let field = Field::new(image, key);
+    let directed = match order {
+        SortOrder::Ascending => Directed::Ascending(field),
+        SortOrder::Descending => Directed::Descending(Reverse(field)),
+    };
+    (unpublished, directed)
+}
+
+/// Sorts images in place by `key`.
+///
+/// The sort is stable, and images that compare equal on `key` are ordered by uuid (always
+/// ascending), so the result does not depend on the input order. When sorting by
+/// [`SortKey::PublishedAt`], images that were never published sort last in either order.
+pub fn sort_images(images: &mut [Image], key: SortKey, order: SortOrder) {
+    images.sort_by_cached_key(|i| (sort_key(i, key, order), i.uuid));
+}
+
+/// Sorts images in place by several keys: by the first, then by the second among images that
+/// compare equal on the first, and so on.
+///
+/// Unlike [`sort_images`], there's no tie-breaking by uuid: images that compare equal on every
+/// key keep their order, e.g. the server's.
+pub fn sort_images_by(images: &mut [Image], keys: &[(SortKey, SortOrder)]) {
+    images.sort_by_cached_key(|i| -> Vec<_> {
+        keys.iter()
+            .map(|&(key, order)| sort_key(i, key, order))
+            .collect()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testutil::image;
+
+    fn uuids(images: &[Image]) -> Vec<u128> {
+        images.iter().map(|i| i.uuid.as_u128()).collect()
+    }
+
+    fn published(n: u128, at: Option<&str>) -> Image {
+        image(n, json!({ "published_at": at }))
+    }
+
+    #[test]
+    fn ties_are_broken_by_uuid_whatever_the_input_order() {
+        let mut images: Vec<_> = [3, 1, 4, 2]
+            .iter()
+            .map(|&n| image(n, json!({ "version": "1.0.0" })))
+            .collect();
+        sort_images(&mut images, SortKey::Version, SortOrder::Descending);
+        assert_eq!(uuids(&images), [1, 2, 3, 4]);
+        images.reverse();
+        sort_images(&mut images, SortKey::Version, SortOrder::Ascending);
+        assert_eq!(uuids(&images), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn unpublished_images_sort_last_in_either_order() {
+        let mut images = vec![
+            published(1, None),
+            published(2, Some("2024-03-01T00:00:00Z")),
+            published(3, None),
+            published(4, Some("2024-01-01T00:00:00Z")),
+        ];
+        sort_images(&mut images, SortKey::PublishedAt, SortOrder::Ascending);
+        assert_eq!(uuids(&images), [4, 2, 1, 3]);
+        sort_images(&mut images, SortKey::PublishedAt, SortOrder::Descending);
+        assert_eq!(uuids(&images), [2, 4, 1, 3]);
+    }
+
+    #[test]
+    fn name_sorts_by_version_within_a_name() {
+        let mut images = vec![
+            image(1, json!({ "name": "b", "version": "1.10.0" })),
+            image(2, json!({ "name": "a", "version": "2.0.0" })),
+            image(3, json!({ "name": "b", "version": "1.9.0" })),
+            image(4, json!({ "name": "a", "version": "1.0.0" })),
+        ];
+        sort_images(&mut images, SortKey::NameVersion, SortOrder::Ascending);
+        assert_eq!(uuids(&images), [4, 2, 3, 1]);
+    }
+
+    #[test]
+    fn several_keys_keep_the_input_order_on_ties() {
+        let mut images = vec![
+            image(4, json!({ "state": "active", "version": "1.0.0" })),
+            image(1, json!({ "state": "disabled", "version": "1.0.0" })),
+            image(3, json!({ "state": "active", "version": "2.0.0" })),
+            image(2, json!({ "state": "active", "version": "1.0.0" })),
+        ];
+        let keys = [
+            (SortKey::State, SortOrder::Ascending),
+            (SortKey::Version, SortOrder::Descending),
+        ];
+        sort_images_by(&mut images, &keys);
+        assert_eq!(uuids(&images), [3, 4, 2, 1]);
+    }
+
+    #[test]
+    fn mixed_version_schemes_sort_in_a_total_order() {
+        // Compared by scheme where they can be and as strings where they can't, 9.0 < 10.0 <
+        // 2024-01-01 < 9.0, which a sort can't put in order, and may panic over.
+        let versions = [
+            "9.0",
+            "10.0",
+            "2024-01-01",
+            "20240215",
+            "2024-02-15T10:00:00Z",
+            "1.0.0-rc1",
+            "1.0.0",
+            "latest",
+            "beta",
+            "",
+        ];
+        let images: Vec<_> = (0..60u128)
+            .map(|n| (n * 37) % 60)
+            .map(|n| {
+                image(
+                    n,
+                    json!({ "version": versions[n as usize % versions.len()] }),
+                )
+            })
+            .collect();
+        for order in [SortOrder::Ascending, SortOrder::Descending] {
+            let mut sorted = images.clone();
+            sort_images(&mut sorted, SortKey::Version, order);
+            // Every pair sorts on its own as it does among the others, which can't be if the
+            // order goes round in a circle.
+            for (i, a) in sorted.iter().enumerate() {
+                for b in &sorted[i + 1..] {
+                    let mut pair = vec![b.clone(), a.clone()];
+                    sort_images(&mut pair, SortKey::Version, order);
+                    assert_eq!(pair[0].uuid, a.uuid, "{:?} and {:?}", a.version, b.version);
+                }
+            }
+
+            let mut seen: Vec<_> = sorted.iter().map(|i| i.version.as_str()).collect();
+            seen.dedup();
+            let mut expected = [
+                "1.0.0-rc1",
+                "1.0.0",
+                "9.0",
+                "10.0",
+                "2024-01-01",
+                "20240215",
+                "2024-02-15T10:00:00Z",
+                "",
+                "beta",
+                "latest",
+            ];
+            if order == SortOrder::Descending {
+                expected.reverse();
+            }
+            assert_eq!(seen, expected);
+        }
+    }
+}
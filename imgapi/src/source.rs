@@ -0,0 +1,191 @@
+use std::error::Error;
+use std::fs;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use url::Url;
+
+use super::blocking::Client;
+use super::download::{self, Prefix};
+use super::{
+    ApiError, DownloadOptions, DownloadReport, Image, ImageFilter, Marker, Progress, Uuid,
+};
+
+/// Somewhere images can be listed and downloaded from: an IMGAPI server ([`Client`]) or a local
+/// directory ([`LocalSource`]).
+pub trait ImageSource {
+    /// List images matching `filter`, sorted by publish date.
+    fn list(&self, filter: Option<&ImageFilter>) -> Result<Vec<Image>, Box<dyn Error>>;
+
+    /// Get a single image.
+    fn get(&self, uuid: &Uuid) -> Result<Image, Box<dyn Error>>;
+
+    /// Write file `index` of an image to `writer`, verifying it against the manifest. See
+    /// [`Client::download_file_to`].
+    fn download_file_to(
+        &self,
+        uuid: &Uuid,
+        index: usize,
+        writer: &mut dyn Write,
+        opts: &DownloadOptions,
+        progress: &mut dyn FnMut(Progress),
+    ) -> Result<DownloadReport, Box<dyn Error>>;
+}
+
+impl ImageSource for Client {
+    fn list(&self, filter: Option<&ImageFilter>) -> Result<Vec<Image>, Box<dyn Error>> {
+        Client::list(self, filter)
+    }
+
+    fn get(&self, uuid: &Uuid) -> Result<Image, Box<dyn Error>> {
+        Client::get(self, uuid)
+    }
+
+    fn download_file_to(
+        &self,
+        uuid: &Uuid,
+        index: usize,
+        writer: &mut dyn Write,
+        opts: &DownloadOptions,
+        progress: &mut dyn FnMut(Progress),
+    ) -> Result<DownloadReport, Box<dyn Error>> {
+        Client::download_file_to(self, uuid, index, writer, opts, progress)
+    }
+}
+
+/// A directory of images, laid out as [`Client::export_to_dir`] and `imgadm` write them: a
+/// `<uuid>.imgmanifest` manifest next to a `<uuid>.zfs` file, with an extension for its
+/// compression (e.g. `<uuid>.zfs.gz`). Files named `<uuid>.file`, as written by
+/// [`Client::download_ancestry`], are found too.
+///
+/// Filters are evaluated with [`ImageFilter::matches`], and markers and limits are applied as the
+/// server would. Missing images are reported as an [`ApiError`] with a 404 status, also like a
+/// server.
+#[derive(Debug, Clone)]
+pub struct LocalSource {
+    dir: PathBuf,
+}
+
+impl LocalSource {
+    /// Creates a source for the images in `dir`.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The directory the images are in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn manifest_path(&self, uuid: &Uuid) -> PathBuf {
+        self.dir.join(format!("{}.imgmanifest", uuid))
+    }
+
+    /// Returns the path of the image's file, if it's there.
+    fn file_path(&self, image: &Image, index: usize) -> Result<PathBuf, Box<dyn Error>> {
+        let file = image
+            .files
+            .get(index)
+            .ok_or_else(|| format!("image {} has no file {}", image.uuid, index))?;
+        if index > 0 {
+            return Err("local sources only hold the first file of each image".into());
+        }
+        let zfs = match file.compression.extension() {
+            Some(ext) => format!("{}.zfs.{}", image.uuid, ext),
+            None => format!("{}.zfs", image.uuid),
+        };
+        [zfs, format!("{}.file", image.uuid)]
+            .iter()
+            .map(|name| self.dir.join(name))
+            .find(|path| path.is_file())
+            .ok_or_else(|| {
+                format!("{}: no file for image {}", self.dir.display(), image.uuid).into()
+            })
+    }
+}
+
+fn not_found(uuid: &Uuid) -> ApiError {
+    ApiError {
+        status: 404,
+        code: "ResourceNotFound".to_string(),
+        message: format!("image {} not found", uuid),
+        ..ApiError::default()
+    }
+}
+
+impl ImageSource for LocalSource {
+    fn list(&self, filter: Option<&ImageFilter>) -> Result<Vec<Image>, Box<dyn Error>> {
+        let default = ImageFilter::default();
+        let filter = filter.unwrap_or(&default);
+        let entries =
+            fs::read_dir(&self.dir).map_err(|e| format!("{}: {}", self.dir.display(), e))?;
+        let mut images = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "imgmanifest") {
+                let image = Image::from_path(&path)?;
+                if filter.matches(&image) {
+                    images.push(image);
+                }
+            }
+        }
+        images.sort_by_key(|i| (i.published_at, i.uuid));
+        if let Some(marker) = filter.marker {
+            let since = match marker {
+                Marker::Image(uuid) => self.get(&uuid)?.published_at,
+                Marker::PublishedAt(t) => Some(t),
+            };
+            images.retain(|i| i.published_at >= since);
+        }
+        if let Some(limit) = filter.limit {
+            images.truncate(limit as usize);
+        }
+        Ok(images)
+    }
+
+    fn get(&self, uuid: &Uuid) -> Result<Image, Box<dyn Error>> {
+        let path = self.manifest_path(uuid);
+        if !path.is_file() {
+            return Err(not_found(uuid).into());
+        }
+        Image::from_path(path)
+    }
+
+    fn download_file_to(
+        &self,
+        uuid: &Uuid,
+        index: usize,
+        mut writer: &mut dyn Write,
+        opts: &DownloadOptions,
+        progress: &mut dyn FnMut(Progress),
+    ) -> Result<DownloadReport, Box<dyn Error>> {
+        let image = self.get(uuid)?;
+        let path = self.file_path(&image, index)?;
+        let file = &image.files[index];
+        let reader = fs::File::open(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        download::transfer(
+            BufReader::new(reader),
+            &mut writer,
+            file,
+            None,
+            opts,
+            Prefix::new(file),
+            progress,
+        )
+    }
+}
+
+/// Opens the image source at `url`: a [`LocalSource`] for `file://` URLs, and a [`Client`] for
+/// anything else.
+pub fn open_source(url: &str) -> Result<Box<dyn ImageSource>, Box<dyn Error>> {
+    let parsed = Url::parse(url)?;
+    if parsed.scheme() == "file" {
+        let dir = parsed
+            .to_file_path()
+            .map_err(|_| format!("{}: not a local path", url))?;
+        return Ok(Box::new(LocalSource::new(dir)));
+    }
+    Ok(Box::new(Client::new(url)?))
+}
@@ -0,0 +1,119 @@
+use std::fmt;
+
+use super::{DateTime, Image, ImageState, Utc, Uuid};
+use crate::size::format_size;
+
+/// The columns commonly shown for an image in listings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageSummary {
+    pub uuid: Uuid,
+
+    /// The first eight characters of the uuid.
+    pub short_uuid: String,
+
+    pub name: String,
+    pub version: String,
+    pub os: String,
+    pub image_type: String,
+    pub state: ImageState,
+    pub published_at: Option<DateTime<Utc>>,
+
+    /// The sum of the sizes of all image files, in bytes.
+    pub size: u64,
+}
+
+impl ImageSummary {
+    /// The publish date as `YYYY-MM-DD`, or `-` if the image has never been published.
+    pub fn published_date(&self) -> String {
+        match self.published_at {
+            Some(p) => p.format("%Y-%m-%d").to_string(),
+            None => "-".to_string(),
+        }
+    }
+
+    /// The total file size, human-formatted.
+    pub fn human_size(&self) -> String {
+        format_size(self.size)
+    }
+}
+
+impl Image {
+    /// Returns the commonly shown columns for this image.
+    pub fn summary(&self) -> ImageSummary {
+        let uuid = self.uuid.to_string();
+        ImageSummary {
+            uuid: self.uuid,
+            short_uuid: uuid[..8].to_string(),
+            name: self.name.clone(),
+            version: self.version.clone(),
+            os: self.os.clone(),
+            image_type: self.image_type.clone(),
+            state: self.state,
+            published_at: self.published_at,
+            size: self.total_file_size(),
+        }
+    }
+}
+
+/// A stable one-line summary of the image, e.g.:
+///
+/// ```text
+/// base-64-lts@21.4.0 (1d05e788-5409-11eb-b12f-037bd7fee4ee) smartos zone-dataset active 2021-01-11 113.1M
+/// ```
+impl fmt::Display for Image {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = self.summary();
+        write!(
+            f,
+            "{}@{} ({}) {} {} {} {} {}",
+            s.name,
+            s.version,
+            s.uuid,
+            s.os,
+            s.image_type,
+            s.state,
+            s.published_date(),
+            s.human_size()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testutil::image;
+    use serde_json::json;
+
+    #[test]
+    fn summarizes_an_image_on_one_line() {
+        let image = image(
+            1,
+            json!({
+                "uuid": "1d05e788-5409-11eb-b12f-037bd7fee4ee",
+                "name": "base-64-lts",
+                "version": "21.4.0",
+                "published_at": "2021-01-11T12:34:56Z",
+                "files": [{"sha1": "", "size": 118_594_560, "compression": "gzip"}],
+            }),
+        );
+        assert_eq!(
+            image.to_string(),
+            "base-64-lts@21.4.0 (1d05e788-5409-11eb-b12f-037bd7fee4ee) smartos zone-dataset \
+             active 2021-01-11 113.1M"
+        );
+        let summary = image.summary();
+        assert_eq!(summary.short_uuid, "1d05e788");
+        assert_eq!(summary.size, 118_594_560);
+    }
+
+    #[test]
+    fn an_unpublished_image_has_no_date() {
+        let mut image = image(1, json!({"state": "unactivated"}));
+        image.published_at = None;
+        assert_eq!(image.summary().published_date(), "-");
+        assert!(
+            image.to_string().ends_with(" unactivated - 0B"),
+            "{}",
+            image
+        );
+    }
+}
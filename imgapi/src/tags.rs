@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+
+use serde_json::{Number, Value};
+
+use super::Image;
+
+/// A value that can be stored in an image's [`Image::tags`].
+///
+/// Tags are arbitrary JSON on the wire, but in practice they are strings, booleans, or numbers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagValue {
+    Bool(bool),
+    Number(Number),
+    String(String),
+}
+
+impl From<bool> for TagValue {
+    fn from(v: bool) -> Self {
+        Self::Bool(v)
+    }
+}
+
+impl From<&str> for TagValue {
+    fn from(v: &str) -> Self {
+        Self::String(v.to_string())
+    }
+}
+
+impl From<String> for TagValue {
+    fn from(v: String) -> Self {
+        Self::String(v)
+    }
+}
+
+impl From<i64> for TagValue {
+    fn from(v: i64) -> Self {
+        Self::Number(v.into())
+    }
+}
+
+impl From<u64> for TagValue {
+    fn from(v: u64) -> Self {
+        Self::Number(v.into())
+    }
+}
+
+impl TagValue {
+    /// Interprets `s` as it would most likely be meant on a command line: `true` and `false` as
+    /// booleans, anything JSON would take as a number as one, and everything else as a string.
+    pub fn infer(s: &str) -> Self {
+        match s {
+            "true" => Self::Bool(true),
+            "false" => Self::Bool(false),
+            _ => match s.parse::<Number>() {
+                Ok(n) => Self::Number(n),
+                Err(_) => Self::String(s.to_string()),
+            },
+        }
+    }
+}
+
+impl From<TagValue> for Value {
+    fn from(v: TagValue) -> Self {
+        match v {
+            TagValue::Bool(b) => Value::Bool(b),
+            TagValue::Number(n) => Value::Number(n),
+            TagValue::String(s) => Value::String(s),
+        }
+    }
+}
+
+impl Image {
+    /// Returns the raw value of the tag `key`, if present.
+    pub fn tag(&self, key: &str) -> Option<&Value> {
+        self.tags.as_ref()?.get(key)
+    }
+
+    /// Returns the value of the tag `key` if it is present and is a string.
+    pub fn tag_str(&self, key: &str) -> Option<&str> {
+        self.tag(key)?.as_str()
+    }
+
+    /// Returns the value of the tag `key` if it is present and is a boolean.
+    pub fn tag_bool(&self, key: &str) -> Option<bool> {
+        self.tag(key)?.as_bool()
+    }
+
+    /// Returns the value of the tag `key` if it is present and is a number.
+    pub fn tag_num(&self, key: &str) -> Option<f64> {
+        self.tag(key)?.as_f64()
+    }
+
+    /// Indicates whether the image has a tag named `key`, regardless of its value.
+    pub fn has_tag(&self, key: &str) -> bool {
+        self.tag(key).is_some()
+    }
+
+    /// Returns all tags whose key starts with `prefix`, e.g. `docker:`.
+    pub fn tags_matching<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a Value)> {
+        self.tags
+            .iter()
+            .flat_map(|tags| tags.iter())
+            .filter(move |(k, _)| k.starts_with(prefix))
+            .map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Sets the tag `key` to `value`, returning the previous value if there was one.
+    ///
+    /// The [`Image::tags`] map is created if the image did not have any tags.
+    pub fn set_tag<V: Into<TagValue>>(&mut self, key: &str, value: V) -> Option<Value> {
+        self.tags
+            .get_or_insert_with(BTreeMap::new)
+            .insert(key.to_string(), value.into().into())
+    }
+
+    /// Removes the tag `key`, returning its value if it was present.
+    ///
+    /// If this removes the last tag, [`Image::tags`] is reset to `None`, as if the image never had
+    /// any tags.
+    pub fn remove_tag(&mut self, key: &str) -> Option<Value> {
+        let tags = self.tags.as_mut()?;
+        let removed = tags.remove(key);
+        if tags.is_empty() {
+            self.tags = None;
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testutil::image;
+
+    fn tagged() -> Image {
+        image(
+            1,
+            json!({
+                "tags": {
+                    "role": "db",
+                    "smartdc_service": true,
+                    "weight": 2.5,
+                    "docker:repo": "busybox",
+                    "docker:tag:latest": true,
+                },
+            }),
+        )
+    }
+
+    #[test]
+    fn reads_tags_by_type() {
+        let image = tagged();
+        assert_eq!(image.tag_str("role"), Some("db"));
+        assert_eq!(image.tag_bool("smartdc_service"), Some(true));
+        assert_eq!(image.tag_num("weight"), Some(2.5));
+        assert_eq!(image.tag("role"), Some(&json!("db")));
+        assert!(image.has_tag("weight"));
+    }
+
+    #[test]
+    fn tags_of_another_type_read_as_missing() {
+        let image = tagged();
+        assert_eq!(image.tag_str("smartdc_service"), None);
+        assert_eq!(image.tag_bool("role"), None);
+        assert_eq!(image.tag_num("role"), None);
+    }
+
+    #[test]
+    fn missing_tags() {
+        let tagged = tagged();
+        assert_eq!(tagged.tag("missing"), None);
+        assert_eq!(tagged.tag_str("missing"), None);
+        assert!(!tagged.has_tag("missing"));
+
+        let untagged = image(2, json!({}));
+        assert_eq!(untagged.tag("role"), None);
+        assert!(!untagged.has_tag("role"));
+        assert_eq!(untagged.tags_matching("").count(), 0);
+    }
+
+    #[test]
+    fn tags_matching_a_prefix() {
+        let image = tagged();
+        let docker: Vec<_> = image.tags_matching("docker:").collect();
+        assert_eq!(
+            docker,
+            [
+                ("docker:repo", &json!("busybox")),
+                ("docker:tag:latest", &json!(true)),
+            ]
+        );
+        assert_eq!(image.tags_matching("").count(), 5);
+        assert_eq!(image.tags_matching("nope").count(), 0);
+    }
+
+    #[test]
+    fn set_and_remove_round_trip() {
+        let mut image = image(1, json!({}));
+        assert_eq!(image.tags, None);
+
+        assert_eq!(image.set_tag("role", "db"), None);
+        assert_eq!(image.set_tag("count", 3u64), None);
+        assert_eq!(image.set_tag("on", true), None);
+        assert_eq!(image.tag_str("role"), Some("db"));
+        assert_eq!(image.tag_num("count"), Some(3.0));
+        assert_eq!(image.tag_bool("on"), Some(true));
+
+        assert_eq!(image.set_tag("role", "web"), Some(json!("db")));
+        assert_eq!(image.tag_str("role"), Some("web"));
+
+        assert_eq!(image.remove_tag("role"), Some(json!("web")));
+        assert_eq!(image.remove_tag("role"), None);
+        assert_eq!(image.remove_tag("count"), Some(json!(3)));
+        assert_eq!(image.remove_tag("on"), Some(json!(true)));
+        // Removing the last tag leaves the image as if it never had any.
+        assert_eq!(image.tags, None);
+        assert_eq!(image.to_json().unwrap().get("tags"), None);
+    }
+
+    #[test]
+    fn infers_values_from_strings() {
+        assert_eq!(TagValue::infer("true"), TagValue::Bool(true));
+        assert_eq!(TagValue::infer("false"), TagValue::Bool(false));
+        assert_eq!(TagValue::infer("12"), TagValue::from(12u64));
+        assert_eq!(
+            TagValue::infer("-1.5"),
+            TagValue::Number(Number::from_f64(-1.5).unwrap())
+        );
+        assert_eq!(TagValue::infer("True"), TagValue::from("True"));
+        assert_eq!(TagValue::infer("1.2.3"), TagValue::from("1.2.3"));
+    }
+}
@@ -0,0 +1,421 @@
+//! Helpers for the unit tests: test manifests, and local HTTP servers to point clients at.
+
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_json::{json, Value};
+use url::form_urlencoded;
+
+use super::blocking::Client;
+use super::{file_digest, Image, Uuid};
+
+/// The uuid numbered `n`, e.g. `00000000-0000-0000-0000-000000000003`.
+pub fn uuid(n: u128) -> Uuid {
+    Uuid::from_u128(n)
+}
+
+/// A minimal active manifest of image `n`, with `fields` merged into it.
+pub fn image(n: u128, fields: Value) -> Image {
+    let mut manifest = json!({
+        "v": 2,
+        "uuid": uuid(n),
+        "owner": uuid(0),
+        "name": "base",
+        "version": "1.0.0",
+        "state": "active",
+        "disabled": false,
+        "public": true,
+        "published_at": "2024-01-01T00:00:00Z",
+        "type": "zone-dataset",
+        "os": "smartos",
+        "files": [{"sha1": "", "size": 0, "compression": "gzip"}],
+    });
+    if let Value::Object(fields) = fields {
+        manifest
+            .as_object_mut()
+            .expect("a manifest is an object")
+            .extend(fields);
+    }
+    serde_json::from_value(manifest).expect("a valid manifest")
+}
+
+/// Image `n` with `file` as its only file, uncompressed, and `fields` merged into its manifest.
+pub fn image_with_file(n: u128, file: &[u8], fields: Value) -> Image {
+    let digest = file_digest(file).expect("reading from memory");
+    let mut image = image(n, fields);
+    image.files[0].sha1 = digest.sha1;
+    image.files[0].size = digest.size;
+    image.files[0].compression = super::Compression::None;
+    image
+}
+
+/// Every ordering of `items`.
+pub fn permutations<T: Clone>(items: &[T]) -> Vec<Vec<T>> {
+    if items.len() <= 1 {
+        return vec![items.to_vec()];
+    }
+    let mut all = Vec::new();
+    for i in 0..items.len() {
+        let mut rest = items.to_vec();
+        let first = rest.remove(i);
+        for mut p in permutations(&rest) {
+            p.insert(0, first.clone());
+            all.push(p);
+        }
+    }
+    all
+}
+
+/// A request received by a [`Server`].
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+
+    /// The path and query, e.g. `/images?limit=1000`.
+    pub target: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// The path, without the query.
+    pub fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+
+    /// The value of query parameter `name`.
+    pub fn query(&self, name: &str) -> Option<String> {
+        let (_, query) = self.target.split_once('?')?;
+        form_urlencoded::parse(query.as_bytes())
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.into_owned())
+    }
+
+    /// The value of header `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// A response for a [`Server`] to send. A `Content-Length` is added unless it has one, so a
+/// response can claim more bytes than it sends, as an interrupted transfer would.
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    pub fn json(status: u16, value: &Value) -> Self {
+        Self::new(status, value.to_string()).with_header("Content-Type", "application/json")
+    }
+
+    /// An IMGAPI error response.
+    pub fn error(status: u16, code: &str, message: &str) -> Self {
+        Self::json(status, &json!({ "code": code, "message": message }))
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// A local HTTP server, answering each request with a handler on a thread of its own, and
+/// recording the requests. Connections are closed after each response.
+pub struct Server {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl Server {
+    pub fn start<H>(handler: H) -> Self
+    where
+        H: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("binding a local port");
+        let url = format!(
+            "http://{}/",
+            listener.local_addr().expect("a local address")
+        );
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler = Arc::new(handler);
+        let recorded = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (handler, recorded) = (Arc::clone(&handler), Arc::clone(&recorded));
+                thread::spawn(move || {
+                    let _ = serve(stream, &*handler, &recorded);
+                });
+            }
+        });
+        Self { url, requests }
+    }
+
+    pub fn client(&self) -> Client {
+        Client::new(&self.url).expect("a valid URL")
+    }
+
+    /// The requests received so far, in the order they arrived.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests
+            .lock()
+            .expect("requests lock poisoned")
+            .clone()
+    }
+}
+
+fn serve(
+    stream: TcpStream,
+    handler: &dyn Fn(&Request) -> Response,
+    recorded: &Mutex<Vec<Request>>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Ok(()),
+    };
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        match line.trim_end().split_once(':') {
+            Some((name, value)) => headers.push((name.to_string(), value.trim().to_string())),
+            None => break,
+        }
+    }
+    let mut req = Request {
+        method,
+        target,
+        headers,
+        body: Vec::new(),
+    };
+    if let Some(len) = req.header("content-length").and_then(|l| l.parse().ok()) {
+        req.body.resize(len, 0);
+        reader.read_exact(&mut req.body)?;
+    } else if req.header("transfer-encoding") == Some("chunked") {
+        loop {
+            line.clear();
+            reader.read_line(&mut line)?;
+            let len = usize::from_str_radix(line.trim(), 16).unwrap_or_default();
+            let mut chunk = vec![0; len + 2];
+            reader.read_exact(&mut chunk)?;
+            if len == 0 {
+                break;
+            }
+            req.body.extend_from_slice(&chunk[..len]);
+        }
+    }
+
+    let resp = handler(&req);
+    recorded.lock().expect("requests lock poisoned").push(req);
+    let mut out = io::BufWriter::new(stream);
+    write!(
+        out,
+        "HTTP/1.1 {} Status\r\nConnection: close\r\n",
+        resp.status
+    )?;
+    if !resp
+        .headers
+        .iter()
+        .any(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+    {
+        write!(out, "Content-Length: {}\r\n", resp.body.len())?;
+    }
+    for (name, value) in &resp.headers {
+        write!(out, "{}: {}\r\n", name, value)?;
+    }
+    out.write_all(b"\r\n")?;
+    out.write_all(&resp.body)?;
+    out.flush()
+}
+
+/// An image held by an [`Imgapi`].
+struct Stored {
+    manifest: Value,
+    file: Option<Vec<u8>>,
+}
+
+/// An in-memory IMGAPI server, with enough of the API for listing and getting images, importing,
+/// uploading, activating and deleting them, and downloading their files.
+pub struct Imgapi {
+    pub server: Server,
+    images: Arc<Mutex<BTreeMap<Uuid, Stored>>>,
+    corrupt: Arc<Mutex<HashSet<Uuid>>>,
+}
+
+impl Imgapi {
+    pub fn start() -> Self {
+        let images = Arc::new(Mutex::new(BTreeMap::new()));
+        let corrupt = Arc::new(Mutex::new(HashSet::new()));
+        let (i, c) = (Arc::clone(&images), Arc::clone(&corrupt));
+        let server = Server::start(move |req| {
+            let mut images = i.lock().expect("images lock poisoned");
+            handle(&mut images, &c.lock().expect("corrupt lock poisoned"), req)
+        });
+        Self {
+            server,
+            images,
+            corrupt,
+        }
+    }
+
+    pub fn client(&self) -> Client {
+        self.server.client()
+    }
+
+    /// Adds `image` with `file` as its file, which should match the manifest's.
+    pub fn add(&self, image: &Image, file: &[u8]) {
+        let stored = Stored {
+            manifest: serde_json::to_value(image).expect("serializing a manifest"),
+            file: Some(file.to_vec()),
+        };
+        self.lock().insert(image.uuid, stored);
+    }
+
+    /// Sends image `uuid`'s file with its first byte changed, from now on.
+    pub fn corrupt(&self, uuid: Uuid) {
+        self.corrupt
+            .lock()
+            .expect("corrupt lock poisoned")
+            .insert(uuid);
+    }
+
+    /// The uuids of the images on the server, in any state, in order.
+    pub fn uuids(&self) -> Vec<Uuid> {
+        self.lock().keys().copied().collect()
+    }
+
+    /// Image `uuid`, as the server has it.
+    pub fn image(&self, uuid: Uuid) -> Option<Image> {
+        self.lock()
+            .get(&uuid)
+            .map(|s| serde_json::from_value(s.manifest.clone()).expect("a valid manifest"))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<Uuid, Stored>> {
+        self.images.lock().expect("images lock poisoned")
+    }
+}
+
+fn handle(images: &mut BTreeMap<Uuid, Stored>, corrupt: &HashSet<Uuid>, req: &Request) -> Response {
+    let segments: Vec<&str> = req.path().trim_matches('/').split('/').collect();
+    let not_found = || Response::error(404, "ResourceNotFound", "image not found");
+    let uuid = match segments.get(1).map(|s| s.parse::<Uuid>()) {
+        Some(Ok(uuid)) => uuid,
+        Some(Err(_)) => return not_found(),
+        None if segments == ["images"] && req.method == "GET" => return list(images, req),
+        None => return Response::error(405, "BadMethod", "unsupported request"),
+    };
+    match (
+        req.method.as_str(),
+        &segments[2..],
+        req.query("action").as_deref(),
+    ) {
+        ("GET", [], _) => match images.get(&uuid) {
+            Some(stored) => Response::json(200, &stored.manifest),
+            None => not_found(),
+        },
+        ("POST", [], Some("import")) => {
+            if images.contains_key(&uuid) {
+                return Response::error(409, "ImageUuidAlreadyExists", "image already exists");
+            }
+            let mut manifest: Value = serde_json::from_slice(&req.body).expect("a JSON manifest");
+            manifest["state"] = json!("unactivated");
+            manifest["files"] = json!([]);
+            manifest
+                .as_object_mut()
+                .expect("a manifest is an object")
+                .remove("published_at");
+            images.insert(
+                uuid,
+                Stored {
+                    manifest: manifest.clone(),
+                    file: None,
+                },
+            );
+            Response::json(200, &manifest)
+        }
+        ("POST", [], Some("activate")) => match images.get_mut(&uuid) {
+            Some(stored) => {
+                stored.manifest["state"] = json!("active");
+                stored.manifest["published_at"] = json!("2024-06-01T00:00:00Z");
+                Response::json(200, &stored.manifest)
+            }
+            None => not_found(),
+        },
+        ("DELETE", [], _) => match images.remove(&uuid) {
+            Some(_) => Response::new(204, Vec::new()),
+            None => not_found(),
+        },
+        ("PUT", ["file"], _) => match images.get_mut(&uuid) {
+            Some(stored) => {
+                let digest = file_digest(&req.body[..]).expect("reading from memory");
+                stored.manifest["files"] = json!([{
+                    "sha1": digest.sha1,
+                    "size": digest.size,
+                    "compression": req.query("compression").unwrap_or_default(),
+                }]);
+                stored.file = Some(req.body.clone());
+                Response::json(200, &stored.manifest)
+            }
+            None => not_found(),
+        },
+        ("GET", ["file"], _) => match images.get(&uuid).and_then(|s| s.file.clone()) {
+            Some(mut file) => {
+                if corrupt.contains(&uuid) && !file.is_empty() {
+                    file[0] ^= 0xff;
+                }
+                Response::new(200, file)
+            }
+            None => not_found(),
+        },
+        _ => Response::error(405, "BadMethod", "unsupported request"),
+    }
+}
+
+/// Lists images as ListImages does, for the `state`, `name`, `limit` and `marker` parameters,
+/// in order of publication.
+fn list(images: &BTreeMap<Uuid, Stored>, req: &Request) -> Response {
+    let state = req.query("state").unwrap_or_else(|| "active".to_string());
+    let name = req.query("name");
+    let mut matching: Vec<&Value> = images
+        .values()
+        .map(|s| &s.manifest)
+        .filter(|m| state == "all" || m["state"] == json!(state))
+        .filter(|m| name.as_ref().is_none_or(|n| m["name"] == json!(n)))
+        .collect();
+    let published = |m: &Value| m["published_at"].as_str().map(str::to_string);
+    matching.sort_by_key(|m| (published(m), m["uuid"].as_str().map(str::to_string)));
+    if let Some(marker) = req.query("marker").and_then(|m| m.parse::<Uuid>().ok()) {
+        let at = images.get(&marker).and_then(|s| published(&s.manifest));
+        matching.retain(|m| published(m) >= at);
+    }
+    let limit = req
+        .query("limit")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(1000usize);
+    matching.truncate(limit.min(1000));
+    Response::json(
+        200,
+        &Value::from(matching.into_iter().cloned().collect::<Vec<_>>()),
+    )
+}
@@ -0,0 +1,197 @@
+use std::fmt;
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A transfer rate limit, in bytes per second.
+///
+/// Limits apply to the blocking client's transfers; there's no async client for them to apply to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BytesPerSec(pub u64);
+
+impl fmt::Display for BytesPerSec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/s", super::size::format_size(self.0))
+    }
+}
+
+/// A token bucket holding the bytes that may be read without exceeding a rate limit.
+///
+/// The bucket starts empty and holds at most a tenth of a second's worth of bytes, so the average
+/// rate stays close to the limit even over short transfers.
+#[derive(Debug)]
+pub(crate) struct Bucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    pub(crate) fn new(limit: BytesPerSec) -> Self {
+        let rate = limit.0.max(1) as f64;
+        Self {
+            rate,
+            capacity: (rate / 10.0).max(1.0),
+            tokens: 0.0,
+            last: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let earned = now.duration_since(self.last).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + earned).min(self.capacity);
+        self.last = now;
+    }
+
+    /// Takes up to `max` tokens at `now`, or returns how long to wait for at least one.
+    fn take(&mut self, max: usize, now: Instant) -> Result<usize, Duration> {
+        self.refill(now);
+        if self.tokens < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate));
+        }
+        let n = (self.tokens as usize).clamp(1, max);
+        self.tokens -= n as f64;
+        Ok(n)
+    }
+
+    /// Returns tokens that were taken but not used.
+    fn give_back(&mut self, n: usize) {
+        self.tokens = (self.tokens + n as f64).min(self.capacity);
+    }
+}
+
+/// A bucket shared by several readers, so that their combined rate stays within one limit.
+pub(crate) type SharedBucket = Arc<Mutex<Bucket>>;
+
+/// A reader that limits how fast it can be read from, using a token bucket that may be shared with
+/// other readers.
+pub(crate) struct Throttled<R> {
+    inner: R,
+    bucket: SharedBucket,
+}
+
+impl<R: Read> Throttled<R> {
+    pub(crate) fn new(inner: R, limit: BytesPerSec) -> Self {
+        Self::shared(inner, Arc::new(Mutex::new(Bucket::new(limit))))
+    }
+
+    pub(crate) fn shared(inner: R, bucket: SharedBucket) -> Self {
+        Self { inner, bucket }
+    }
+}
+
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let max = loop {
+            // Sleep without holding the lock, so other readers can take tokens meanwhile.
+            let wait = match self
+                .bucket
+                .lock()
+                .expect("bucket lock poisoned")
+                .take(buf.len(), Instant::now())
+            {
+                Ok(n) => break n,
+                Err(wait) => wait,
+            };
+            thread::sleep(wait);
+        };
+        let result = self.inner.read(&mut buf[..max]);
+        let used = *result.as_ref().unwrap_or(&0);
+        if used < max {
+            self.bucket
+                .lock()
+                .expect("bucket lock poisoned")
+                .give_back(max - used);
+        }
+        result
+    }
+}
+
+/// Wraps `reader` in a [`Throttled`] reader if there is a limit.
+pub(crate) fn maybe_throttle<'a, R: Read + Send + 'a>(
+    reader: R,
+    limit: Option<BytesPerSec>,
+) -> Box<dyn Read + Send + 'a> {
+    match limit {
+        Some(limit) => Box::new(Throttled::new(reader, limit)),
+        None => Box::new(reader),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bucket for `limit` bytes a second, and the moment it was made, to count time from.
+    fn bucket(limit: u64) -> (Bucket, Instant) {
+        let bucket = Bucket::new(BytesPerSec(limit));
+        let start = bucket.last;
+        (bucket, start)
+    }
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn starts_empty_and_fills_at_the_rate() {
+        let (mut bucket, start) = bucket(1000);
+        assert_eq!(bucket.take(500, start), Err(ms(1)));
+        assert_eq!(bucket.take(500, start + ms(50)), Ok(50));
+        assert_eq!(bucket.take(500, start + ms(50)), Err(ms(1)));
+        assert_eq!(bucket.take(10, start + ms(80)), Ok(10));
+        assert_eq!(bucket.take(500, start + ms(80)), Ok(20));
+    }
+
+    #[test]
+    fn holds_at_most_a_tenth_of_a_second() {
+        let (mut bucket, start) = bucket(1000);
+        assert_eq!(
+            bucket.take(10_000, start + Duration::from_secs(60)),
+            Ok(100)
+        );
+        bucket.give_back(1000);
+        assert_eq!(
+            bucket.take(10_000, start + Duration::from_secs(60)),
+            Ok(100)
+        );
+    }
+
+    #[test]
+    fn a_zero_limit_is_one_byte_a_second() {
+        let (mut bucket, start) = bucket(0);
+        assert_eq!(bucket.take(10, start), Err(Duration::from_secs(1)));
+        assert_eq!(bucket.take(10, start + Duration::from_secs(5)), Ok(1));
+    }
+
+    #[test]
+    fn a_transfer_takes_its_size_over_the_limit() {
+        let (mut bucket, start) = bucket(1000);
+        let (mut now, mut sent) = (start, 0);
+        while sent < 5000 {
+            match bucket.take(64 * 1024, now) {
+                Ok(n) => sent += n,
+                Err(wait) => now += wait,
+            }
+        }
+        let elapsed = now - start;
+        assert!(elapsed >= ms(4990) && elapsed <= ms(5010), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn throttles_a_reader() {
+        let data = vec![7; 20_000];
+        let started = Instant::now();
+        let mut read = Vec::new();
+        Throttled::new(&data[..], BytesPerSec(100_000))
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!(read, data);
+        assert!(started.elapsed() >= ms(190), "{:?}", started.elapsed());
+    }
+}
@@ -0,0 +1,108 @@
+//! Lenient (de)serialization of manifest timestamps such as [`Image::published_at`].
+//!
+//! [`Image::published_at`]: crate::Image::published_at
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use serde::{de, Deserialize, Deserializer, Serializer};
+
+/// The format IMGAPI itself uses for timestamps.
+const CANONICAL_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3fZ";
+
+/// Timestamp formats without any timezone, found in old manifests. These are assumed to be UTC.
+const NAIVE_FORMATS: &[&str] = &["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"];
+
+/// Parses a timestamp in any of the forms found in real manifests, normalizing it to UTC.
+///
+/// This accepts RFC 3339 timestamps with or without fractional seconds and with either `Z` or a
+/// numeric offset, as well as timestamps with no timezone at all.
+pub fn parse(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Ok(dt.with_timezone(&Utc));
+    }
+    NAIVE_FORMATS
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+        .map(|dt| Utc.from_utc_datetime(&dt))
+        .ok_or_else(|| format!("invalid timestamp: \"{}\"", s))
+}
+
+/// Formats a timestamp the way IMGAPI does, e.g. `2021-01-11T17:27:49.000Z`.
+pub fn format(dt: &DateTime<Utc>) -> String {
+    dt.format(CANONICAL_FORMAT).to_string()
+}
+
+pub(crate) mod option {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(v: &Option<DateTime<Utc>>, s: S) -> Result<S::Ok, S::Error> {
+        match v {
+            Some(dt) => s.serialize_str(&format(dt)),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+        match Option::<String>::deserialize(d)? {
+            Some(s) => parse(&s).map(Some).map_err(de::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::image;
+    use serde_json::json;
+
+    #[test]
+    fn parses_each_form_to_the_same_instant() {
+        let forms = [
+            "2021-01-11T17:27:49.000Z",
+            "2021-01-11T17:27:49Z",
+            "2021-01-11T18:27:49+01:00",
+            "2021-01-11T12:27:49.000-05:00",
+            "2021-01-11T17:27:49",
+            "2021-01-11T17:27:49.000",
+            "2021-01-11 17:27:49",
+        ];
+        for s in &forms {
+            let dt = parse(s).unwrap_or_else(|e| panic!("{}: {}", s, e));
+            assert_eq!(format(&dt), "2021-01-11T17:27:49.000Z", "{}", s);
+            assert_eq!(parse(&format(&dt)), Ok(dt), "{}", s);
+        }
+    }
+
+    #[test]
+    fn keeps_milliseconds() {
+        let dt = parse("2021-01-11T17:27:49.123456Z").unwrap();
+        assert_eq!(format(&dt), "2021-01-11T17:27:49.123Z");
+    }
+
+    #[test]
+    fn rejects_what_isnt_a_timestamp() {
+        for s in &[
+            "",
+            "2021-01-11",
+            "yesterday",
+            "2021-13-01T00:00:00Z",
+            "1610386069",
+        ] {
+            assert_eq!(parse(s), Err(format!("invalid timestamp: \"{}\"", s)));
+        }
+    }
+
+    #[test]
+    fn round_trips_through_a_manifest() {
+        let manifest = image(1, json!({"published_at": "2021-01-11 17:27:49"}));
+        let written = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(written["published_at"], "2021-01-11T17:27:49.000Z");
+
+        let unpublished = image(1, json!({"published_at": null}));
+        assert_eq!(unpublished.published_at, None);
+        let mut bad = written;
+        bad["published_at"] = json!("soon");
+        let err = serde_json::from_value::<crate::Image>(bad).unwrap_err();
+        assert!(err.to_string().contains("invalid timestamp"), "{}", err);
+    }
+}
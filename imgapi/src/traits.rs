@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+/// The traits of a server that an image's [`Traits`] are evaluated against.
+pub type ServerTraits = Traits;
+
+/// A single trait value.
+///
+/// Traits are usually booleans (e.g. `ssd: true`), strings, or arrays of strings. Anything else is
+/// preserved as-is in [`TraitValue::Other`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TraitValue {
+    Bool(bool),
+    String(String),
+    Array(Vec<String>),
+    Other(Value),
+}
+
+impl TraitValue {
+    /// Whether this value and a value of the same trait on the other side are compatible.
+    ///
+    /// A string matches an array that contains it, two arrays match if they have any element in
+    /// common, and everything else is compared for equality.
+    fn matches(&self, other: &TraitValue) -> bool {
+        match (self, other) {
+            (Self::String(s), Self::Array(a)) | (Self::Array(a), Self::String(s)) => a.contains(s),
+            (Self::Array(a), Self::Array(b)) => a.iter().any(|v| b.contains(v)),
+            (a, b) => a == b,
+        }
+    }
+
+    /// Whether this value is the boolean `false`.
+    fn is_false(&self) -> bool {
+        matches!(self, Self::Bool(false))
+    }
+}
+
+/// An object that defines a collection of properties that is used by other APIs to evaluate where
+/// customer VMs should be placed.
+///
+/// A manifest whose `traits` isn't an object still parses: the value is kept as it is, so it's
+/// written back unchanged, and reported by [`Traits::malformed`] and [`Image::validate`]. Such
+/// traits hold no traits of their own and match no server.
+///
+/// [`Image::validate`]: crate::Image::validate
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Traits {
+    traits: BTreeMap<String, TraitValue>,
+    malformed: Option<Value>,
+}
+
+impl Serialize for Traits {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.malformed {
+            Some(v) => v.serialize(serializer),
+            None => self.traits.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Traits {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        if !value.is_object() {
+            return Ok(Self {
+                traits: BTreeMap::new(),
+                malformed: Some(value),
+            });
+        }
+        // Every value is a TraitValue, if only TraitValue::Other.
+        let traits = serde_json::from_value(value).map_err(serde::de::Error::custom)?;
+        Ok(Self {
+            traits,
+            malformed: None,
+        })
+    }
+}
+
+impl Traits {
+    /// Returns the value of the trait `key`, if present.
+    pub fn get(&self, key: &str) -> Option<&TraitValue> {
+        self.traits.get(key)
+    }
+
+    /// The manifest's `traits` value, if it isn't an object.
+    pub fn malformed(&self) -> Option<&Value> {
+        self.malformed.as_ref()
+    }
+
+    /// Returns the value of the trait `key` if it is present and is a boolean.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key)? {
+            TraitValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the trait `key` if it is present and is a string.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            TraitValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the trait `key` if it is present and is an array of strings.
+    pub fn get_array(&self, key: &str) -> Option<&[String]> {
+        match self.get(key)? {
+            TraitValue::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    /// Sets the trait `key` to `value`, returning the previous value if there was one. Malformed
+    /// traits are replaced.
+    pub fn insert(&mut self, key: &str, value: TraitValue) -> Option<TraitValue> {
+        self.malformed = None;
+        self.traits.insert(key.to_string(), value)
+    }
+
+    /// Removes the trait `key`, returning its value if it was present.
+    pub fn remove(&mut self, key: &str) -> Option<TraitValue> {
+        self.traits.remove(key)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.traits.is_empty() && self.malformed.is_none()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &TraitValue)> {
+        self.traits.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Returns the traits exactly as they would be sent on the wire.
+    pub fn raw(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+
+    /// Whether a server with the given traits is eligible for VMs provisioned from this image.
+    ///
+    /// This mirrors DAPI: every trait set on either side must be compatible with the same trait on
+    /// the other side. A trait that is missing on one side only matches if the other side sets it
+    /// to `false`.
+    pub fn matches(&self, server: &ServerTraits) -> bool {
+        if self.malformed.is_some() || server.malformed.is_some() {
+            return false;
+        }
+        let keys = self.traits.keys().chain(server.traits.keys());
+        keys.into_iter()
+            .all(|key| match (self.get(key), server.get(key)) {
+                (Some(img), Some(srv)) => img.matches(srv),
+                (Some(v), None) | (None, Some(v)) => v.is_false(),
+                (None, None) => true,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::image;
+    use serde_json::json;
+
+    fn traits(value: Value) -> Traits {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn reads_each_kind_of_value() {
+        let t = traits(json!({"ssd": true, "zone": "east", "hw": ["a", "b"], "n": 3}));
+        assert_eq!(t.get_bool("ssd"), Some(true));
+        assert_eq!(t.get_str("zone"), Some("east"));
+        assert_eq!(
+            t.get_array("hw"),
+            Some(&["a".to_string(), "b".to_string()][..])
+        );
+        assert_eq!(t.get("n"), Some(&TraitValue::Other(json!(3))));
+        assert_eq!(t.get_str("ssd"), None);
+        assert_eq!(
+            t.raw(),
+            json!({"ssd": true, "zone": "east", "hw": ["a", "b"], "n": 3})
+        );
+    }
+
+    #[test]
+    fn matches_like_dapi() {
+        let cases = [
+            (json!({"ssd": true}), json!({"ssd": true}), true),
+            (json!({"ssd": true}), json!({"ssd": false}), false),
+            (json!({"ssd": true}), json!({}), false),
+            (json!({"ssd": false}), json!({}), true),
+            (json!({}), json!({"ssd": true}), false),
+            (json!({"zone": "east"}), json!({"zone": "west"}), false),
+            (
+                json!({"zone": "east"}),
+                json!({"zone": ["west", "east"]}),
+                true,
+            ),
+            (
+                json!({"zone": ["west", "east"]}),
+                json!({"zone": "east"}),
+                true,
+            ),
+            (json!({"hw": ["a", "b"]}), json!({"hw": ["b", "c"]}), true),
+            (json!({"hw": ["a"]}), json!({"hw": ["c"]}), false),
+            (json!({"n": 3}), json!({"n": 3}), true),
+            (json!({"n": 3}), json!({"n": 4}), false),
+        ];
+        for (image, server, expected) in &cases {
+            let matched = traits(image.clone()).matches(&traits(server.clone()));
+            assert_eq!(matched, *expected, "{} on {}", image, server);
+        }
+    }
+
+    #[test]
+    fn keeps_traits_that_arent_an_object() {
+        let mut manifest = image(1, json!({"traits": ["ssd"]}));
+        let t = manifest.traits.as_ref().unwrap();
+        assert_eq!(t.malformed(), Some(&json!(["ssd"])));
+        assert!(!t.is_empty());
+        assert!(!t.matches(&ServerTraits::default()));
+        assert_eq!(
+            serde_json::to_value(&manifest).unwrap()["traits"],
+            json!(["ssd"])
+        );
+        assert!(manifest.validate().iter().any(|i| i.field == "traits"));
+
+        manifest
+            .traits
+            .as_mut()
+            .unwrap()
+            .insert("ssd", TraitValue::Bool(true));
+        assert_eq!(
+            manifest.traits.as_ref().unwrap().raw(),
+            json!({"ssd": true})
+        );
+        assert!(manifest.validate().iter().all(|i| i.field != "traits"));
+    }
+}
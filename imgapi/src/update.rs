@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use super::{CpuType, DiskDriver, Image, MaybeUrl, NicDriver, Requirements, Uuid};
+
+/// A change to a field of an image that can be unset: leave it as it is, unset it, or set it.
+///
+/// In an UpdateImage request, [`Patch::Keep`] leaves the field out, [`Patch::Clear`] sends it as
+/// `null`, which removes it from the image, and [`Patch::Set`] sends the value. A field that's
+/// missing from JSON deserializes as `Keep`, and one that's `null` as `Clear`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Patch<T> {
+    #[default]
+    Keep,
+    Clear,
+    Set(T),
+}
+
+impl<T> Patch<T> {
+    pub fn is_keep(&self) -> bool {
+        matches!(self, Self::Keep)
+    }
+
+    /// The value it's set to, setting it to `f()` first unless it's already set.
+    pub fn get_or_insert_with<F: FnOnce() -> T>(&mut self, f: F) -> &mut T {
+        if !matches!(self, Self::Set(_)) {
+            *self = Self::Set(f());
+        }
+        match self {
+            Self::Set(v) => v,
+            _ => unreachable!("just set"),
+        }
+    }
+}
+
+impl<T: Clone> Patch<T> {
+    /// Makes the change to `field`.
+    fn apply(&self, field: &mut Option<T>) {
+        match self {
+            Self::Keep => {}
+            Self::Clear => *field = None,
+            Self::Set(v) => *field = Some(v.clone()),
+        }
+    }
+}
+
+/// `None` keeps the field as it is, and `Some` sets it. Clearing a field has to be asked for.
+impl<T> From<Option<T>> for Patch<T> {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Keep, Self::Set)
+    }
+}
+
+impl<T: Serialize> Serialize for Patch<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            // Left out with `skip_serializing_if`; serialized on its own it's as good as unset.
+            Self::Keep | Self::Clear => serializer.serialize_none(),
+            Self::Set(v) => serializer.serialize_some(v),
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Option::deserialize(deserializer)?.map_or(Self::Clear, Self::Set))
+    }
+}
+
+/// The body of an UpdateImage request.
+///
+/// Only the fields IMGAPI allows to be changed after creation are present. Fields left as `None`
+/// or [`Patch::Keep`] are omitted from the request entirely, and so are left unchanged on the
+/// server. Fields that an image can be without can also be removed, with [`Patch::Clear`].
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ImageUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub description: Patch<String>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub homepage: Patch<MaybeUrl>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub eula: Patch<MaybeUrl>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public: Option<bool>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub acl: Patch<Vec<Uuid>>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub requirements: Patch<Requirements>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub tags: Patch<BTreeMap<String, Value>>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub billing_tags: Patch<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub inherited_directories: Patch<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub nic_driver: Patch<NicDriver>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub disk_driver: Patch<DiskDriver>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub cpu_type: Patch<CpuType>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub image_size: Patch<u32>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub generate_passwords: Patch<bool>,
+}
+
+/// Invokes `$m!(field)` for every [`Patch`] field of [`ImageUpdate`], those that are optional on
+/// [`Image`].
+macro_rules! optional_fields {
+    ($m:ident) => {
+        $m!(description);
+        $m!(homepage);
+        $m!(eula);
+        $m!(acl);
+        $m!(requirements);
+        $m!(tags);
+        $m!(billing_tags);
+        $m!(inherited_directories);
+        $m!(nic_driver);
+        $m!(disk_driver);
+        $m!(cpu_type);
+        $m!(image_size);
+        $m!(generate_passwords);
+    };
+}
+
+impl ImageUpdate {
+    /// Returns the names of the fields this update would change, in wire format.
+    pub fn changed_fields(&self) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        macro_rules! check {
+            ($field:ident) => {
+                if !self.$field.is_keep() {
+                    fields.push(stringify!($field));
+                }
+            };
+        }
+        for (field, set) in [
+            ("name", self.name.is_some()),
+            ("version", self.version.is_some()),
+            ("public", self.public.is_some()),
+        ] {
+            if set {
+                fields.push(field);
+            }
+        }
+        optional_fields!(check);
+        fields.sort_unstable();
+        fields
+    }
+
+    /// Whether the update would change nothing.
+    pub fn is_empty(&self) -> bool {
+        self.changed_fields().is_empty()
+    }
+
+    /// Applies the update to a local copy of the manifest, as the server would.
+    pub fn apply_to(&self, image: &mut Image) {
+        macro_rules! apply {
+            ($field:ident) => {
+                self.$field.apply(&mut image.$field);
+            };
+        }
+        if let Some(v) = &self.name {
+            image.name = v.clone();
+        }
+        if let Some(v) = &self.version {
+            image.version = v.clone();
+        }
+        if let Some(p) = self.public {
+            image.public = p;
+        }
+        optional_fields!(apply);
+    }
+}
+
+/// Extracts every mutable field of the image, e.g. as a starting point for editing.
+impl From<&Image> for ImageUpdate {
+    fn from(image: &Image) -> Self {
+        Self {
+            name: Some(image.name.clone()),
+            version: Some(image.version.clone()),
+            description: image.description.clone().into(),
+            homepage: image.homepage.clone().into(),
+            eula: image.eula.clone().into(),
+            public: Some(image.public),
+            acl: image.acl.clone().into(),
+            requirements: image.requirements.clone().into(),
+            tags: image.tags.clone().into(),
+            billing_tags: image.billing_tags.clone().into(),
+            inherited_directories: image.inherited_directories.clone().into(),
+            nic_driver: image.nic_driver.clone().into(),
+            disk_driver: image.disk_driver.clone().into(),
+            cpu_type: image.cpu_type.clone().into(),
+            image_size: image.image_size.into(),
+            generate_passwords: image.generate_passwords.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::image;
+    use serde_json::json;
+
+    #[test]
+    fn sends_only_the_fields_it_changes() {
+        let update = ImageUpdate {
+            version: Some("1.0.1".to_string()),
+            description: Patch::Clear,
+            billing_tags: Patch::Set(vec!["small".to_string()]),
+            ..ImageUpdate::default()
+        };
+        assert_eq!(
+            serde_json::to_value(&update).unwrap(),
+            json!({"version": "1.0.1", "description": null, "billing_tags": ["small"]})
+        );
+        assert_eq!(
+            update.changed_fields(),
+            ["billing_tags", "description", "version"]
+        );
+        assert_eq!(
+            serde_json::to_value(ImageUpdate::default()).unwrap(),
+            json!({})
+        );
+    }
+
+    #[test]
+    fn reads_null_as_clear_and_missing_as_keep() {
+        let update: ImageUpdate =
+            serde_json::from_value(json!({"homepage": null, "image_size": 10240})).unwrap();
+        assert_eq!(update.homepage, Patch::Clear);
+        assert_eq!(update.image_size, Patch::Set(10240));
+        assert_eq!(update.description, Patch::Keep);
+        assert_eq!(update.changed_fields(), ["homepage", "image_size"]);
+    }
+
+    #[test]
+    fn clearing_removes_the_field() {
+        let mut image = image(
+            1,
+            json!({"description": "base", "homepage": "https://example.com"}),
+        );
+        let update = ImageUpdate {
+            description: Patch::Clear,
+            ..ImageUpdate::default()
+        };
+        update.apply_to(&mut image);
+        assert_eq!(image.description, None);
+        assert!(image.homepage.is_some());
+    }
+
+    #[test]
+    fn get_or_insert_with_replaces_a_clear() {
+        let mut tags: Patch<Vec<u32>> = Patch::Clear;
+        tags.get_or_insert_with(Vec::new).push(1);
+        tags.get_or_insert_with(|| vec![0]).push(2);
+        assert_eq!(tags, Patch::Set(vec![1, 2]));
+    }
+}
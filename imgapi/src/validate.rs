@@ -0,0 +1,405 @@
+use std::fmt;
+
+use super::{Brand, CpuType, DiskDriver, Image, MaybeUrl, NicDriver, MAX_FILE_SIZE};
+use crate::size::format_size;
+
+/// How serious a [`ValidationIssue`] is.
+#[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Clone, Copy)]
+pub enum Severity {
+    /// The manifest is usable, but probably not what was intended.
+    Warning,
+
+    /// The manifest is invalid and will be rejected by IMGAPI or produce a broken image.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+        .fmt(f)
+    }
+}
+
+/// A single problem found by [`Image::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+
+    /// The manifest field the issue refers to, e.g. `files[0].size`.
+    pub field: String,
+
+    pub message: String,
+}
+
+impl ValidationIssue {
+    pub fn error(field: &str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(field: &str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.severity, self.field, self.message)
+    }
+}
+
+impl Image {
+    /// Checks the manifest for problems, returning every issue found.
+    ///
+    /// An empty list means the manifest is valid. Issues with [`Severity::Warning`] do not make the
+    /// manifest invalid.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+
+        if self.v != 2 {
+            issues.push(ValidationIssue::error(
+                "v",
+                format!("unsupported manifest version {}", self.v),
+            ));
+        }
+
+        if self.name.is_empty() {
+            issues.push(ValidationIssue::error("name", "must not be empty"));
+        } else if self.name.chars().count() > 512 {
+            issues.push(ValidationIssue::error(
+                "name",
+                "must be at most 512 characters",
+            ));
+        }
+
+        if self.version.is_empty() {
+            issues.push(ValidationIssue::error("version", "must not be empty"));
+        } else if self.version.chars().count() > 128 {
+            issues.push(ValidationIssue::error(
+                "version",
+                "must be at most 128 characters",
+            ));
+        }
+
+        if let Some(MaybeUrl::Invalid(u)) = &self.homepage {
+            issues.push(ValidationIssue::warning(
+                "homepage",
+                format!("invalid URL \"{}\"", u),
+            ));
+        }
+
+        if let Some(MaybeUrl::Invalid(u)) = &self.eula {
+            issues.push(ValidationIssue::warning(
+                "eula",
+                format!("invalid URL \"{}\"", u),
+            ));
+        }
+
+        if let Some(NicDriver::Other(d)) = &self.nic_driver {
+            issues.push(ValidationIssue::warning(
+                "nic_driver",
+                format!("unknown NIC driver \"{}\"", d),
+            ));
+        }
+
+        if let Some(DiskDriver::Other(d)) = &self.disk_driver {
+            issues.push(ValidationIssue::warning(
+                "disk_driver",
+                format!("unknown disk driver \"{}\"", d),
+            ));
+        }
+
+        if let Some(CpuType::Other(c)) = &self.cpu_type {
+            issues.push(ValidationIssue::warning(
+                "cpu_type",
+                format!("unknown CPU type \"{}\"", c),
+            ));
+        }
+
+        if let Some(brand) = self.requirements.as_ref().and_then(|r| r.brand.as_ref()) {
+            let field = "requirements.brand";
+            let expected = match self.image_type.as_str() {
+                "zvol" if !brand.is_hvm() => Some("kvm or bhyve"),
+                "lx-dataset" if *brand != Brand::Lx => Some("lx"),
+                "zone-dataset" if brand.is_hvm() || *brand == Brand::Lx => {
+                    Some("a native zone brand")
+                }
+                _ => None,
+            };
+            if let Brand::Other(b) = brand {
+                issues.push(ValidationIssue::warning(
+                    field,
+                    format!("unknown brand \"{}\"", b),
+                ));
+            } else if let Some(expected) = expected {
+                issues.push(ValidationIssue::error(
+                    field,
+                    format!(
+                        "{} images require {}, not \"{}\"",
+                        self.image_type, expected, brand
+                    ),
+                ));
+            }
+        }
+
+        if let Some(v) = self.traits.as_ref().and_then(|t| t.malformed()) {
+            issues.push(ValidationIssue::error(
+                "traits",
+                format!("must be an object, not {}", v),
+            ));
+        }
+
+        if let Some(req) = &self.requirements {
+            let constraints = [
+                ("min_platform", &req.min_platform),
+                ("max_platform", &req.max_platform),
+            ];
+            for (name, constraint) in constraints.iter() {
+                let invalid = constraint.iter().flat_map(|c| c.invalid_entries());
+                for (sdc, plat) in invalid {
+                    issues.push(ValidationIssue::error(
+                        &format!("requirements.{}.{}", name, sdc),
+                        format!(
+                            "expected an SDC version mapped to a YYYYMMDDTHHMMSSZ timestamp, got \"{}\"",
+                            plat
+                        ),
+                    ));
+                }
+            }
+        }
+
+        for (i, file) in self.files.iter().enumerate() {
+            if file.size > MAX_FILE_SIZE {
+                issues.push(ValidationIssue::error(
+                    &format!("files[{}].size", i),
+                    format!(
+                        "{} bytes ({}) exceeds the maximum file size of {}",
+                        file.size,
+                        format_size(file.size),
+                        format_size(MAX_FILE_SIZE)
+                    ),
+                ));
+            }
+        }
+
+        if self.is_docker() {
+            for (i, file) in self.files.iter().enumerate() {
+                let digests = [
+                    ("digest", &file.digest),
+                    ("uncompressedDigest", &file.uncompressed_digest),
+                ];
+                for (name, digest) in digests.iter() {
+                    let field = format!("files[{}].{}", i, name);
+                    match digest {
+                        None => issues
+                            .push(ValidationIssue::error(&field, "required for docker images")),
+                        Some(d) if !d.starts_with("sha256:") => issues.push(
+                            ValidationIssue::error(&field, "must start with \"sha256:\""),
+                        ),
+                        Some(_) => {}
+                    }
+                }
+            }
+        }
+
+        if self.image_type == "zvol" {
+            if self.nic_driver.is_none() {
+                issues.push(ValidationIssue::error(
+                    "nic_driver",
+                    "required for zvol images",
+                ));
+            }
+            if self.disk_driver.is_none() {
+                issues.push(ValidationIssue::error(
+                    "disk_driver",
+                    "required for zvol images",
+                ));
+            }
+            if self.cpu_type.is_none() {
+                issues.push(ValidationIssue::error(
+                    "cpu_type",
+                    "required for zvol images",
+                ));
+            }
+            if self.image_size.is_none() {
+                issues.push(ValidationIssue::error(
+                    "image_size",
+                    "required for zvol images",
+                ));
+            }
+        }
+
+        issues
+    }
+
+    /// Whether [`Image::validate`] found no errors (warnings are allowed).
+    pub fn is_valid(&self) -> bool {
+        self.validate()
+            .iter()
+            .all(|i| i.severity != Severity::Error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::image;
+    use serde_json::{json, Value};
+
+    use Severity::{Error, Warning};
+
+    /// An image that satisfies every rule for zvols, to break one rule at a time.
+    fn zvol() -> Value {
+        json!({
+            "type": "zvol",
+            "os": "linux",
+            "nic_driver": "virtio",
+            "disk_driver": "virtio",
+            "cpu_type": "host",
+            "image_size": 10240,
+            "requirements": {"brand": "bhyve"},
+        })
+    }
+
+    fn with(mut base: Value, fields: Value) -> Value {
+        if let (Value::Object(base), Value::Object(fields)) = (&mut base, fields) {
+            base.extend(fields);
+        }
+        base
+    }
+
+    fn issues(fields: Value) -> Vec<(Severity, String)> {
+        image(1, fields)
+            .validate()
+            .into_iter()
+            .map(|i| (i.severity, i.field))
+            .collect()
+    }
+
+    #[test]
+    fn a_valid_manifest_has_no_issues() {
+        assert_eq!(issues(json!({})), []);
+        assert_eq!(issues(zvol()), []);
+        assert!(image(1, json!({})).is_valid());
+    }
+
+    #[test]
+    fn each_rule_reports_its_field() {
+        let docker = json!({"type": "docker", "os": "linux"});
+        let file =
+            |size: u64| json!({"files": [{"sha1": "", "size": size, "compression": "gzip"}]});
+        let cases: Vec<(Value, Vec<(Severity, &str)>)> = vec![
+            (json!({"v": 1}), vec![(Error, "v")]),
+            (json!({"name": ""}), vec![(Error, "name")]),
+            (json!({"name": "n".repeat(513)}), vec![(Error, "name")]),
+            (json!({"name": "n".repeat(512)}), vec![]),
+            (json!({"version": ""}), vec![(Error, "version")]),
+            (
+                json!({"version": "1".repeat(129)}),
+                vec![(Error, "version")],
+            ),
+            (
+                json!({"homepage": "/relative"}),
+                vec![(Warning, "homepage")],
+            ),
+            (json!({"eula": "nope"}), vec![(Warning, "eula")]),
+            (json!({"traits": "ssd"}), vec![(Error, "traits")]),
+            (file(MAX_FILE_SIZE), vec![]),
+            (file(MAX_FILE_SIZE + 1), vec![(Error, "files[0].size")]),
+            (
+                json!({"requirements": {"min_platform": {"7.0": "20240101T000000Z"}}}),
+                vec![],
+            ),
+            (
+                json!({"requirements": {"min_platform": {"7.0": "2024-01-01"}}}),
+                vec![(Error, "requirements.min_platform.7.0")],
+            ),
+            (
+                json!({"requirements": {"max_platform": {"seven": "20240101T000000Z"}}}),
+                vec![(Error, "requirements.max_platform.seven")],
+            ),
+            (
+                docker.clone(),
+                vec![
+                    (Error, "files[0].digest"),
+                    (Error, "files[0].uncompressedDigest"),
+                ],
+            ),
+            (
+                with(
+                    docker,
+                    json!({"files": [{
+                        "sha1": "", "size": 0, "compression": "gzip",
+                        "digest": "md5:0", "uncompressedDigest": "sha256:0",
+                    }]}),
+                ),
+                vec![(Error, "files[0].digest")],
+            ),
+            (
+                json!({"type": "zvol", "os": "linux"}),
+                vec![
+                    (Error, "nic_driver"),
+                    (Error, "disk_driver"),
+                    (Error, "cpu_type"),
+                    (Error, "image_size"),
+                ],
+            ),
+            (
+                with(zvol(), json!({"nic_driver": "e1001"})),
+                vec![(Warning, "nic_driver")],
+            ),
+            (
+                with(zvol(), json!({"disk_driver": "scsi2"})),
+                vec![(Warning, "disk_driver")],
+            ),
+            (
+                with(zvol(), json!({"cpu_type": "z80"})),
+                vec![(Warning, "cpu_type")],
+            ),
+            (
+                with(zvol(), json!({"requirements": {"brand": "joyent"}})),
+                vec![(Error, "requirements.brand")],
+            ),
+            (
+                json!({"type": "lx-dataset", "requirements": {"brand": "joyent"}}),
+                vec![(Error, "requirements.brand")],
+            ),
+            (
+                json!({"requirements": {"brand": "kvm"}}),
+                vec![(Error, "requirements.brand")],
+            ),
+            (
+                json!({"requirements": {"brand": "lx"}}),
+                vec![(Error, "requirements.brand")],
+            ),
+            (json!({"requirements": {"brand": "joyent-minimal"}}), vec![]),
+            (
+                json!({"requirements": {"brand": "made-up"}}),
+                vec![(Warning, "requirements.brand")],
+            ),
+        ];
+        for (fields, expected) in cases {
+            let expected: Vec<_> = expected
+                .into_iter()
+                .map(|(s, f)| (s, f.to_string()))
+                .collect();
+            assert_eq!(issues(fields.clone()), expected, "{}", fields);
+        }
+    }
+
+    #[test]
+    fn only_errors_make_a_manifest_invalid() {
+        assert!(image(1, json!({"homepage": "/relative"})).is_valid());
+        assert!(!image(1, json!({"name": ""})).is_valid());
+    }
+}
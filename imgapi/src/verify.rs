@@ -0,0 +1,211 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use super::download::{self, Prefix};
+use super::{Compression, DownloadReport, File, Image};
+
+/// Downloaded bytes that don't match what the manifest promised.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    /// What was compared, e.g. `sha1`, `size` or `digest`.
+    pub check: &'static str,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} mismatch: expected {}, got {}",
+            self.check, self.expected, self.actual
+        )
+    }
+}
+
+impl Error for ChecksumMismatch {}
+
+/// A way in which a local file differs from its manifest, found by [`verify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Discrepancy {
+    /// The file doesn't exist.
+    Missing,
+
+    /// The size or a digest differs.
+    Checksum(ChecksumMismatch),
+
+    /// The file's contents are compressed differently than the manifest says.
+    Compression {
+        expected: Compression,
+        actual: Compression,
+    },
+
+    /// The file couldn't be decompressed to check its `uncompressedDigest`.
+    Undecodable(String),
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Missing => write!(f, "file is missing"),
+            Self::Checksum(m) => m.fmt(f),
+            Self::Compression { expected, actual } => write!(
+                f,
+                "compression mismatch: expected {}, got {}",
+                expected, actual
+            ),
+            Self::Undecodable(e) => write!(f, "can't decompress file: {}", e),
+        }
+    }
+}
+
+/// The result of [`verify`].
+#[derive(Debug, Clone)]
+pub struct VerifyReport {
+    /// The file that was checked.
+    pub path: PathBuf,
+
+    /// The compression of the file's contents, unless it's missing.
+    pub compression: Option<Compression>,
+
+    /// Everything that doesn't match the manifest. Empty if the file is good.
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+impl VerifyReport {
+    /// Whether the file matches the manifest.
+    pub fn is_ok(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+}
+
+/// Every way a file's actual size and digests, as measured while downloading, differ from the
+/// manifest's [`File`].
+///
+/// Docker digests using an algorithm other than SHA-256 can't be checked, and are ignored, as are
+/// digests that weren't measured.
+pub(crate) fn mismatches(file: &File, report: &DownloadReport) -> Vec<ChecksumMismatch> {
+    let mismatch = |check, expected: &str, actual: &str| ChecksumMismatch {
+        check,
+        expected: expected.to_string(),
+        actual: actual.to_string(),
+    };
+    let mut found = Vec::new();
+    if report.bytes != file.size {
+        found.push(mismatch(
+            "size",
+            &file.size.to_string(),
+            &report.bytes.to_string(),
+        ));
+    }
+    if !report.sha1.eq_ignore_ascii_case(&file.sha1) {
+        found.push(mismatch("sha1", &file.sha1, &report.sha1));
+    }
+    let digests = [
+        ("digest", &file.digest, &report.digest),
+        (
+            "uncompressedDigest",
+            &file.uncompressed_digest,
+            &report.uncompressed_digest,
+        ),
+    ];
+    for (check, expected, actual) in digests.iter() {
+        if let (Some(expected), Some(actual)) = (expected, actual) {
+            if expected.starts_with("sha256:") && !expected.eq_ignore_ascii_case(actual) {
+                found.push(mismatch(check, expected, actual));
+            }
+        }
+    }
+    found
+}
+
+/// Compares a download against a manifest's [`File`], failing on the first mismatch.
+pub(crate) fn check(file: &File, report: &DownloadReport) -> Result<(), ChecksumMismatch> {
+    match mismatches(file, report).into_iter().next() {
+        Some(m) => Err(m),
+        None => Ok(()),
+    }
+}
+
+/// Checks a local copy of file `file_index` of an image against its manifest, e.g. before
+/// installing bits that were copied around on removable media.
+///
+/// The size and SHA-1 are checked, as are the Docker `digest` and `uncompressedDigest` if the
+/// manifest has them, and the compression of the file's contents is compared with
+/// [`File::compression`]. Every discrepancy is reported, rather than just the first. Errors are
+/// only returned if the manifest has no such file, or the file can't be read.
+pub fn verify<P: AsRef<Path>>(
+    manifest: &Image,
+    file_index: usize,
+    path: P,
+) -> Result<VerifyReport, Box<dyn Error>> {
+    let path = path.as_ref();
+    let with_path = |e: io::Error| format!("{}: {}", path.display(), e);
+    let file = manifest
+        .files
+        .get(file_index)
+        .ok_or_else(|| format!("image {} has no file {}", manifest.uuid, file_index))?;
+    let mut report = VerifyReport {
+        path: path.to_path_buf(),
+        compression: None,
+        discrepancies: Vec::new(),
+    };
+
+    let mut reader = match fs::File::open(path) {
+        Ok(f) => BufReader::new(f),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            report.discrepancies.push(Discrepancy::Missing);
+            return Ok(report);
+        }
+        Err(e) => return Err(with_path(e).into()),
+    };
+    let compression = Compression::sniff(&mut reader).map_err(with_path)?;
+    report.compression = Some(compression);
+    if file.compression != Compression::Auto && file.compression != compression {
+        report.discrepancies.push(Discrepancy::Compression {
+            expected: file.compression,
+            actual: compression,
+        });
+    }
+
+    let (bytes, sha1, digest) = Prefix::read(file, reader).map_err(with_path)?.finish();
+    let uncompressed_digest = match &file.uncompressed_digest {
+        Some(d) if d.starts_with("sha256:") => {
+            let reader = BufReader::new(fs::File::open(path).map_err(with_path)?);
+            let mut sha256 = Sha256::new();
+            let decoded = download::decoder(compression, reader)
+                .and_then(|mut r| Ok(io::copy(&mut r, &mut sha256)?));
+            match decoded {
+                Ok(_) => Some(download::sha256_digest(sha256)),
+                Err(e) => {
+                    report
+                        .discrepancies
+                        .push(Discrepancy::Undecodable(e.to_string()));
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let measured = DownloadReport {
+        bytes,
+        written: bytes,
+        sha1,
+        digest,
+        uncompressed_digest,
+        resumed_from: 0,
+        elapsed: Default::default(),
+    };
+    report.discrepancies.extend(
+        mismatches(file, &measured)
+            .into_iter()
+            .map(Discrepancy::Checksum),
+    );
+    Ok(report)
+}
@@ -0,0 +1,276 @@
+use std::cmp::Ordering;
+
+use chrono::{NaiveDate, Utc};
+
+use super::{DateTime, Image, Uuid};
+
+/// A version string interpreted according to one of the schemes commonly used by images.
+///
+/// The derived ordering is total: versions in the same scheme compare as [`cmp_version_strings`]
+/// compares them, and versions in different schemes by the rank of their scheme, semver first,
+/// then dates, then anything else.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum VersionKey {
+    /// A dotted numeric version such as `1.12.3` or a bare number, with an optional pre-release.
+    /// Trailing zero components are dropped, so that `1.2` and `1.2.0` are the same version.
+    Semver(Vec<u64>, Release),
+
+    /// A date or timestamp, normalized to `YYYYMMDDHHMMSS`.
+    Date(String),
+
+    /// A version in no scheme we know, compared as a plain string.
+    Raw(String),
+}
+
+/// Whether a [`VersionKey::Semver`] is a pre-release, which sorts before the release it precedes.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Release {
+    Pre(String),
+    Final,
+}
+
+impl VersionKey {
+    fn parse(s: &str) -> Option<Self> {
+        Self::parse_semver(s).or_else(|| Self::parse_date(s))
+    }
+
+    /// The key to sort the version `s` by. A bare number that reads as a date, e.g. `20240215`, is
+    /// taken as one, since that's how [`cmp_version_strings`] compares it with other dates.
+    pub(crate) fn sort_key(s: &str) -> Self {
+        let trimmed = s.trim();
+        let bare_number = !trimmed.is_empty() && trimmed.chars().all(|c| c.is_ascii_digit());
+        let date = if bare_number {
+            Self::parse_date(s)
+        } else {
+            None
+        };
+        date.or_else(|| Self::parse(s))
+            .unwrap_or_else(|| Self::Raw(s.to_string()))
+    }
+
+    fn parse_semver(s: &str) -> Option<Self> {
+        let s = s.trim().trim_start_matches('v');
+        let s = s.split('+').next()?;
+        let (core, pre) = match s.split_once('-') {
+            Some((core, pre)) => (core, Release::Pre(pre.to_string())),
+            None => (s, Release::Final),
+        };
+
+        let mut parts = core
+            .split('.')
+            .map(|p| {
+                if p.is_empty() || !p.chars().all(|c| c.is_ascii_digit()) {
+                    None
+                } else {
+                    p.parse().ok()
+                }
+            })
+            .collect::<Option<Vec<u64>>>()?;
+
+        // A single component followed by a dash is far more likely to be a date like
+        // "2024-02-15" than a version with a pre-release.
+        if parts.len() == 1 && pre != Release::Final {
+            return None;
+        }
+        while parts.last() == Some(&0) {
+            parts.pop();
+        }
+        Some(Self::Semver(parts, pre))
+    }
+
+    fn parse_date(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if !s.chars().all(|c| c.is_ascii_digit() || "-:.TZ".contains(c)) {
+            return None;
+        }
+
+        let mut digits: String = s.chars().filter(|c| c.is_ascii_digit()).collect();
+        if digits.len() < 8 || NaiveDate::parse_from_str(&digits[..8], "%Y%m%d").is_err() {
+            return None;
+        }
+        digits.truncate(14);
+        while digits.len() < 14 {
+            digits.push('0');
+        }
+        Some(Self::Date(digits))
+    }
+
+    /// Compares two keys in the same scheme, or returns `None` if their schemes differ.
+    fn cmp_same_scheme(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Semver(..), Self::Semver(..)) | (Self::Date(_), Self::Date(_)) => {
+                Some(self.cmp(other))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Compares the version strings `a` and `b`, if they use a common versioning scheme.
+///
+/// Dotted numeric versions (`1.12.3`, `20240215`) are compared component by component, and dates
+/// or timestamps (`2024-02-15T10:00:00Z`) chronologically. Returns `None` if either string can't
+/// be interpreted or the two use different schemes.
+pub fn cmp_version_strings(a: &str, b: &str) -> Option<Ordering> {
+    let (a_key, b_key) = (VersionKey::parse(a)?, VersionKey::parse(b)?);
+    if let Some(o) = a_key.cmp_same_scheme(&b_key) {
+        return Some(o);
+    }
+    // "20240215" parses as a plain number, but may need to be compared against a real date.
+    let (a_date, b_date) = (VersionKey::parse_date(a)?, VersionKey::parse_date(b)?);
+    a_date.cmp_same_scheme(&b_date)
+}
+
+/// The key [`cmp_versions`] orders `image` by.
+pub(crate) fn version_order(image: &Image) -> (VersionKey, Option<DateTime<Utc>>, Uuid) {
+    (
+        VersionKey::sort_key(&image.version),
+        image.published_at,
+        image.uuid,
+    )
+}
+
+/// Compares two images by version.
+///
+/// Versions in the same scheme compare as [`cmp_version_strings`] compares them, and versions in
+/// different schemes by the rank of the scheme: semver first, then dates, then anything else, as
+/// `img list -s version` sorts them. Equal versions are ordered by [`Image::published_at`]
+/// (unpublished images sort first), and finally by uuid, so that the ordering is total and
+/// doesn't depend on the order the images come in.
+pub fn cmp_versions(a: &Image, b: &Image) -> Ordering {
+    version_order(a).cmp(&version_order(b))
+}
+
+/// Returns the newest provisionable image named `name`, according to [`cmp_versions`].
+///
+/// Only images for which [`Image::is_provisionable`] is true are considered.
+pub fn latest_by_name<'a, I>(images: I, name: &str) -> Option<&'a Image>
+where
+    I: IntoIterator<Item = &'a Image>,
+{
+    images
+        .into_iter()
+        .filter(|i| i.name == name && i.is_provisionable())
+        .max_by_key(|i| version_order(i))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testutil::{image, permutations, uuid};
+
+    /// Versions in every scheme, and some in none.
+    const MIXED: &[&str] = &[
+        "1.2.0",
+        "1.2",
+        "v1.10.0",
+        "1.9.3",
+        "1.0.0-rc1",
+        "1.0.0",
+        "2",
+        "20240215",
+        "2024-02-16",
+        "2024-02-15T10:00:00Z",
+        "20230101T000000Z",
+        "latest",
+        "beta",
+        "",
+        "1.x",
+    ];
+
+    #[test]
+    fn compares_within_a_scheme() {
+        let cmp = cmp_version_strings;
+        assert_eq!(cmp("1.2", "1.2.0"), Some(Ordering::Equal));
+        assert_eq!(cmp("1.10.0", "1.9.3"), Some(Ordering::Greater));
+        assert_eq!(cmp("v1.0.0", "1.0.0"), Some(Ordering::Equal));
+        assert_eq!(cmp("1.0.0-rc1", "1.0.0"), Some(Ordering::Less));
+        assert_eq!(cmp("1.0.0-rc1", "1.0.0-rc2"), Some(Ordering::Less));
+        assert_eq!(
+            cmp("2024-02-15", "2024-02-15T10:00:00Z"),
+            Some(Ordering::Less)
+        );
+        assert_eq!(cmp("20240215", "2024-02-16"), Some(Ordering::Less));
+    }
+
+    #[test]
+    fn doesnt_compare_across_schemes() {
+        assert_eq!(cmp_version_strings("1.2.3", "2024-02-15"), None);
+        assert_eq!(cmp_version_strings("1.2.3", "latest"), None);
+        assert_eq!(cmp_version_strings("latest", "beta"), None);
+    }
+
+    #[test]
+    fn sort_key_agrees_with_cmp_version_strings() {
+        for a in MIXED {
+            for b in MIXED {
+                if let Some(o) = cmp_version_strings(a, b) {
+                    let keys = (VersionKey::sort_key(a), VersionKey::sort_key(b));
+                    assert_eq!(keys.0.cmp(&keys.1), o, "{:?} vs {:?}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sort_key_ranks_schemes() {
+        let mut versions = MIXED.to_vec();
+        versions.sort_by_key(|v| VersionKey::sort_key(v));
+        assert_eq!(
+            versions,
+            [
+                "1.0.0-rc1",
+                "1.0.0",
+                "1.2.0",
+                "1.2",
+                "1.9.3",
+                "v1.10.0",
+                "2",
+                "20230101T000000Z",
+                "20240215",
+                "2024-02-15T10:00:00Z",
+                "2024-02-16",
+                "",
+                "1.x",
+                "beta",
+                "latest",
+            ]
+        );
+    }
+
+    /// Images 1, 2, ... with `versions`, all published at the same time.
+    fn versioned(versions: &[&str]) -> Vec<Image> {
+        (1..)
+            .zip(versions)
+            .map(|(n, v)| image(n, json!({ "version": v })))
+            .collect()
+    }
+
+    /// Asserts that `latest_by_name` picks image `expected` from `images`, in every order.
+    fn assert_latest_in_any_order(images: &[Image], expected: u128) {
+        for order in permutations(images) {
+            let latest = latest_by_name(&order, "base").expect("a latest image");
+            let versions: Vec<_> = order.iter().map(|i| i.version.as_str()).collect();
+            assert_eq!(latest.uuid, uuid(expected), "in the order {:?}", versions);
+        }
+    }
+
+    #[test]
+    fn latest_of_equal_versions_with_leading_zeros_is_by_uuid() {
+        // All the same version, so the tie goes to the last uuid.
+        let images = versioned(&["1.01", "1.1.0", "1.001", "v1.1"]);
+        assert_latest_in_any_order(&images, 4);
+    }
+
+    #[test]
+    fn latest_across_schemes_doesnt_depend_on_order() {
+        // Comparing versions only when both were in the same scheme, and otherwise by uuid, went
+        // round in a circle: 9.0 < 10.0 as semver, but 10.0 < 2024-01-01 < 9.0 by uuid.
+        let images = versioned(&["10.0", "2024-01-01", "9.0"]);
+        assert_latest_in_any_order(&images, 2);
+        let images = versioned(&["10.0", "2024-01-01", "9.0", "latest"]);
+        assert_latest_in_any_order(&images, 4);
+    }
+}
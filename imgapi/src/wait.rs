@@ -0,0 +1,74 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use super::{Image, ImageError, ImageState, Uuid};
+
+/// Options for [`Client::wait_for_state`](super::blocking::Client::wait_for_state).
+#[derive(Debug, Clone)]
+pub struct WaitOptions {
+    /// How long to wait before giving up, or `None` to wait for as long as it takes.
+    pub timeout: Option<Duration>,
+
+    /// How long to wait between polls.
+    pub interval: Duration,
+}
+
+impl Default for WaitOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Some(Duration::from_secs(600)),
+            interval: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Why [`Client::wait_for_state`](super::blocking::Client::wait_for_state) stopped waiting.
+#[derive(Debug, Clone)]
+pub enum WaitError {
+    /// The image went into the `failed` state, with the error the server gave, if any.
+    Failed {
+        uuid: Uuid,
+        error: Option<ImageError>,
+    },
+
+    /// The image wasn't in the state in time. `state` is the state it was last seen in.
+    TimedOut {
+        uuid: Uuid,
+        state: ImageState,
+        after: Duration,
+    },
+}
+
+impl WaitError {
+    pub(crate) fn failed(image: &Image) -> Self {
+        Self::Failed {
+            uuid: image.uuid,
+            error: image.error.clone(),
+        }
+    }
+}
+
+impl fmt::Display for WaitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Failed { uuid, error: None } => write!(f, "image {} failed", uuid),
+            Self::Failed {
+                uuid,
+                error: Some(e),
+            } => match &e.code {
+                Some(code) => write!(f, "image {} failed: {} ({})", uuid, e.message, code),
+                None => write!(f, "image {} failed: {}", uuid, e.message),
+            },
+            Self::TimedOut { uuid, state, after } => write!(
+                f,
+                "timed out after {}s waiting for image {}, which is still {}",
+                after.as_secs(),
+                uuid,
+                state
+            ),
+        }
+    }
+}
+
+impl Error for WaitError {}
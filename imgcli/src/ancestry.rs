@@ -0,0 +1,122 @@
+use std::error::Error;
+
+use serde_json::{json, Value};
+
+use imgapi::blocking::Client;
+use imgapi::{AncestryError, ApiError, Image, Uuid};
+
+use super::{not_found, style, table};
+
+/// A link in an origin chain, as walked by [`walk`].
+#[derive(Debug, Clone)]
+pub enum Link {
+    Image(Box<Image>),
+
+    /// An origin the server doesn't have.
+    Missing(Uuid),
+
+    /// An origin that's already further down the chain.
+    Cycle(Uuid),
+}
+
+/// The origin chain of image `uuid`, from base to leaf.
+///
+/// Unlike [`Client::get_ancestry`], a broken chain isn't an error: the walk stops at the first
+/// missing or repeated origin, which is the first link. Only a missing leaf is an error.
+pub fn walk(client: &Client, uuid: &Uuid) -> Result<Vec<Link>, Box<dyn Error>> {
+    let leaf = client.get(uuid).map_err(not_found(uuid))?;
+    let mut next = leaf.origin;
+    let mut links = vec![Link::Image(Box::new(leaf))];
+    while let Some(origin) = next {
+        let seen = links
+            .iter()
+            .any(|l| matches!(l, Link::Image(i) if i.uuid == origin));
+        if seen {
+            links.push(Link::Cycle(origin));
+            break;
+        }
+        match client.get(&origin) {
+            Ok(image) => {
+                next = image.origin;
+                links.push(Link::Image(Box::new(image)));
+            }
+            Err(e)
+                if e.downcast_ref::<ApiError>()
+                    .is_some_and(|e| e.is_not_found()) =>
+            {
+                links.push(Link::Missing(origin));
+                break;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    links.reverse();
+    Ok(links)
+}
+
+/// Why the chain is broken, if it is.
+pub fn broken(links: &[Link]) -> Option<AncestryError> {
+    let image = match links.get(1) {
+        Some(Link::Image(i)) => i.uuid,
+        _ => return None,
+    };
+    match links.first()? {
+        Link::Image(_) => None,
+        Link::Missing(origin) => Some(AncestryError::DanglingOrigin {
+            image,
+            origin: *origin,
+        }),
+        Link::Cycle(uuid) => Some(AncestryError::Cycle(*uuid)),
+    }
+}
+
+/// The chain as a JSON array from base to leaf: a manifest per image, with a broken link as
+/// `{"uuid": ..., "missing": true}` or `{"uuid": ..., "cycle": true}`.
+pub fn to_json(links: &[Link]) -> Result<Value, Box<dyn Error>> {
+    links
+        .iter()
+        .map(|link| {
+            Ok(match link {
+                Link::Image(image) => image.to_json()?,
+                Link::Missing(uuid) => json!({ "uuid": uuid, "missing": true }),
+                Link::Cycle(uuid) => json!({ "uuid": uuid, "cycle": true }),
+            })
+        })
+        .collect()
+}
+
+/// Renders the chain as a table, one row per image from base to leaf.
+pub fn render(links: &[Link], no_header: bool, parseable: bool, color: bool) -> String {
+    let mut rows = Vec::new();
+    if !no_header {
+        rows.push(
+            ["UUID", "IMAGE", "STATE", "PUB"]
+                .iter()
+                .map(|h| h.to_string())
+                .collect(),
+        );
+    }
+    rows.extend(links.iter().map(|link| match link {
+        Link::Image(image) => vec![
+            image.uuid.to_string(),
+            format!("{}@{}", image.name, image.version),
+            style::state(image, color && !parseable),
+            image
+                .published_at
+                .map_or_else(|| "-".to_string(), |t| t.format("%Y-%m-%d").to_string()),
+        ],
+        Link::Missing(uuid) => vec![
+            uuid.to_string(),
+            "(missing)".to_string(),
+            "-".to_string(),
+            "-".to_string(),
+        ],
+        Link::Cycle(uuid) => vec![
+            uuid.to_string(),
+            "(cycle)".to_string(),
+            "-".to_string(),
+            "-".to_string(),
+        ],
+    }));
+    table::layout(&rows, parseable)
+}
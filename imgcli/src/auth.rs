@@ -0,0 +1,131 @@
+use std::error::Error;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use structopt::StructOpt;
+
+use imgapi::{KeyError, RequestSigner};
+
+use super::sources::{Source, SourceAuth};
+use super::UsageError;
+
+/// The credentials to sign requests with, for private servers.
+#[derive(Debug, Clone, Default, StructOpt)]
+pub struct AuthArgs {
+    /// The account to sign requests as, by login or UUID. Defaults to the source's.
+    #[structopt(long, global = true, env = "IMG_ACCOUNT")]
+    pub account: Option<String>,
+
+    /// The fingerprint of the key to sign requests with, MD5 or SHA-256, as `ssh-keygen -l`
+    /// prints it. Without --key-file, the key is looked up in the SSH agent.
+    #[structopt(long, global = true, env = "IMG_KEY_ID")]
+    pub key_id: Option<String>,
+
+    /// The private key to sign requests with, if it isn't in the SSH agent. The passphrase of an
+    /// encrypted key is asked for on the terminal.
+    #[structopt(long, global = true, env = "IMG_KEY_FILE")]
+    pub key_file: Option<PathBuf>,
+}
+
+impl AuthArgs {
+    /// Fills in whatever wasn't given from `source`'s credentials.
+    pub fn or_source(self, source: Option<&Source>) -> Self {
+        let auth = match source.and_then(|s| s.auth.as_ref()) {
+            Some(auth) => auth,
+            None => return self,
+        };
+        Self {
+            account: self.account.or_else(|| Some(auth.account.clone())),
+            key_id: self.key_id.or_else(|| Some(auth.key_id.clone())),
+            key_file: self.key_file.or_else(|| auth.key_file.clone()),
+        }
+    }
+
+    /// The credentials as a source stores them, if any were given. A source needs both the
+    /// account and the key's fingerprint.
+    pub fn to_source_auth(&self) -> Result<Option<SourceAuth>, UsageError> {
+        match (&self.account, &self.key_id) {
+            (Some(account), Some(key_id)) => Ok(Some(SourceAuth {
+                account: account.clone(),
+                key_id: key_id.clone(),
+                key_file: self.key_file.clone(),
+            })),
+            (None, None) if self.key_file.is_none() => Ok(None),
+            (Some(_), None) => Err(UsageError("--account needs --key-id".to_string())),
+            (None, _) => Err(UsageError(
+                "--key-id and --key-file need --account".to_string(),
+            )),
+        }
+    }
+
+    /// What to sign requests with, or `None` if no account was given. A key file is read, and
+    /// asked for the passphrase of, if given, and otherwise the key is looked up in the SSH agent.
+    pub fn signer(&self) -> Result<Option<RequestSigner>, Box<dyn Error>> {
+        let account = match &self.account {
+            Some(account) => account,
+            None if self.key_id.is_some() || self.key_file.is_some() => {
+                return Err(UsageError("--key-id and --key-file need --account".to_string()).into())
+            }
+            None => return Ok(None),
+        };
+        let signer = match (&self.key_file, &self.key_id) {
+            (Some(path), key_id) => from_key_file(account, key_id.as_deref(), path)?,
+            (None, Some(key_id)) => RequestSigner::from_agent(account, key_id)?,
+            (None, None) => {
+                let e = "--account needs --key-id to pick a key from the SSH agent, or --key-file";
+                return Err(UsageError(e.to_string()).into());
+            }
+        };
+        Ok(Some(signer))
+    }
+}
+
+/// Reads the key in `path`, asking for its passphrase on the terminal if it's encrypted.
+fn from_key_file(
+    account: &str,
+    key_id: Option<&str>,
+    path: &Path,
+) -> Result<RequestSigner, Box<dyn Error>> {
+    match RequestSigner::from_key_file(account, key_id, path, None) {
+        Err(KeyError::NeedsPassphrase(_)) => {}
+        result => return Ok(result?),
+    }
+    if !io::stdin().is_terminal() {
+        return Err(format!(
+            "{} is encrypted, and stdin isn't a terminal to ask for its passphrase on; add the \
+             key to the SSH agent and use --key-id instead",
+            path.display()
+        )
+        .into());
+    }
+    let passphrase = read_passphrase(&format!("Passphrase for {}: ", path.display()))?;
+    Ok(RequestSigner::from_key_file(
+        account,
+        key_id,
+        path,
+        Some(passphrase.as_bytes()),
+    )?)
+}
+
+/// Asks for a passphrase on stderr and reads it from the terminal, with echo turned off by
+/// `stty` if it can be.
+fn read_passphrase(prompt: &str) -> io::Result<String> {
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+    let stty = |arg| {
+        Command::new("stty")
+            .arg(arg)
+            .status()
+            .is_ok_and(|s| s.success())
+    };
+    let hidden = stty("-echo");
+    let mut line = String::new();
+    let read = io::stdin().lock().read_line(&mut line);
+    if hidden {
+        stty("echo");
+        eprintln!();
+    }
+    read?;
+    Ok(line.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
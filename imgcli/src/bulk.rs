@@ -0,0 +1,68 @@
+use std::error::Error;
+use std::io::BufRead;
+
+use serde_json::{json, Value};
+
+use imgapi::blocking::Client;
+use imgapi::Uuid;
+
+/// A line of `img get --stdin` input: a UUID, or the line if it isn't one.
+pub type Entry = Result<Uuid, String>;
+
+/// Reads one UUID per line from `reader`, skipping blank lines and `#` comments, which may also
+/// follow a UUID on its line.
+pub fn read_uuids(reader: impl BufRead) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if !line.is_empty() {
+            entries.push(Uuid::parse_str(line).map_err(|_| line.to_string()));
+        }
+    }
+    Ok(entries)
+}
+
+/// Fetches the manifest of each of `entries`, at most `concurrency` at once, raw if `raw` is set,
+/// returning them in the same order. Lookups that fail, and lines that aren't UUIDs, are returned
+/// as `{"uuid": ..., "error": ...}` objects, and counted in the second value returned.
+pub fn get(
+    client: &Client,
+    entries: &[Entry],
+    raw: bool,
+    concurrency: usize,
+) -> (Vec<Value>, usize) {
+    let uuids: Vec<Uuid> = entries
+        .iter()
+        .filter_map(|e| e.as_ref().ok())
+        .copied()
+        .collect();
+    let mut fetched = if raw {
+        client.get_many_raw(&uuids, concurrency)
+    } else {
+        client
+            .get_many(&uuids, concurrency)
+            .into_iter()
+            .map(|r| r.and_then(|i| i.to_json().map_err(|e| e.to_string())))
+            .collect()
+    }
+    .into_iter();
+    let mut failed = 0;
+    let manifests = entries
+        .iter()
+        .map(|entry| {
+            let (uuid, result) = match entry {
+                Ok(uuid) => (
+                    uuid.to_string(),
+                    fetched.next().expect("one result per UUID"),
+                ),
+                Err(line) => (line.clone(), Err("not a UUID".to_string())),
+            };
+            result.unwrap_or_else(|error| {
+                failed += 1;
+                json!({"uuid": uuid, "error": error})
+            })
+        })
+        .collect();
+    (manifests, failed)
+}
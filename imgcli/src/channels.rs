@@ -0,0 +1,59 @@
+use std::error::Error;
+
+use serde_json::Value;
+
+use imgapi::{Channel, ChannelInfo};
+
+use super::table;
+
+/// The channel the invocation would use: `explicit`, if given with --channel or by the source,
+/// and otherwise the server's default.
+pub fn current<'a>(
+    channels: &'a [ChannelInfo],
+    explicit: Option<&'a Channel>,
+) -> Option<&'a Channel> {
+    explicit.or_else(|| channels.iter().find(|c| c.default).map(|c| &c.name))
+}
+
+/// The channels as a JSON array of the server's channel objects, each with `"current": true` or
+/// `false` for whether it's the one in use.
+pub fn to_json(
+    channels: &[ChannelInfo],
+    current: Option<&Channel>,
+) -> Result<Value, Box<dyn Error>> {
+    channels
+        .iter()
+        .map(|c| {
+            let mut value = serde_json::to_value(c)?;
+            value["current"] = Value::Bool(Some(&c.name) == current);
+            Ok(value)
+        })
+        .collect()
+}
+
+/// Renders the channels as a table, with the one in use marked with `*` in the first column.
+pub fn render(
+    channels: &[ChannelInfo],
+    current: Option<&Channel>,
+    no_header: bool,
+    parseable: bool,
+) -> String {
+    let mut rows = Vec::new();
+    if !no_header {
+        rows.push(
+            ["", "NAME", "DEFAULT", "DESCRIPTION"]
+                .iter()
+                .map(|h| h.to_string())
+                .collect(),
+        );
+    }
+    rows.extend(channels.iter().map(|c| {
+        vec![
+            if Some(&c.name) == current { "*" } else { "" }.to_string(),
+            c.name.to_string(),
+            if c.default { "yes" } else { "-" }.to_string(),
+            c.description.clone().unwrap_or_else(|| "-".to_string()),
+        ]
+    }));
+    table::layout(&rows, parseable)
+}
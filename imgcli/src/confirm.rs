@@ -0,0 +1,38 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// A change that wasn't confirmed, either because the user said no or because there was no one to
+/// ask.
+#[derive(Debug)]
+pub struct Refused(String);
+
+impl fmt::Display for Refused {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for Refused {}
+
+/// Asks `question` on stderr and waits for a yes, unless `yes` is set (by `--yes`). Without a
+/// terminal on stdin to answer on, the answer is no.
+pub fn confirm(question: &str, yes: bool) -> Result<(), Box<dyn Error>> {
+    if yes {
+        return Ok(());
+    }
+    if !io::stdin().is_terminal() {
+        return Err(Refused(
+            "refusing to go ahead without --yes, since stdin isn't a terminal".into(),
+        )
+        .into());
+    }
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    match answer.trim().to_ascii_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => Err(Refused("cancelled".into()).into()),
+    }
+}
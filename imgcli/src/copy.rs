@@ -0,0 +1,182 @@
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+use imgapi::blocking::Client;
+use imgapi::size::format_size;
+use imgapi::{ApiError, CopyOptions, Image, ImageState, Uuid};
+
+use super::progress::ProgressBar;
+
+/// What `img copy` found on each server: the images to copy, from base to leaf, and those the
+/// destination already has, as it has them.
+#[derive(Debug)]
+pub struct Plan {
+    /// The image being copied, as opposed to its ancestors.
+    pub leaf: Uuid,
+    pub missing: Vec<Image>,
+    pub present: Vec<Image>,
+}
+
+impl Plan {
+    /// The total size of the files to copy, in bytes.
+    pub fn bytes(&self) -> u64 {
+        self.missing.iter().map(Image::total_file_size).sum()
+    }
+}
+
+/// An image that failed to copy, and why.
+#[derive(Debug, Serialize)]
+pub struct Failure {
+    pub uuid: Uuid,
+    pub error: String,
+}
+
+/// What [`copy`] did.
+#[derive(Debug, Default, Serialize)]
+pub struct CopyReport {
+    /// The images that were copied, from base to leaf.
+    pub copied: Vec<Uuid>,
+
+    /// The images the destination already had.
+    pub present: Vec<Uuid>,
+
+    /// The image that failed to copy, if one did.
+    pub failed: Option<Failure>,
+
+    /// The images that weren't tried, because one before them failed.
+    pub not_copied: Vec<Uuid>,
+
+    /// The total size of the copied files, in bytes.
+    pub bytes: u64,
+}
+
+/// The image `uuid` on `dst`, or `None` if it isn't there.
+fn lookup(dst: &Client, uuid: &Uuid) -> Result<Option<Image>, Box<dyn Error>> {
+    match dst.get(uuid) {
+        Ok(image) => Ok(Some(image)),
+        Err(e)
+            if e.downcast_ref::<ApiError>()
+                .is_some_and(ApiError::is_not_found) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Works out what copying image `uuid` from `src` to `dst` involves, along with the ancestors
+/// `dst` is missing if `ancestry` is set. Without it, the image's origin has to be on `dst`
+/// already.
+pub fn plan(
+    src: &Client,
+    dst: &Client,
+    uuid: &Uuid,
+    ancestry: bool,
+    to: &str,
+) -> Result<Plan, Box<dyn Error>> {
+    let images = if ancestry {
+        src.get_ancestry(uuid)?.into_iter().collect()
+    } else {
+        vec![src.get(uuid)?]
+    };
+    let mut plan = Plan {
+        leaf: *uuid,
+        missing: Vec::new(),
+        present: Vec::new(),
+    };
+    for image in images {
+        match lookup(dst, &image.uuid)? {
+            Some(copy) => plan.present.push(copy),
+            None => plan.missing.push(image),
+        }
+    }
+    if let (false, Some(origin)) = (ancestry, plan.missing.first().and_then(|i| i.origin)) {
+        if lookup(dst, &origin)?.is_none() {
+            return Err(format!(
+                "the origin of image {}, {}, isn't on {}; use --include-ancestry to copy it too",
+                uuid, origin, to
+            )
+            .into());
+        }
+    }
+    Ok(plan)
+}
+
+/// Prints `plan` on stderr before copying: each image in the chain from base to leaf, and whether
+/// it's on `to` already, in what state if it isn't active, or will be copied, and then the totals
+/// if there's anything to copy. A dry run's totals are left for its summary.
+pub fn print_plan(plan: &Plan, to: &str, dry_run: bool) {
+    let describe = |i: &Image| format!("{}@{} ({})", i.name, i.version, i.uuid);
+    for image in &plan.present {
+        let state = match image.state {
+            ImageState::Active => String::new(),
+            state => format!(", {}", state),
+        };
+        eprintln!("already on {}: {}{}", to, describe(image), state);
+    }
+    for image in &plan.missing {
+        eprintln!(
+            "{} {}, {}",
+            if dry_run { "would copy:" } else { "to copy:" },
+            describe(image),
+            format_size(image.total_file_size())
+        );
+    }
+    if dry_run || plan.missing.is_empty() {
+        return;
+    }
+    eprintln!(
+        "copying {} image(s) ({}) to {}, {} already there",
+        plan.missing.len(),
+        format_size(plan.bytes()),
+        to,
+        plan.present.len()
+    );
+}
+
+/// Copies the images `plan` is missing from `src` to `dst`, from base to leaf, one at a time with
+/// a progress bar for each file. Ancestors are always activated, since their descendants need them
+/// to be, and the image itself only if `activate` is set. Copying stops at the first failure, and
+/// the report says which images were left out.
+pub fn copy(src: &Client, dst: &Client, plan: &Plan, activate: bool, color: bool) -> CopyReport {
+    let mut report = CopyReport {
+        present: plan.present.iter().map(|i| i.uuid).collect(),
+        ..CopyReport::default()
+    };
+    for image in &plan.missing {
+        if report.failed.is_some() {
+            report.not_copied.push(image.uuid);
+            continue;
+        }
+        let mut bar = ProgressBar::new("copied", color);
+        bar.note(&format!(
+            "copying {}@{} ({})",
+            image.name, image.version, image.uuid
+        ));
+        let opts = CopyOptions {
+            activate: activate || image.uuid != plan.leaf,
+        };
+        // The callback has to own what it updates, and the bar is finished once it's done.
+        let shared = Arc::new(Mutex::new(bar));
+        let progress = Arc::clone(&shared);
+        let result = imgapi::copy_image_with_progress(src, dst, image, &opts, move |p| {
+            progress.lock().expect("progress lock poisoned").update(p)
+        });
+        shared.lock().expect("progress lock poisoned").finish();
+        match result {
+            Ok(_) => {
+                report.copied.push(image.uuid);
+                report.bytes += image.total_file_size();
+            }
+            Err(e) => {
+                report.failed = Some(Failure {
+                    uuid: image.uuid,
+                    error: e.to_string(),
+                })
+            }
+        }
+    }
+    report
+}
@@ -0,0 +1,81 @@
+use std::error::Error;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use imgapi::blocking::Client;
+use imgapi::{ApiError, Compression, Image, Severity};
+
+use super::progress::ProgressBar;
+
+/// Reads the manifest at `path`, or from stdin if it's `-`.
+pub fn read_manifest(path: &Path) -> Result<Image, Box<dyn Error>> {
+    if path == Path::new("-") {
+        Image::from_reader(io::stdin().lock()).map_err(|e| format!("stdin: {}", e).into())
+    } else {
+        Image::from_path(path)
+    }
+}
+
+/// Replaces a server error that lists problems with individual fields, such as a
+/// `ValidationFailed`, with one that has a line per field.
+fn by_field(e: Box<dyn Error>) -> Box<dyn Error> {
+    match e.downcast_ref::<ApiError>() {
+        Some(api) if !api.errors.is_empty() => {
+            let mut message = format!("the server rejected the manifest: {}", api);
+            for f in &api.errors {
+                message.push_str(&format!("\n  {}: {}", f.field, f.message));
+                if !f.code.is_empty() {
+                    message.push_str(&format!(" ({})", f.code));
+                }
+            }
+            message.into()
+        }
+        _ => e,
+    }
+}
+
+/// Creates an image from `manifest` (CreateImage), uploads `file` as its file with a progress bar,
+/// and activates it if `activate` is set, returning the image as the server last reported it. The
+/// progress bar is colored if `color` is.
+///
+/// The manifest is checked with [`Image::validate`] first: warnings are printed on stderr, and
+/// errors are all printed before giving up, without anything being sent.
+pub fn create(
+    client: &Client,
+    manifest: &Image,
+    file: &Path,
+    compression: Compression,
+    activate: bool,
+    color: bool,
+) -> Result<Image, Box<dyn Error>> {
+    let issues = manifest.validate();
+    for issue in &issues {
+        eprintln!("{}", issue);
+    }
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(format!("the manifest has {} error(s)", errors).into());
+    }
+
+    let image = client.create(manifest).map_err(by_field)?;
+    eprintln!("created image {}", image.uuid);
+    let bar = Arc::new(Mutex::new(ProgressBar::new("uploaded", color)));
+    let progress = Arc::clone(&bar);
+    let result = client.add_file_with_progress(&image.uuid, file, compression, move |p| {
+        progress.lock().expect("progress lock poisoned").update(p)
+    });
+    bar.lock().expect("progress lock poisoned").finish();
+    let report = result.map_err(|e| format!("uploading {}: {}", file.display(), e))?;
+    for warning in &report.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    if activate {
+        client.activate(&image.uuid)
+    } else {
+        Ok(report.image)
+    }
+}
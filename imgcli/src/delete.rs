@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use imgapi::blocking::Client;
+use imgapi::{ApiError, Image, ImageFilter, StateFilter, Uuid};
+
+/// The error code IMGAPI refuses to delete an image with while other images have it as their
+/// origin.
+const HAS_DEPENDENTS: &str = "ImageHasDependentImages";
+
+/// Whether `e` is the server refusing to delete an image that others depend on.
+pub fn has_dependents(e: &(dyn Error + 'static)) -> bool {
+    e.downcast_ref::<ApiError>()
+        .is_some_and(|e| e.code == HAS_DEPENDENTS)
+}
+
+/// The images built on image `uuid`, directly or not, in the order they can be deleted in: every
+/// image comes before its origin. Images in any state are included.
+///
+/// IMGAPI can't list images by origin, so this lists every image and works the tree out here.
+pub fn dependents(client: &Client, uuid: &Uuid) -> Result<Vec<Image>, Box<dyn Error>> {
+    let filter = ImageFilter {
+        state: Some(StateFilter::All),
+        ..ImageFilter::default()
+    };
+    let mut children: HashMap<Uuid, Vec<Image>> = HashMap::new();
+    for image in client.list_all(&filter, |_, _| ())? {
+        if let Some(origin) = image.origin {
+            children.entry(origin).or_default().push(image);
+        }
+    }
+    let mut order = Vec::new();
+    push_leaf_first(uuid, &mut children, &mut order);
+    Ok(order)
+}
+
+/// Appends the descendants of `uuid` to `order`, leaves first. Each image is taken out of
+/// `children` as it's visited, so that a cycle can't recurse forever.
+fn push_leaf_first(uuid: &Uuid, children: &mut HashMap<Uuid, Vec<Image>>, order: &mut Vec<Image>) {
+    for child in children.remove(uuid).unwrap_or_default() {
+        push_leaf_first(&child.uuid, children, order);
+        order.push(child);
+    }
+}
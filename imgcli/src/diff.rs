@@ -0,0 +1,113 @@
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+
+use imgapi::blocking::Client;
+use imgapi::{Image, ManifestDiff};
+
+use super::create;
+use super::resolve::{resolve, ImageRef};
+use super::style;
+
+/// How `img diff` shows the differences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffFormat {
+    /// Aligned `field: old -> new` lines.
+    Lines,
+
+    /// An RFC 6902 JSON Patch that turns the first manifest into the second.
+    JsonPatch,
+}
+
+impl FromStr for DiffFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lines" => Ok(Self::Lines),
+            "json-patch" => Ok(Self::JsonPatch),
+            _ => Err(format!(
+                "unknown diff format {:?} (expected lines or json-patch)",
+                s
+            )),
+        }
+    }
+}
+
+/// Loads a manifest given to `img diff`: a local manifest if `arg` is `-` or names a file, and
+/// otherwise an image on the server, as for `img info`.
+pub fn load(client: &Client, arg: &str) -> Result<Image, Box<dyn Error>> {
+    let path = Path::new(arg);
+    if arg == "-" || path.is_file() {
+        return create::read_manifest(path);
+    }
+    let image: ImageRef = arg
+        .parse()
+        .map_err(|e| format!("{:?} is neither a manifest file nor an image: {}", arg, e))?;
+    let uuid = resolve(client, &image)?;
+    client.get(&uuid).map_err(super::not_found(&uuid))
+}
+
+/// Renders `diff` as one `field: old -> new` line per change, with the old values lined up, and
+/// colored red and the new ones green if `color` is.
+pub fn render(diff: &ManifestDiff, color: bool) -> String {
+    let width = diff.iter().map(|c| c.path.len()).max().unwrap_or(0);
+    let show = |v: &Option<Value>| match v {
+        Some(v) => v.to_string(),
+        None => "(none)".to_string(),
+    };
+    diff.iter()
+        .map(|c| {
+            format!(
+                "{:width$} {} -> {}\n",
+                format!("{}:", c.path),
+                style::removed(&show(&c.old), color),
+                style::added(&show(&c.new), color),
+                width = width + 1
+            )
+        })
+        .collect()
+}
+
+/// An RFC 6902 JSON Patch that turns manifest `a` into `b`.
+///
+/// Objects are compared key by key, and arrays position by position if they're the same length.
+/// Arrays whose length changed are replaced as a whole, since patching them element by element
+/// would depend on the order the operations are applied in.
+pub fn json_patch(a: &Image, b: &Image) -> Result<Value, Box<dyn Error>> {
+    let mut ops = Vec::new();
+    patch_values("", &a.to_json()?, &b.to_json()?, &mut ops);
+    Ok(Value::Array(ops))
+}
+
+/// Escapes `key` for use in a JSON pointer.
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn patch_values(path: &str, a: &Value, b: &Value, ops: &mut Vec<Value>) {
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            for (key, old) in a {
+                let path = format!("{}/{}", path, escape(key));
+                match b.get(key) {
+                    Some(new) => patch_values(&path, old, new, ops),
+                    None => ops.push(json!({"op": "remove", "path": path})),
+                }
+            }
+            for (key, new) in b.iter().filter(|(k, _)| !a.contains_key(*k)) {
+                let path = format!("{}/{}", path, escape(key));
+                ops.push(json!({"op": "add", "path": path, "value": new}));
+            }
+        }
+        (Value::Array(a), Value::Array(b)) if a.len() == b.len() => {
+            for (i, (old, new)) in a.iter().zip(b).enumerate() {
+                patch_values(&format!("{}/{}", path, i), old, new, ops);
+            }
+        }
+        (a, b) if a != b => ops.push(json!({"op": "replace", "path": path, "value": b})),
+        _ => {}
+    }
+}
@@ -0,0 +1,44 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// The XDG base directory in `var`, or `fallback` under the home directory if it isn't set, with
+/// `img` appended.
+fn xdg_dir(var: &str, fallback: &str) -> Result<PathBuf, String> {
+    let dir = match env::var_os(var).filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => env::var_os("HOME")
+            .filter(|d| !d.is_empty())
+            .map(|home| Path::new(&home).join(fallback))
+            .ok_or_else(|| {
+                format!(
+                    "can't find a directory for img: neither {} nor HOME is set",
+                    var
+                )
+            })?,
+    };
+    Ok(dir.join("img"))
+}
+
+/// Where `img` keeps its configuration: `$XDG_CONFIG_HOME/img`, or `~/.config/img`.
+pub fn config_dir() -> Result<PathBuf, String> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// Where `img` keeps its data, e.g. imported images: `$XDG_DATA_HOME/img`, or
+/// `~/.local/share/img`.
+pub fn data_dir() -> Result<PathBuf, String> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+/// Where `img` keeps caches, e.g. of catalogs: `$XDG_CACHE_HOME/img`, or `~/.cache/img`.
+pub fn cache_dir() -> Result<PathBuf, String> {
+    xdg_dir("XDG_CACHE_HOME", ".cache")
+}
+
+/// Where node-triton keeps its configuration and profiles: `~/.triton`.
+pub fn triton_dir() -> Result<PathBuf, String> {
+    env::var_os("HOME")
+        .filter(|d| !d.is_empty())
+        .map(|home| Path::new(&home).join(".triton"))
+        .ok_or_else(|| "can't find ~/.triton: HOME isn't set".to_string())
+}
@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+/// Parses a duration like `30s`, `5m`, `1h` or `500ms`. A bare number is a number of seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let err = || format!("{:?} isn't a duration like 30s, 5m or 1h", s);
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().map_err(|_| err())?;
+    let secs = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(err()),
+    };
+    Ok(Duration::from_secs_f64(secs))
+}
@@ -0,0 +1,185 @@
+use std::str::FromStr;
+
+use imgapi::size::format_size;
+use imgapi::{File, Image};
+
+use super::table;
+
+/// A column of `img files` output, as chosen with `-o`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileColumn {
+    Index,
+    Size,
+    Compression,
+    Sha1,
+    Digest,
+    UncompressedDigest,
+}
+
+/// Every column, in the order `-o all` shows them.
+const ALL: &[FileColumn] = &[
+    FileColumn::Index,
+    FileColumn::Size,
+    FileColumn::Compression,
+    FileColumn::Sha1,
+    FileColumn::Digest,
+    FileColumn::UncompressedDigest,
+];
+
+impl FileColumn {
+    /// The column's name, as given to `-o`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Index => "index",
+            Self::Size => "size",
+            Self::Compression => "compression",
+            Self::Sha1 => "sha1",
+            Self::Digest => "digest",
+            Self::UncompressedDigest => "uncompressed_digest",
+        }
+    }
+
+    /// The value of this column for file `index`. Unset digests are shown as `-`.
+    fn value(self, index: usize, file: &File, bytes: bool) -> String {
+        match self {
+            Self::Index => index.to_string(),
+            Self::Size if bytes => file.size.to_string(),
+            Self::Size => format_size(file.size),
+            Self::Compression => file.compression.to_string(),
+            Self::Sha1 => file.sha1.clone(),
+            Self::Digest => file.digest.clone().unwrap_or_else(|| "-".to_string()),
+            Self::UncompressedDigest => file
+                .uncompressed_digest
+                .clone()
+                .unwrap_or_else(|| "-".to_string()),
+        }
+    }
+}
+
+impl FromStr for FileColumn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ALL.iter().copied().find(|c| c.name() == s).ok_or_else(|| {
+            let names: Vec<_> = ALL.iter().map(|c| c.name()).collect();
+            format!(
+                "unknown column {:?} (expected one of: {}, or default or all)",
+                s,
+                names.join(", ")
+            )
+        })
+    }
+}
+
+/// The `-o` argument to `img files`: a comma-separated list of columns, or `default` or `all`.
+/// `None` stands for `default`, which depends on the image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileColumns(pub Option<Vec<FileColumn>>);
+
+impl FromStr for FileColumns {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self(None)),
+            "all" => Ok(Self(Some(ALL.to_vec()))),
+            _ => s
+                .split(',')
+                .map(FileColumn::from_str)
+                .collect::<Result<_, _>>()
+                .map(|c| Self(Some(c))),
+        }
+    }
+}
+
+/// How `img files` lays out its output.
+#[derive(Debug, Clone)]
+pub struct FilesTable {
+    pub columns: FileColumns,
+    pub no_header: bool,
+    pub parseable: bool,
+    pub bytes: bool,
+}
+
+impl FilesTable {
+    /// The columns to show for `image`. By default these are the index, size, compression and
+    /// SHA-1, plus the digests for Docker images.
+    fn columns(&self, image: &Image) -> Vec<FileColumn> {
+        match &self.columns.0 {
+            Some(columns) => columns.clone(),
+            None if image.is_docker() => ALL.to_vec(),
+            None => ALL[..4].to_vec(),
+        }
+    }
+
+    fn rows(&self, image: &Image, columns: &[FileColumn]) -> Vec<Vec<String>> {
+        let bytes = self.bytes || self.parseable;
+        image
+            .files
+            .iter()
+            .enumerate()
+            .map(|(i, f)| columns.iter().map(|c| c.value(i, f, bytes)).collect())
+            .collect()
+    }
+
+    fn header(columns: &[FileColumn]) -> Vec<String> {
+        columns.iter().map(|c| c.name().to_uppercase()).collect()
+    }
+
+    /// Renders the files of `image`, laid out with [`table::layout`].
+    pub fn render(&self, image: &Image) -> String {
+        let columns = self.columns(image);
+        let mut rows = Vec::new();
+        if !self.no_header {
+            rows.push(Self::header(&columns));
+        }
+        rows.extend(self.rows(image, &columns));
+        table::layout(&rows, self.parseable)
+    }
+
+    /// Renders the files of every image in an origin chain, from base to leaf: a section per image
+    /// headed by its name, version and UUID, then a footer with the total size.
+    ///
+    /// A parseable listing is a single table with the image UUID as the first column instead, so
+    /// that every line is a record.
+    pub fn render_chain(&self, images: &[Image]) -> String {
+        if self.parseable {
+            let mut rows = Vec::new();
+            for image in images {
+                let columns = self.columns(image);
+                if !self.no_header && rows.is_empty() {
+                    let mut header = vec!["IMAGE".to_string()];
+                    header.extend(Self::header(&columns));
+                    rows.push(header);
+                }
+                rows.extend(self.rows(image, &columns).into_iter().map(|row| {
+                    let mut row = row;
+                    row.insert(0, image.uuid.to_string());
+                    row
+                }));
+            }
+            return table::layout(&rows, true);
+        }
+
+        let mut out = String::new();
+        for image in images {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&format!(
+                "{}@{} ({})\n",
+                image.name, image.version, image.uuid
+            ));
+            out.push_str(&self.render(image));
+        }
+        let total: u64 = images.iter().map(Image::total_file_size).sum();
+        let files: usize = images.iter().map(|i| i.files.len()).sum();
+        let total = if self.bytes {
+            total.to_string()
+        } else {
+            format_size(total)
+        };
+        out.push_str(&format!("\nTotal: {} in {} file(s)\n", total, files));
+        out
+    }
+}
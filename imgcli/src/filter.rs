@@ -0,0 +1,437 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+
+use structopt::StructOpt;
+
+use imgapi::{Channel, ImageFilter, Marker, OperatingSystem, StateFilter, Uuid};
+
+use super::UsageError;
+
+/// The keys `img list` accepts in `key=value` filters, which are the IMGAPI ListImages query
+/// parameters.
+const KEYS: &[&str] = &[
+    "account",
+    "billing_tag",
+    "channel",
+    "inclAdminFields",
+    "limit",
+    "marker",
+    "name",
+    "os",
+    "owner",
+    "public",
+    "state",
+    "tag",
+    "type",
+    "version",
+];
+
+/// A single `key=value` filter argument to `img list`.
+#[derive(Debug, Clone)]
+pub enum FilterArg {
+    Account(Uuid),
+    Channel(Channel),
+    IncludeAdminFields(bool),
+    Owner(Uuid),
+    State(StateFilter),
+    Name(String),
+    Version(String),
+    Public(bool),
+    Os(OperatingSystem),
+    Type(String),
+    /// A tag key and value, from either `tag.key=value` or `tag=key=value`.
+    Tag(String, String),
+    BillingTag(String),
+    Limit(u32),
+    Marker(Marker),
+}
+
+impl FromStr for FilterArg {
+    type Err = String;
+
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        let (k, v) = arg
+            .split_once('=')
+            .ok_or_else(|| bare_word("filter", arg, KEYS, "os=linux"))?;
+        if v.is_empty() {
+            return Err(format!("{}= needs a value", k));
+        }
+        let uuid =
+            |key: &str| Uuid::parse_str(v).map_err(|_| format!("{} must be a valid UUID", key));
+        let boolean = |key: &str| {
+            bool::from_str(v).map_err(|_| format!("{} must be either true or false", key))
+        };
+        Ok(match k {
+            "account" => Self::Account(uuid(k)?),
+            "channel" => Self::Channel(Channel::from_str(v).map_err(|e| e.to_string())?),
+            "inclAdminFields" => Self::IncludeAdminFields(boolean(k)?),
+            "owner" => Self::Owner(uuid(k)?),
+            "state" => Self::State(StateFilter::from_str(v).map_err(|e| e.to_string())?),
+            "name" => Self::Name(v.to_string()),
+            "version" => Self::Version(v.to_string()),
+            "public" => Self::Public(boolean(k)?),
+            "os" => {
+                Self::Os(OperatingSystem::from_str(v).map_err(|_| {
+                    "os must be one of: smartos, linux, windows, bsd, illumos, other"
+                })?)
+            }
+            "type" => Self::Type(v.to_string()),
+            "billing_tag" => Self::BillingTag(v.to_string()),
+            "limit" => Self::Limit(u32::from_str(v).map_err(|_| "limit must be an integer")?),
+            "tag" => {
+                let (key, value) = v
+                    .split_once('=')
+                    .ok_or_else(|| format!("expected tag=key=value, got {:?}", arg))?;
+                Self::tag(key, value)?
+            }
+            _ if k.starts_with("tag.") => Self::tag(&k["tag.".len()..], v)?,
+            "marker" => Self::Marker(Marker::from_str(v)?),
+            _ => {
+                return Err(format!(
+                    "unexpected query filter: {} (expected one of: {}){}",
+                    arg,
+                    KEYS.join(", "),
+                    did_you_mean(k, KEYS)
+                ))
+            }
+        })
+    }
+}
+
+/// The error for `arg`, a bare word where a `key=value` argument of the given kind was expected,
+/// e.g. `os` in `os linux`, with a hint of what to write instead: the key with a value if the word
+/// is one of `keys`, and otherwise `example`.
+pub fn bare_word(kind: &str, arg: &str, keys: &[&str], example: &str) -> String {
+    let hint = if keys.contains(&arg) {
+        format!("did you mean {}=<value>?", arg)
+    } else {
+        format!("{}s look like {}", kind, example)
+    };
+    format!("expected a key=value {}, got {:?}; {}", kind, arg, hint)
+}
+
+/// `; did you mean <key>?` if `word` is close to one of `keys`, e.g. a typo or the wrong case,
+/// and otherwise nothing. Short keys have to be closer, so that `foo` doesn't suggest `os`.
+pub fn did_you_mean(word: &str, keys: &[&str]) -> String {
+    let distance = |key: &str| edit_distance(&word.to_lowercase(), &key.to_lowercase());
+    match keys.iter().min_by_key(|k| distance(k)) {
+        Some(key) if distance(key) <= (key.len() / 2).max(1) => {
+            format!("; did you mean {}?", key)
+        }
+        _ => String::new(),
+    }
+}
+
+/// The number of single-character insertions, deletions and substitutions that turn `a` into
+/// `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substituted = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+impl FilterArg {
+    fn tag(key: &str, value: &str) -> Result<Self, String> {
+        if key.is_empty() {
+            return Err("tag filters need a key, e.g. tag.role=db".to_string());
+        }
+        Ok(Self::Tag(key.to_string(), value.to_string()))
+    }
+
+    /// The query parameter the filter is for, e.g. `os`.
+    fn key(&self) -> &'static str {
+        match self {
+            Self::Account(_) => "account",
+            Self::Channel(_) => "channel",
+            Self::IncludeAdminFields(_) => "inclAdminFields",
+            Self::Owner(_) => "owner",
+            Self::State(_) => "state",
+            Self::Name(_) => "name",
+            Self::Version(_) => "version",
+            Self::Public(_) => "public",
+            Self::Os(_) => "os",
+            Self::Type(_) => "type",
+            Self::Tag(..) => "tag",
+            Self::BillingTag(_) => "billing_tag",
+            Self::Limit(_) => "limit",
+            Self::Marker(_) => "marker",
+        }
+    }
+
+    /// Sets the corresponding field of `filter`. Tags and billing tags accumulate, and must all
+    /// match; anything else replaces an earlier value.
+    pub fn apply(self, filter: &mut ImageFilter) -> Result<(), String> {
+        match self {
+            Self::Account(v) => filter.account = Some(v),
+            Self::Channel(v) => filter.channel = Some(v),
+            Self::IncludeAdminFields(v) => filter.include_admin_fields = Some(v),
+            Self::Owner(v) => filter.owner = Some(v),
+            Self::State(v) => filter.state = Some(v),
+            Self::Name(v) => filter.name = Some(v),
+            Self::Version(v) => filter.version = Some(v),
+            Self::Public(v) => filter.public = Some(v),
+            Self::Os(v) => filter.os = Some(v),
+            Self::Type(v) => filter.image_type = Some(v),
+            Self::Tag(k, v) => {
+                let tags = filter.tag.get_or_insert_with(BTreeMap::new);
+                match tags.get(&k) {
+                    Some(existing) if *existing != v => {
+                        return Err(format!(
+                            "conflicting filters for tag {:?}: {:?} and {:?}",
+                            k, existing, v
+                        ))
+                    }
+                    _ => {
+                        tags.insert(k, v);
+                    }
+                }
+            }
+            Self::BillingTag(v) => filter.billing_tag.get_or_insert_with(Vec::new).push(v),
+            Self::Limit(v) => filter.limit = Some(v),
+            Self::Marker(v) => filter.marker = Some(v),
+        }
+        Ok(())
+    }
+}
+
+/// Flags for common filters, which combine with `key=value` ones.
+#[derive(Debug, Clone, Default, StructOpt)]
+pub struct FilterFlags {
+    /// Only list Docker images, as with type=docker.
+    #[structopt(long)]
+    pub docker: bool,
+
+    /// Only list public images, as with public=true.
+    #[structopt(long, conflicts_with = "private")]
+    pub public: bool,
+
+    /// Only list private images, as with public=false.
+    #[structopt(long)]
+    pub private: bool,
+
+    /// Only list images owned by the account requests are signed as, as with owner=<UUID>.
+    #[structopt(long)]
+    pub mine: bool,
+}
+
+impl FilterFlags {
+    /// The filters the flags stand for, with the flag each comes from. `account` is the account
+    /// requests are signed as, which --mine needs as a UUID.
+    fn to_args(&self, account: Option<&str>) -> Result<Vec<(&'static str, FilterArg)>, UsageError> {
+        let mut args = Vec::new();
+        if self.docker {
+            args.push(("--docker", FilterArg::Type("docker".to_string())));
+        }
+        if self.public || self.private {
+            let flag = if self.public { "--public" } else { "--private" };
+            args.push((flag, FilterArg::Public(self.public)));
+        }
+        if self.mine {
+            let account = account.ok_or_else(|| {
+                UsageError(
+                    "--mine needs an account: use --account, or a source with credentials"
+                        .to_string(),
+                )
+            })?;
+            let uuid = Uuid::parse_str(account).map_err(|_| {
+                UsageError(format!(
+                    "--mine needs the account's UUID, but the account is {:?}",
+                    account
+                ))
+            })?;
+            args.push(("--mine", FilterArg::Owner(uuid)));
+        }
+        Ok(args)
+    }
+}
+
+/// Builds a filter from `img list` arguments and filter flags. Only tag and billing tag filters
+/// can be given more than once, and a flag can't contradict a `key=value` filter, e.g. --private
+/// and public=true, though it may repeat one. `account` is the account requests are signed as, if
+/// any.
+pub fn build(
+    args: Vec<FilterArg>,
+    flags: &FilterFlags,
+    account: Option<&str>,
+) -> Result<ImageFilter, UsageError> {
+    let mut filter = ImageFilter::default();
+    let mut seen = BTreeSet::new();
+    for arg in args {
+        let repeatable = matches!(arg, FilterArg::Tag(..) | FilterArg::BillingTag(_));
+        if !repeatable && !seen.insert(arg.key()) {
+            return Err(UsageError(format!(
+                "{}= is given more than once; only tag and billing_tag filters can be repeated",
+                arg.key()
+            )));
+        }
+        arg.apply(&mut filter).map_err(UsageError)?;
+    }
+    for (flag, arg) in flags.to_args(account)? {
+        let conflict = match &arg {
+            FilterArg::Type(v) => filter
+                .image_type
+                .as_ref()
+                .filter(|t| *t != v)
+                .map(|t| format!("type={}", t)),
+            FilterArg::Public(v) => filter
+                .public
+                .filter(|p| p != v)
+                .map(|p| format!("public={}", p)),
+            FilterArg::Owner(v) => filter
+                .owner
+                .filter(|o| o != v)
+                .map(|o| format!("owner={}", o)),
+            _ => None,
+        };
+        if let Some(filter) = conflict {
+            return Err(UsageError(format!("{} contradicts {}", flag, filter)));
+        }
+        arg.apply(&mut filter).map_err(UsageError)?;
+    }
+    Ok(filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testutil::image;
+
+    /// The filter built from `args`, which must be valid.
+    fn filter(args: &[&str]) -> ImageFilter {
+        let args = args.iter().map(|a| a.parse().unwrap()).collect();
+        build(args, &FilterFlags::default(), None).unwrap()
+    }
+
+    /// The filter built from `args`, as the query it's sent as.
+    fn query(args: &[&str]) -> Result<String, String> {
+        let args = args
+            .iter()
+            .map(|a| a.parse())
+            .collect::<Result<Vec<FilterArg>, _>>()?;
+        build(args, &FilterFlags::default(), None)
+            .map(|f| f.to_string())
+            .map_err(|e| e.0)
+    }
+
+    #[test]
+    fn takes_tags_either_way() {
+        assert_eq!(query(&["tag.role=db"]), Ok("tag.role=db".to_string()));
+        assert_eq!(query(&["tag=role=db"]), Ok("tag.role=db".to_string()));
+        assert_eq!(query(&["tag=k=a=b"]), Ok("tag.k=a%3Db".to_string()));
+        assert_eq!(
+            query(&["tag.role=db", "tag=zone=east"]),
+            Ok("tag.role=db&tag.zone=east".to_string())
+        );
+        assert_eq!(
+            query(&["tag.role=db", "tag=role=db"]),
+            Ok("tag.role=db".to_string())
+        );
+        assert_eq!(
+            query(&["tag.role=db", "tag.role=web"]),
+            Err("conflicting filters for tag \"role\": \"db\" and \"web\"".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_a_tag_without_a_key_or_value() {
+        assert_eq!(
+            query(&["tag.=db"]),
+            Err("tag filters need a key, e.g. tag.role=db".to_string())
+        );
+        assert_eq!(
+            query(&["tag==db"]),
+            Err("tag filters need a key, e.g. tag.role=db".to_string())
+        );
+        assert_eq!(
+            query(&["tag=role"]),
+            Err("expected tag=key=value, got \"tag=role\"".to_string())
+        );
+    }
+
+    #[test]
+    fn suggests_the_key_an_unknown_one_is_close_to() {
+        let err = query(&["nmae=base"]).unwrap_err();
+        assert!(
+            err.starts_with("unexpected query filter: nmae=base"),
+            "{}",
+            err
+        );
+        assert!(err.ends_with("; did you mean name?"), "{}", err);
+        assert!(query(&["OS=linux"])
+            .unwrap_err()
+            .ends_with("; did you mean os?"));
+        assert!(query(&["foo=1"]).unwrap_err().ends_with(")"));
+    }
+
+    #[test]
+    fn every_key_needs_a_value() {
+        for key in KEYS {
+            assert_eq!(
+                query(&[&format!("{}=", key)]),
+                Err(format!("{}= needs a value", key))
+            );
+        }
+        assert_eq!(
+            query(&["os"]),
+            Err("expected a key=value filter, got \"os\"; did you mean os=<value>?".to_string())
+        );
+        assert_eq!(
+            query(&["linux"]),
+            Err(
+                "expected a key=value filter, got \"linux\"; filters look like os=linux"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn only_tags_and_billing_tags_repeat() {
+        assert_eq!(
+            query(&["billing_tag=a", "billing_tag=b"]),
+            Ok("billing_tag=a&billing_tag=b".to_string())
+        );
+        assert_eq!(
+            query(&["os=linux", "os=smartos"]),
+            Err(
+                "os= is given more than once; only tag and billing_tag filters can be repeated"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn takes_any_state_or_all() {
+        assert_eq!(query(&["state=all"]), Ok("state=all".to_string()));
+        assert_eq!(query(&["state=disabled"]), Ok("state=disabled".to_string()));
+
+        let all = filter(&["state=all"]);
+        let active = ImageFilter::default();
+        let disabled = image(1, json!({"state": "disabled"}));
+        assert!(all.matches(&image(1, json!({}))) && active.matches(&image(1, json!({}))));
+        assert!(all.matches(&disabled) && !active.matches(&disabled));
+    }
+
+    #[test]
+    fn rejects_a_state_that_isnt_one() {
+        assert_eq!(
+            query(&["state=gone"]),
+            Err(
+                "invalid image state \"gone\", expected one of: active, unactivated, disabled, \
+                 creating, failed, all"
+                    .to_string()
+            )
+        );
+        assert_eq!(query(&["state=All"]).map_err(|_| ()), Err(()));
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use imgapi::blocking::Client;
+use imgapi::size::format_size;
+use imgapi::{CancelToken, DownloadRequest, ExportOptions, Image, Uuid};
+
+use super::dirs;
+use super::progress::BatchProgressBar;
+
+const INDEX_FILE: &str = "index.json";
+
+/// The default image store: `images` in the data directory.
+pub fn default_store() -> Result<PathBuf, String> {
+    Ok(dirs::data_dir()?.join("images"))
+}
+
+/// An imported image, as recorded in the store's index.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Imported {
+    pub name: String,
+    pub version: String,
+
+    /// The names of the image's file and manifest in the store.
+    pub file: String,
+    pub manifest: String,
+
+    pub sha1: String,
+    pub size: u64,
+
+    /// The server the image was imported from.
+    pub source: String,
+    pub imported_at: DateTime<Utc>,
+}
+
+/// A directory of imported images, laid out as `imgadm` exports them (`<uuid>.imgmanifest` and
+/// `<uuid>.zfs.gz`, say), with an `index.json` of what's been imported.
+///
+/// Images are only recorded in the index once their file has been downloaded and verified, and
+/// always after their origin, so an indexed image's whole chain is in the store.
+#[derive(Debug)]
+pub struct Store {
+    dir: PathBuf,
+    index: BTreeMap<Uuid, Imported>,
+}
+
+impl Store {
+    /// Opens the store in `dir`, creating it if needed.
+    pub fn open(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let with_path = |e: &dyn Error| format!("{}: {}", dir.join(INDEX_FILE).display(), e);
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let index = match fs::read(dir.join(INDEX_FILE)) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| with_path(&e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(with_path(&e).into()),
+        };
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            index,
+        })
+    }
+
+    /// Whether the image has been imported, and its file is still there.
+    pub fn contains(&self, uuid: &Uuid) -> bool {
+        self.index
+            .get(uuid)
+            .is_some_and(|i| self.dir.join(&i.file).is_file())
+    }
+
+    /// Downloads `image` into the store and records it in the index.
+    fn import(&mut self, client: &Client, image: &Image) -> Result<(), Box<dyn Error>> {
+        let opts = ExportOptions::default();
+        let download = client
+            .export_to_dir(&image.uuid, &self.dir, &opts)?
+            .remove(0);
+        let name = |p: &Path| p.file_name().unwrap_or_default().to_string_lossy().into();
+        let file = name(&download.file_path);
+        let manifest = name(&download.manifest_path);
+        self.record(client, image, file, manifest, download.report.sha1)
+    }
+
+    /// Downloads `images`, an image and its ancestors from base to leaf, into the store, with at
+    /// most `concurrency` downloads at once, and records them in the index in that order. An image
+    /// that fails to download is reported, and neither it nor those after it are recorded.
+    fn import_many(
+        &mut self,
+        client: &Client,
+        images: &[Image],
+        concurrency: usize,
+        color: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let files: Vec<String> = images
+            .iter()
+            .map(|i| super::download_name(i, 0, false))
+            .collect();
+        let requests: Vec<DownloadRequest> = images
+            .iter()
+            .zip(&files)
+            .map(|(i, file)| DownloadRequest::new(i.uuid, self.dir.join(file)))
+            .collect();
+        let starting = images
+            .iter()
+            .map(|i| format!("downloading {}@{} ({})", i.name, i.version, i.uuid))
+            .collect();
+        let mut bar = BatchProgressBar::new("downloaded", starting, color);
+        let results = client.download_many(&requests, concurrency, &CancelToken::new(), |p| {
+            bar.update(p)
+        });
+        bar.finish();
+        for ((image, file), result) in images.iter().zip(files).zip(results) {
+            let report = result.map_err(|e| format!("image {}: {}", image.uuid, e))?;
+            let manifest = format!("{}.imgmanifest", image.uuid);
+            let mut stripped = image.clone();
+            stripped.strip_admin_fields();
+            stripped.to_path(self.dir.join(&manifest))?;
+            self.record(client, image, file, manifest, report.sha1)?;
+        }
+        Ok(())
+    }
+
+    /// Records `image`, imported from `client`'s server, in the index.
+    fn record(
+        &mut self,
+        client: &Client,
+        image: &Image,
+        file: String,
+        manifest: String,
+        sha1: String,
+    ) -> Result<(), Box<dyn Error>> {
+        self.index.insert(
+            image.uuid,
+            Imported {
+                name: image.name.clone(),
+                version: image.version.clone(),
+                file,
+                manifest,
+                sha1,
+                size: image.total_file_size(),
+                source: client.base_url().to_string(),
+                imported_at: Utc::now(),
+            },
+        );
+        self.save()
+    }
+
+    /// Writes the index through a temporary file, so that it's never left half-written.
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        let path = self.dir.join(INDEX_FILE);
+        let with_path = |e: io::Error| format!("{}: {}", path.display(), e);
+        let mut tmp = tempfile::NamedTempFile::new_in(&self.dir).map_err(with_path)?;
+        serde_json::to_writer_pretty(&mut tmp, &self.index)?;
+        tmp.write_all(b"\n").map_err(with_path)?;
+        tmp.persist(&path).map_err(|e| with_path(e.error))?;
+        Ok(())
+    }
+}
+
+/// What [`import`] did, or would do.
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    /// The images that were downloaded, from base to leaf.
+    pub downloaded: Vec<Uuid>,
+
+    /// The images that were already in the store. If the image itself was, its ancestors aren't
+    /// looked up, and only it is listed.
+    pub present: Vec<Uuid>,
+
+    /// The total size of the downloaded files, in bytes.
+    pub bytes: u64,
+}
+
+/// Imports image `uuid` and its ancestors into `store`, skipping those already there, with at
+/// most `concurrency` downloads at once. With `dry_run`, only reports what would be downloaded,
+/// on stderr.
+pub fn import(
+    client: &Client,
+    store: &mut Store,
+    uuid: &Uuid,
+    dry_run: bool,
+    concurrency: usize,
+    color: bool,
+) -> Result<ImportReport, Box<dyn Error>> {
+    let mut report = ImportReport::default();
+    // An indexed image's ancestors were imported before it, so there's nothing to look up.
+    if store.contains(uuid) {
+        report.present.push(*uuid);
+        return Ok(report);
+    }
+    let mut missing = Vec::new();
+    for image in client.get_ancestry(uuid)? {
+        if store.contains(&image.uuid) {
+            report.present.push(image.uuid);
+            continue;
+        }
+        let size = image.total_file_size();
+        eprintln!(
+            "{} {}@{} ({}, {})",
+            if dry_run { "would import" } else { "importing" },
+            image.name,
+            image.version,
+            image.uuid,
+            format_size(size)
+        );
+        report.downloaded.push(image.uuid);
+        report.bytes += size;
+        missing.push(image);
+    }
+    if dry_run {
+        return Ok(report);
+    }
+    // One at a time, failures keep their type, e.g. for a download that didn't match its
+    // checksum.
+    if concurrency > 1 && missing.len() > 1 {
+        store.import_many(client, &missing, concurrency, color)?;
+    } else {
+        for image in &missing {
+            store.import(client, image)?;
+        }
+    }
+    Ok(report)
+}
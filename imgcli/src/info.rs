@@ -0,0 +1,89 @@
+use serde_json::Value;
+
+use imgapi::size::format_size;
+use imgapi::Image;
+
+use super::style;
+
+/// Renders the `img info` summary of `image`: its name and version, then one aligned
+/// `Label: value` line per field that's set. Sizes are exact byte counts if `bytes` is set, and
+/// the state is colored if `color` is.
+pub fn render(image: &Image, bytes: bool, color: bool) -> String {
+    let size = |n: u64| {
+        if bytes {
+            n.to_string()
+        } else {
+            format_size(n)
+        }
+    };
+    let mut fields: Vec<(&str, String)> = vec![
+        ("UUID", image.uuid.to_string()),
+        ("State", style::state(image, color)),
+    ];
+    if let Some(error) = &image.error {
+        let text = match &error.code {
+            Some(code) => format!("{} ({})", error.message, code),
+            None => error.message.clone(),
+        };
+        fields.push(("Error", text));
+    }
+    fields.push(("OS", image.os.clone()));
+    fields.push(("Type", image.image_type.clone()));
+    if let Some(t) = image.published_at {
+        fields.push(("Published", t.format("%Y-%m-%d %H:%M:%S UTC").to_string()));
+    }
+    fields.push(("Owner", image.owner.to_string()));
+    if !image.files.is_empty() {
+        fields.push(("Size", size(image.total_file_size())));
+    }
+    if let Some(d) = &image.description {
+        fields.push(("Description", d.clone()));
+    }
+    if let Some(req) = &image.requirements {
+        if let Some(ram) = req.min_ram {
+            fields.push(("Min RAM", size(u64::from(ram) * 1024 * 1024)));
+        }
+        if let Some(brand) = &req.brand {
+            fields.push(("Brand", brand.to_string()));
+        }
+        if let Some(platform) = &req.min_platform {
+            let bounds: Vec<_> = platform
+                .0
+                .iter()
+                .map(|(sdc, ts)| format!("{}: {}", sdc, ts))
+                .collect();
+            fields.push(("Min platform", bounds.join(", ")));
+        }
+    }
+    if let Some(origin) = image.origin {
+        fields.push(("Origin", origin.to_string()));
+    }
+    if let Some(channels) = image.channels.as_ref().filter(|c| !c.is_empty()) {
+        let names: Vec<_> = channels.iter().map(|c| c.as_str()).collect();
+        fields.push(("Channels", names.join(", ")));
+    }
+    if let Some(tags) = &image.tags {
+        for (key, value) in tags {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                v => v.to_string(),
+            };
+            fields.push(("Tags", format!("{}={}", key, value)));
+        }
+    }
+
+    let width = fields.iter().map(|(l, _)| l.len()).max().unwrap_or(0) + 1;
+    let mut out = format!("{}@{}\n", image.name, image.version);
+    let mut previous = "";
+    for (label, value) in fields {
+        // Only the first of several values for a label, e.g. tags, is labelled.
+        let label = if label == previous {
+            String::new()
+        } else {
+            previous = label;
+            format!("{}:", label)
+        };
+        out.push_str(&format!("  {:width$} {}\n", label, value, width = width));
+    }
+    out
+}
@@ -0,0 +1,34 @@
+use std::error::Error;
+
+use imgapi::blocking::Client;
+use imgapi::{Image, ImageState, Uuid};
+
+/// A change to whether an image can be provisioned, as made by `img activate`, `enable` and
+/// `disable`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateChange {
+    Activate,
+    Enable,
+    Disable,
+}
+
+impl StateChange {
+    /// What `image` is already, if the change would make no difference to it.
+    pub fn already(self, image: &Image) -> Option<&'static str> {
+        match self {
+            Self::Activate if image.state == ImageState::Active => Some("active"),
+            Self::Enable if !image.disabled => Some("enabled"),
+            Self::Disable if image.disabled => Some("disabled"),
+            _ => None,
+        }
+    }
+
+    /// Makes the change, returning the updated image.
+    pub fn apply(self, client: &Client, uuid: &Uuid) -> Result<Image, Box<dyn Error>> {
+        match self {
+            Self::Activate => client.activate(uuid),
+            Self::Enable => client.enable(uuid),
+            Self::Disable => client.disable(uuid),
+        }
+    }
+}
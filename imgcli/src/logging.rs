@@ -0,0 +1,45 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Writes log records to stderr, prefixed with their level like `img`'s own warnings. Records from
+/// `img` and `imgapi` are shown down to the level chosen with -v, and those from other crates,
+/// such as the HTTP client, only if they're warnings or errors.
+struct StderrLogger;
+
+static LOGGER: StderrLogger = StderrLogger;
+
+fn is_ours(target: &str) -> bool {
+    target == "img" || target.starts_with("img::") || target.starts_with("imgapi")
+}
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+            && (metadata.level() <= Level::Warn || is_ours(metadata.target()))
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let level = match record.level() {
+                Level::Warn => "warning".to_string(),
+                level => level.to_string().to_ascii_lowercase(),
+            };
+            eprintln!("{}: {}", level, record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Sets up logging for `verbosity`, the number of times -v was given: warnings only by default,
+/// then info, debug (each request) and trace (response bodies too).
+pub fn init(verbosity: u64) {
+    let level = match verbosity {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
@@ -1,66 +1,2319 @@
 use std::env;
 use std::error::Error;
-use std::str::FromStr;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
 
-use imgapi::{self, Uuid};
+use log::info;
+use structopt::StructOpt;
+use url::Url;
+
+use imgapi::blocking::{Client, DryRunMode};
+use imgapi::CacheMode;
+use imgapi::{
+    self, ApiError, CancelToken, Channel, ChecksumMismatch, Compression, Decompress,
+    DownloadOptions, DownloadRequest, DryRun, Image, ImageFilter, ImageState, RequestTimeout,
+    TransportChecksumMismatch, Uuid, WaitError, WaitOptions,
+};
+
+mod ancestry;
+mod auth;
+mod bulk;
+mod channels;
+mod confirm;
+mod copy;
+mod create;
+mod delete;
+mod diff;
+mod dirs;
+mod duration;
+mod files;
+mod filter;
+mod import;
+mod info;
+mod lifecycle;
+mod logging;
+mod offline;
+mod output;
+mod profiles;
+mod progress;
+mod resolve;
+mod search;
+mod sort;
+mod sources;
+mod style;
+mod table;
+mod tags;
+#[cfg(test)]
+mod testutil;
+mod update;
+mod validate;
+
+use auth::AuthArgs;
+use diff::DiffFormat;
+use files::{FileColumns, FilesTable};
+use filter::{FilterArg, FilterFlags};
+use lifecycle::StateChange;
+use offline::CacheCommand;
+use output::Output;
+use progress::{BatchProgressBar, ProgressBar};
+use resolve::{resolve, ImageRef};
+use sort::SortSpec;
+use sources::{Source, SourcesCommand, SourcesConfig};
+use style::ColorChoice;
+use table::{Columns, Table};
+use tags::TagCommand;
+use update::UpdateArg;
+
+/// The most requests or downloads --concurrency allows at once.
+const MAX_CONCURRENCY: usize = 16;
+
+/// The exit statuses, as listed in `img --help`. See [`exit_code`].
+const EXIT_STATUS: &str = "EXIT STATUS:
+    0    Success
+    1    Any other failure, including a change that wasn't confirmed
+    2    The command line was invalid
+    3    An image wasn't found
+    4    The server refused or failed to do what was asked, or an image failed
+    5    A request, or waiting for an image, timed out
+    6    A download didn't match its checksum";
+
+/// Lists and inspects images on an IMGAPI server.
+///
+/// With --json, `list` prints an array of manifests, as IMGAPI returns them but without null
+/// fields, and `get` prints one manifest, or an array of them if given several images. Nothing else
+/// is written to stdout: warnings, progress and errors all go to stderr.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "img", after_help = EXIT_STATUS)]
+struct Opt {
+    /// The IMGAPI server to use. Defaults to the IMGAPI_URL environment variable, then to the
+    /// Triton profile in TRITON_PROFILE, then to the default source, and finally to the Joyent
+    /// public server.
+    #[structopt(long, global = true, parse(try_from_str = parse_server_url))]
+    url: Option<Url>,
+
+    /// The configured source to use, by name. See `img sources`.
+    #[structopt(short = "S", long, global = true, conflicts_with = "url")]
+    source: Option<String>,
+
+    /// The node-triton profile to use, by name, for its IMGAPI server and credentials. --account
+    /// and --key-id override the profile's. See `img profiles`.
+    #[structopt(long, global = true, conflicts_with_all = &["url", "source"])]
+    profile: Option<String>,
+
+    /// The channel to use, or `*` for all of them, rather than the source's, the `channel` in
+    /// sources.json, or the server's default channel, in that order. Images outside it are
+    /// treated as not found.
+    #[structopt(long, global = true)]
+    channel: Option<Channel>,
+
+    #[structopt(flatten)]
+    auth: AuthArgs,
+
+    /// Give up on a request the server takes longer than this to answer, e.g. `30s` or `5m`, or
+    /// on a download that stalls for this long.
+    #[structopt(
+        long,
+        global = true,
+        default_value = "30s",
+        parse(try_from_str = duration::parse_duration)
+    )]
+    timeout: Duration,
+
+    /// Give up on connecting to the server after this long. Defaults to however long the operating
+    /// system allows.
+    #[structopt(long, global = true, parse(try_from_str = duration::parse_duration))]
+    connect_timeout: Option<Duration>,
+
+    /// How many requests or downloads to run at once, at most 16, in commands that do several:
+    /// `get --stdin`, `download` of several images, and `import`. With 1, they run one at a
+    /// time, in order.
+    #[structopt(
+        long,
+        global = true,
+        default_value = "4",
+        parse(try_from_str = parse_concurrency)
+    )]
+    concurrency: usize,
+
+    /// Don't check the server's TLS certificate, e.g. for a lab server with a self-signed one.
+    /// Anyone on the network path can then impersonate the server, so only use it when that's
+    /// acceptable. Sources can be added with it to always use it for them.
+    #[structopt(short = "k", long, global = true)]
+    insecure: bool,
+
+    /// Change nothing: print the first request that would change something, with its headers and
+    /// body, and stop without sending it. Commands that only read stop at their first request
+    /// instead, so that nothing at all is sent. `import` looks up what it would download, and
+    /// only prints that.
+    #[structopt(long, global = true)]
+    dry_run: bool,
+
+    /// Don't use the network: list, search, latest, get, info, files, ancestry and diff answer from
+    /// the server's catalog as last saved by `img cache refresh`, noting how old it is on stderr,
+    /// and other commands fail. With `"offline_fallback": true` in sources.json, those commands
+    /// also do this when the server can't be reached.
+    #[structopt(long, global = true)]
+    offline: bool,
+
+    /// Print results as JSON.
+    #[structopt(short, long, global = true)]
+    json: bool,
+
+    /// Print JSON on a single line rather than pretty-printed.
+    #[structopt(long, global = true)]
+    compact: bool,
+
+    /// Show sizes as an exact number of bytes, rather than e.g. `245.1M`.
+    #[structopt(long, global = true)]
+    bytes: bool,
+
+    /// When to color output: `auto` (on a terminal, unless NO_COLOR is set), `always` or `never`.
+    #[structopt(long, global = true, default_value = "auto")]
+    color: ColorChoice,
+
+    /// Log what's going on to stderr: -v for the server and credentials used, -vv for every
+    /// request too, and -vvv for response bodies as well.
+    #[structopt(short, long, global = true, parse(from_occurrences))]
+    verbose: u64,
+
+    #[structopt(subcommand)]
+    cmd: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Lists images matching the given filters, as a table with a row per image. When nothing
+    /// matches, just the header row is printed, or nothing with -H, and img still exits with
+    /// status 0. With --count, prints just the number of matching images.
+    ///
+    /// Without --all or --limit, only the server's first page of up to 1000 images is listed, and
+    /// a note on stderr says when there may be more.
+    List {
+        /// Fetch every matching image, a page at a time, rather than just the first page.
+        #[structopt(short, long)]
+        all: bool,
+
+        /// The most images to list, fetching as many pages as that takes, as does a `limit=`
+        /// filter. A note on stderr says when there are more.
+        #[structopt(long, conflicts_with = "all")]
+        limit: Option<u32>,
+
+        /// The columns to show, separated by commas: uuid, name, version, os, type, pub, size,
+        /// state, owner, channels and description. `default` is the first six, and `all` is every
+        /// column.
+        #[structopt(short = "o", long = "output", default_value = "default")]
+        columns: Columns,
+
+        /// The fields to sort by, separated by commas: published_at, name, version, size and
+        /// state. A leading `-` sorts by that field in descending order, e.g. `-s -published_at`.
+        #[structopt(
+            short,
+            long = "sort",
+            default_value = "published_at",
+            allow_hyphen_values = true
+        )]
+        sort: SortSpec,
+
+        /// Leave out the header row.
+        #[structopt(short = "H")]
+        no_header: bool,
+
+        /// Print tab-separated values, without padding or truncating columns, and with sizes in
+        /// bytes.
+        #[structopt(short, long)]
+        parseable: bool,
+
+        /// Print just the number of matching images, rather than the table. With --json, prints
+        /// `{"count": N}`.
+        #[structopt(long, conflicts_with_all = &["columns", "sort", "no-header", "parseable"])]
+        count: bool,
+
+        /// Print just the UUIDs of the matching images, one per line, in the order of the table,
+        /// e.g. for `img list -q name=old | xargs -n1 img delete -f`.
+        #[structopt(short, long, conflicts_with_all = &["json", "columns", "count"])]
+        quiet: bool,
+
+        #[structopt(flatten)]
+        flags: FilterFlags,
+
+        /// Filters in `key=value` form, e.g. `os=linux state=active`. The keys are those of the
+        /// IMGAPI ListImages query parameters.
+        filters: Vec<FilterArg>,
+    },
+
+    /// Searches every image's name, version, description and tags for all of the given terms,
+    /// ignoring case, and lists the images that match, those matching in their name first. With
+    /// --json, prints `{"score": ..., "matched": [...], "image": {...}}` for each match, where
+    /// `matched` names the fields that matched, e.g. `name` or `tags.role`.
+    Search {
+        /// Treat the terms as regular expressions.
+        #[structopt(long)]
+        regex: bool,
+
+        /// The columns to show, as for `img list`.
+        #[structopt(short = "o", long = "output", default_value = "default")]
+        columns: Columns,
+
+        /// Leave out the header row.
+        #[structopt(short = "H")]
+        no_header: bool,
+
+        /// Print tab-separated values, as for `img list`.
+        #[structopt(short, long)]
+        parseable: bool,
+
+        /// Print just the UUIDs of the matching images, one per line, best match first.
+        #[structopt(short, long, conflicts_with_all = &["json", "columns"])]
+        quiet: bool,
+
+        #[structopt(flatten)]
+        flags: FilterFlags,
+
+        #[structopt(required = true)]
+        terms: Vec<String>,
+    },
+
+    /// Prints the UUID of the newest active image with the given name, e.g. `img latest
+    /// name=base-64-lts`, comparing versions like `1.12.3` or `20240215` numerically. With --json,
+    /// prints its manifest. With -v, prints the image's row of the `img list` table rather than
+    /// just its UUID. Exits with status 3 if no image matches.
+    Latest {
+        /// Print just the UUID, even with -v.
+        #[structopt(short, long, conflicts_with = "json")]
+        quiet: bool,
+
+        #[structopt(flatten)]
+        flags: FilterFlags,
+
+        /// Filters in `key=value` form, as for `img list`. A `name` filter is required.
+        #[structopt(required = true)]
+        filters: Vec<FilterArg>,
+    },
+
+    /// Prints a summary of an image. With --json, prints its manifest.
+    Info {
+        /// The image: a UUID or its first 8 or more digits, `name@version`, or a name for its
+        /// latest active version.
+        image: ImageRef,
+    },
+
+    /// Shows the origin chain of an image, from the base image to this one, flagging missing
+    /// origins and cycles. With --json, prints the chain as an array of manifests, with a broken
+    /// link as `{"uuid": ..., "missing": true}` or `{"uuid": ..., "cycle": true}`.
+    Ancestry {
+        /// Leave out the header row.
+        #[structopt(short = "H")]
+        no_header: bool,
+
+        /// Print tab-separated values.
+        #[structopt(short, long)]
+        parseable: bool,
+
+        /// The image: a UUID or its first 8 or more digits, `name@version`, or a name for its
+        /// latest active version.
+        image: ImageRef,
+    },
+
+    /// Compares two manifests, each either a local manifest file (or `-` for stdin) or an image
+    /// on the server, and prints the fields that differ. Exits with status 1 if they differ, and 0
+    /// if they don't. With --json, prints the changes as an array of `{path, old, new}` objects.
+    Diff {
+        /// How to show the differences: `lines`, or `json-patch` for an RFC 6902 JSON Patch that
+        /// turns the first manifest into the second.
+        #[structopt(long, default_value = "lines")]
+        format: DiffFormat,
+
+        /// The first manifest: a file, or an image as for `img info`.
+        first: String,
+
+        /// The second manifest.
+        second: String,
+    },
+
+    /// Downloads a file of each image, verifying it against the manifest, and prints its SHA-1 and
+    /// path like `sha1sum` does. With --json, prints `{"path": ..., "sha1": ..., "bytes": ...}`.
+    ///
+    /// Several images are downloaded --concurrency at a time, and printed in the order given.
+    /// With --json, they're printed as an array, with `{"uuid": ..., "error": ...}` for those that
+    /// failed. A failure doesn't stop the other downloads, but img exits with status 1.
+    Download {
+        /// The directory to download into, under the name `imgadm` would give the file, e.g.
+        /// `<uuid>.zfs.gz`. Defaults to the current directory.
+        #[structopt(short = "O", long, conflicts_with = "output")]
+        dir: Option<PathBuf>,
+
+        /// The path to download to, for a single image.
+        #[structopt(short, long)]
+        output: Option<PathBuf>,
+
+        /// Decompress the file as it's downloaded.
+        #[structopt(long)]
+        decompress: bool,
+
+        /// Continue an interrupted download, and keep what was downloaded if this one fails too.
+        #[structopt(long)]
+        resume: bool,
+
+        /// Which of each image's files to download.
+        #[structopt(long, default_value = "0")]
+        file_index: usize,
+
+        /// The images: UUIDs or their first 8 or more digits, `name@version`, or names for their
+        /// latest active version.
+        #[structopt(required = true)]
+        images: Vec<ImageRef>,
+    },
+
+    /// Imports an image and its ancestors into a local store, in the layout `imgadm` exports
+    /// images in, skipping those already there. With --json, prints `{"downloaded": [...],
+    /// "present": [...], "bytes": ...}` with the UUIDs of the images from base to leaf. With
+    /// --dry-run, only prints what would be downloaded.
+    Import {
+        /// The store directory. Defaults to `~/.local/share/img/images`.
+        #[structopt(long)]
+        store: Option<PathBuf>,
+
+        /// The image: a UUID or its first 8 or more digits, `name@version`, or a name for its
+        /// latest active version.
+        image: ImageRef,
+    },
+
+    /// Copies an image from one configured source to another, keeping its UUID, e.g. to promote it
+    /// from a build server to production. This needs admin access to the destination
+    /// (AdminImportImage). What's already there and what will be copied are listed first, with
+    /// the total size, and with --dry-run that's all. Copying stops at the first image that fails,
+    /// and img then lists which images were copied and which weren't. With --json, prints
+    /// `{"copied": [...], "present": [...], "failed": ..., "not_copied": [...], "bytes": ...}`.
+    Copy {
+        /// The source to copy from, by name.
+        #[structopt(long)]
+        from: String,
+
+        /// The source to copy to, by name.
+        #[structopt(long)]
+        to: String,
+
+        /// Copy the ancestors the destination is missing too. Without this, the image's origin
+        /// has to be there already.
+        #[structopt(long)]
+        include_ancestry: bool,
+
+        /// Activate the image once it's copied. Otherwise it's left unactivated, to be activated
+        /// with `img activate`. Ancestors are always activated.
+        #[structopt(long)]
+        activate: bool,
+
+        /// The image on the source, as for `img info`.
+        image: ImageRef,
+    },
+
+    /// Creates an image from a manifest (CreateImage), uploads its file, and prints its UUID. The
+    /// manifest is validated locally first. With --json, prints the image's manifest.
+    Create {
+        /// The manifest, or `-` to read it from stdin.
+        #[structopt(short, long)]
+        manifest: PathBuf,
+
+        /// The image's file.
+        #[structopt(short, long)]
+        file: PathBuf,
+
+        /// The file's compression: bzip2, gzip, xz or none, or auto to detect it from the file's
+        /// contents.
+        #[structopt(long, default_value = "auto")]
+        compression: Compression,
+
+        /// Activate the image once its file is uploaded.
+        #[structopt(long)]
+        activate: bool,
+    },
+
+    /// Checks a local manifest for problems before it's used with `img create`, printing each
+    /// issue found with the field it's about. Exits non-zero if there are errors, but not for
+    /// warnings alone unless --strict is given. With --json, prints an array of the issues.
+    Validate {
+        /// Also check that this file matches the manifest's size, SHA-1 and compression.
+        #[structopt(short, long)]
+        file: Option<PathBuf>,
+
+        /// Fail on warnings too.
+        #[structopt(long)]
+        strict: bool,
+
+        /// The manifest, or `-` to read it from stdin.
+        manifest: PathBuf,
+    },
+
+    /// Changes the mutable fields of an image (UpdateImage), after showing what would change and
+    /// asking for confirmation. With --json, prints the updated manifest.
+    Update {
+        /// Send the UpdateImage payload in this JSON file, or `-` for stdin, instead of making
+        /// key=value changes.
+        #[structopt(long, conflicts_with = "changes", required_unless = "changes")]
+        file: Option<PathBuf>,
+
+        /// Don't ask for confirmation.
+        #[structopt(short, long)]
+        yes: bool,
+
+        /// The image, as for `img info`.
+        image: ImageRef,
+
+        /// Changes in `key=value` form: name, version, description, homepage, public,
+        /// billing_tags (separated by commas), `tag.<key>`, or `acl+` or `acl-` with an account
+        /// UUID to give or take away access, e.g. `tag.role=db acl+=<uuid>`.
+        changes: Vec<UpdateArg>,
+    },
+
+    /// Activates an image once its file has been added (ActivateImage), and prints its state. With
+    /// --json, prints the updated manifest.
+    Activate {
+        /// The image, as for `img info`.
+        image: ImageRef,
+    },
+
+    /// Enables a disabled image, so that it can be provisioned again, and prints its state. With
+    /// --json, prints the updated manifest.
+    Enable {
+        /// The image, as for `img info`.
+        image: ImageRef,
+    },
+
+    /// Disables an image, so that it can't be provisioned but is still listed, and prints its
+    /// state. With --json, prints the updated manifest.
+    Disable {
+        /// The image, as for `img info`.
+        image: ImageRef,
+    },
+
+    /// Gives accounts access to a private image (AddImageAcl), and prints its ACL. Without any
+    /// accounts, just prints the ACL. With --json, prints the updated manifest.
+    Share {
+        /// The image, as for `img info`.
+        image: ImageRef,
+
+        /// The UUIDs of the accounts.
+        #[structopt(parse(try_from_str = parse_account))]
+        accounts: Vec<Uuid>,
+    },
+
+    /// Takes access to a private image away from accounts (RemoveImageAcl), and prints its ACL.
+    /// With --json, prints the updated manifest.
+    Unshare {
+        /// The image, as for `img info`.
+        image: ImageRef,
+
+        /// The UUIDs of the accounts.
+        #[structopt(required = true, parse(try_from_str = parse_account))]
+        accounts: Vec<Uuid>,
+    },
+
+    /// Prints who can see an image: its owner, whether it's public or private, and the accounts
+    /// on its ACL, one per line. With --json, prints just the ACL, as an array of account UUIDs.
+    Acl {
+        /// Instead, say whether this account can see the image, as its owner, because the image is
+        /// public, or from the ACL, and exit with status 0 if it can and 1 if it can't. With
+        /// --json, prints `{"account": ..., "access": true}` or `false`.
+        #[structopt(long, value_name = "account", parse(try_from_str = parse_account))]
+        check: Option<Uuid>,
+
+        /// The image, as for `img info`.
+        image: ImageRef,
+    },
+
+    /// Lists, sets and removes an image's tags.
+    Tag(TagCommand),
+
+    /// Clones an image shared with the account given with --account (or the source's) into an
+    /// image that account owns (CloneImage), and prints the new image's UUID. The account has to
+    /// be a UUID. With --json, prints the new image's manifest.
+    Clone {
+        /// Wait for the new image to be active, for servers that clone images in the background.
+        #[structopt(long)]
+        wait: bool,
+
+        /// The image, as for `img info`.
+        image: ImageRef,
+    },
+
+    /// Exports an image's manifest and file to Manta (ExportImage), and prints their paths there.
+    /// With --json, prints `{"manta_url": ..., "image_path": ..., "manifest_path": ...}`.
+    Export {
+        /// The image, as for `img info`.
+        image: ImageRef,
+
+        /// Where to put the image in Manta: a directory ending in `/`, or the path to name the
+        /// files after, e.g. `/<account>/stor/images/base` for `base.imgmanifest` and
+        /// `base.zfs.gz`.
+        manta_path: String,
+    },
+
+    /// Waits for an image to be in a state, e.g. for an image being created to be active, noting
+    /// the states it goes through. Exits with status 4 if the image fails, printing why, and 5 if
+    /// it times out. With --json, prints the image's manifest once it's in the state.
+    Wait {
+        /// The state to wait for.
+        #[structopt(long, default_value = "active")]
+        state: ImageState,
+
+        /// How long to wait before giving up, e.g. `30s`, `10m` or `1h`. (--timeout is how long
+        /// each check may take.)
+        #[structopt(long, default_value = "10m", parse(try_from_str = duration::parse_duration))]
+        wait_timeout: Duration,
+
+        /// How often to check the image.
+        #[structopt(long, default_value = "5s", parse(try_from_str = duration::parse_duration))]
+        interval: Duration,
+
+        /// The image, as for `img info`.
+        image: ImageRef,
+    },
+
+    /// Adds an image to a channel (ChannelAddImage), and prints the channels it's in. With --json,
+    /// prints the updated manifest.
+    ChannelAdd {
+        /// The image, as for `img info`.
+        image: ImageRef,
+
+        /// The channel to add it to.
+        #[structopt(name = "CHANNEL")]
+        to: Channel,
+    },
+
+    /// Removes an image from a channel, and prints the channels it's still in. Removing an image
+    /// from the last channel it's in deletes it, which needs --force. With --json, prints the
+    /// updated manifest, or `{"uuid": ..., "deleted": true}`.
+    ChannelRemove {
+        /// Remove the image from its last channel, deleting it.
+        #[structopt(long)]
+        force: bool,
+
+        /// The image, as for `img info`.
+        image: ImageRef,
+
+        /// The channel to remove it from.
+        #[structopt(name = "CHANNEL")]
+        from: Channel,
+    },
+
+    /// Deletes an image (DeleteImage), after showing a summary of it and asking for confirmation.
+    /// With --channel, the image is only removed from that channel, unless it's in no other. With
+    /// --json, prints an array of the UUIDs of the deleted images.
+    Delete {
+        /// Don't ask for confirmation.
+        #[structopt(short = "f", long = "yes", alias = "force")]
+        yes: bool,
+
+        /// Also delete the images built on this one, most derived first, after asking again.
+        #[structopt(short, long)]
+        recursive: bool,
+
+        /// The image, as for `img info`.
+        image: ImageRef,
+    },
+
+    /// Lists the files of an image. With --json, prints the manifest's array of files, or with
+    /// --ancestry an array of `{"uuid": ..., "files": [...]}` objects from base to leaf.
+    Files {
+        /// List the files of every image in the origin chain, from the base image to this one.
+        #[structopt(long)]
+        ancestry: bool,
+
+        /// The columns to show, separated by commas: index, size, compression, sha1, digest and
+        /// uncompressed_digest. `default` is the first four, plus the digests for Docker images,
+        /// and `all` is every column.
+        #[structopt(short = "o", long = "output", default_value = "default")]
+        columns: FileColumns,
+
+        /// Leave out the header row.
+        #[structopt(short = "H")]
+        no_header: bool,
+
+        /// Print tab-separated values, with sizes in bytes. With --ancestry, the image UUID is
+        /// the first column.
+        #[structopt(short, long)]
+        parseable: bool,
+
+        /// The image: a UUID or its first 8 or more digits, `name@version`, or a name for its
+        /// latest active version.
+        image: ImageRef,
+    },
+
+    /// Lists the server's channels, marking the one in use with `*`. With --json, prints an array
+    /// of the server's channel objects, each with `"current": true` or `false`.
+    Channels {
+        /// Leave out the header row.
+        #[structopt(short = "H")]
+        no_header: bool,
+
+        /// Print tab-separated values.
+        #[structopt(short, long)]
+        parseable: bool,
+    },
+
+    /// Lists the node-triton profiles, in `~/.triton/profiles.json` and `~/.triton/profiles.d`,
+    /// marking the one in use with `*`. A profile's IMGAPI server is its `imgapiUrl`, or else its
+    /// CloudAPI URL with the host's `cloudapi.` replaced with `imgapi.`. With --json, prints an
+    /// array of the profiles, each with a `current` field.
+    Profiles {
+        /// Leave out the header row.
+        #[structopt(short = "H")]
+        no_header: bool,
+
+        /// Print tab-separated values.
+        #[structopt(short, long)]
+        parseable: bool,
+    },
+
+    /// Manages the configured sources, in `~/.config/img/sources.json`.
+    Sources(SourcesCommand),
+
+    /// Manages the cached catalogs that --offline answers from.
+    Cache(CacheCommand),
+
+    /// Prints the manifest of an image.
+    ///
+    /// With --stdin, or `-` as the image, the UUIDs of the images are read from stdin, one per
+    /// line, and their manifests fetched --concurrency at a time. Images that can't be fetched are
+    /// printed in their place as `{"uuid": ..., "error": ...}`, and make img exit with status 1
+    /// once the rest have been printed.
+    Get {
+        /// Print the manifests exactly as the server returned them, rather than as this version of
+        /// img understands them.
+        #[structopt(long)]
+        raw: bool,
+
+        /// Read the UUIDs of the images from stdin, ignoring blank lines and `#` comments.
+        #[structopt(long)]
+        stdin: bool,
+
+        /// Print one manifest per line, rather than an array of them.
+        #[structopt(long)]
+        json_lines: bool,
+
+        /// The images, as for `img info`. With more than one, the manifests are printed as an
+        /// array.
+        #[structopt(required_unless = "stdin", conflicts_with = "stdin")]
+        images: Vec<ImageRef>,
+    },
+}
+
+/// An image that doesn't exist. `img` exits with status 3 for these, so that scripts can tell
+/// them apart from other failures.
+#[derive(Debug)]
+enum NotFound {
+    Image(Uuid),
+
+    /// No image matched what was asked for, e.g. by `img latest`.
+    NoMatch(String),
+}
+
+impl fmt::Display for NotFound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Image(uuid) => write!(f, "image {} not found", uuid),
+            Self::NoMatch(what) => write!(f, "no {}", what),
+        }
+    }
+}
+
+impl Error for NotFound {}
+
+/// A command line that doesn't make sense, found after parsing it, e.g. options that need each
+/// other. `img` exits with status 2 for these, as it does for ones that don't parse.
+#[derive(Debug)]
+struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for UsageError {}
+
+/// Replaces a 404 from the server for image `uuid` with [`NotFound`].
+fn not_found(uuid: &Uuid) -> impl FnOnce(Box<dyn Error>) -> Box<dyn Error> + '_ {
+    move |e| match e.downcast_ref::<ApiError>() {
+        Some(e) if e.is_not_found() => Box::new(NotFound::Image(*uuid)),
+        _ => e,
+    }
+}
 
 fn main() {
-    if let Err(e) = process() {
-        eprintln!("error: {}", e);
-    }
-}
-
-fn process() -> Result<(), Box<dyn Error>> {
-    let mut filter = imgapi::ImageFilter::default();
-    for arg in env::args() {
-        if let Some((k, v)) = arg.split_once("=") {
-            let v = v.to_string();
-            match k {
-                "account" => {
-                    filter.account =
-                        Some(Uuid::parse_str(&v).map_err(|_| "account must be a valid UUID")?)
-                }
-                "channel" => filter.channel = Some(v),
-                "inclAdminFields" => {
-                    filter.include_admin_fields = Some(
-                        bool::from_str(&v)
-                            .map_err(|_| "inclAdminFields must be either true or false")?,
-                    )
+    let (result, color) = match Opt::from_iter_safe(env::args_os()) {
+        Ok(opt) => {
+            logging::init(opt.verbose);
+            let color = opt.color.stderr();
+            let json = opt.json;
+            let result = process(opt).or_else(|e| match e.downcast_ref::<DryRun>() {
+                Some(request) => Ok(print_request(request, json)?),
+                None => Err(e),
+            });
+            (result, color)
+        }
+        // --help and --version aren't errors, and what they print is what was asked for.
+        Err(e) if !e.use_stderr() => {
+            let result = writeln!(io::stdout().lock(), "{}", e.message);
+            (result.map_err(Into::into), false)
+        }
+        Err(e) => {
+            eprintln!("{}", e.message);
+            std::process::exit(2);
+        }
+    };
+    match result {
+        Ok(()) => {}
+        // Whatever was reading stdout, e.g. `head -1`, has all it wanted.
+        Err(e) if broken_pipe(e.as_ref()) => {}
+        Err(e) => {
+            eprintln!("{} {}", style::error_label(color), e);
+            std::process::exit(exit_code(e.as_ref()));
+        }
+    }
+}
+
+/// Whether `e` came from writing to a pipe that was closed, as it is by `img list | head -1`. That
+/// isn't a failure, so `img` exits quietly with status 0, rather than panicking as `println!`
+/// would.
+fn broken_pipe(e: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(e);
+    while let Some(e) = source {
+        if let Some(e) = e.downcast_ref::<io::Error>() {
+            return e.kind() == io::ErrorKind::BrokenPipe;
+        }
+        source = e.source();
+    }
+    false
+}
+
+/// What the client sends with --dry-run: nothing for commands that only read, and only reads for
+/// commands that change something, so that they get as far as the first change. Import's and
+/// copy's dry runs are their own, which look images up to say what they would download or copy.
+fn dry_run(opt: &Opt) -> DryRunMode {
+    if !opt.dry_run {
+        return DryRunMode::Off;
+    }
+    match opt.cmd {
+        Command::Import { .. } | Command::Copy { .. } => DryRunMode::Off,
+        Command::Create { .. }
+        | Command::Update { .. }
+        | Command::Activate { .. }
+        | Command::Enable { .. }
+        | Command::Disable { .. }
+        | Command::Share { .. }
+        | Command::Tag(TagCommand::Add { .. })
+        | Command::Tag(TagCommand::Rm { .. })
+        | Command::Unshare { .. }
+        | Command::Clone { .. }
+        | Command::Export { .. }
+        | Command::ChannelAdd { .. }
+        | Command::ChannelRemove { .. }
+        | Command::Delete { .. } => DryRunMode::Changes,
+        _ => DryRunMode::All,
+    }
+}
+
+/// Whether `cmd` only looks images up, so that it can answer from a cached catalog offline.
+fn works_offline(cmd: &Command) -> bool {
+    matches!(
+        cmd,
+        Command::List { .. }
+            | Command::Search { .. }
+            | Command::Latest { .. }
+            | Command::Get { .. }
+            | Command::Info { .. }
+            | Command::Files { .. }
+            | Command::Ancestry { .. }
+            | Command::Diff { .. }
+            | Command::Acl { .. }
+            | Command::Tag(TagCommand::Ls { .. })
+    )
+}
+
+/// Prints a request that wasn't sent because of --dry-run, like the start of an HTTP request:
+/// the method and URL, the headers, and then any body, pretty-printed if it's JSON. With `json`,
+/// prints `{"method": ..., "url": ..., "headers": {...}, "body": ...}` instead, where the body is
+/// JSON, a string, or null.
+fn print_request(request: &DryRun, json: bool) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    if json {
+        let body = request.body.as_ref().map(|body| {
+            serde_json::from_slice::<serde_json::Value>(body)
+                .unwrap_or_else(|_| String::from_utf8_lossy(body).into_owned().into())
+        });
+        let headers: serde_json::Map<_, _> = request
+            .headers
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone().into()))
+            .collect();
+        let value = serde_json::json!({
+            "method": request.method,
+            "url": request.url,
+            "headers": headers,
+            "body": body,
+        });
+        writeln!(stdout, "{:#}", value)?;
+        return Ok(());
+    }
+    writeln!(stdout, "{} {}", request.method, request.url)?;
+    for (name, value) in &request.headers {
+        writeln!(stdout, "{}: {}", name, value)?;
+    }
+    if let Some(body) = &request.body {
+        writeln!(stdout)?;
+        match serde_json::from_slice::<serde_json::Value>(body) {
+            Ok(json) => writeln!(stdout, "{:#}", json)?,
+            Err(_) => writeln!(stdout, "{}", String::from_utf8_lossy(body))?,
+        }
+    } else if request.streamed_body {
+        writeln!(stdout)?;
+        writeln!(stdout, "(a streamed body, such as an image file)")?;
+    }
+    Ok(())
+}
+
+/// The status `img` exits with after `e`: 2 if the command line didn't make sense, 3 if an image
+/// wasn't found, 4 if the server refused or failed to do what was asked, or an image failed, 5 if
+/// a request or waiting for an image timed out, 6 if a download didn't match its checksum, and 1
+/// for anything else, including a change that wasn't confirmed. These are listed in
+/// [`EXIT_STATUS`] too.
+fn exit_code(e: &(dyn Error + 'static)) -> i32 {
+    match e.downcast_ref::<WaitError>() {
+        Some(WaitError::Failed { .. }) => return 4,
+        Some(WaitError::TimedOut { .. }) => return 5,
+        None => {}
+    }
+    if e.is::<UsageError>() {
+        2
+    } else if e.is::<NotFound>() {
+        3
+    } else if e.is::<RequestTimeout>() {
+        5
+    } else if e.is::<ApiError>() {
+        4
+    } else if e.is::<ChecksumMismatch>() || e.is::<TransportChecksumMismatch>() {
+        6
+    } else {
+        1
+    }
+}
+
+/// Parses a `--url` or `IMGAPI_URL` value, which must be an absolute http or https URL.
+fn parse_server_url(s: &str) -> Result<Url, String> {
+    let url = Url::parse(s).map_err(|e| format!("{:?} is not a valid URL: {}", s, e))?;
+    match url.scheme() {
+        "http" | "https" => Ok(url),
+        scheme => Err(format!(
+            "{:?} is not an http or https URL (the scheme is {})",
+            s, scheme
+        )),
+    }
+}
+
+/// Parses a --concurrency value, which must be between 1 and [`MAX_CONCURRENCY`].
+fn parse_concurrency(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(n) if (1..=MAX_CONCURRENCY).contains(&n) => Ok(n),
+        _ => Err(format!(
+            "{:?} isn't a number from 1 to {}",
+            s, MAX_CONCURRENCY
+        )),
+    }
+}
+
+/// Parses an account UUID, e.g. for an ACL.
+fn parse_account(s: &str) -> Result<Uuid, String> {
+    Uuid::parse_str(s).map_err(|_| format!("{:?} isn't an account UUID", s))
+}
+
+/// Works out which server to use: the one given with --url, -S or --profile, then IMGAPI_URL,
+/// then TRITON_PROFILE, then the default source, and finally the Joyent public server. The source
+/// is returned too, if the server is one, with a profile standing in for one.
+fn server(opt: &Opt) -> Result<(Url, Option<Source>), Box<dyn Error>> {
+    if let Some(url) = &opt.url {
+        return Ok((url.clone(), None));
+    }
+    let config = || SourcesConfig::load(&sources::config_path()?);
+    if let Some(name) = &opt.source {
+        let source = config()?.get(name)?.clone();
+        return Ok((source.url.clone(), Some(source)));
+    }
+    if let Some(name) = &opt.profile {
+        return profile_server(name);
+    }
+    if let Ok(url) = env::var("IMGAPI_URL") {
+        let url = parse_server_url(&url).map_err(|e| format!("IMGAPI_URL: {}", e))?;
+        return Ok((url, None));
+    }
+    if let Some(name) = env_profile() {
+        return profile_server(&name)
+            .map_err(|e| format!("{}: {}", profiles::PROFILE_VAR, e).into());
+    }
+    match config()?.default_source() {
+        Some(source) => Ok((source.url.clone(), Some(source.clone()))),
+        None => Ok((Url::parse(imgapi::JOYENT_IMGAPI_SERVER)?, None)),
+    }
+}
+
+/// A client for `source`, as img uses one with -S: with its channel, credentials and --insecure,
+/// unless they're given on the command line.
+fn source_client(
+    opt: &Opt,
+    config: &SourcesConfig,
+    source: &Source,
+) -> Result<Client, Box<dyn Error>> {
+    let channel = match &opt.channel {
+        Some(channel) => Some(channel.clone()),
+        None => config.channel_for(Some(source)).cloned(),
+    };
+    let insecure = opt.insecure || source.insecure;
+    if insecure {
+        eprintln!(
+            "warning: not checking the TLS certificate of {}",
+            source.url.host_str().unwrap_or_default()
+        );
+    }
+    info!("source {}: server {}", source.name, source.url);
+    Ok(Client::new(source.url.as_str())?
+        .with_timeouts(Some(opt.timeout), opt.connect_timeout)?
+        .with_insecure(insecure)?
+        .with_dry_run(dry_run(opt))
+        .with_channel(channel)
+        .with_signer(opt.auth.clone().or_source(Some(source)).signer()?))
+}
+
+/// The profile named in TRITON_PROFILE, if it's set.
+fn env_profile() -> Option<String> {
+    env::var(profiles::PROFILE_VAR)
+        .ok()
+        .filter(|p| !p.is_empty())
+}
+
+/// The server and credentials of the node-triton profile called `name`.
+fn profile_server(name: &str) -> Result<(Url, Option<Source>), Box<dyn Error>> {
+    let profiles = profiles::load(&dirs::triton_dir()?)?;
+    let source = profiles::find(&profiles, name)?.to_source()?;
+    Ok((source.url.clone(), Some(source)))
+}
+
+fn process(opt: Opt) -> Result<(), Box<dyn Error>> {
+    let out = Output {
+        json: opt.json,
+        compact: opt.compact,
+        color: opt.color.stdout(),
+    };
+    let mut stdout = io::stdout().lock();
+    // --json is a global option, so clap only catches it conflicting with -q when it comes after
+    // the subcommand.
+    if opt.json
+        && matches!(
+            opt.cmd,
+            Command::List { quiet: true, .. }
+                | Command::Search { quiet: true, .. }
+                | Command::Latest { quiet: true, .. }
+        )
+    {
+        return Err(UsageError("--json can't be used with --quiet".to_string()).into());
+    }
+    if let Command::Sources(cmd) = opt.cmd {
+        return sources::run(cmd, opt.channel, &opt.auth, opt.insecure, &out);
+    }
+    if let Command::Validate {
+        file,
+        strict,
+        manifest,
+    } = opt.cmd
+    {
+        let manifest = create::read_manifest(&manifest)?;
+        let issues = validate::validate(&manifest, file.as_deref())?;
+        if out.json {
+            out.write_json(&validate::to_json(&issues))?;
+        } else {
+            for issue in &issues {
+                writeln!(stdout, "{}", issue)?;
+            }
+        }
+        return if validate::fails(&issues, strict) {
+            Err(validate::summary(&issues).into())
+        } else {
+            Ok(())
+        };
+    }
+    if let Command::Profiles {
+        no_header,
+        parseable,
+    } = opt.cmd
+    {
+        let profiles = profiles::load(&dirs::triton_dir()?)?;
+        let current = opt.profile.clone().or_else(env_profile);
+        if let Some(name) = current.as_deref() {
+            if profiles::find(&profiles, name).is_err() {
+                eprintln!("warning: there's no profile {:?}", name);
+            }
+        }
+        return if out.json {
+            out.write_json(&profiles::to_json(&profiles, current.as_deref())?)
+        } else {
+            write!(
+                stdout,
+                "{}",
+                profiles::render(&profiles, current.as_deref(), no_header, parseable)
+            )?;
+            Ok(())
+        };
+    }
+    if let Command::Copy {
+        from,
+        to,
+        include_ancestry,
+        activate,
+        image,
+    } = &opt.cmd
+    {
+        if opt.offline {
+            let e = "img copy needs both servers, so it can't be used with --offline";
+            return Err(UsageError(e.to_string()).into());
+        }
+        if from == to {
+            let e = format!("--from and --to are both {}", from);
+            return Err(UsageError(e).into());
+        }
+        let config = SourcesConfig::load(&sources::config_path()?)?;
+        let src = source_client(&opt, &config, config.get(from)?)?;
+        let dst = source_client(&opt, &config, config.get(to)?)?;
+        let uuid = resolve(&src, image)?;
+        let plan =
+            copy::plan(&src, &dst, &uuid, *include_ancestry, to).map_err(not_found(&uuid))?;
+        copy::print_plan(&plan, to, opt.dry_run);
+        let report = if opt.dry_run {
+            copy::CopyReport {
+                copied: plan.missing.iter().map(|i| i.uuid).collect(),
+                present: plan.present.iter().map(|i| i.uuid).collect(),
+                bytes: plan.bytes(),
+                ..copy::CopyReport::default()
+            }
+        } else {
+            copy::copy(&src, &dst, &plan, *activate, opt.color.stderr())
+        };
+        if out.json {
+            out.write_json(&serde_json::to_value(&report)?)?;
+        }
+        return match &report.failed {
+            None => {
+                if !out.json {
+                    writeln!(
+                        stdout,
+                        "{} {} image(s) ({}) to {}, {} already there",
+                        if opt.dry_run { "would copy" } else { "copied" },
+                        report.copied.len(),
+                        imgapi::size::format_size(report.bytes),
+                        to,
+                        report.present.len()
+                    )?;
                 }
-                "owner" => {
-                    filter.owner =
-                        Some(Uuid::parse_str(&v).map_err(|_| "owner must be a valid UUID")?)
+                Ok(())
+            }
+            Some(failure) => {
+                for uuid in &report.copied {
+                    eprintln!("copied to {}: {}", to, uuid);
                 }
-                // "state" => filter.state = Some(imgapi::ImageState.pa
-                "name" => filter.name = Some(v),
-                "version" => filter.version = Some(v),
-                "public" => {
-                    filter.public = Some(
-                        bool::from_str(&v).map_err(|_| "public must be either true or false")?,
-                    )
+                eprintln!("not copied: {} ({})", failure.uuid, failure.error);
+                for uuid in &report.not_copied {
+                    eprintln!("not copied: {} (an image before it failed)", uuid);
+                }
+                Err(format!(
+                    "copied {} of {} images to {}",
+                    report.copied.len(),
+                    plan.missing.len(),
+                    to
+                )
+                .into())
+            }
+        };
+    }
+    let (url, source) = server(&opt)?;
+    // Without a config directory, there's no config to take settings from.
+    let config = || match sources::config_path() {
+        Ok(path) => SourcesConfig::load(&path),
+        Err(_) => Ok(SourcesConfig::default()),
+    };
+    let channel = match &opt.channel {
+        Some(channel) => Some(channel.clone()),
+        None => config()?.channel_for(source.as_ref()).cloned(),
+    };
+    let auth = opt.auth.clone().or_source(source.as_ref());
+    let insecure = opt.insecure || source.as_ref().is_some_and(|s| s.insecure);
+    let mut client = Client::new(url.as_str())?
+        .with_timeouts(Some(opt.timeout), opt.connect_timeout)?
+        .with_insecure(insecure)?
+        .with_dry_run(dry_run(&opt))
+        .with_channel(channel)
+        .with_signer(auth.signer()?);
+    let mut shown = url.clone();
+    if shown.password().is_some() {
+        let _ = shown.set_password(Some("REDACTED"));
+    }
+    match &source {
+        Some(source) => info!("server {} (source {})", shown, source.name),
+        None => info!("server {}", shown),
+    }
+    let mut offline = opt.offline;
+    if let Command::Cache(_) = opt.cmd {
+        if offline {
+            let e = "img cache refresh needs the server, so it can't be used with --offline";
+            return Err(UsageError(e.to_string()).into());
+        }
+        client = client.with_cache(offline::cache_for(&url)?, CacheMode::Off);
+    } else if offline && !works_offline(&opt.cmd) {
+        let e = "this command needs the server, so it can't be used with --offline";
+        return Err(UsageError(e.to_string()).into());
+    } else if offline || (works_offline(&opt.cmd) && !opt.dry_run && config()?.offline_fallback) {
+        let cache = offline::cache_for(&url)?;
+        let catalog = cache.catalog(client.channel());
+        if !offline && catalog.is_some() {
+            let timeout = opt.connect_timeout.unwrap_or(offline::PROBE_TIMEOUT);
+            if let Err(e) = offline::reachable(&url, timeout) {
+                eprintln!("warning: can't reach {} ({}), so going offline", shown, e);
+                offline = true;
+            }
+        }
+        if offline {
+            let catalog = catalog.ok_or_else(|| {
+                let channel = match client.channel() {
+                    Some(channel) => format!(" for channel {}", channel),
+                    None => String::new(),
+                };
+                format!(
+                    "there's no cached catalog of {}{}; run `img cache refresh` while it can be \
+                     reached",
+                    shown, channel
+                )
+            })?;
+            eprintln!(
+                "warning: offline: showing the catalog of {} cached {} ago ({} images)",
+                shown,
+                offline::format_age(catalog.age),
+                catalog.images.len()
+            );
+            client = client.with_cache(cache, CacheMode::OfflineOnly);
+        }
+    }
+    if insecure && !offline {
+        eprintln!(
+            "warning: not checking the TLS certificate of {}",
+            url.host_str().unwrap_or_default()
+        );
+    }
+    if let Some(channel) = client.channel() {
+        info!("channel {}", channel);
+    }
+    match client.signer() {
+        Some(signer) => info!("signing requests with {}", signer.key_id()),
+        None => info!("not signing requests"),
+    }
+    match opt.cmd {
+        Command::List {
+            all,
+            limit,
+            columns,
+            sort,
+            no_header,
+            parseable,
+            count,
+            quiet,
+            flags,
+            filters,
+        } => {
+            let mut filter = filter::build(filters, &flags, auth.account.as_deref())?;
+            match (limit, filter.limit) {
+                (Some(_), Some(_)) => {
+                    let e = "--limit and a limit= filter can't both be given";
+                    return Err(UsageError(e.to_string()).into());
                 }
-                "os" => {
-                    filter.os = Some(imgapi::OperatingSystem::from_str(&v).map_err(|_| {
-                        "os must be one of: smartos, linux, windows, bsd, illumos, other"
-                    })?)
+                (None, Some(_)) if all => {
+                    let e = "--all lists every image, so it can't be used with a limit= filter";
+                    return Err(UsageError(e.to_string()).into());
                 }
-                "type" => filter.image_type = Some(v),
-                "tag" => todo!(),
-                "billing_tag" => match filter.billing_tag {
-                    Some(ref mut tags) => tags.push(v),
-                    None => filter.billing_tag = Some(vec![v]),
+                (Some(limit), None) => filter.limit = Some(limit),
+                _ => {}
+            }
+            let mut images = match filter.limit {
+                // Asking for one more than the limit says whether there are more.
+                Some(limit) => {
+                    filter.limit = Some(limit.saturating_add(1));
+                    let mut images = list_all(&client, &filter)?;
+                    if images.len() > limit as usize {
+                        images.truncate(limit as usize);
+                        eprintln!(
+                            "note: there are more than {} matching images; use --all to list \
+                             them all",
+                            limit
+                        );
+                    }
+                    images
+                }
+                None if all => list_all(&client, &filter)?,
+                None => {
+                    let images = client.list(Some(&filter))?;
+                    if images.len() >= imgapi::MAX_PAGE_SIZE as usize {
+                        eprintln!(
+                            "note: only the server's first page of {} images was listed, and \
+                             there may be more; use --all to list them all, or --limit",
+                            images.len()
+                        );
+                    }
+                    images
+                }
+            };
+            if count {
+                if out.json {
+                    out.write_json(&serde_json::json!({ "count": images.len() }))?;
+                } else {
+                    writeln!(stdout, "{}", images.len())?;
+                }
+                return Ok(());
+            }
+            imgapi::sort_images_by(&mut images, &sort.0);
+            if quiet {
+                return Ok(print_uuids(&images)?);
+            }
+            let table = Table {
+                columns: columns.0,
+                no_header,
+                parseable,
+                bytes: opt.bytes,
+                color: out.color,
+            };
+            out.images(&images, &table)?;
+        }
+        Command::Search {
+            regex,
+            columns,
+            no_header,
+            parseable,
+            quiet,
+            flags,
+            terms,
+        } => {
+            let search = search::Search::new(&terms, regex)?;
+            let filter = filter::build(Vec::new(), &flags, auth.account.as_deref())?;
+            let images = list_all(&client, &filter)?;
+            let matches = search.run(&images);
+            if quiet {
+                print_uuids(matches.iter().map(|m| m.image))?;
+            } else if out.json {
+                out.write_json(&search::to_json(&matches)?)?;
+            } else {
+                let table = Table {
+                    columns: columns.0,
+                    no_header,
+                    parseable,
+                    bytes: opt.bytes,
+                    color: out.color,
+                };
+                let images: Vec<Image> = matches.iter().map(|m| m.image.clone()).collect();
+                write!(stdout, "{}", table.render(&images))?;
+            }
+        }
+        Command::Latest {
+            quiet,
+            flags,
+            filters,
+        } => {
+            let filter = filter::build(filters, &flags, auth.account.as_deref())?;
+            let name = match &filter.name {
+                Some(name) if !name.starts_with('~') => name.clone(),
+                _ => {
+                    let e = "img latest needs an exact name=<name> filter";
+                    return Err(UsageError(e.to_string()).into());
+                }
+            };
+            let images = list_all(&client, &filter)?;
+            let image = imgapi::latest_by_name(&images, &name)
+                .ok_or_else(|| NotFound::NoMatch(format!("active image named {:?}", name)))?;
+            if quiet {
+                print_uuids([image])?;
+            } else if out.json {
+                out.write_json(&image.to_json()?)?;
+            } else if opt.verbose > 0 {
+                let table = Table {
+                    columns: Columns::default().0,
+                    no_header: false,
+                    parseable: false,
+                    bytes: opt.bytes,
+                    color: out.color,
+                };
+                write!(stdout, "{}", table.render(std::slice::from_ref(image)))?;
+            } else {
+                writeln!(stdout, "{}", image.uuid)?;
+            }
+        }
+        Command::Get {
+            raw,
+            stdin,
+            json_lines,
+            images,
+        } => {
+            if stdin || matches!(images.as_slice(), [ImageRef::Name(n)] if n == "-") {
+                let entries = bulk::read_uuids(io::stdin().lock())?;
+                let (manifests, failed) = bulk::get(&client, &entries, raw, opt.concurrency);
+                if json_lines {
+                    out.json_lines(&manifests)?;
+                } else {
+                    out.write_json(&serde_json::Value::Array(manifests))?;
+                }
+                if failed > 0 {
+                    return Err(format!(
+                        "{} of {} images couldn't be fetched",
+                        failed,
+                        entries.len()
+                    )
+                    .into());
+                }
+                return Ok(());
+            }
+            let mut manifests = Vec::new();
+            for image in &images {
+                let uuid = &resolve(&client, image)?;
+                manifests.push(
+                    if raw {
+                        client.get_raw(uuid, None)
+                    } else {
+                        client.get(uuid).and_then(|i| Ok(i.to_json()?))
+                    }
+                    .map_err(not_found(uuid))?,
+                );
+            }
+            if json_lines {
+                out.json_lines(&manifests)?;
+            } else {
+                out.manifests(manifests)?;
+            }
+        }
+        Command::Info { image } => {
+            let uuid = resolve(&client, &image)?;
+            let image = client.get(&uuid).map_err(not_found(&uuid))?;
+            if out.json {
+                out.write_json(&image.to_json()?)?;
+            } else {
+                write!(stdout, "{}", info::render(&image, opt.bytes, out.color))?;
+            }
+        }
+        Command::Files {
+            ancestry,
+            columns,
+            no_header,
+            parseable,
+            image,
+        } => {
+            let uuid = resolve(&client, &image)?;
+            let images = if ancestry {
+                client
+                    .get_ancestry(&uuid)
+                    .map_err(not_found(&uuid))?
+                    .into_vec()
+            } else {
+                vec![client.get(&uuid).map_err(not_found(&uuid))?]
+            };
+            if out.json {
+                let files = |image: &Image| -> Result<_, Box<dyn Error>> {
+                    Ok(image.to_json()?["files"].take())
+                };
+                let value = if ancestry {
+                    images
+                        .iter()
+                        .map(|i| Ok(serde_json::json!({ "uuid": i.uuid, "files": files(i)? })))
+                        .collect::<Result<_, Box<dyn Error>>>()?
+                } else {
+                    files(&images[0])?
+                };
+                return out.write_json(&value);
+            }
+            let table = FilesTable {
+                columns,
+                no_header,
+                parseable,
+                bytes: opt.bytes,
+            };
+            if ancestry {
+                write!(stdout, "{}", table.render_chain(&images))?;
+            } else {
+                write!(stdout, "{}", table.render(&images[0]))?;
+            }
+        }
+        Command::Ancestry {
+            no_header,
+            parseable,
+            image,
+        } => {
+            let uuid = resolve(&client, &image)?;
+            let links = ancestry::walk(&client, &uuid)?;
+            if out.json {
+                out.write_json(&ancestry::to_json(&links)?)?;
+            } else {
+                write!(
+                    stdout,
+                    "{}",
+                    ancestry::render(&links, no_header, parseable, out.color)
+                )?;
+            }
+            if let Some(e) = ancestry::broken(&links) {
+                return Err(e.into());
+            }
+        }
+        Command::Diff {
+            format,
+            first,
+            second,
+        } => {
+            let a = diff::load(&client, &first)?;
+            let b = diff::load(&client, &second)?;
+            let changes = imgapi::diff(&a, &b);
+            if format == DiffFormat::JsonPatch {
+                out.write_json(&diff::json_patch(&a, &b)?)?;
+            } else if out.json {
+                out.write_json(&serde_json::to_value(&changes)?)?;
+            } else {
+                write!(stdout, "{}", diff::render(&changes, out.color))?;
+            }
+            if !changes.is_empty() {
+                // As with diff(1), differing isn't an error, but scripts can still tell.
+                stdout.flush()?;
+                std::process::exit(1);
+            }
+        }
+        Command::Download {
+            dir,
+            output,
+            decompress,
+            resume,
+            file_index,
+            images,
+        } => {
+            let opts = DownloadOptions {
+                decompress: if decompress {
+                    Decompress::Auto
+                } else {
+                    Decompress::Keep
                 },
-                "limit" => {
-                    filter.limit = Some(u32::from_str(&v).map_err(|_| "limit must be an integer")?)
+                resume,
+                keep_partial: resume,
+                ..DownloadOptions::default()
+            };
+            let image = match images.as_slice() {
+                [image] => image,
+                _ if output.is_some() => {
+                    let e = "--output is for a single image; use --dir to download several";
+                    return Err(UsageError(e.to_string()).into());
+                }
+                _ => {
+                    let download = Download {
+                        dir: dir.unwrap_or_default(),
+                        file_index,
+                        decompress,
+                        opts,
+                    };
+                    let color = opt.color.stderr();
+                    return download_many(
+                        &client,
+                        &out,
+                        &images,
+                        &download,
+                        opt.concurrency,
+                        color,
+                    );
+                }
+            };
+            let uuid = resolve(&client, image)?;
+            let image = client.get(&uuid).map_err(not_found(&uuid))?;
+            let file = nth_file(&image, file_index)?;
+            let dest = match output {
+                Some(path) => path,
+                None => dir
+                    .unwrap_or_default()
+                    .join(download_name(&image, file_index, decompress)),
+            };
+            let mut bar = ProgressBar::new("downloaded", opt.color.stderr());
+            let result = client
+                .download_file_with_progress(&uuid, file_index, &dest, &opts, |p| bar.update(p));
+            bar.finish();
+            let report = result?;
+            if out.json {
+                out.write_json(&serde_json::json!({
+                    "path": dest,
+                    "sha1": report.sha1,
+                    "bytes": report.bytes,
+                    "size": file.size,
+                }))?;
+            } else {
+                writeln!(stdout, "{}  {}", report.sha1, dest.display())?;
+            }
+        }
+        Command::Import { store, image } => {
+            let dry_run = opt.dry_run;
+            let uuid = resolve(&client, &image)?;
+            let dir = match store {
+                Some(dir) => dir,
+                None => import::default_store()?,
+            };
+            let mut store = import::Store::open(&dir)?;
+            let color = opt.color.stderr();
+            let report =
+                import::import(&client, &mut store, &uuid, dry_run, opt.concurrency, color)
+                    .map_err(not_found(&uuid))?;
+            if out.json {
+                out.write_json(&serde_json::to_value(&report)?)?;
+            } else {
+                writeln!(
+                    stdout,
+                    "{} {} image(s) ({}), {} already present",
+                    if dry_run { "would import" } else { "imported" },
+                    report.downloaded.len(),
+                    imgapi::size::format_size(report.bytes),
+                    report.present.len()
+                )?;
+            }
+        }
+        Command::Channels {
+            no_header,
+            parseable,
+        } => {
+            let channels =
+                client
+                    .list_channels()
+                    .map_err(|e| match e.downcast_ref::<ApiError>() {
+                        Some(e) if e.is_not_found() => {
+                            format!("{} doesn't support channels", client.base_url()).into()
+                        }
+                        _ => e,
+                    })?;
+            let current = channels::current(&channels, client.channel());
+            if let Some(c) = client
+                .channel()
+                .filter(|c| !channels.iter().any(|i| &i.name == *c))
+            {
+                eprintln!("warning: the server has no channel {:?}", c.as_str());
+            }
+            if out.json {
+                out.write_json(&channels::to_json(&channels, current)?)?;
+            } else {
+                write!(
+                    stdout,
+                    "{}",
+                    channels::render(&channels, current, no_header, parseable)
+                )?;
+            }
+        }
+        Command::Create {
+            manifest,
+            file,
+            compression,
+            activate,
+        } => {
+            let manifest = create::read_manifest(&manifest)?;
+            let image = create::create(
+                &client,
+                &manifest,
+                &file,
+                compression,
+                activate,
+                opt.color.stderr(),
+            )?;
+            if out.json {
+                out.write_json(&image.to_json()?)?;
+            } else {
+                writeln!(stdout, "{}", image.uuid)?;
+            }
+        }
+        Command::Update {
+            file,
+            yes,
+            image,
+            changes,
+        } => {
+            update::check_repeats(&changes)?;
+            let uuid = resolve(&client, &image)?;
+            let before = client.get(&uuid).map_err(not_found(&uuid))?;
+            let update = match file {
+                Some(path) => update::read_file(&path)?,
+                None => update::build(&before, changes)?,
+            };
+            let mut after = before.clone();
+            update.apply_to(&mut after);
+            let diff = imgapi::diff(&before, &after);
+            if diff.is_empty() {
+                eprintln!("nothing to change");
+                return if out.json {
+                    out.write_json(&before.to_json()?)
+                } else {
+                    Ok(())
+                };
+            }
+            // Keep stdout for the manifest.
+            if out.json {
+                eprint!("{}", diff);
+            } else {
+                write!(stdout, "{}", diff)?;
+            }
+            confirm::confirm(
+                &format!("Update {}@{} ({})?", before.name, before.version, uuid),
+                yes || opt.dry_run,
+            )?;
+            let image = client.update(&uuid, &update)?;
+            if out.json {
+                out.write_json(&image.to_json()?)?;
+            }
+        }
+        Command::Activate { image } => change_state(&client, &out, &image, StateChange::Activate)?,
+        Command::Enable { image } => change_state(&client, &out, &image, StateChange::Enable)?,
+        Command::Disable { image } => change_state(&client, &out, &image, StateChange::Disable)?,
+        Command::Share { image, accounts } => {
+            let uuid = resolve(&client, &image)?;
+            let mut image = client.get(&uuid).map_err(not_found(&uuid))?;
+            if !accounts.is_empty() {
+                refuse_public(&image)?;
+                image = client.add_acl(&uuid, &accounts)?;
+            }
+            print_acl(&out, &image)?;
+        }
+        Command::Unshare { image, accounts } => {
+            let uuid = resolve(&client, &image)?;
+            let image = client.get(&uuid).map_err(not_found(&uuid))?;
+            refuse_public(&image)?;
+            print_acl(&out, &client.remove_acl(&uuid, &accounts)?)?;
+        }
+        Command::Acl { check, image } => {
+            let uuid = resolve(&client, &image)?;
+            let image = client.get(&uuid).map_err(not_found(&uuid))?;
+            match check {
+                Some(account) => {
+                    if !check_access(&out, &image, &account)? {
+                        stdout.flush()?;
+                        std::process::exit(1);
+                    }
+                }
+                None => print_access(&out, &image)?,
+            }
+        }
+        Command::Tag(cmd) => {
+            let image = match &cmd {
+                TagCommand::Ls { image }
+                | TagCommand::Add { image, .. }
+                | TagCommand::Rm { image, .. } => image,
+            };
+            let uuid = resolve(&client, image)?;
+            let mut image = client.get(&uuid).map_err(not_found(&uuid))?;
+            let update = match &cmd {
+                TagCommand::Ls { .. } => None,
+                TagCommand::Add { string, tags, .. } => {
+                    let update = tags::add(&mut image, tags, *string)?;
+                    if update.is_none() {
+                        eprintln!("nothing to change");
+                    }
+                    update
+                }
+                TagCommand::Rm { keys, .. } => Some(tags::remove(&mut image, keys)?),
+            };
+            if let Some(update) = update {
+                image = client.update(&uuid, &update)?;
+            }
+            tags::print(&out, &image)?;
+        }
+        Command::Clone { wait, image } => {
+            let account = clone_account(&auth)?;
+            let uuid = resolve(&client, &image)?;
+            let mut clone = client
+                .clone_image(&uuid, &account)
+                .map_err(not_found(&uuid))?;
+            eprintln!(
+                "cloned {}@{} ({}) into {}, owned by {}",
+                clone.name, clone.version, uuid, clone.uuid, clone.owner
+            );
+            if wait && clone.state != ImageState::Active {
+                clone = wait_for(&client, &clone, ImageState::Active, &WaitOptions::default())?;
+            }
+            if out.json {
+                out.write_json(&clone.to_json()?)?;
+            } else {
+                writeln!(stdout, "{}", clone.uuid)?;
+            }
+        }
+        Command::Export { image, manta_path } => {
+            let uuid = resolve(&client, &image)?;
+            let export = client
+                .export_to_manta(&uuid, &manta_path)
+                .map_err(not_found(&uuid))?;
+            if out.json {
+                out.write_json(&serde_json::to_value(&export)?)?;
+            } else {
+                writeln!(stdout, "manifest: {}", export.manifest_path)?;
+                writeln!(stdout, "file:     {}", export.image_path)?;
+                eprintln!("in {}", export.manta_url);
+            }
+        }
+        Command::Wait {
+            state,
+            wait_timeout,
+            interval,
+            image,
+        } => {
+            let uuid = resolve(&client, &image)?;
+            let image = client.get(&uuid).map_err(not_found(&uuid))?;
+            let opts = WaitOptions {
+                timeout: Some(wait_timeout),
+                interval,
+            };
+            let image = if image.state == state {
+                image
+            } else {
+                wait_for(&client, &image, state, &opts)?
+            };
+            if out.json {
+                out.write_json(&image.to_json()?)?;
+            } else {
+                writeln!(
+                    stdout,
+                    "{}@{} ({}) is {}",
+                    image.name, image.version, uuid, state
+                )?;
+            }
+        }
+        Command::ChannelAdd { image, to } => {
+            let uuid = resolve(&client, &image)?;
+            let image = client.channel_add(&uuid, &to).map_err(not_found(&uuid))?;
+            if out.json {
+                out.write_json(&image.to_json()?)?;
+            } else {
+                print_channels(image.channels.as_deref().unwrap_or_default())?;
+            }
+        }
+        Command::ChannelRemove { force, image, from } => {
+            let uuid = resolve(&client, &image)?;
+            let image = client.get(&uuid).map_err(not_found(&uuid))?;
+            let mut channels = image.channels.unwrap_or_default();
+            if !channels.contains(&from) {
+                return Err(format!("image {} isn't in channel {}", uuid, from).into());
+            }
+            channels.retain(|c| *c != from);
+            if channels.is_empty() {
+                eprintln!(
+                    "WARNING: {} is the only channel image {} is in, so removing the image from it \
+                     DELETES the image",
+                    from, uuid
+                );
+                if !force {
+                    return Err(
+                        "not removing the image from its last channel without --force".into(),
+                    );
+                }
+            }
+            client.channel_remove(&uuid, &from)?;
+            if channels.is_empty() {
+                eprintln!("deleted image {}", uuid);
+                if out.json {
+                    out.write_json(&serde_json::json!({ "uuid": uuid, "deleted": true }))?;
+                }
+            } else if out.json {
+                let image = client.get_in_channel(&uuid, Some(&channels[0]))?;
+                out.write_json(&image.to_json()?)?;
+            } else {
+                print_channels(&channels)?;
+            }
+        }
+        Command::Delete {
+            yes,
+            recursive,
+            image,
+        } => {
+            let uuid = resolve(&client, &image)?;
+            let image = client.get(&uuid).map_err(not_found(&uuid))?;
+            eprint!("{}", info::render(&image, opt.bytes, false));
+            // A dry run stops at the first DELETE, before anything's deleted.
+            let yes = yes || opt.dry_run;
+            confirm::confirm(
+                &format!("Delete {}@{} ({})?", image.name, image.version, uuid),
+                yes,
+            )?;
+            let mut doomed = Vec::new();
+            if recursive {
+                doomed = delete::dependents(&client, &uuid)?;
+                if !doomed.is_empty() {
+                    eprintln!("{} image(s) are built on it:", doomed.len());
+                    for i in &doomed {
+                        eprintln!("  {}@{} ({})", i.name, i.version, i.uuid);
+                    }
+                    confirm::confirm("Delete them too?", yes)?;
                 }
-                "marker" => todo!(),
-                _ => return Err(format!("unexpected query filter: {}", arg).into()),
+            }
+            doomed.push(image);
+            let mut deleted = Vec::new();
+            for image in &doomed {
+                if let Err(e) = client.delete(&image.uuid) {
+                    if !delete::has_dependents(e.as_ref()) {
+                        return Err(e);
+                    }
+                    eprintln!(
+                        "{}@{} ({}) has images built on it:",
+                        image.name, image.version, image.uuid
+                    );
+                    for child in delete::dependents(&client, &image.uuid)? {
+                        eprintln!("  {}@{} ({})", child.name, child.version, child.uuid);
+                    }
+                    eprintln!("use --recursive to delete them all");
+                    return Err(e);
+                }
+                if !out.json {
+                    writeln!(
+                        stdout,
+                        "deleted {}@{} ({})",
+                        image.name, image.version, image.uuid
+                    )?;
+                }
+                deleted.push(image.uuid);
+            }
+            if out.json {
+                out.write_json(&serde_json::to_value(&deleted)?)?;
+            }
+        }
+        Command::Cache(CacheCommand::Refresh) => {
+            let count = paging(|on_page| client.refresh_catalog(on_page))?;
+            if out.json {
+                out.write_json(&serde_json::json!({ "images": count }))?;
+            } else {
+                writeln!(stdout, "cached {} images from {}", count, shown)?;
             }
         }
+        Command::Sources(_)
+        | Command::Validate { .. }
+        | Command::Profiles { .. }
+        | Command::Copy { .. } => unreachable!("handled above"),
+    }
+
+    Ok(())
+}
+
+/// Makes `change` to `image` and prints its new state, or notes that there was nothing to do.
+fn change_state(
+    client: &Client,
+    out: &Output,
+    image: &ImageRef,
+    change: StateChange,
+) -> Result<(), Box<dyn Error>> {
+    let uuid = resolve(client, image)?;
+    let mut image = client.get(&uuid).map_err(not_found(&uuid))?;
+    match change.already(&image) {
+        Some(already) => eprintln!(
+            "{}@{} ({}) is already {}",
+            image.name, image.version, uuid, already
+        ),
+        None => image = change.apply(client, &uuid)?,
+    }
+    if out.json {
+        return out.write_json(&image.to_json()?);
     }
+    let mut stdout = io::stdout().lock();
+    writeln!(
+        stdout,
+        "{}@{} ({}): {}",
+        image.name,
+        image.version,
+        uuid,
+        style::state(&image, out.color)
+    )?;
+    Ok(())
+}
+
+/// The account `img clone` clones into: the one requests are signed as, which IMGAPI needs as a
+/// UUID.
+fn clone_account(auth: &AuthArgs) -> Result<Uuid, Box<dyn Error>> {
+    let account = auth.account.as_deref().ok_or_else(|| {
+        UsageError(
+            "cloning needs an account: use --account, or a source with credentials".to_string(),
+        )
+    })?;
+    Ok(Uuid::parse_str(account).map_err(|_| {
+        UsageError(format!(
+            "cloning needs the account's UUID, but the account is {:?}",
+            account
+        ))
+    })?)
+}
+
+/// Waits for `image` to be in `state`, noting each state it goes through on stderr.
+fn wait_for(
+    client: &Client,
+    image: &Image,
+    state: ImageState,
+    opts: &WaitOptions,
+) -> Result<Image, Box<dyn Error>> {
+    eprintln!(
+        "waiting for image {} to be {} (it's {})",
+        image.uuid, state, image.state
+    );
+    let mut last = image.state;
+    client.wait_for_state(&image.uuid, state, opts, |i| {
+        if i.state != last {
+            eprintln!("image {} is {}", i.uuid, i.state);
+            last = i.state;
+        }
+    })
+}
 
-    let images = imgapi::blocking::list(Some(&filter))?;
-    println!("found {} image(s) matching filter", images.len());
+/// Prints the UUIDs of `images`, one per line, for -q.
+fn print_uuids<'a>(images: impl IntoIterator<Item = &'a Image>) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    for image in images {
+        writeln!(stdout, "{}", image.uuid)?;
+    }
+    Ok(())
+}
 
+/// Prints the names of `channels`, one per line.
+fn print_channels(channels: &[Channel]) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    for channel in channels {
+        writeln!(stdout, "{}", channel)?;
+    }
     Ok(())
 }
+
+/// Every account can see a public image, so its ACL has no effect, and changing it is a mistake.
+fn refuse_public(image: &Image) -> Result<(), Box<dyn Error>> {
+    if image.public {
+        return Err(format!(
+            "image {} is public, so every account can already see it; to share it with just some \
+             accounts, first make it private with `img update {} public=false`",
+            image.uuid, image.uuid
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Prints the accounts on the image's ACL, one per line, or with --json the image's manifest.
+fn print_acl(out: &Output, image: &Image) -> Result<(), Box<dyn Error>> {
+    if out.json {
+        return out.write_json(&image.to_json()?);
+    }
+    let mut stdout = io::stdout().lock();
+    match image.acl.as_deref() {
+        Some(acl) if !acl.is_empty() => {
+            for account in acl {
+                writeln!(stdout, "{}", account)?;
+            }
+        }
+        _ => eprintln!("image {} isn't shared with any accounts", image.uuid),
+    }
+    if image.public {
+        eprintln!(
+            "note: image {} is public, so every account can see it",
+            image.uuid
+        );
+    }
+    Ok(())
+}
+
+/// Prints the image's owner, whether it's public, and the accounts on its ACL, or with --json just
+/// the ACL.
+fn print_access(out: &Output, image: &Image) -> Result<(), Box<dyn Error>> {
+    let acl = image.acl.as_deref().unwrap_or_default();
+    if out.json {
+        return out.write_json(&serde_json::to_value(acl)?);
+    }
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "owner:   {}", image.owner)?;
+    writeln!(
+        stdout,
+        "access:  {}",
+        if image.public { "public" } else { "private" }
+    )?;
+    for account in acl {
+        writeln!(stdout, "acl:     {}", account)?;
+    }
+    if image.public {
+        eprintln!(
+            "note: image {} is public, so every account can see it, and its ACL doesn't matter",
+            image.uuid
+        );
+    } else if acl.is_empty() {
+        eprintln!("image {} isn't shared with any accounts", image.uuid);
+    }
+    Ok(())
+}
+
+/// Says whether `account` can see the image, and why, returning whether it can.
+fn check_access(out: &Output, image: &Image, account: &Uuid) -> Result<bool, Box<dyn Error>> {
+    let access = image.has_access(account);
+    let mut stdout = io::stdout().lock();
+    if out.json {
+        out.write_json(&serde_json::json!({"account": account, "access": access}))?;
+    } else if !access {
+        writeln!(stdout, "account {} can't see image {}", account, image.uuid)?;
+    } else {
+        let why = if image.owner == *account {
+            "it owns it"
+        } else if image.public {
+            "the image is public"
+        } else {
+            "it's on the ACL"
+        };
+        writeln!(
+            stdout,
+            "account {} can see image {}, as {}",
+            account, image.uuid, why
+        )?;
+    }
+    Ok(access)
+}
+
+/// Where to download each image to and what of it, for [`download_many`].
+struct Download {
+    dir: PathBuf,
+    file_index: usize,
+    decompress: bool,
+    opts: DownloadOptions,
+}
+
+/// Downloads a file of each of `images`, at most `concurrency` at once, printing the SHA-1 and
+/// path of each, or with --json an array of what was downloaded, in the order given. A failure
+/// doesn't stop the other downloads, but is reported once they're done. Progress is shown on
+/// stderr, colored if `color` is.
+fn download_many(
+    client: &Client,
+    out: &Output,
+    images: &[ImageRef],
+    download: &Download,
+    concurrency: usize,
+    color: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut manifests = Vec::new();
+    for image in images {
+        let uuid = resolve(client, image)?;
+        let image = client.get(&uuid).map_err(not_found(&uuid))?;
+        nth_file(&image, download.file_index)?;
+        manifests.push(image);
+    }
+    let requests: Vec<DownloadRequest> = manifests
+        .iter()
+        .map(|image| DownloadRequest {
+            uuid: image.uuid,
+            index: download.file_index,
+            dest: download.dir.join(download_name(
+                image,
+                download.file_index,
+                download.decompress,
+            )),
+            opts: download.opts.clone(),
+        })
+        .collect();
+    let starting = manifests
+        .iter()
+        .map(|i| format!("downloading {}@{} ({})", i.name, i.version, i.uuid))
+        .collect();
+    let mut bar = BatchProgressBar::new("downloaded", starting, color);
+    let results = client.download_many(&requests, concurrency, &CancelToken::new(), |p| {
+        bar.update(p)
+    });
+    bar.finish();
+
+    let mut stdout = io::stdout().lock();
+    let mut failed = 0;
+    let mut values = Vec::new();
+    for ((image, request), result) in manifests.iter().zip(&requests).zip(results) {
+        match result {
+            Ok(report) if out.json => values.push(serde_json::json!({
+                "path": request.dest,
+                "sha1": report.sha1,
+                "bytes": report.bytes,
+                "size": image.files[request.index].size,
+            })),
+            Ok(report) => writeln!(stdout, "{}  {}", report.sha1, request.dest.display())?,
+            Err(error) => {
+                failed += 1;
+                eprintln!("image {}: {}", image.uuid, error);
+                if out.json {
+                    values.push(serde_json::json!({ "uuid": image.uuid, "error": error }));
+                }
+            }
+        }
+    }
+    if out.json {
+        out.write_json(&serde_json::Value::Array(values))?;
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} downloads failed", failed, requests.len()).into());
+    }
+    Ok(())
+}
+
+/// File `index` of `image`, or an error saying it has no such file.
+fn nth_file(image: &Image, index: usize) -> Result<&imgapi::File, String> {
+    image.files.get(index).ok_or_else(|| {
+        format!(
+            "image {} has {} file(s), so there's no file {}",
+            image.uuid,
+            image.files.len(),
+            index
+        )
+    })
+}
+
+/// The name `imgadm` gives file `index` of `image`: `<uuid>.zfs`, with an extension for its
+/// compression unless it's decompressed. Files after the first get their index too, e.g.
+/// `<uuid>.1.zfs.gz`.
+fn download_name(image: &Image, index: usize, decompressed: bool) -> String {
+    let stem = match index {
+        0 => image.uuid.to_string(),
+        _ => format!("{}.{}", image.uuid, index),
+    };
+    match image.files[index].compression.extension() {
+        Some(ext) if !decompressed => format!("{}.zfs.{}", stem, ext),
+        _ => format!("{}.zfs", stem),
+    }
+}
+
+/// Fetches every page of images matching `filter`, noting progress on stderr once there's more
+/// than one page.
+fn list_all(client: &Client, filter: &ImageFilter) -> Result<Vec<Image>, Box<dyn Error>> {
+    paging(|on_page| client.list_all(filter, on_page))
+}
+
+/// Runs `fetch`, which fetches pages of images, noting its progress on stderr as [`list_all`]
+/// does.
+fn paging<T, F>(fetch: F) -> Result<T, Box<dyn Error>>
+where
+    F: FnOnce(&mut dyn FnMut(usize, usize)) -> Result<T, Box<dyn Error>>,
+{
+    let mut paged = false;
+    let result = fetch(&mut |pages, images| {
+        if pages > 1 {
+            paged = true;
+            eprint!("\rfetched {} images ({} pages)", images, pages);
+        }
+    });
+    if paged {
+        eprintln!();
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use structopt::clap::ErrorKind;
+
+    use super::*;
+
+    /// Parses `args`, the first of which is the program name, as `img` parses its command line.
+    fn parse(args: &[&str]) -> Result<Opt, structopt::clap::Error> {
+        Opt::from_iter_safe(args)
+    }
+
+    /// The names of the subcommands listed in `help`, under SUBCOMMANDS.
+    fn subcommands(help: &str) -> Vec<String> {
+        help.lines()
+            .skip_while(|l| *l != "SUBCOMMANDS:")
+            .skip(1)
+            .take_while(|l| !l.is_empty())
+            .filter(|l| l.starts_with("    ") && !l[4..].starts_with(' '))
+            .filter_map(|l| l.split_whitespace().next())
+            .filter(|name| *name != "help")
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn every_subcommand_has_help() {
+        let help = parse(&["img", "--help"]).unwrap_err();
+        assert_eq!(help.kind, ErrorKind::HelpDisplayed);
+        let mut commands = subcommands(&help.message);
+        assert!(commands.len() > 20, "{:?}", commands);
+        for nested in ["tag", "sources", "cache"] {
+            let help = parse(&["img", nested, "--help"]).unwrap_err();
+            let nested_commands = subcommands(&help.message);
+            assert!(!nested_commands.is_empty(), "{}", nested);
+            commands.extend(nested_commands.iter().map(|c| format!("{} {}", nested, c)));
+        }
+        for command in &commands {
+            let mut args = vec!["img"];
+            args.extend(command.split(' '));
+            args.push("--help");
+            let help = parse(&args).unwrap_err();
+            assert_eq!(help.kind, ErrorKind::HelpDisplayed, "{}", command);
+            assert!(
+                help.message.contains(&format!("img {}", command)),
+                "{}: {}",
+                command,
+                help.message
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_what_doesnt_parse() {
+        let cases: &[(&[&str], ErrorKind)] = &[
+            (&["img"], ErrorKind::MissingArgumentOrSubcommand),
+            (&["img", "frobnicate"], ErrorKind::UnknownArgument),
+            (&["img", "list", "--frobnicate"], ErrorKind::UnknownArgument),
+            (&["img", "list", "os"], ErrorKind::ValueValidation),
+            (&["img", "list", "colour=red"], ErrorKind::ValueValidation),
+            (&["img", "get"], ErrorKind::MissingRequiredArgument),
+            (&["img", "get", "base@"], ErrorKind::ValueValidation),
+            (
+                &["img", "get", "--stdin", "base"],
+                ErrorKind::ArgumentConflict,
+            ),
+            (
+                &["img", "list", "--all", "--limit", "5"],
+                ErrorKind::ArgumentConflict,
+            ),
+        ];
+        for (args, kind) in cases {
+            match parse(args) {
+                Ok(opt) => panic!("{:?} parsed as {:?}", args, opt),
+                Err(e) => assert_eq!(e.kind, *kind, "{:?}: {}", args, e.message),
+            }
+        }
+    }
+
+    #[test]
+    fn takes_filters_after_list() {
+        let opt = parse(&["img", "list", "os=linux", "state=active"]).unwrap();
+        match opt.cmd {
+            Command::List { filters, .. } => {
+                assert!(matches!(
+                    filters.as_slice(),
+                    [FilterArg::Os(_), FilterArg::State(_)]
+                ));
+            }
+            cmd => panic!("parsed as {:?}", cmd),
+        }
+    }
+
+    #[test]
+    fn doesnt_take_the_program_name_as_a_filter() {
+        let opt = parse(&["os=linux", "list"]).unwrap();
+        match opt.cmd {
+            Command::List { filters, .. } => assert!(filters.is_empty(), "{:?}", filters),
+            cmd => panic!("parsed as {:?}", cmd),
+        }
+    }
+
+    #[test]
+    fn takes_images_after_get() {
+        let uuid = "2b683a82-a066-11e3-97ab-2faa44701c5a";
+        let opt = parse(&["img", "get", uuid, "base@1.0.0"]).unwrap();
+        match opt.cmd {
+            Command::Get { images, .. } => assert_eq!(
+                images,
+                [
+                    ImageRef::Uuid(uuid.parse().unwrap()),
+                    ImageRef::NameVersion("base".to_string(), "1.0.0".to_string()),
+                ]
+            ),
+            cmd => panic!("parsed as {:?}", cmd),
+        }
+    }
+}
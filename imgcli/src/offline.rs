@@ -0,0 +1,75 @@
+use std::error::Error;
+use std::net::TcpStream;
+use std::time::Duration;
+
+use structopt::StructOpt;
+use url::Url;
+
+use imgapi::CatalogCache;
+
+use super::dirs;
+
+/// How long to wait to find out whether the server can be reached, for `offline_fallback`, unless
+/// --connect-timeout says otherwise.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Manages the cached catalogs that --offline answers from, in `~/.cache/img/catalogs`.
+#[derive(Debug, StructOpt)]
+pub enum CacheCommand {
+    /// Fetches every image in the channel, in any state, and saves them as the server's catalog,
+    /// replacing the one cached before. With --json, prints `{"images": N}`.
+    Refresh,
+}
+
+/// The cache for the server at `url`: a directory in `catalogs` in the cache directory, named
+/// after the server's host, port and path, e.g. `images.example.com` or `10.0.0.5_8080`.
+pub fn cache_for(url: &Url) -> Result<CatalogCache, Box<dyn Error>> {
+    let mut name = url.host_str().unwrap_or_default().to_string();
+    if let Some(port) = url.port() {
+        name.push_str(&format!("_{}", port));
+    }
+    for segment in url.path_segments().into_iter().flatten() {
+        if !segment.is_empty() {
+            name.push('_');
+            name.push_str(segment);
+        }
+    }
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-.".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let dir = dirs::cache_dir()?.join("catalogs").join(name);
+    Ok(CatalogCache::new(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?)
+}
+
+/// Checks whether the server at `url` can be connected to within `timeout`, returning why not
+/// if it can't.
+pub fn reachable(url: &Url, timeout: Duration) -> Result<(), String> {
+    let addrs = url.socket_addrs(|| None).map_err(|e| e.to_string())?;
+    let mut error = "it has no addresses".to_string();
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(_) => return Ok(()),
+            Err(e) => error = e.to_string(),
+        }
+    }
+    Err(error)
+}
+
+/// How old a cached catalog is, roughly, e.g. `5 minutes` or `1 day`.
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    let (n, unit) = match secs {
+        0..=59 => (secs, "second"),
+        60..=3599 => (secs / 60, "minute"),
+        3600..=86399 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+    format!("{} {}{}", n, unit, if n == 1 { "" } else { "s" })
+}
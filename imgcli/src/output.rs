@@ -0,0 +1,72 @@
+use std::error::Error;
+use std::io::{self, Write};
+
+use serde_json::Value;
+
+use imgapi::Image;
+
+use super::table::Table;
+
+/// How results are written to stdout. Progress and other chatter always goes to stderr, so that
+/// stdout holds nothing but the results.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Output {
+    /// Write JSON rather than human-readable text.
+    pub json: bool,
+
+    /// Write JSON on a single line rather than pretty-printed.
+    pub compact: bool,
+
+    /// Color human-readable output.
+    pub color: bool,
+}
+
+impl Output {
+    /// Writes `value` to stdout as JSON, followed by a newline.
+    pub fn write_json(&self, value: &Value) -> Result<(), Box<dyn Error>> {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        if self.compact {
+            serde_json::to_writer(&mut out, value)?;
+        } else {
+            serde_json::to_writer_pretty(&mut out, value)?;
+        }
+        out.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Writes manifests, which are JSON whether or not `--json` was given: a single manifest on
+    /// its own, or an array of them.
+    pub fn manifests(&self, mut manifests: Vec<Value>) -> Result<(), Box<dyn Error>> {
+        if manifests.len() == 1 {
+            return self.write_json(&manifests.remove(0));
+        }
+        self.write_json(&Value::Array(manifests))
+    }
+
+    /// Writes `values` as JSON Lines: each on a line of its own, however `compact` is set.
+    pub fn json_lines(&self, values: &[Value]) -> Result<(), Box<dyn Error>> {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        for value in values {
+            serde_json::to_writer(&mut out, value)?;
+            out.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Writes a list of images: a JSON array of manifests with `--json`, otherwise `table`.
+    pub fn images(&self, images: &[Image], table: &Table) -> Result<(), Box<dyn Error>> {
+        if self.json {
+            let manifests = images
+                .iter()
+                .map(Image::to_json)
+                .collect::<Result<Vec<_>, _>>()?;
+            return self.write_json(&Value::Array(manifests));
+        }
+        io::stdout()
+            .lock()
+            .write_all(table.render(images).as_bytes())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use url::Url;
+
+use super::sources::{Source, SourceAuth, SourceType};
+use super::table;
+
+/// The environment variable node-triton takes the profile to use from.
+pub const PROFILE_VAR: &str = "TRITON_PROFILE";
+
+/// A node-triton profile: a CloudAPI endpoint and the credentials to use with it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    /// Set from the key in `profiles.json`, or the file name in `profiles.d`, if missing.
+    #[serde(default)]
+    pub name: String,
+
+    /// The CloudAPI URL.
+    pub url: Url,
+
+    pub account: String,
+
+    /// The fingerprint of the key in the SSH agent to sign requests with.
+    pub key_id: String,
+
+    /// The IMGAPI server to use with the profile, for when it can't be worked out from the
+    /// CloudAPI URL. node-triton ignores it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub imgapi_url: Option<Url>,
+
+    /// Whether to skip checking the server's TLS certificate, as node-triton does.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub insecure: bool,
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+impl Profile {
+    /// The profile's IMGAPI server: its `imgapiUrl`, or else the CloudAPI URL with the
+    /// `cloudapi.` at the start of the host name replaced with `imgapi.`, as Triton names services
+    /// in DNS, e.g. `https://cloudapi.us-west-1.example.com` becomes
+    /// `https://imgapi.us-west-1.example.com`.
+    pub fn imgapi_url(&self) -> Result<Url, String> {
+        if let Some(url) = &self.imgapi_url {
+            return Ok(url.clone());
+        }
+        let host = self.url.host_str().unwrap_or_default();
+        let rest = host.strip_prefix("cloudapi.").ok_or_else(|| {
+            format!(
+                "can't work out the IMGAPI server for profile {:?} from its CloudAPI URL {}; add \
+                 an \"imgapiUrl\" to the profile",
+                self.name, self.url
+            )
+        })?;
+        let mut url = self.url.clone();
+        url.set_host(Some(&format!("imgapi.{}", rest)))
+            .map_err(|e| format!("profile {:?}: {}", self.name, e))?;
+        url.set_path("/");
+        Ok(url)
+    }
+
+    /// The profile as an unsaved source, so that it's used like one.
+    pub fn to_source(&self) -> Result<Source, String> {
+        Ok(Source {
+            name: self.name.clone(),
+            url: self.imgapi_url()?,
+            source_type: SourceType::Imgapi,
+            default: false,
+            channel: None,
+            auth: Some(SourceAuth {
+                account: self.account.clone(),
+                key_id: self.key_id.clone(),
+                key_file: None,
+            }),
+            insecure: self.insecure,
+        })
+    }
+}
+
+/// Reads the profiles in `dir`: those in `profiles.json`, which may be an object of profiles by
+/// name or an array of them, and those in `profiles.d/<name>.json`, which is where node-triton
+/// keeps them. Profiles are sorted by name, and missing files are skipped.
+pub fn load(dir: &Path) -> Result<Vec<Profile>, Box<dyn Error>> {
+    let mut profiles = BTreeMap::new();
+    let path = dir.join("profiles.json");
+    if let Some(value) = read_json(&path)? {
+        let with_path = |e: serde_json::Error| format!("{}: {}", path.display(), e);
+        let listed: Vec<Profile> = match value {
+            Value::Object(map) => map
+                .into_iter()
+                .map(|(name, v)| {
+                    let mut p: Profile = serde_json::from_value(v)?;
+                    p.name = name;
+                    Ok(p)
+                })
+                .collect::<Result<_, serde_json::Error>>()
+                .map_err(with_path)?,
+            value => serde_json::from_value(value).map_err(with_path)?,
+        };
+        for p in listed {
+            if p.name.is_empty() {
+                return Err(format!("{}: a profile has no name", path.display()).into());
+            }
+            profiles.insert(p.name.clone(), p);
+        }
+    }
+    let entries = match fs::read_dir(dir.join("profiles.d")) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(profiles.into_values().collect())
+        }
+        Err(e) => return Err(format!("{}: {}", dir.join("profiles.d").display(), e).into()),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_none_or(|e| e != "json") {
+            continue;
+        }
+        if let Some(value) = read_json(&path)? {
+            let mut p: Profile =
+                serde_json::from_value(value).map_err(|e| format!("{}: {}", path.display(), e))?;
+            if p.name.is_empty() {
+                let stem = path.file_stem().unwrap_or_default();
+                p.name = stem.to_string_lossy().into_owned();
+            }
+            profiles.insert(p.name.clone(), p);
+        }
+    }
+    Ok(profiles.into_values().collect())
+}
+
+/// Reads the JSON file at `path`, or returns `None` if there's no such file.
+fn read_json(path: &Path) -> Result<Option<Value>, Box<dyn Error>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(
+            serde_json::from_slice(&bytes).map_err(|e| format!("{}: {}", path.display(), e))?,
+        )),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("{}: {}", path.display(), e).into()),
+    }
+}
+
+/// Finds the profile called `name`.
+pub fn find<'a>(profiles: &'a [Profile], name: &str) -> Result<&'a Profile, String> {
+    profiles.iter().find(|p| p.name == name).ok_or_else(|| {
+        let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+        match names.len() {
+            0 => format!("no profile {:?}: there are no Triton profiles", name),
+            _ => format!(
+                "no profile {:?} (the profiles are {})",
+                name,
+                names.join(", ")
+            ),
+        }
+    })
+}
+
+/// The profiles as a JSON array, each with `"current": true` or `false` for whether it's the one
+/// in use.
+pub fn to_json(profiles: &[Profile], current: Option<&str>) -> Result<Value, Box<dyn Error>> {
+    profiles
+        .iter()
+        .map(|p| {
+            let mut value = serde_json::to_value(p)?;
+            value["current"] = Value::Bool(Some(p.name.as_str()) == current);
+            Ok(value)
+        })
+        .collect()
+}
+
+/// Renders the profiles as a table, with the one in use marked with `*` in the first column. The
+/// IMGAPI column is `-` for profiles whose IMGAPI server can't be worked out.
+pub fn render(
+    profiles: &[Profile],
+    current: Option<&str>,
+    no_header: bool,
+    parseable: bool,
+) -> String {
+    let mut rows = Vec::new();
+    if !no_header {
+        rows.push(
+            ["", "NAME", "ACCOUNT", "URL", "IMGAPI"]
+                .iter()
+                .map(|h| h.to_string())
+                .collect(),
+        );
+    }
+    rows.extend(profiles.iter().map(|p| {
+        vec![
+            if Some(p.name.as_str()) == current {
+                "*"
+            } else {
+                ""
+            }
+            .to_string(),
+            p.name.clone(),
+            p.account.clone(),
+            p.url.to_string(),
+            p.imgapi_url().map_or("-".to_string(), |u| u.to_string()),
+        ]
+    }));
+    table::layout(&rows, parseable)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// A `~/.triton` with `profiles.json` holding `listed`, if it's given, and a file in
+    /// `profiles.d` for each of `files`.
+    fn triton_dir(listed: Option<Value>, files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        if let Some(listed) = listed {
+            fs::write(dir.path().join("profiles.json"), listed.to_string()).unwrap();
+        }
+        if !files.is_empty() {
+            fs::create_dir(dir.path().join("profiles.d")).unwrap();
+        }
+        for (name, contents) in files {
+            fs::write(dir.path().join("profiles.d").join(name), contents).unwrap();
+        }
+        dir
+    }
+
+    fn profile(url: &str) -> Value {
+        json!({ "url": url, "account": "me", "keyId": "SHA256:abc" })
+    }
+
+    fn names(profiles: &[Profile]) -> Vec<&str> {
+        profiles.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    #[test]
+    fn reads_profiles_from_both_places() {
+        let object = json!({ "west": profile("https://cloudapi.us-west-1.example.com") });
+        let dir = triton_dir(
+            Some(object),
+            &[
+                (
+                    "east.json",
+                    &profile("https://cloudapi.us-east-1.example.com").to_string(),
+                ),
+                ("notes.txt", "not a profile"),
+            ],
+        );
+        let profiles = load(dir.path()).unwrap();
+        assert_eq!(names(&profiles), ["east", "west"]);
+        assert_eq!(profiles[0].account, "me");
+        assert_eq!(profiles[0].key_id, "SHA256:abc");
+
+        let mut named = profile("https://cloudapi.example.com");
+        named["name"] = json!("lab");
+        let array = triton_dir(Some(json!([named])), &[]);
+        assert_eq!(names(&load(array.path()).unwrap()), ["lab"]);
+
+        let empty = tempfile::tempdir().unwrap();
+        assert_eq!(load(empty.path()).unwrap(), []);
+    }
+
+    #[test]
+    fn works_out_the_imgapi_server() {
+        let mut listed = json!({
+            "cloud": profile("https://cloudapi.us-west-1.example.com:8443/path"),
+            "explicit": profile("https://api.example.com"),
+            "unknown": profile("https://api.example.com"),
+        });
+        listed["explicit"]["imgapiUrl"] = json!("https://images.example.com/");
+        let dir = triton_dir(Some(listed), &[]);
+        let profiles = load(dir.path()).unwrap();
+        let imgapi = |name| find(&profiles, name).unwrap().imgapi_url();
+        assert_eq!(
+            imgapi("cloud").map(String::from),
+            Ok("https://imgapi.us-west-1.example.com:8443/".to_string())
+        );
+        assert_eq!(
+            imgapi("explicit").map(String::from),
+            Ok("https://images.example.com/".to_string())
+        );
+        assert!(imgapi("unknown")
+            .unwrap_err()
+            .contains("add an \"imgapiUrl\""));
+
+        let source = find(&profiles, "cloud").unwrap().to_source().unwrap();
+        let auth = source.auth.unwrap();
+        assert_eq!(
+            (auth.account.as_str(), auth.key_id.as_str()),
+            ("me", "SHA256:abc")
+        );
+        assert_eq!(
+            find(&profiles, "nope"),
+            Err("no profile \"nope\" (the profiles are cloud, explicit, unknown)".to_string())
+        );
+    }
+
+    #[test]
+    fn names_the_malformed_file() {
+        let dir = triton_dir(None, &[("broken.json", "{\"url\": ")]);
+        let err = load(dir.path()).unwrap_err().to_string();
+        let path = dir.path().join("profiles.d").join("broken.json");
+        assert!(err.starts_with(&format!("{}: ", path.display())), "{}", err);
+
+        let dir = triton_dir(Some(json!({ "west": { "url": "not a url" } })), &[]);
+        let err = load(dir.path()).unwrap_err().to_string();
+        let path = dir.path().join("profiles.json");
+        assert!(err.starts_with(&format!("{}: ", path.display())), "{}", err);
+
+        let dir = triton_dir(Some(json!([profile("https://cloudapi.example.com")])), &[]);
+        let err = load(dir.path()).unwrap_err().to_string();
+        assert!(err.ends_with("a profile has no name"), "{}", err);
+    }
+}
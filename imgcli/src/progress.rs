@@ -0,0 +1,186 @@
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+use imgapi::size::format_size;
+use imgapi::{BatchProgress, Progress};
+
+use super::style;
+
+/// How often a progress line is printed when stderr isn't a terminal.
+const LINE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The width of the bar itself, between the brackets.
+const BAR_WIDTH: usize = 30;
+
+/// Reports transfer progress on stderr: a bar redrawn in place on a terminal, and otherwise a
+/// line every few seconds, so that logs don't fill up with carriage returns.
+#[derive(Debug)]
+pub struct ProgressBar {
+    /// What's being done, in the past tense, e.g. `downloaded`.
+    action: &'static str,
+    tty: bool,
+
+    /// Whether to color the bar, on a terminal.
+    color: bool,
+    started: Instant,
+    last_line: Option<Instant>,
+    drawn: bool,
+}
+
+impl ProgressBar {
+    /// Returns a bar for a transfer described by `action`, as in `downloaded 12.3M of 27.0M`,
+    /// colored if `color` is.
+    pub fn new(action: &'static str, color: bool) -> Self {
+        Self {
+            action,
+            tty: io::stderr().is_terminal(),
+            color,
+            started: Instant::now(),
+            last_line: None,
+            drawn: false,
+        }
+    }
+
+    /// Updates the report with `progress`.
+    pub fn update(&mut self, progress: Progress) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        // The average rate is steadier than the rate since the last update.
+        let rate = if elapsed > 0.0 {
+            progress.bytes as f64 / elapsed
+        } else {
+            progress.rate
+        };
+        let mut stderr = io::stderr().lock();
+        if self.tty {
+            let _ = write!(stderr, "\r{}\x1b[K", bar(progress, rate, self.color));
+            self.drawn = true;
+        } else if self.last_line.is_none_or(|t| t.elapsed() >= LINE_INTERVAL) {
+            let _ = writeln!(stderr, "{}", line(self.action, progress, rate));
+            self.last_line = Some(Instant::now());
+        }
+    }
+
+    /// Ends the bar's line, if one was drawn.
+    pub fn finish(&mut self) {
+        if self.drawn {
+            eprintln!();
+            self.drawn = false;
+        }
+    }
+
+    /// Prints `line` on stderr above the bar, which is drawn again on the next update.
+    pub fn note(&mut self, line: &str) {
+        if self.drawn {
+            eprint!("\r\x1b[K");
+            self.drawn = false;
+        }
+        eprintln!("{}", line);
+    }
+}
+
+/// Reports the progress of several downloads at once on stderr: a line as each one starts, and a
+/// [`ProgressBar`] for all of them together, so that downloads running at the same time don't
+/// garble each other's output.
+#[derive(Debug)]
+pub struct BatchProgressBar {
+    bar: ProgressBar,
+
+    /// The line to print as each download starts, e.g. `downloading base@1.0.0 (<uuid>)`.
+    starting: Vec<String>,
+    started: Vec<bool>,
+}
+
+impl BatchProgressBar {
+    /// Returns a bar for downloads that, as each starts, print the line in `starting` for it.
+    pub fn new(action: &'static str, starting: Vec<String>, color: bool) -> Self {
+        Self {
+            bar: ProgressBar::new(action, color),
+            started: vec![false; starting.len()],
+            starting,
+        }
+    }
+
+    /// Updates the report with `progress`.
+    pub fn update(&mut self, progress: BatchProgress) {
+        if !self.started[progress.request] {
+            self.started[progress.request] = true;
+            self.bar.note(&self.starting[progress.request]);
+        }
+        self.bar.update(Progress {
+            bytes: progress.bytes,
+            total: Some(progress.total),
+            rate: progress.file.rate,
+        });
+    }
+
+    /// Ends the bar's line, if one was drawn.
+    pub fn finish(&mut self) {
+        self.bar.finish();
+    }
+}
+
+/// Formats a duration in seconds as `m:ss`, or `h:mm:ss` if it's an hour or more.
+fn format_eta(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{}:{:02}", m, s)
+    }
+}
+
+/// The estimated time left, if it can be estimated.
+fn eta(progress: Progress, rate: f64) -> Option<String> {
+    let total = progress.total?;
+    if rate <= 0.0 {
+        return None;
+    }
+    Some(format_eta(
+        total.saturating_sub(progress.bytes) as f64 / rate,
+    ))
+}
+
+/// E.g. `[=========>          ]  45% 12.3M/27.0M 4.5M/s ETA 0:03`, with the bar colored if
+/// `color` is.
+fn bar(progress: Progress, rate: f64, color: bool) -> String {
+    let speed = format!("{}/s", format_size(rate as u64));
+    match (progress.total, progress.fraction()) {
+        (Some(total), Some(fraction)) => {
+            let filled = (fraction * BAR_WIDTH as f64) as usize;
+            let mut bar = "=".repeat(filled);
+            if filled < BAR_WIDTH {
+                bar.push('>');
+            }
+            let pad = " ".repeat(BAR_WIDTH - bar.len());
+            format!(
+                "[{}{}] {:3.0}% {}/{} {} ETA {}",
+                style::progress(&bar, color),
+                pad,
+                fraction * 100.0,
+                format_size(progress.bytes),
+                format_size(total),
+                speed,
+                eta(progress, rate).unwrap_or_else(|| "-".to_string()),
+            )
+        }
+        _ => format!("{} {}", format_size(progress.bytes), speed),
+    }
+}
+
+/// E.g. `downloaded 12.3M of 27.0M (45%), 4.5M/s, 0:03 left`.
+fn line(action: &str, progress: Progress, rate: f64) -> String {
+    let speed = format_size(rate as u64);
+    match (progress.total, progress.fraction()) {
+        (Some(total), Some(fraction)) => format!(
+            "{} {} of {} ({:.0}%), {}/s, {} left",
+            action,
+            format_size(progress.bytes),
+            format_size(total),
+            fraction * 100.0,
+            speed,
+            eta(progress, rate).unwrap_or_else(|| "unknown time".to_string())
+        ),
+        _ => format!("{} {}, {}/s", action, format_size(progress.bytes), speed),
+    }
+}
@@ -0,0 +1,222 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use imgapi::blocking::Client;
+use imgapi::{Image, ImageFilter, StateFilter, Uuid};
+
+use super::NotFound;
+
+/// The shortest UUID prefix that's looked up as one. Shorter hex strings are taken as names only.
+const MIN_PREFIX_LEN: usize = 8;
+
+/// An image as given on the command line: a UUID, an unambiguous prefix of one, `name@version`,
+/// or a bare name, which stands for the latest active version.
+///
+/// A word that could be either a prefix or a name, such as `deadbeef`, is a prefix, which
+/// [`resolve`] also tries as a name if no UUID starts with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageRef {
+    Uuid(Uuid),
+
+    /// At least [`MIN_PREFIX_LEN`] hex digits, with or without the hyphens, as given.
+    Prefix(String),
+
+    NameVersion(String, String),
+    Name(String),
+}
+
+impl FromStr for ImageRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(uuid) = Uuid::parse_str(s) {
+            return Ok(Self::Uuid(uuid));
+        }
+        let hex = s.chars().filter(|&c| c != '-').count();
+        if hex >= MIN_PREFIX_LEN && s.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+            return Ok(Self::Prefix(s.to_string()));
+        }
+        match s.split_once('@') {
+            Some((name, version)) if !name.is_empty() && !version.is_empty() => {
+                Ok(Self::NameVersion(name.to_string(), version.to_string()))
+            }
+            Some(_) => Err(format!("expected name@version, got {:?}", s)),
+            None if s.is_empty() => Err("expected a UUID, name@version or name".to_string()),
+            None => Ok(Self::Name(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ImageRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Uuid(uuid) => uuid.fmt(f),
+            Self::Prefix(prefix) => prefix.fmt(f),
+            Self::NameVersion(name, version) => write!(f, "{}@{}", name, version),
+            Self::Name(name) => name.fmt(f),
+        }
+    }
+}
+
+/// Several images matched a reference that should name just one, e.g. images with the same name
+/// and version from different owners.
+#[derive(Debug)]
+pub struct Ambiguous {
+    pub image: ImageRef,
+    pub candidates: Vec<Image>,
+}
+
+impl fmt::Display for Ambiguous {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} matches {} images; use a UUID instead:",
+            self.image,
+            self.candidates.len()
+        )?;
+        for image in &self.candidates {
+            write!(
+                f,
+                "\n  {}  {}@{}  owner {}",
+                image.uuid, image.name, image.version, image.owner
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for Ambiguous {}
+
+/// Works out the UUID of `image`. A full UUID is taken as is, without asking the server, and a
+/// prefix is matched against every image, in any state. A prefix that no UUID starts with is
+/// looked up as a name, as an all-hex name like `cafe1234` can't be told apart from one.
+///
+/// Names are looked up with a ListImages name filter, and the matches narrowed down here, since
+/// the server also matches on substrings. `name@version` matches images in any state, and a bare
+/// name the newest active image by [`imgapi::cmp_versions`].
+pub fn resolve(client: &Client, image: &ImageRef) -> Result<Uuid, Box<dyn Error>> {
+    let (name, version) = match image {
+        ImageRef::Uuid(uuid) => return Ok(*uuid),
+        ImageRef::Prefix(prefix) => return resolve_prefix(client, image, prefix),
+        ImageRef::NameVersion(name, version) => (name, Some(version)),
+        ImageRef::Name(name) => (name, None),
+    };
+    let filter = ImageFilter {
+        name: Some(name.clone()),
+        version: version.cloned(),
+        state: version.map(|_| StateFilter::All),
+        ..ImageFilter::default()
+    };
+    let images = client.list_all(&filter, |_, _| ())?;
+    let version = match version {
+        Some(version) => version.clone(),
+        None => imgapi::latest_by_name(&images, name)
+            .ok_or_else(|| NotFound::NoMatch(format!("active image named {:?}", name)))?
+            .version
+            .clone(),
+    };
+    let mut candidates: Vec<Image> = images
+        .into_iter()
+        .filter(|i| i.name == *name && i.version == version)
+        .filter(|i| matches!(image, ImageRef::NameVersion(..)) || i.is_provisionable())
+        .collect();
+    match candidates.len() {
+        0 => Err(NotFound::NoMatch(format!("image {}@{}", name, version)).into()),
+        1 => Ok(candidates.remove(0).uuid),
+        _ => Err(Ambiguous {
+            image: image.clone(),
+            candidates,
+        }
+        .into()),
+    }
+}
+
+fn resolve_prefix(client: &Client, image: &ImageRef, given: &str) -> Result<Uuid, Box<dyn Error>> {
+    let filter = ImageFilter {
+        state: Some(StateFilter::All),
+        ..ImageFilter::default()
+    };
+    // Hyphens have to be where a UUID has them, if there are any.
+    let prefix = given.to_ascii_lowercase();
+    let matches = |uuid: &Uuid| {
+        let uuid = uuid.to_string();
+        if prefix.contains('-') {
+            uuid.starts_with(&prefix)
+        } else {
+            uuid.replace('-', "").starts_with(&prefix)
+        }
+    };
+    let mut candidates: Vec<Image> = client
+        .list_all(&filter, |_, _| ())?
+        .into_iter()
+        .filter(|i| matches(&i.uuid))
+        .collect();
+    match candidates.len() {
+        0 => match resolve(client, &ImageRef::Name(given.to_string())) {
+            Err(e) if e.is::<NotFound>() => Err(NotFound::NoMatch(format!(
+                "image with a UUID starting with {}, or active image named {:?}",
+                prefix, given
+            ))
+            .into()),
+            resolved => resolved,
+        },
+        1 => Ok(candidates.remove(0).uuid),
+        _ => Err(Ambiguous {
+            image: image.clone(),
+            candidates,
+        }
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<ImageRef, String> {
+        s.parse()
+    }
+
+    #[test]
+    fn takes_a_uuid_or_a_long_enough_hex_word_as_one() {
+        let uuid = "1d05e788-5409-11eb-b12f-037bd7fee4ee";
+        assert_eq!(
+            parse(uuid),
+            Ok(ImageRef::Uuid(Uuid::parse_str(uuid).unwrap()))
+        );
+        for prefix in &["1d05e788", "1D05E788", "1d05e788-54", "1d05e788540911"] {
+            assert_eq!(parse(prefix), Ok(ImageRef::Prefix(prefix.to_string())));
+        }
+        assert_eq!(
+            parse("cafe1234"),
+            Ok(ImageRef::Prefix("cafe1234".to_string()))
+        );
+    }
+
+    #[test]
+    fn takes_anything_else_as_a_name() {
+        let name = |s: &str| Ok(ImageRef::Name(s.to_string()));
+        assert_eq!(parse("1d05e78"), name("1d05e78"));
+        assert_eq!(parse("base-64-lts"), name("base-64-lts"));
+        assert_eq!(parse("cafe1234x"), name("cafe1234x"));
+        assert_eq!(
+            parse("base-64-lts@21.4.0"),
+            Ok(ImageRef::NameVersion(
+                "base-64-lts".to_string(),
+                "21.4.0".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn rejects_what_names_nothing() {
+        assert_eq!(
+            parse(""),
+            Err("expected a UUID, name@version or name".to_string())
+        );
+        for s in &["base@", "@1.0.0", "@"] {
+            assert_eq!(parse(s), Err(format!("expected name@version, got {:?}", s)));
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use std::error::Error;
+
+use regex_automata::meta::Regex;
+use regex_automata::util::syntax;
+use serde_json::{json, Value};
+
+use imgapi::Image;
+
+use super::UsageError;
+
+/// How much a term matching each field counts towards an image's score, so that images with the
+/// term in their name come before those that only mention it in their description.
+const NAME_SCORE: u32 = 4;
+const TAG_SCORE: u32 = 2;
+const VERSION_SCORE: u32 = 2;
+const DESCRIPTION_SCORE: u32 = 1;
+
+/// A search term: a case-insensitive substring, or a case-insensitive regular expression with
+/// --regex.
+#[derive(Debug)]
+enum Term {
+    Text(String),
+    Regex(Regex),
+}
+
+impl Term {
+    fn is_match(&self, s: &str) -> bool {
+        match self {
+            Self::Text(text) => s.to_lowercase().contains(text),
+            Self::Regex(re) => re.is_match(s),
+        }
+    }
+}
+
+/// Images whose name, version, description or tags match every one of a list of terms.
+#[derive(Debug)]
+pub struct Search {
+    terms: Vec<Term>,
+}
+
+impl Search {
+    /// A search for `terms`, as regular expressions if `regex` is set.
+    pub fn new(terms: &[String], regex: bool) -> Result<Self, Box<dyn Error>> {
+        let terms = terms
+            .iter()
+            .map(|t| {
+                if !regex {
+                    return Ok(Term::Text(t.to_lowercase()));
+                }
+                Regex::builder()
+                    .syntax(syntax::Config::new().case_insensitive(true))
+                    .build(t)
+                    .map(Term::Regex)
+                    .map_err(|e| {
+                        let detail = match e.syntax_error() {
+                            Some(e) => e.to_string(),
+                            None => e.to_string(),
+                        };
+                        UsageError(format!("invalid regular expression {:?}: {}", t, detail))
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { terms })
+    }
+
+    /// How well `image` matches, and the fields that matched, e.g. `name` or `tags.role`, or
+    /// `None` if some term doesn't match any field. A tag matches on its key or its value.
+    fn score(&self, image: &Image) -> Option<(u32, Vec<String>)> {
+        let mut fields = vec![
+            ("name".to_string(), vec![image.name.clone()], NAME_SCORE),
+            (
+                "version".to_string(),
+                vec![image.version.clone()],
+                VERSION_SCORE,
+            ),
+        ];
+        for (key, value) in image.tags.iter().flatten() {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                v => v.to_string(),
+            };
+            fields.push((format!("tags.{}", key), vec![key.clone(), value], TAG_SCORE));
+        }
+        if let Some(description) = &image.description {
+            let texts = vec![description.clone()];
+            fields.push(("description".to_string(), texts, DESCRIPTION_SCORE));
+        }
+
+        let mut score = 0;
+        let mut matched = Vec::new();
+        for term in &self.terms {
+            let mut any = false;
+            for (field, texts, points) in &fields {
+                if texts.iter().any(|t| term.is_match(t)) {
+                    any = true;
+                    score += points;
+                    if !matched.contains(field) {
+                        matched.push(field.clone());
+                    }
+                }
+            }
+            if !any {
+                return None;
+            }
+        }
+        Some((score, matched))
+    }
+
+    /// The images in `images` that match, best first, and newest first among equally good ones.
+    pub fn run<'a>(&self, images: &'a [Image]) -> Vec<Match<'a>> {
+        let mut matches: Vec<Match> = images
+            .iter()
+            .filter_map(|image| {
+                let (score, fields) = self.score(image)?;
+                Some(Match {
+                    image,
+                    score,
+                    fields,
+                })
+            })
+            .collect();
+        matches.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| b.image.published_at.cmp(&a.image.published_at))
+                .then_with(|| a.image.uuid.cmp(&b.image.uuid))
+        });
+        matches
+    }
+}
+
+/// An image found by a [`Search`].
+#[derive(Debug)]
+pub struct Match<'a> {
+    pub image: &'a Image,
+    pub score: u32,
+
+    /// The fields that matched, e.g. `name` or `tags.role`.
+    pub fields: Vec<String>,
+}
+
+/// The matches as a JSON array of `{"score": ..., "matched": [...], "image": {...}}` objects.
+pub fn to_json(matches: &[Match]) -> Result<Value, Box<dyn Error>> {
+    matches
+        .iter()
+        .map(|m| {
+            Ok(json!({
+                "score": m.score,
+                "matched": m.fields,
+                "image": m.image.to_json()?,
+            }))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testutil::image;
+
+    fn search(terms: &[&str], regex: bool) -> Search {
+        let terms: Vec<String> = terms.iter().map(|t| t.to_string()).collect();
+        Search::new(&terms, regex).unwrap()
+    }
+
+    /// The uuids of the images in `matches`, by the number they were made with, and their scores.
+    fn found(matches: &[Match]) -> Vec<(u128, u32)> {
+        matches
+            .iter()
+            .map(|m| (m.image.uuid.as_u128(), m.score))
+            .collect()
+    }
+
+    #[test]
+    fn text_terms_are_case_insensitive_substrings() {
+        let images = [
+            image(1, json!({"name": "Minimal-64"})),
+            image(2, json!({"name": "base-64"})),
+        ];
+        let matches = search(&["MINIMAL"], false).run(&images);
+        assert_eq!(found(&matches), [(1, NAME_SCORE)]);
+        assert_eq!(matches[0].fields, ["name"]);
+    }
+
+    #[test]
+    fn text_terms_are_not_patterns() {
+        let images = [image(1, json!({"version": "1.0.0"}))];
+        assert_eq!(search(&["1.0"], false).run(&images).len(), 1);
+        assert!(search(&["1.0.0$"], false).run(&images).is_empty());
+    }
+
+    #[test]
+    fn regex_terms_are_case_insensitive() {
+        let images = [
+            image(1, json!({"name": "minimal-64"})),
+            image(2, json!({"name": "Minimal-32"})),
+            image(3, json!({"name": "base-64"})),
+        ];
+        let matches = search(&["^MINIMAL-\\d+$"], true).run(&images);
+        assert_eq!(found(&matches), [(1, NAME_SCORE), (2, NAME_SCORE)]);
+    }
+
+    #[test]
+    fn an_invalid_regex_is_a_usage_error() {
+        let err = Search::new(&["(".to_string()], true).unwrap_err();
+        let err = err.downcast::<UsageError>().unwrap();
+        assert!(
+            err.0.starts_with("invalid regular expression \"(\": "),
+            "{}",
+            err.0
+        );
+    }
+
+    #[test]
+    fn every_term_has_to_match() {
+        let images = [
+            image(
+                1,
+                json!({"name": "minimal", "description": "a small image"}),
+            ),
+            image(2, json!({"name": "minimal"})),
+        ];
+        let matches = search(&["minimal", "small"], false).run(&images);
+        assert_eq!(found(&matches), [(1, NAME_SCORE + DESCRIPTION_SCORE)]);
+        assert_eq!(matches[0].fields, ["name", "description"]);
+    }
+
+    #[test]
+    fn a_tag_matches_on_its_key_or_its_value() {
+        let images = [
+            image(1, json!({"tags": {"role": "db"}})),
+            image(2, json!({"tags": {"kernel": 5}})),
+        ];
+        let by_key = search(&["role"], false).run(&images);
+        assert_eq!(found(&by_key), [(1, TAG_SCORE)]);
+        assert_eq!(by_key[0].fields, ["tags.role"]);
+        let by_value = search(&["5"], false).run(&images);
+        assert_eq!(found(&by_value), [(2, TAG_SCORE)]);
+        assert_eq!(by_value[0].fields, ["tags.kernel"]);
+    }
+
+    #[test]
+    fn a_term_scores_for_every_field_it_matches() {
+        let images = [image(
+            1,
+            json!({"name": "db", "description": "a db", "tags": {"db": "yes"}}),
+        )];
+        let matches = search(&["db"], false).run(&images);
+        assert_eq!(
+            found(&matches),
+            [(1, NAME_SCORE + TAG_SCORE + DESCRIPTION_SCORE)]
+        );
+        assert_eq!(matches[0].fields, ["name", "tags.db", "description"]);
+    }
+
+    #[test]
+    fn matches_in_the_name_come_before_other_fields() {
+        let images = [
+            image(1, json!({"name": "base", "description": "for postgres"})),
+            image(2, json!({"name": "base", "tags": {"postgres": true}})),
+            image(3, json!({"name": "postgres"})),
+        ];
+        let matches = search(&["postgres"], false).run(&images);
+        assert_eq!(
+            found(&matches),
+            [(3, NAME_SCORE), (2, TAG_SCORE), (1, DESCRIPTION_SCORE)]
+        );
+    }
+
+    #[test]
+    fn equal_scores_are_newest_first_then_by_uuid() {
+        let images = [
+            image(1, json!({"published_at": "2024-01-01T00:00:00Z"})),
+            image(2, json!({"published_at": null})),
+            image(3, json!({"published_at": "2024-06-01T00:00:00Z"})),
+            image(4, json!({"published_at": "2024-01-01T00:00:00Z"})),
+        ];
+        let matches = search(&["base"], false).run(&images);
+        let order: Vec<u128> = found(&matches).into_iter().map(|(n, _)| n).collect();
+        assert_eq!(order, [3, 1, 4, 2]);
+    }
+
+    #[test]
+    fn matches_as_json() {
+        let images = [image(1, json!({"description": "the base image"}))];
+        let matches = search(&["base"], false).run(&images);
+        let json = to_json(&matches).unwrap();
+        assert_eq!(json[0]["score"], NAME_SCORE + DESCRIPTION_SCORE);
+        assert_eq!(json[0]["matched"], json!(["name", "description"]));
+        assert_eq!(json[0]["image"], images[0].to_json().unwrap());
+        assert_eq!(json.as_array().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,35 @@
+use std::str::FromStr;
+
+use imgapi::{SortKey, SortOrder};
+
+/// The fields `-s` accepts.
+const FIELDS: &[&str] = &["published_at", "name", "version", "size", "state"];
+
+/// The `-s` argument to `img list`: comma-separated fields to sort by, each descending if it has a
+/// leading `-`, e.g. `-s name,-published_at`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortSpec(pub Vec<(SortKey, SortOrder)>);
+
+impl FromStr for SortSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|field| {
+                let (name, order) = match field.strip_prefix('-') {
+                    Some(name) => (name, SortOrder::Descending),
+                    None => (field, SortOrder::Ascending),
+                };
+                let key = SortKey::from_str(name).map_err(|_| {
+                    format!(
+                        "unknown sort field {:?} (expected one of: {})",
+                        name,
+                        FIELDS.join(", ")
+                    )
+                })?;
+                Ok((key, order))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
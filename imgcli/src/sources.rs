@@ -0,0 +1,433 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use structopt::StructOpt;
+use url::Url;
+
+use imgapi::Channel;
+
+use super::auth::AuthArgs;
+use super::dirs;
+use super::output::Output;
+use super::parse_server_url;
+use super::table;
+
+/// The kind of server a source is. Only IMGAPI servers are supported for now; imgadm's `docker`
+/// and `dsapi` sources are rejected when the config is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceType {
+    #[default]
+    Imgapi,
+}
+
+/// The credentials to sign requests to a source with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceAuth {
+    /// The account login or UUID.
+    pub account: String,
+
+    /// The fingerprint of the signing key.
+    pub key_id: String,
+
+    /// The private key, if it isn't in the SSH agent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<PathBuf>,
+}
+
+/// A named image server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Source {
+    pub name: String,
+
+    pub url: Url,
+
+    #[serde(rename = "type", default)]
+    pub source_type: SourceType,
+
+    /// Whether this source is used when no other is chosen.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub default: bool,
+
+    /// The channel to list images in, unless another is asked for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<Channel>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<SourceAuth>,
+
+    /// Whether to accept invalid TLS certificates from the server, as with --insecure.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub insecure: bool,
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+/// The sources config file, `sources.json` in the `img` config directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourcesConfig {
+    pub sources: Vec<Source>,
+
+    /// The channel to use with any server, unless another is asked for or the source names its
+    /// own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<Channel>,
+
+    /// Whether commands that only look images up should use the catalog cached by `img cache
+    /// refresh` when the server can't be reached, as with --offline.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub offline_fallback: bool,
+}
+
+/// The path of the sources config: `$XDG_CONFIG_HOME/img/sources.json`, or
+/// `~/.config/img/sources.json` if `XDG_CONFIG_HOME` isn't set.
+pub fn config_path() -> Result<PathBuf, String> {
+    Ok(dirs::config_dir()?.join("sources.json"))
+}
+
+impl SourcesConfig {
+    /// Reads the config at `path`. A missing file is an empty config.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let bytes = match fs::read(path) {
+            Ok(b) => b,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("{}: {}", path.display(), e).into()),
+        };
+        let malformed = |e: &dyn Error| {
+            format!(
+                "{}: {} (fix the file, or remove it to start over)",
+                path.display(),
+                e
+            )
+        };
+        let config: Self = serde_json::from_slice(&bytes).map_err(|e| malformed(&e))?;
+        config.validate().map_err(|e| malformed(e.as_ref()))?;
+        Ok(config)
+    }
+
+    /// Writes the config to `path`, through a temporary file that's renamed into place so the
+    /// config is never left half-written.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.validate()?;
+        let with_path = |e: io::Error| format!("{}: {}", path.display(), e);
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        fs::create_dir_all(dir).map_err(with_path)?;
+        let mut tmp = tempfile::NamedTempFile::new_in(dir).map_err(with_path)?;
+        serde_json::to_writer_pretty(&mut tmp, self)?;
+        tmp.write_all(b"\n").map_err(with_path)?;
+        tmp.persist(path).map_err(|e| with_path(e.error))?;
+        Ok(())
+    }
+
+    /// Checks that names are unique and non-empty, URLs are http or https, and there's at most
+    /// one default.
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        for (i, source) in self.sources.iter().enumerate() {
+            if source.name.is_empty() {
+                return Err(format!("source {} has an empty name", i + 1).into());
+            }
+            if self.sources[..i].iter().any(|s| s.name == source.name) {
+                return Err(format!("there's more than one source named {:?}", source.name).into());
+            }
+            parse_server_url(source.url.as_str())
+                .map_err(|e| format!("source {:?}: {}", source.name, e))?;
+        }
+        if self.sources.iter().filter(|s| s.default).count() > 1 {
+            return Err("more than one source is marked as the default".into());
+        }
+        Ok(())
+    }
+
+    /// The source named `name`.
+    pub fn get(&self, name: &str) -> Result<&Source, String> {
+        self.sources
+            .iter()
+            .find(|s| s.name == name)
+            .ok_or_else(|| self.no_such_source(name))
+    }
+
+    fn no_such_source(&self, name: &str) -> String {
+        let names: Vec<_> = self.sources.iter().map(|s| s.name.as_str()).collect();
+        if names.is_empty() {
+            format!("no source named {:?}: no sources are configured", name)
+        } else {
+            format!(
+                "no source named {:?} (expected one of: {})",
+                name,
+                names.join(", ")
+            )
+        }
+    }
+
+    /// The channel `source` lists images in: its own, or else the config's, or `None` for the
+    /// server's default channel.
+    pub fn channel_for<'a>(&'a self, source: Option<&'a Source>) -> Option<&'a Channel> {
+        source
+            .and_then(|s| s.channel.as_ref())
+            .or(self.channel.as_ref())
+    }
+
+    /// The default source, if there is one.
+    pub fn default_source(&self) -> Option<&Source> {
+        self.sources.iter().find(|s| s.default)
+    }
+
+    /// Adds `source`, making it the only default if it's marked as one. The first source added is
+    /// always the default.
+    pub fn add(&mut self, mut source: Source) -> Result<(), String> {
+        if self.sources.iter().any(|s| s.name == source.name) {
+            return Err(format!("there's already a source named {:?}", source.name));
+        }
+        if self.sources.is_empty() {
+            source.default = true;
+        }
+        if source.default {
+            self.sources.iter_mut().for_each(|s| s.default = false);
+        }
+        self.sources.push(source);
+        Ok(())
+    }
+
+    /// Removes the source named `name`, returning it.
+    pub fn remove(&mut self, name: &str) -> Result<Source, String> {
+        match self.sources.iter().position(|s| s.name == name) {
+            Some(i) => Ok(self.sources.remove(i)),
+            None => Err(self.no_such_source(name)),
+        }
+    }
+
+    /// Makes the source named `name` the default.
+    pub fn set_default(&mut self, name: &str) -> Result<(), String> {
+        self.get(name)?;
+        for source in &mut self.sources {
+            source.default = source.name == name;
+        }
+        Ok(())
+    }
+}
+
+/// `img sources` subcommands, which edit the sources config.
+#[derive(Debug, StructOpt)]
+pub enum SourcesCommand {
+    /// Lists the configured sources. The CHANNEL column is the channel each source uses, marked
+    /// `(config)` if it's the `channel` set for all sources in sources.json. With --json, prints
+    /// the config's array of sources.
+    List,
+
+    /// Adds a source. The first source added is the default. With --channel, images are listed in
+    /// that channel when the source is used, unless another is asked for, and with --account and
+    /// --key-id (and optionally --key-file), requests to it are signed with that key. With
+    /// --insecure, its TLS certificate is never checked.
+    Add {
+        /// The name to refer to the source by with -S.
+        name: String,
+
+        /// The base URL of the IMGAPI server.
+        #[structopt(name = "URL", parse(try_from_str = parse_server_url))]
+        url: Url,
+
+        /// Make this the default source.
+        #[structopt(long)]
+        default: bool,
+    },
+
+    /// Removes a source.
+    Remove { name: String },
+
+    /// Makes a source the default.
+    SetDefault { name: String },
+}
+
+/// Runs an `img sources` subcommand. `channel`, `auth` and `insecure` are the ones given with
+/// --channel, --account, --key-id and --key-file, and --insecure, which `add` stores with the
+/// source.
+pub fn run(
+    cmd: SourcesCommand,
+    channel: Option<Channel>,
+    auth: &AuthArgs,
+    insecure: bool,
+    out: &Output,
+) -> Result<(), Box<dyn Error>> {
+    let path = config_path()?;
+    let mut config = SourcesConfig::load(&path)?;
+    match cmd {
+        SourcesCommand::List => {
+            if out.json {
+                return out.write_json(&serde_json::to_value(&config.sources)?);
+            }
+            let mut rows = vec![["NAME", "URL", "TYPE", "DEFAULT", "CHANNEL"]
+                .iter()
+                .map(|h| h.to_string())
+                .collect::<Vec<_>>()];
+            rows.extend(config.sources.iter().map(|s| {
+                vec![
+                    s.name.clone(),
+                    s.url.to_string(),
+                    match s.source_type {
+                        SourceType::Imgapi => "imgapi",
+                    }
+                    .to_string(),
+                    if s.default { "yes" } else { "-" }.to_string(),
+                    match (&s.channel, &config.channel) {
+                        (Some(c), _) => c.to_string(),
+                        (None, Some(c)) => format!("{} (config)", c),
+                        (None, None) => "-".to_string(),
+                    },
+                ]
+            }));
+            write!(io::stdout().lock(), "{}", table::layout(&rows, false))?;
+            return Ok(());
+        }
+        SourcesCommand::Add { name, url, default } => {
+            let auth = auth.to_source_auth()?;
+            config.add(Source {
+                name,
+                url,
+                source_type: SourceType::Imgapi,
+                default,
+                channel,
+                auth,
+                insecure,
+            })?;
+        }
+        SourcesCommand::Remove { name } => drop(config.remove(&name)?),
+        SourcesCommand::SetDefault { name } => config.set_default(&name)?,
+    }
+    config.save(&path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(name: &str) -> Source {
+        Source {
+            name: name.to_string(),
+            url: Url::parse(&format!("https://{}.example.com/", name)).unwrap(),
+            source_type: SourceType::Imgapi,
+            default: false,
+            channel: None,
+            auth: None,
+            insecure: false,
+        }
+    }
+
+    fn defaults(config: &SourcesConfig) -> Vec<&str> {
+        let defaults = config.sources.iter().filter(|s| s.default);
+        defaults.map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn the_first_source_is_the_default_until_another_is_made_it() {
+        let mut config = SourcesConfig::default();
+        config.add(source("a")).unwrap();
+        config.add(source("b")).unwrap();
+        assert_eq!(defaults(&config), ["a"]);
+        config
+            .add(Source {
+                default: true,
+                ..source("c")
+            })
+            .unwrap();
+        assert_eq!(defaults(&config), ["c"]);
+        config.set_default("b").unwrap();
+        assert_eq!(defaults(&config), ["b"]);
+        assert_eq!(config.default_source().map(|s| s.name.as_str()), Some("b"));
+
+        assert_eq!(
+            config.add(source("a")),
+            Err("there's already a source named \"a\"".to_string())
+        );
+        assert_eq!(
+            config.set_default("d"),
+            Err("no source named \"d\" (expected one of: a, b, c)".to_string())
+        );
+        assert_eq!(config.remove("b").map(|s| s.name), Ok("b".to_string()));
+        assert_eq!(config.default_source(), None);
+        assert_eq!(
+            SourcesConfig::default().remove("a").map(|s| s.name),
+            Err("no source named \"a\": no sources are configured".to_string())
+        );
+    }
+
+    #[test]
+    fn saves_and_loads_through_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("img").join("sources.json");
+        assert_eq!(
+            SourcesConfig::load(&path).unwrap(),
+            SourcesConfig::default()
+        );
+
+        let mut config = SourcesConfig {
+            channel: Some("dev".parse().unwrap()),
+            ..SourcesConfig::default()
+        };
+        config.add(source("a")).unwrap();
+        config
+            .add(Source {
+                channel: Some("release".parse().unwrap()),
+                auth: Some(SourceAuth {
+                    account: "me".to_string(),
+                    key_id: "SHA256:abc".to_string(),
+                    key_file: None,
+                }),
+                insecure: true,
+                ..source("b")
+            })
+            .unwrap();
+        config.save(&path).unwrap();
+        assert_eq!(SourcesConfig::load(&path).unwrap(), config);
+        assert_eq!(
+            config
+                .channel_for(config.get("a").ok())
+                .map(|c| c.to_string()),
+            Some("dev".to_string())
+        );
+        assert_eq!(
+            config
+                .channel_for(config.get("b").ok())
+                .map(|c| c.to_string()),
+            Some("release".to_string())
+        );
+
+        let written: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(written["sources"][0].get("auth"), None);
+        assert_eq!(written["sources"][1]["auth"]["keyId"], "SHA256:abc");
+    }
+
+    #[test]
+    fn says_how_to_recover_from_a_malformed_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sources.json");
+        let malformed = [
+            "{",
+            r#"{"sources": [{"name": "a"}]}"#,
+            r#"{"sources": [], "extra": true}"#,
+            r#"{"sources": [{"name": "a", "url": "ftp://a.example.com/"}]}"#,
+            r#"{"sources": [{"name": "", "url": "https://a.example.com/"}]}"#,
+            r#"{"sources": [{"name": "a", "url": "https://a.example.com/"},
+                            {"name": "a", "url": "https://b.example.com/"}]}"#,
+            r#"{"sources": [{"name": "a", "url": "https://a.example.com/", "default": true},
+                            {"name": "b", "url": "https://b.example.com/", "default": true}]}"#,
+        ];
+        for contents in &malformed {
+            fs::write(&path, contents).unwrap();
+            let err = SourcesConfig::load(&path).unwrap_err().to_string();
+            assert!(err.starts_with(&format!("{}: ", path.display())), "{}", err);
+            assert!(
+                err.ends_with("(fix the file, or remove it to start over)"),
+                "{}",
+                err
+            );
+        }
+    }
+}
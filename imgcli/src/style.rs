@@ -0,0 +1,108 @@
+//! All of `img`'s coloring. Output is colored only through [`paint`], so with `--color never`
+//! it's exactly what it would be if `img` had no colors at all.
+
+use std::env;
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+
+use imgapi::{Image, ImageState};
+
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+const RED: &str = "31";
+
+/// When to color output (`--color`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Only on a terminal, and only if `NO_COLOR` isn't set.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether output to stdout should be colored.
+    pub fn stdout(self) -> bool {
+        self.enabled(io::stdout().is_terminal())
+    }
+
+    /// Whether output to stderr, such as errors and progress bars, should be colored.
+    pub fn stderr(self) -> bool {
+        self.enabled(io::stderr().is_terminal())
+    }
+
+    fn enabled(self, terminal: bool) -> bool {
+        match self {
+            Self::Auto => terminal && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(format!(
+                "unknown color choice {:?} (expected auto, always or never)",
+                s
+            )),
+        }
+    }
+}
+
+/// Wraps `s` in the ANSI escape sequences for `color`, if `enabled`.
+fn paint(s: &str, color: &str, enabled: bool) -> String {
+    if enabled {
+        format!("\x1b[{}m{}\x1b[0m", color, s)
+    } else {
+        s.to_string()
+    }
+}
+
+/// The image's state, noting if the image is disabled, colored by how usable the image is: green
+/// for active, yellow for not yet, and red for never.
+pub fn state(image: &Image, color: bool) -> String {
+    let text = if image.disabled && image.state != ImageState::Disabled {
+        format!("{} (disabled)", image.state)
+    } else {
+        image.state.to_string()
+    };
+    state_colored(&text, image, color)
+}
+
+/// `s`, colored as [`state`] colors `image`'s state.
+pub fn state_colored(s: &str, image: &Image, color: bool) -> String {
+    let code = match image.state {
+        _ if image.disabled => RED,
+        ImageState::Active => GREEN,
+        ImageState::Unactivated | ImageState::Creating => YELLOW,
+        ImageState::Disabled | ImageState::Failed => RED,
+    };
+    paint(s, code, color)
+}
+
+/// A value a diff adds, in green.
+pub fn added(s: &str, color: bool) -> String {
+    paint(s, GREEN, color)
+}
+
+/// A value a diff removes, in red.
+pub fn removed(s: &str, color: bool) -> String {
+    paint(s, RED, color)
+}
+
+/// The `error:` that error messages start with, in red.
+pub fn error_label(color: bool) -> String {
+    paint("error:", RED, color)
+}
+
+/// The filled part of a progress bar, in green.
+pub fn progress(s: &str, color: bool) -> String {
+    paint(s, GREEN, color)
+}
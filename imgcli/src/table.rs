@@ -0,0 +1,402 @@
+use std::str::FromStr;
+
+use imgapi::size::format_size;
+use imgapi::Image;
+
+use super::style;
+
+/// A column of `img list` output, as chosen with `-o`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Uuid,
+    Name,
+    Version,
+    Os,
+    Type,
+    Published,
+    Size,
+    State,
+    Owner,
+    Channels,
+    Description,
+}
+
+/// The columns shown without `-o`, or with `-o default`: those imgadm shows.
+const DEFAULT: &[Column] = &[
+    Column::Uuid,
+    Column::Name,
+    Column::Version,
+    Column::Os,
+    Column::Type,
+    Column::Published,
+];
+
+/// Every column, in the order `-o all` shows them.
+const ALL: &[Column] = &[
+    Column::Uuid,
+    Column::Name,
+    Column::Version,
+    Column::Os,
+    Column::Type,
+    Column::Published,
+    Column::Size,
+    Column::State,
+    Column::Owner,
+    Column::Channels,
+    Column::Description,
+];
+
+impl Column {
+    /// The column's name, as given to `-o`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Uuid => "uuid",
+            Self::Name => "name",
+            Self::Version => "version",
+            Self::Os => "os",
+            Self::Type => "type",
+            Self::Published => "pub",
+            Self::Size => "size",
+            Self::State => "state",
+            Self::Owner => "owner",
+            Self::Channels => "channels",
+            Self::Description => "description",
+        }
+    }
+
+    /// The widest a value in this column may be before it's truncated, for free-form fields that
+    /// would otherwise push the rest of the table off the screen.
+    fn max_width(self) -> Option<usize> {
+        match self {
+            Self::Name => Some(40),
+            Self::Channels => Some(30),
+            Self::Description => Some(60),
+            _ => None,
+        }
+    }
+
+    /// The value of this column for `image`. Unset values are shown as `-`. Sizes are shown like
+    /// `245.1M`, or as an exact number of bytes if `bytes` is set.
+    pub fn value(self, image: &Image, bytes: bool) -> String {
+        match self {
+            Self::Uuid => image.uuid.to_string(),
+            Self::Name => image.name.clone(),
+            Self::Version => image.version.clone(),
+            Self::Os => image.os.clone(),
+            Self::Type => image.image_type.clone(),
+            Self::Published => image
+                .published_at
+                .map_or_else(|| "-".to_string(), |t| t.format("%Y-%m-%d").to_string()),
+            Self::Size if bytes => image.total_file_size().to_string(),
+            Self::Size => format_size(image.total_file_size()),
+            Self::State => image.state.to_string(),
+            Self::Owner => image.owner.to_string(),
+            Self::Channels => match &image.channels {
+                Some(c) if !c.is_empty() => {
+                    c.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(",")
+                }
+                _ => "-".to_string(),
+            },
+            Self::Description => image.description.clone().unwrap_or_else(|| "-".to_string()),
+        }
+    }
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ALL.iter().copied().find(|c| c.name() == s).ok_or_else(|| {
+            let names: Vec<_> = ALL.iter().map(|c| c.name()).collect();
+            format!(
+                "unknown column {:?} (expected one of: {}, or default or all)",
+                s,
+                names.join(", ")
+            )
+        })
+    }
+}
+
+/// The `-o` argument: a comma-separated list of columns, or `default` or `all`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Columns(pub Vec<Column>);
+
+impl Default for Columns {
+    fn default() -> Self {
+        Self(DEFAULT.to_vec())
+    }
+}
+
+impl FromStr for Columns {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self::default()),
+            "all" => Ok(Self(ALL.to_vec())),
+            _ => s
+                .split(',')
+                .map(Column::from_str)
+                .collect::<Result<_, _>>()
+                .map(Self),
+        }
+    }
+}
+
+/// Cuts `s` down to `width` characters, marking the cut with an ellipsis.
+fn truncate(s: &str, width: usize) -> String {
+    if s.chars().count() <= width {
+        return s.to_string();
+    }
+    let mut cut: String = s.chars().take(width - 1).collect();
+    cut.push('…');
+    cut
+}
+
+/// How `img list` lays out its table.
+#[derive(Debug, Clone)]
+pub struct Table {
+    pub columns: Vec<Column>,
+
+    /// Leave out the header row (`-H`).
+    pub no_header: bool,
+
+    /// Separate columns with a tab, without padding or truncating them, and show exact sizes
+    /// (`-p`).
+    pub parseable: bool,
+
+    /// Show sizes as an exact number of bytes (`--bytes`).
+    pub bytes: bool,
+
+    /// Color the state column, unless the table is parseable.
+    pub color: bool,
+}
+
+impl Table {
+    /// Renders `images` as a table, one line per image after the header row, laid out with
+    /// [`layout`]. Long free-form values are truncated unless the table is parseable.
+    pub fn render(&self, images: &[Image]) -> String {
+        let mut rows = Vec::new();
+        if !self.no_header {
+            rows.push(
+                self.columns
+                    .iter()
+                    .map(|c| c.name().to_uppercase())
+                    .collect::<Vec<_>>(),
+            );
+        }
+        let bytes = self.bytes || self.parseable;
+        rows.extend(images.iter().map(|image| {
+            self.columns
+                .iter()
+                .map(|c| match c.max_width() {
+                    _ if *c == Column::State && self.color && !self.parseable => {
+                        style::state_colored(&c.value(image, bytes), image, true)
+                    }
+                    // Tabs and newlines in descriptions would break up the record.
+                    _ if self.parseable => c.value(image, bytes).replace(['\t', '\n'], " "),
+                    Some(width) => truncate(&c.value(image, bytes), width),
+                    None => c.value(image, bytes),
+                })
+                .collect()
+        }));
+
+        layout(&rows, self.parseable)
+    }
+}
+
+/// Lays out `rows` of cells, which must all have the same number of cells.
+///
+/// Columns are padded to their widest cell and separated by two spaces, except for the last
+/// column, so that lines have no trailing whitespace. Parseable rows are tab-separated instead.
+pub fn layout(rows: &[Vec<String>], parseable: bool) -> String {
+    let mut out = String::new();
+    if parseable {
+        for row in rows {
+            out.push_str(&row.join("\t"));
+            out.push('\n');
+        }
+        return out;
+    }
+    let columns = rows.first().map_or(0, |r| r.len());
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| rows.iter().map(|r| width(&r[i])).max().unwrap_or(0))
+        .collect();
+    for row in rows {
+        let last = row.len().saturating_sub(1);
+        for (i, cell) in row.iter().enumerate() {
+            out.push_str(cell);
+            if i != last {
+                let pad = widths[i] - width(cell);
+                out.extend(std::iter::repeat_n(' ', pad + 2));
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// The number of characters `s` takes up on screen, not counting ANSI color sequences.
+fn width(s: &str) -> usize {
+    let mut width = 0;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testutil::image;
+
+    fn fixtures() -> Vec<Image> {
+        vec![
+            image(1, json!({})),
+            image(
+                2,
+                json!({
+                    "name": "minimal-64-lts",
+                    "version": "21.4.0",
+                    "os": "linux",
+                    "type": "lx-dataset",
+                    "published_at": null,
+                    "description": "A minimal image\twith a tab",
+                }),
+            ),
+        ]
+    }
+
+    fn table(columns: &str) -> Table {
+        Table {
+            columns: columns.parse::<Columns>().unwrap().0,
+            no_header: false,
+            parseable: false,
+            bytes: false,
+            color: false,
+        }
+    }
+
+    #[test]
+    fn renders_the_default_columns_like_imgadm() {
+        assert_eq!(
+            table("default").render(&fixtures()),
+            concat!(
+                "UUID                                  NAME            ",
+                "VERSION  OS       TYPE          PUB\n",
+                "00000000-0000-0000-0000-000000000001  base            ",
+                "1.0.0    smartos  zone-dataset  2024-01-01\n",
+                "00000000-0000-0000-0000-000000000002  minimal-64-lts  ",
+                "21.4.0   linux    lx-dataset    -\n",
+            )
+        );
+    }
+
+    #[test]
+    fn renders_the_chosen_columns_in_order() {
+        assert_eq!(
+            table("size,name,description").render(&fixtures()),
+            "SIZE  NAME            DESCRIPTION\n\
+             1.5M  base            -\n\
+             1.5M  minimal-64-lts  A minimal image\twith a tab\n"
+        );
+        let bytes = Table {
+            bytes: true,
+            ..table("size")
+        };
+        assert_eq!(bytes.render(&fixtures()), "SIZE\n1572864\n1572864\n");
+    }
+
+    #[test]
+    fn truncates_long_free_form_values() {
+        let long = image(
+            1,
+            json!({ "name": "n".repeat(50), "description": "d".repeat(70) }),
+        );
+        let rendered = table("name,description").render(&[long]);
+        let row = rendered.lines().nth(1).unwrap();
+        assert_eq!(row, format!("{}…  {}…", "n".repeat(39), "d".repeat(59)));
+    }
+
+    #[test]
+    fn parses_column_lists() {
+        assert_eq!("all".parse::<Columns>().unwrap().0, ALL);
+        assert_eq!("default".parse::<Columns>(), Ok(Columns::default()));
+        assert_eq!(
+            "uuid,pub".parse::<Columns>().unwrap().0,
+            [Column::Uuid, Column::Published]
+        );
+        assert_eq!(
+            "uuid,published".parse::<Columns>(),
+            Err(
+                "unknown column \"published\" (expected one of: uuid, name, version, os, type, \
+                 pub, size, state, owner, channels, description, or default or all)"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn doesnt_count_colors_towards_the_width() {
+        let colored = Table {
+            color: true,
+            ..table("state,name")
+        };
+        let disabled = image(2, json!({ "state": "disabled" }));
+        let rendered = colored.render(&[image(1, json!({})), disabled]);
+        assert!(rendered.contains("\x1b["), "{:?}", rendered);
+        let widths: Vec<usize> = rendered.lines().map(width).collect();
+        assert_eq!(widths, [14, 14, 14], "{}", rendered);
+    }
+
+    #[test]
+    fn leaves_out_the_header_with_no_header() {
+        let table = Table {
+            no_header: true,
+            ..table("uuid,name")
+        };
+        assert_eq!(
+            table.render(&fixtures()),
+            "00000000-0000-0000-0000-000000000001  base\n\
+             00000000-0000-0000-0000-000000000002  minimal-64-lts\n"
+        );
+        assert_eq!(table.render(&[]), "");
+    }
+
+    #[test]
+    fn separates_parseable_columns_with_tabs() {
+        let parseable = Table {
+            parseable: true,
+            color: true,
+            ..table("name,size,pub,description,state")
+        };
+        let long = image(
+            3,
+            json!({ "name": "n".repeat(50), "description": "line\nbreak" }),
+        );
+        let mut images = fixtures();
+        images.push(long);
+        assert_eq!(
+            parseable.render(&images),
+            format!(
+                "NAME\tSIZE\tPUB\tDESCRIPTION\tSTATE\n\
+                 base\t1572864\t2024-01-01\t-\tactive\n\
+                 minimal-64-lts\t1572864\t-\tA minimal image with a tab\tactive\n\
+                 {}\t1572864\t2024-01-01\tline break\tactive\n",
+                "n".repeat(50)
+            )
+        );
+        let bare = Table {
+            no_header: true,
+            ..parseable
+        };
+        assert!(bare.render(&images).starts_with("base\t"));
+    }
+}
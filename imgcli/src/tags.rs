@@ -0,0 +1,154 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use serde_json::Value;
+use structopt::StructOpt;
+
+use imgapi::{Image, ImageUpdate, Patch, TagValue};
+
+use super::filter::bare_word;
+use super::output::Output;
+use super::resolve::ImageRef;
+use super::UsageError;
+
+/// `img tag` subcommands, which change an image's tags with UpdateImage, sending just the tags.
+#[derive(Debug, StructOpt)]
+pub enum TagCommand {
+    /// Lists an image's tags, one `key=value` per line. With --json, prints the manifest.
+    Ls {
+        /// The image, as for `img info`.
+        image: ImageRef,
+    },
+
+    /// Sets tags on an image, keeping its others, and prints its tags. A value of `true` or
+    /// `false` is set as a boolean, and one that looks like a number as a number, unless --string
+    /// is given. With --json, prints the updated manifest.
+    Add {
+        /// Set every value as a string.
+        #[structopt(long)]
+        string: bool,
+
+        /// The image, as for `img info`.
+        image: ImageRef,
+
+        /// The tags, as `key=value`.
+        #[structopt(required = true)]
+        tags: Vec<TagArg>,
+    },
+
+    /// Removes tags from an image, keeping its others, and prints its tags. With --json, prints
+    /// the updated manifest.
+    Rm {
+        /// The image, as for `img info`.
+        image: ImageRef,
+
+        /// The keys of the tags.
+        #[structopt(required = true)]
+        keys: Vec<String>,
+    },
+}
+
+/// A `key=value` argument to `img tag add`. The value is kept as given until --string says how
+/// to take it.
+#[derive(Debug, Clone)]
+pub struct TagArg {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for TagArg {
+    type Err = String;
+
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        let (key, value) = arg
+            .split_once('=')
+            .ok_or_else(|| bare_word("tag", arg, &[], "role=db"))?;
+        if key.is_empty() {
+            return Err(format!("tag {:?} has no key", arg));
+        }
+        Ok(Self {
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+/// Checks that no key is given more than once.
+fn check_repeats<'a>(keys: impl IntoIterator<Item = &'a str>) -> Result<(), UsageError> {
+    let mut seen = BTreeSet::new();
+    for key in keys {
+        if !seen.insert(key) {
+            return Err(UsageError(format!("tag {:?} is given more than once", key)));
+        }
+    }
+    Ok(())
+}
+
+/// Sets the tags in `args` on `image`, inferring their types unless `string` is set, and returns
+/// the UpdateImage payload that makes the same change on the server, or `None` if the image
+/// already has those tags.
+pub fn add(
+    image: &mut Image,
+    args: &[TagArg],
+    string: bool,
+) -> Result<Option<ImageUpdate>, UsageError> {
+    check_repeats(args.iter().map(|a| a.key.as_str()))?;
+    let before = image.tags.clone();
+    for arg in args {
+        let value = if string {
+            TagValue::from(arg.value.as_str())
+        } else {
+            TagValue::infer(&arg.value)
+        };
+        image.set_tag(&arg.key, value);
+    }
+    Ok(changed(image, before))
+}
+
+/// Removes the tags `keys` from `image`, and returns the UpdateImage payload that makes the same
+/// change on the server. It's an error for the image not to have one of them.
+pub fn remove(image: &mut Image, keys: &[String]) -> Result<ImageUpdate, Box<dyn Error>> {
+    check_repeats(keys.iter().map(String::as_str))?;
+    let before = image.tags.clone();
+    for key in keys {
+        if image.remove_tag(key).is_none() {
+            return Err(format!("image {} has no tag {:?}", image.uuid, key).into());
+        }
+    }
+    Ok(changed(image, before).expect("a tag was removed"))
+}
+
+/// An update with just the tags `image` has now, if they aren't `before`. Tags are sent whole, so
+/// removing the last one sends an empty object, rather than leaving the field out.
+fn changed(image: &Image, before: Option<BTreeMap<String, Value>>) -> Option<ImageUpdate> {
+    if image.tags == before {
+        return None;
+    }
+    Some(ImageUpdate {
+        tags: Patch::Set(image.tags.clone().unwrap_or_default()),
+        ..ImageUpdate::default()
+    })
+}
+
+/// Prints the image's tags, one `key=value` per line with strings unquoted and other values as
+/// JSON, or with --json the image's manifest.
+pub fn print(out: &Output, image: &Image) -> Result<(), Box<dyn Error>> {
+    if out.json {
+        return out.write_json(&image.to_json()?);
+    }
+    match &image.tags {
+        Some(tags) if !tags.is_empty() => {
+            let mut stdout = io::stdout().lock();
+            for (key, value) in tags {
+                match value {
+                    Value::String(s) => writeln!(stdout, "{}={}", key, s)?,
+                    v => writeln!(stdout, "{}={}", key, v)?,
+                }
+            }
+        }
+        _ => eprintln!("image {} has no tags", image.uuid),
+    }
+    Ok(())
+}
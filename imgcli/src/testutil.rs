@@ -0,0 +1,30 @@
+//! Helpers for the unit tests.
+
+use serde_json::{json, Value};
+
+use imgapi::{Image, Uuid};
+
+/// A minimal active manifest of image `n`, with a 1.5M file, and with `fields` merged into it.
+pub fn image(n: u128, fields: Value) -> Image {
+    let mut manifest = json!({
+        "v": 2,
+        "uuid": Uuid::from_u128(n),
+        "owner": Uuid::nil(),
+        "name": "base",
+        "version": "1.0.0",
+        "state": "active",
+        "disabled": false,
+        "public": true,
+        "published_at": "2024-01-01T00:00:00Z",
+        "type": "zone-dataset",
+        "os": "smartos",
+        "files": [{"sha1": "", "size": 1_572_864, "compression": "gzip"}],
+    });
+    if let Value::Object(fields) = fields {
+        manifest
+            .as_object_mut()
+            .expect("a manifest is an object")
+            .extend(fields);
+    }
+    serde_json::from_value(manifest).expect("a valid manifest")
+}
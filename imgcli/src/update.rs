@@ -0,0 +1,167 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use imgapi::{Image, ImageUpdate, MaybeUrl, Patch, TagValue, Uuid};
+
+use super::filter::{bare_word, did_you_mean};
+use super::UsageError;
+
+/// A single `key=value` change argument to `img update`.
+#[derive(Debug, Clone)]
+pub enum UpdateArg {
+    Name(String),
+    Version(String),
+    Description(String),
+    Homepage(MaybeUrl),
+    Public(bool),
+
+    /// A tag, from `tag.key=value`, with the value's type inferred by [`TagValue::infer`].
+    Tag(String, TagValue),
+
+    /// The billing tags, from a comma-separated list. An empty list removes them all.
+    BillingTags(Vec<String>),
+
+    /// An account to give access to, from `acl+=<uuid>`.
+    AclAdd(Uuid),
+
+    /// An account to take access away from, from `acl-=<uuid>`.
+    AclRemove(Uuid),
+}
+
+/// The keys `img update` accepts.
+const KEYS: &[&str] = &[
+    "name",
+    "version",
+    "description",
+    "homepage",
+    "public",
+    "tag.<key>",
+    "billing_tags",
+    "acl+",
+    "acl-",
+];
+
+impl FromStr for UpdateArg {
+    type Err = String;
+
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        let (k, v) = arg
+            .split_once('=')
+            .ok_or_else(|| bare_word("change", arg, KEYS, "version=1.0.1"))?;
+        let uuid =
+            |key: &str| Uuid::parse_str(v).map_err(|_| format!("{} must be a valid UUID", key));
+        Ok(match k {
+            "name" => Self::Name(v.to_string()),
+            "version" => Self::Version(v.to_string()),
+            "description" => Self::Description(v.to_string()),
+            "homepage" => Self::Homepage(MaybeUrl::from(v.to_string())),
+            "public" => {
+                Self::Public(bool::from_str(v).map_err(|_| "public must be either true or false")?)
+            }
+            "billing_tags" => Self::BillingTags(
+                v.split(',')
+                    .filter(|t| !t.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            "acl+" => Self::AclAdd(uuid("acl+")?),
+            "acl-" => Self::AclRemove(uuid("acl-")?),
+            _ if k.starts_with("tag.") && k.len() > "tag.".len() => {
+                Self::Tag(k["tag.".len()..].to_string(), TagValue::infer(v))
+            }
+            _ => {
+                return Err(format!(
+                    "unexpected change: {} (expected one of: {}){}",
+                    arg,
+                    KEYS.join(", "),
+                    did_you_mean(k, KEYS)
+                ))
+            }
+        })
+    }
+}
+
+impl UpdateArg {
+    /// The field the change is to, e.g. `name` or `tag.role`, or `None` for ACL changes, which
+    /// can be repeated.
+    fn key(&self) -> Option<String> {
+        Some(
+            match self {
+                Self::Name(_) => "name",
+                Self::Version(_) => "version",
+                Self::Description(_) => "description",
+                Self::Homepage(_) => "homepage",
+                Self::Public(_) => "public",
+                Self::Tag(k, _) => return Some(format!("tag.{}", k)),
+                Self::BillingTags(_) => "billing_tags",
+                Self::AclAdd(_) | Self::AclRemove(_) => return None,
+            }
+            .to_string(),
+        )
+    }
+}
+
+/// Checks that `args` change each field only once, though accounts can be added to and removed
+/// from the ACL in turn.
+pub fn check_repeats(args: &[UpdateArg]) -> Result<(), UsageError> {
+    let mut seen = BTreeSet::new();
+    for key in args.iter().filter_map(UpdateArg::key) {
+        if !seen.insert(key.clone()) {
+            return Err(UsageError(format!("{}= is given more than once", key)));
+        }
+    }
+    Ok(())
+}
+
+/// Builds the UpdateImage payload for `args`, applied in order to `image`. Tags and the ACL are
+/// sent whole, so those of `image` are carried over, with the changes made to them.
+pub fn build(image: &Image, args: Vec<UpdateArg>) -> Result<ImageUpdate, String> {
+    let mut update = ImageUpdate::default();
+    for arg in args {
+        match arg {
+            UpdateArg::Name(v) => update.name = Some(v),
+            UpdateArg::Version(v) => update.version = Some(v),
+            UpdateArg::Description(v) => update.description = Patch::Set(v),
+            UpdateArg::Homepage(v) => update.homepage = Patch::Set(v),
+            UpdateArg::Public(v) => update.public = Some(v),
+            UpdateArg::Tag(k, v) => {
+                update
+                    .tags
+                    .get_or_insert_with(|| image.tags.clone().unwrap_or_default())
+                    .insert(k, v.into());
+            }
+            UpdateArg::BillingTags(v) => update.billing_tags = Patch::Set(v),
+            UpdateArg::AclAdd(account) => {
+                if account == image.owner {
+                    return Err(format!("account {} owns the image", account));
+                }
+                let acl = update
+                    .acl
+                    .get_or_insert_with(|| image.acl.clone().unwrap_or_default());
+                if !acl.contains(&account) {
+                    acl.push(account);
+                }
+            }
+            UpdateArg::AclRemove(account) => update
+                .acl
+                .get_or_insert_with(|| image.acl.clone().unwrap_or_default())
+                .retain(|a| *a != account),
+        }
+    }
+    Ok(update)
+}
+
+/// Reads an UpdateImage payload from the JSON file at `path`, or from stdin if it's `-`.
+pub fn read_file(path: &Path) -> Result<ImageUpdate, Box<dyn Error>> {
+    if path == Path::new("-") {
+        return serde_json::from_reader(io::stdin().lock())
+            .map_err(|e| format!("stdin: {}", e).into());
+    }
+    let with_path = |e: &dyn Error| format!("{}: {}", path.display(), e);
+    let file = fs::File::open(path).map_err(|e| with_path(&e))?;
+    Ok(serde_json::from_reader(io::BufReader::new(file)).map_err(|e| with_path(&e))?)
+}
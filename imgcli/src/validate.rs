@@ -0,0 +1,70 @@
+use std::error::Error;
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use imgapi::{Discrepancy, Image, Severity, ValidationIssue};
+
+/// Checks `manifest` with [`Image::validate`], and against the IMGAPI JSON schema if `img` was
+/// built with the `schema` feature. If `file` is given, it's checked against the manifest's first
+/// file too, and every way it differs is reported as an error.
+pub fn validate(
+    manifest: &Image,
+    file: Option<&Path>,
+) -> Result<Vec<ValidationIssue>, Box<dyn Error>> {
+    let mut issues = manifest.validate();
+    #[cfg(feature = "schema")]
+    issues.extend(manifest.validate_schema());
+    if let Some(path) = file {
+        let report = imgapi::verify(manifest, 0, path)?;
+        issues.extend(report.discrepancies.iter().map(file_issue));
+    }
+    Ok(issues)
+}
+
+/// An error for the manifest field that `d` shows a local file doesn't match.
+fn file_issue(d: &Discrepancy) -> ValidationIssue {
+    let field = match d {
+        Discrepancy::Checksum(m) => format!("files[0].{}", m.check),
+        Discrepancy::Compression { .. } => "files[0].compression".to_string(),
+        Discrepancy::Missing | Discrepancy::Undecodable(_) => "files[0]".to_string(),
+    };
+    ValidationIssue::error(&field, d.to_string())
+}
+
+/// Whether `issues` should make `img validate` fail: if there are errors, or with `strict`, any
+/// issues at all.
+pub fn fails(issues: &[ValidationIssue], strict: bool) -> bool {
+    issues
+        .iter()
+        .any(|i| strict || i.severity == Severity::Error)
+}
+
+/// A summary of `issues` for the error `img validate` fails with, e.g. `2 errors, 1 warning`.
+pub fn summary(issues: &[ValidationIssue]) -> String {
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    let warnings = issues.len() - errors;
+    let count = |n: usize, what: &str| format!("{} {}{}", n, what, if n == 1 { "" } else { "s" });
+    format!(
+        "the manifest has {}, {}",
+        count(errors, "error"),
+        count(warnings, "warning")
+    )
+}
+
+/// `issues` as a JSON array of `{"severity", "field", "message"}` objects.
+pub fn to_json(issues: &[ValidationIssue]) -> Value {
+    issues
+        .iter()
+        .map(|i| {
+            json!({
+                "severity": i.severity.to_string(),
+                "field": i.field,
+                "message": i.message,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,117 @@
+//! Runs `img` against a local stand-in for an IMGAPI server, checking the status it exits with
+//! and how it fails.
+
+use std::process::Stdio;
+use std::sync::{mpsc, Mutex};
+
+use serde_json::{json, Value};
+
+mod common;
+
+use common::*;
+
+#[test]
+fn help_and_version_succeed() {
+    let server = Server::empty();
+    for args in [&["--help"][..], &["--version"], &["list", "--help"]] {
+        let output = run(&server, args);
+        assert_status(&output, 0);
+        assert!(!stdout(&output).is_empty(), "{:?}", args);
+    }
+    assert!(stdout(&run(&server, &["--help"])).contains("EXIT STATUS"));
+}
+
+#[test]
+fn exits_2_for_a_command_line_that_doesnt_parse() {
+    let server = Server::empty();
+    let output = run(&server, &["list", "--no-such-option"]);
+    assert_status(&output, 2);
+    assert!(stdout(&output).is_empty());
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn exits_2_for_a_command_line_that_doesnt_make_sense() {
+    let server = Server::empty();
+    let output = run(&server, &["list", "--limit", "5", "limit=3"]);
+    assert_status(&output, 2);
+    assert!(stderr(&output).contains("--limit and a limit= filter"));
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn exits_3_for_an_image_that_isnt_found() {
+    let server = Server::empty();
+    let output = run(&server, &["info", &uuid(1)]);
+    assert_status(&output, 3);
+    assert!(stderr(&output).contains(&format!("image {} not found", uuid(1))));
+}
+
+#[test]
+fn exits_4_when_the_server_fails() {
+    let server = Server::start(|_| Response::error(500, "InternalError"));
+    assert_status(&run(&server, &["list"]), 4);
+}
+
+#[test]
+fn exits_6_for_a_download_that_doesnt_match_its_checksum() {
+    let server = Server::start(|req| match req.path() {
+        "/images/00000000-0000-0000-0000-000000000001" => {
+            let mut image = manifest(1);
+            image["files"] = json!([{"sha1": "0".repeat(40), "size": 4, "compression": "none"}]);
+            Response::json(200, &image)
+        }
+        "/images/00000000-0000-0000-0000-000000000001/file" => Response {
+            status: 200,
+            body: b"data".to_vec(),
+        },
+        _ => Response::error(404, "ResourceNotFound"),
+    });
+    let dir = tempfile::tempdir().expect("a temporary directory");
+    let dest = dir.path().join("file");
+    let dest = dest.to_str().expect("a UTF-8 path");
+    let output = run(&server, &["download", "-o", dest, &uuid(1)]);
+    assert_status(&output, 6);
+    assert!(stderr(&output).contains("sha1 mismatch"));
+}
+
+#[test]
+fn exits_1_for_a_change_that_isnt_confirmed() {
+    let server = Server::start(|req| match req.method.as_str() {
+        "GET" => Response::json(200, &manifest(1)),
+        _ => Response::error(500, "InternalError"),
+    });
+    let output = run(&server, &["delete", &uuid(1)]);
+    assert_status(&output, 1);
+    assert!(stderr(&output).contains("without --yes"));
+    assert!(server.requests().iter().all(|r| r.method == "GET"));
+}
+
+#[test]
+fn a_closed_pipe_isnt_a_failure() {
+    let (closed, wait) = mpsc::channel::<()>();
+    let wait = Mutex::new(wait);
+    // The list isn't sent until stdout has been closed, so that writing to it fails.
+    let server = Server::start(move |_| {
+        let _ = wait.lock().expect("wait lock poisoned").recv();
+        Response::json(200, &Value::Array((1..=3).map(manifest).collect()))
+    });
+    for args in [&["list"][..], &["list", "-q"], &["--json", "list"]] {
+        let home = tempfile::tempdir().expect("a temporary directory");
+        let mut child = img(home.path(), &server, args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("running img");
+        drop(child.stdout.take());
+        closed.send(()).expect("the server is running");
+        let output = child.wait_with_output().expect("waiting for img");
+        assert_status(&output, 0);
+        assert!(
+            stderr(&output).is_empty(),
+            "{:?}: {}",
+            args,
+            stderr(&output)
+        );
+    }
+}
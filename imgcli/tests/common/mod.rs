@@ -0,0 +1,318 @@
+//! A local stand-in for an IMGAPI server, the images it serves, and helpers for running `img`
+//! against it. Not every test file uses every helper.
+
+#![allow(dead_code)]
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_json::{json, Value};
+
+/// The UUID numbered `n`, e.g. `00000000-0000-0000-0000-000000000003`.
+pub fn uuid(n: u32) -> String {
+    format!("00000000-0000-0000-0000-{:012}", n)
+}
+
+/// A minimal active manifest of image `n`, published `n` seconds after the others before it.
+pub fn manifest(n: u32) -> Value {
+    json!({
+        "v": 2,
+        "uuid": uuid(n),
+        "owner": uuid(0),
+        "name": "base",
+        "version": format!("1.0.{}", n),
+        "state": "active",
+        "disabled": false,
+        "public": true,
+        "published_at": format!("2024-01-01T00:{:02}:{:02}Z", n / 60 % 60, n % 60),
+        "type": "zone-dataset",
+        "os": "smartos",
+        "files": [{"sha1": "", "size": 0, "compression": "gzip"}],
+    })
+}
+
+/// Image `n`, with `fields` set to other values.
+pub fn with(n: u32, fields: Value) -> Value {
+    let mut image = manifest(n);
+    if let (Some(image), Value::Object(fields)) = (image.as_object_mut(), fields) {
+        image.extend(fields);
+    }
+    image
+}
+
+/// A request received by a [`Server`]: its method, and its path and query.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub target: String,
+}
+
+impl Request {
+    pub fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+
+    /// The value of query parameter `name`, which had better not need decoding.
+    pub fn query(&self, name: &str) -> Option<&str> {
+        let (_, query) = self.target.split_once('?')?;
+        query
+            .split('&')
+            .filter_map(|p| p.split_once('='))
+            .find(|(k, _)| *k == name)
+            .map(|(_, v)| v)
+    }
+}
+
+/// A response for a [`Server`] to send.
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json(status: u16, value: &Value) -> Self {
+        Self {
+            status,
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    /// An IMGAPI error response.
+    pub fn error(status: u16, code: &str) -> Self {
+        Self::json(status, &json!({ "code": code, "message": code }))
+    }
+}
+
+/// A local HTTP server, answering each request with a handler and recording the requests.
+pub struct Server {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl Server {
+    pub fn start<H>(handler: H) -> Self
+    where
+        H: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("binding a local port");
+        let url = format!(
+            "http://{}/",
+            listener.local_addr().expect("a local address")
+        );
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler = Arc::new(handler);
+        let recorded = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (handler, recorded) = (Arc::clone(&handler), Arc::clone(&recorded));
+                thread::spawn(move || {
+                    let _ = serve(stream, &*handler, &recorded);
+                });
+            }
+        });
+        Self { url, requests }
+    }
+
+    /// A server with no images, which answers every request with a 404.
+    pub fn empty() -> Self {
+        Self::start(|_| Response::error(404, "ResourceNotFound"))
+    }
+
+    /// The requests received so far, in the order they arrived.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests
+            .lock()
+            .expect("requests lock poisoned")
+            .clone()
+    }
+}
+
+fn serve(
+    stream: TcpStream,
+    handler: &dyn Fn(&Request) -> Response,
+    recorded: &Mutex<Vec<Request>>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let req = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => Request {
+            method: method.to_string(),
+            target: target.to_string(),
+        },
+        _ => return Ok(()),
+    };
+    let mut length = 0;
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        match line.trim_end().split_once(':') {
+            Some((name, value)) if name.eq_ignore_ascii_case("content-length") => {
+                length = value.trim().parse().unwrap_or_default();
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+    reader.read_exact(&mut vec![0; length])?;
+
+    let resp = handler(&req);
+    recorded.lock().expect("requests lock poisoned").push(req);
+    let mut out = io::BufWriter::new(stream);
+    write!(
+        out,
+        "HTTP/1.1 {} Status\r\nConnection: close\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\n\r\n",
+        resp.status,
+        resp.body.len()
+    )?;
+    out.write_all(&resp.body)?;
+    out.flush()
+}
+
+/// `img` with `args`, with a home directory of `home`, so that no config or environment of the
+/// user running the tests is picked up.
+pub fn img_in(home: &Path, args: &[&str]) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_img"));
+    cmd.env_clear()
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_DATA_HOME", home.join("data"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .args(args)
+        .stdin(Stdio::null());
+    cmd
+}
+
+/// `img` with `args`, using `server`, and with a home directory of `home`.
+pub fn img(home: &Path, server: &Server, args: &[&str]) -> Command {
+    let mut cmd = img_in(home, &["--url", &server.url]);
+    cmd.args(args);
+    cmd
+}
+
+/// Runs `img` with `args` against `server` until it exits.
+pub fn run(server: &Server, args: &[&str]) -> Output {
+    let home = tempfile::tempdir().expect("a temporary directory");
+    img(home.path(), server, args)
+        .output()
+        .expect("running img")
+}
+
+/// Runs `img` with `args` and a home directory of `home` until it exits.
+pub fn run_in(home: &Path, args: &[&str]) -> Output {
+    img_in(home, args).output().expect("running img")
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+/// Asserts that `img` exited with `status`, showing what it wrote to stderr if it didn't.
+pub fn assert_status(output: &Output, status: i32) {
+    assert_eq!(output.status.code(), Some(status), "{}", stderr(output));
+}
+
+/// The lines `img` wrote to stdout.
+pub fn lines(output: &Output) -> Vec<String> {
+    stdout(output).lines().map(str::to_string).collect()
+}
+
+/// A server listing images 1 to `n`, published in that order, whatever the filters. As with
+/// IMGAPI, a page is at most 1000 images, starting at the `marker` image if there is one.
+pub fn listing(n: u32) -> Server {
+    Server::start(move |req| {
+        if req.path() != "/images" {
+            return Response::error(404, "ResourceNotFound");
+        }
+        let first = req
+            .query("marker")
+            .and_then(|m| m.rsplit('-').next()?.parse().ok())
+            .unwrap_or(1);
+        let limit = req
+            .query("limit")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(1000)
+            .min(1000);
+        let page = (first..=n).take(limit).map(manifest).collect();
+        Response::json(200, &Value::Array(page))
+    })
+}
+
+/// A server with `images`, which lists them all whatever the filters, as they all fit on a page,
+/// and gets each by its uuid.
+pub fn catalog(images: Vec<Value>) -> Server {
+    Server::start(move |req| {
+        if req.path() == "/images" {
+            return Response::json(200, &Value::Array(images.clone()));
+        }
+        let found = images
+            .iter()
+            .find(|i| req.path() == format!("/images/{}", i["uuid"].as_str().unwrap_or_default()));
+        match found {
+            Some(image) => Response::json(200, image),
+            None => Response::error(404, "ResourceNotFound"),
+        }
+    })
+}
+
+/// The files of images 1 to 3 of a [`chain`], and their SHA-1s.
+pub const CHAIN_FILES: [(&str, &str); 3] = [
+    ("base", "1405df66cbe219b0bf6355bc3d60361a8376b6b4"),
+    ("app", "7d1043473d55bfa90e8530d35801d4e381bc69f0"),
+    ("leaf", "98798241748efaccb230386437b7873a478f5bd4"),
+];
+
+/// Image `n` of a [`chain`], with its file and its origin, image `n - 1`, if it has one.
+pub fn link(n: u32) -> Value {
+    let (file, sha1) = CHAIN_FILES[n as usize - 1];
+    let files = json!([{"sha1": sha1, "size": file.len(), "compression": "none"}]);
+    match n {
+        1 => with(n, json!({ "files": files })),
+        _ => with(n, json!({ "files": files, "origin": uuid(n - 1) })),
+    }
+}
+
+/// A server with images 1 to 3, each the origin of the next, which gets each image and its file
+/// by its uuid.
+pub fn chain() -> Server {
+    Server::start(|req| {
+        for n in 1..=3 {
+            if req.path() == format!("/images/{}", uuid(n)) {
+                return Response::json(200, &link(n));
+            }
+            if req.path() == format!("/images/{}/file", uuid(n)) {
+                return Response {
+                    status: 200,
+                    body: CHAIN_FILES[n as usize - 1].0.as_bytes().to_vec(),
+                };
+            }
+        }
+        Response::error(404, "ResourceNotFound")
+    })
+}
+
+/// The images whose files `server` was asked for, by number.
+pub fn files_fetched(server: &Server) -> Vec<u32> {
+    server
+        .requests()
+        .iter()
+        .filter_map(|r| {
+            r.path()
+                .strip_suffix("/file")?
+                .rsplit('-')
+                .next()?
+                .parse()
+                .ok()
+        })
+        .collect()
+}
@@ -0,0 +1,211 @@
+//! `img copy` from one configured source to another.
+
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+mod common;
+
+use common::*;
+
+/// The number of the image a request's path is about, e.g. 3 for `/images/<uuid(3)>/file`.
+fn image_number(req: &Request) -> Option<u32> {
+    let path = req.path().strip_prefix("/images/")?;
+    path.split('/').next()?.rsplit('-').next()?.parse().ok()
+}
+
+/// A server to copy images of a [`chain`] to, which has the images in `present` already, and
+/// fails to take the file of image `failing`, if it's set.
+fn destination(present: &'static [u32], failing: Option<u32>) -> Server {
+    Server::start(move |req| {
+        let n = match image_number(req) {
+            Some(n) => n,
+            None => return Response::error(404, "ResourceNotFound"),
+        };
+        let mut unactivated = link(n);
+        unactivated["state"] = json!("unactivated");
+        match (req.method.as_str(), req.query("action")) {
+            ("GET", _) if present.contains(&n) => Response::json(200, &link(n)),
+            ("GET", _) => Response::error(404, "ResourceNotFound"),
+            ("POST", Some("import")) => Response::json(200, &unactivated),
+            ("PUT", _) if failing == Some(n) => Response::error(500, "InternalError"),
+            ("PUT", _) => Response::json(200, &unactivated),
+            ("POST", Some("activate")) => Response::json(200, &link(n)),
+            ("DELETE", _) => Response {
+                status: 204,
+                body: Vec::new(),
+            },
+            _ => Response::error(400, "InvalidParameter"),
+        }
+    })
+}
+
+/// Adds `src` as the source `build` and `dst` as the source `prod` in the config in `home`.
+fn copying(home: &Path, src: &Server, dst: &Server) {
+    assert_status(&run_in(home, &["sources", "add", "build", &src.url]), 0);
+    assert_status(&run_in(home, &["sources", "add", "prod", &dst.url]), 0);
+}
+
+/// The requests other than GETs that `server` received, as `METHOD path`, with the action if
+/// there is one.
+fn changes(server: &Server) -> Vec<String> {
+    server
+        .requests()
+        .iter()
+        .filter(|r| r.method != "GET")
+        .map(|r| match r.query("action") {
+            Some(action) => format!("{} {}?action={}", r.method, r.path(), action),
+            None => format!("{} {}", r.method, r.path()),
+        })
+        .collect()
+}
+
+#[test]
+fn copies_an_image_with_the_ancestors_the_destination_is_missing() {
+    let home = tempfile::tempdir().unwrap();
+    let (src, dst) = (chain(), destination(&[1], None));
+    copying(home.path(), &src, &dst);
+    let output = run_in(
+        home.path(),
+        &[
+            "--json",
+            "copy",
+            "--from",
+            "build",
+            "--to",
+            "prod",
+            "--include-ancestry",
+            &uuid(3),
+        ],
+    );
+    assert_status(&output, 0);
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["copied"], json!([uuid(2), uuid(3)]));
+    assert_eq!(report["present"], json!([uuid(1)]));
+    assert_eq!(report["failed"], Value::Null);
+    assert_eq!(report["bytes"], 3 + 4);
+    assert!(
+        stderr(&output).contains(&format!("already on prod: base@1.0.1 ({})", uuid(1))),
+        "{}",
+        stderr(&output)
+    );
+
+    // The ancestor is activated for the image to be usable, and the image itself is left for
+    // `img activate`.
+    assert_eq!(files_fetched(&src), [2, 3]);
+    assert_eq!(
+        changes(&dst),
+        [
+            format!("POST /images/{}?action=import", uuid(2)),
+            format!("PUT /images/{}/file", uuid(2)),
+            format!("POST /images/{}?action=activate", uuid(2)),
+            format!("POST /images/{}?action=import", uuid(3)),
+            format!("PUT /images/{}/file", uuid(3)),
+        ]
+    );
+}
+
+#[test]
+fn copying_an_image_the_destination_has_copies_nothing() {
+    let home = tempfile::tempdir().unwrap();
+    let (src, dst) = (chain(), destination(&[1, 2, 3], None));
+    copying(home.path(), &src, &dst);
+    let output = run_in(
+        home.path(),
+        &[
+            "copy",
+            "--from",
+            "build",
+            "--to",
+            "prod",
+            "--include-ancestry",
+            &uuid(3),
+        ],
+    );
+    assert_status(&output, 0);
+    assert_eq!(
+        stdout(&output),
+        "copied 0 image(s) (0B) to prod, 3 already there\n"
+    );
+    assert!(files_fetched(&src).is_empty());
+    assert!(changes(&dst).is_empty(), "{:?}", changes(&dst));
+}
+
+#[test]
+fn a_dry_run_copy_only_lists_what_it_would_copy() {
+    let home = tempfile::tempdir().unwrap();
+    let (src, dst) = (chain(), destination(&[1], None));
+    copying(home.path(), &src, &dst);
+    let output = run_in(
+        home.path(),
+        &[
+            "--dry-run",
+            "copy",
+            "--from",
+            "build",
+            "--to",
+            "prod",
+            "--include-ancestry",
+            &uuid(3),
+        ],
+    );
+    assert_status(&output, 0);
+    assert_eq!(
+        stdout(&output),
+        "would copy 2 image(s) (7B) to prod, 1 already there\n"
+    );
+    let expected = format!("would copy: base@1.0.3 ({}), 4B", uuid(3));
+    assert!(stderr(&output).contains(&expected), "{}", stderr(&output));
+    assert!(files_fetched(&src).is_empty());
+    assert!(changes(&dst).is_empty(), "{:?}", changes(&dst));
+}
+
+#[test]
+fn copying_without_the_ancestry_needs_the_origin_there() {
+    let home = tempfile::tempdir().unwrap();
+    let (src, dst) = (chain(), destination(&[1], None));
+    copying(home.path(), &src, &dst);
+    let output = run_in(
+        home.path(),
+        &["copy", "--from", "build", "--to", "prod", &uuid(3)],
+    );
+    assert_status(&output, 1);
+    assert!(
+        stderr(&output).contains("use --include-ancestry to copy it too"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(changes(&dst).is_empty(), "{:?}", changes(&dst));
+}
+
+#[test]
+fn a_copy_that_fails_partway_says_which_images_landed() {
+    let home = tempfile::tempdir().unwrap();
+    let (src, dst) = (chain(), destination(&[], Some(2)));
+    copying(home.path(), &src, &dst);
+    let output = run_in(
+        home.path(),
+        &[
+            "copy",
+            "--from",
+            "build",
+            "--to",
+            "prod",
+            "--include-ancestry",
+            &uuid(3),
+        ],
+    );
+    assert_status(&output, 1);
+    let stderr = stderr(&output);
+    for expected in [
+        format!("copied to prod: {}", uuid(1)),
+        format!("not copied: {} (", uuid(2)),
+        format!("not copied: {} (an image before it failed)", uuid(3)),
+        "copied 1 of 3 images to prod".to_string(),
+    ] {
+        assert!(stderr.contains(&expected), "{:?} in {}", expected, stderr);
+    }
+    // The image that failed isn't left half-copied.
+    assert!(changes(&dst).contains(&format!("DELETE /images/{}", uuid(2))));
+    assert!(!changes(&dst).iter().any(|c| c.contains(&uuid(3))));
+}
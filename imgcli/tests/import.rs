@@ -0,0 +1,110 @@
+//! `img import` into a local store.
+
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+mod common;
+
+use common::*;
+
+/// Runs `img import` of `image` from `server` into `store`, returning what it reported.
+fn import(home: &Path, server: &Server, store: &Path, image: u32) -> Value {
+    let store = store.to_str().expect("a UTF-8 path");
+    let output = img(
+        home,
+        server,
+        &["--json", "import", "--store", store, &uuid(image)],
+    )
+    .output()
+    .expect("running img");
+    assert_status(&output, 0);
+    serde_json::from_slice(&output.stdout).expect("JSON")
+}
+
+#[test]
+fn imports_an_image_and_its_ancestors() {
+    let home = tempfile::tempdir().unwrap();
+    let store = home.path().join("store");
+    let server = chain();
+    let report = import(home.path(), &server, &store, 3);
+    assert_eq!(report["downloaded"], json!([uuid(1), uuid(2), uuid(3)]));
+    assert_eq!(report["present"], json!([]));
+    assert_eq!(report["bytes"], 4 + 3 + 4);
+
+    let mut fetched = files_fetched(&server);
+    fetched.sort_unstable();
+    assert_eq!(fetched, [1, 2, 3]);
+    for n in 1..=3 {
+        let file = std::fs::read(store.join(format!("{}.zfs", uuid(n)))).unwrap();
+        assert_eq!(file, CHAIN_FILES[n as usize - 1].0.as_bytes());
+        assert!(store.join(format!("{}.imgmanifest", uuid(n))).is_file());
+    }
+    let index: Value =
+        serde_json::from_slice(&std::fs::read(store.join("index.json")).unwrap()).unwrap();
+    let indexed: Vec<&String> = index.as_object().unwrap().keys().collect();
+    assert_eq!(indexed, [&uuid(1), &uuid(2), &uuid(3)]);
+    assert_eq!(index[uuid(3)]["sha1"], CHAIN_FILES[2].1);
+    assert_eq!(index[uuid(3)]["source"], server.url);
+}
+
+#[test]
+fn importing_an_image_again_does_nothing() {
+    let home = tempfile::tempdir().unwrap();
+    let store = home.path().join("store");
+    import(home.path(), &chain(), &store, 3);
+
+    let server = chain();
+    let store_arg = store.to_str().unwrap();
+    let output = img(
+        home.path(),
+        &server,
+        &["import", "--store", store_arg, &uuid(3)],
+    )
+    .output()
+    .unwrap();
+    assert_status(&output, 0);
+    assert_eq!(
+        stdout(&output),
+        "imported 0 image(s) (0B), 1 already present\n"
+    );
+    // The image's ancestors were imported before it, so they aren't even looked up.
+    assert!(server.requests().is_empty(), "{:?}", server.requests());
+}
+
+#[test]
+fn imports_only_the_images_missing_from_the_store() {
+    let home = tempfile::tempdir().unwrap();
+    let store = home.path().join("store");
+    import(home.path(), &chain(), &store, 2);
+
+    let server = chain();
+    let report = import(home.path(), &server, &store, 3);
+    assert_eq!(report["downloaded"], json!([uuid(3)]));
+    assert_eq!(report["present"], json!([uuid(1), uuid(2)]));
+    assert_eq!(report["bytes"], 4);
+    assert_eq!(files_fetched(&server), [3]);
+}
+
+#[test]
+fn a_dry_run_import_downloads_nothing() {
+    let home = tempfile::tempdir().unwrap();
+    let store = home.path().join("store");
+    let server = chain();
+    let store_arg = store.to_str().unwrap();
+    let output = img(
+        home.path(),
+        &server,
+        &["--dry-run", "import", "--store", store_arg, &uuid(3)],
+    )
+    .output()
+    .unwrap();
+    assert_status(&output, 0);
+    assert_eq!(
+        stdout(&output),
+        "would import 3 image(s) (11B), 0 already present\n"
+    );
+    assert!(stderr(&output).contains(&format!("would import base@1.0.1 ({}, 4B)", uuid(1))));
+    assert!(files_fetched(&server).is_empty());
+    assert!(!store.join("index.json").exists());
+}
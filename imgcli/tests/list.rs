@@ -0,0 +1,155 @@
+//! `img list`: the columns and formats it prints, and the pages it fetches.
+
+use serde_json::json;
+
+mod common;
+
+use common::*;
+
+#[test]
+fn quiet_prints_just_the_uuids() {
+    let server = listing(3);
+    let output = run(&server, &["list", "-q"]);
+    assert_status(&output, 0);
+    assert_eq!(lines(&output), [uuid(1), uuid(2), uuid(3)]);
+
+    let output = run(&server, &["search", "-q", "base"]);
+    assert_status(&output, 0);
+    let mut found = lines(&output);
+    found.sort();
+    assert_eq!(found, [uuid(1), uuid(2), uuid(3)]);
+
+    let output = run(&server, &["latest", "-q", "name=base"]);
+    assert_status(&output, 0);
+    assert_eq!(lines(&output), [uuid(3)]);
+}
+
+#[test]
+fn quiet_follows_the_sort_and_the_filters() {
+    let server = listing(3);
+    let output = run(
+        &server,
+        &["list", "-q", "-s", "-published_at", "os=smartos"],
+    );
+    assert_status(&output, 0);
+    assert_eq!(lines(&output), [uuid(3), uuid(2), uuid(1)]);
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(
+        requests[0].target.contains("os=smartos"),
+        "{:?}",
+        requests[0]
+    );
+}
+
+#[test]
+fn quiet_cant_be_used_with_json_or_columns() {
+    let server = listing(3);
+    let commands: &[&[&str]] = &[
+        &["list", "-q", "--json"],
+        &["--json", "list", "-q"],
+        &["list", "-q", "-o", "uuid"],
+        &["search", "-q", "--json", "base"],
+        &["--json", "search", "-q", "base"],
+        &["search", "-q", "-o", "uuid", "base"],
+        &["latest", "-q", "--json", "name=base"],
+        &["--json", "latest", "-q", "name=base"],
+    ];
+    for args in commands {
+        let output = run(&server, args);
+        assert_status(&output, 2);
+        assert!(stdout(&output).is_empty(), "{:?}", args);
+    }
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn limit_lists_past_the_first_page() {
+    let server = listing(2500);
+    let output = run(&server, &["list", "-q", "--limit", "1500"]);
+    assert_status(&output, 0);
+    assert_eq!(lines(&output), (1..=1500).map(uuid).collect::<Vec<_>>());
+    assert!(stderr(&output).contains("note: there are more than 1500 matching images"));
+    assert_eq!(server.requests().len(), 2);
+
+    let output = run(&server, &["list", "-q", "--limit", "2500"]);
+    assert_status(&output, 0);
+    assert_eq!(lines(&output).len(), 2500);
+    assert!(!stderr(&output).contains("note:"), "{}", stderr(&output));
+}
+
+#[test]
+fn notes_when_only_the_first_page_is_listed() {
+    let server = listing(1500);
+    let output = run(&server, &["list", "-q"]);
+    assert_status(&output, 0);
+    assert_eq!(lines(&output).len(), 1000);
+    assert!(stderr(&output).contains("note: only the server's first page of 1000 images"));
+
+    let output = run(&server, &["list", "-q", "--all"]);
+    assert_status(&output, 0);
+    assert_eq!(lines(&output).len(), 1500);
+    assert!(!stderr(&output).contains("note:"), "{}", stderr(&output));
+}
+
+#[test]
+fn a_dry_run_list_has_a_query_only_for_filters() {
+    let server = Server::empty();
+    let output = run(&server, &["--dry-run", "list"]);
+    assert_status(&output, 0);
+    assert_eq!(lines(&output)[0], format!("GET {}images", server.url));
+
+    let output = run(&server, &["--dry-run", "list", "os=linux"]);
+    assert_status(&output, 0);
+    assert_eq!(
+        lines(&output)[0],
+        format!("GET {}images?os=linux", server.url)
+    );
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn warns_about_an_invalid_homepage_and_lists_the_rest() {
+    let server = Server::start(|_| {
+        let mut bad = manifest(1);
+        bad["homepage"] = json!("not a url");
+        Response::json(200, &json!([bad, manifest(2)]))
+    });
+    let output = run(&server, &["list", "-q"]);
+    assert_status(&output, 0);
+    assert_eq!(lines(&output), [uuid(1), uuid(2)]);
+    assert_eq!(
+        stderr(&output).trim_end(),
+        format!(
+            "warning: image {} (base@1.0.1) has an invalid homepage URL \"not a url\"",
+            uuid(1)
+        )
+    );
+}
+
+#[test]
+fn parseable_output_composes_with_columns_and_sorting() {
+    let server = listing(3);
+    let output = run(
+        &server,
+        &[
+            "list",
+            "-H",
+            "-p",
+            "-o",
+            "version,uuid",
+            "-s",
+            "-published_at",
+        ],
+    );
+    assert_status(&output, 0);
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "1.0.3\t{}\n1.0.2\t{}\n1.0.1\t{}\n",
+            uuid(3),
+            uuid(2),
+            uuid(1)
+        )
+    );
+}
@@ -0,0 +1,129 @@
+//! Resolving the images given on the command line: UUIDs and their prefixes, `name@version`,
+//! and bare names.
+
+use serde_json::{json, Value};
+
+mod common;
+
+use common::*;
+
+/// The uuid of the image `img info` resolves `image` to, or its exit status and stderr.
+fn resolved(server: &Server, image: &str) -> Result<String, (Option<i32>, String)> {
+    let output = run(server, &["--json", "info", image]);
+    if !output.status.success() {
+        return Err((output.status.code(), stderr(&output)));
+    }
+    let info: Value = serde_json::from_slice(&output.stdout).expect("JSON");
+    Ok(info["uuid"].as_str().expect("a uuid").to_string())
+}
+
+/// Two uuids with the same first 8 digits.
+const FIRST: &str = "1d05e788-5409-11eb-b12f-037bd7fee4ee";
+const SECOND: &str = "1d05e788-aaaa-11eb-b12f-037bd7fee4ee";
+
+#[test]
+fn resolves_a_prefix_with_or_without_hyphens() {
+    let server = catalog(vec![
+        with(1, json!({ "uuid": FIRST })),
+        with(2, json!({ "uuid": SECOND, "state": "disabled" })),
+    ]);
+    assert_eq!(resolved(&server, "1d05e788-54"), Ok(FIRST.to_string()));
+    assert_eq!(resolved(&server, "1D05E78854"), Ok(FIRST.to_string()));
+    assert_eq!(resolved(&server, "1d05e788aa"), Ok(SECOND.to_string()));
+    assert_eq!(resolved(&server, FIRST), Ok(FIRST.to_string()));
+
+    let (status, stderr) = resolved(&server, "1d05-e788").unwrap_err();
+    assert_eq!(status, Some(3), "{}", stderr);
+    assert!(
+        stderr.contains("no image with a UUID starting with 1d05-e788"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn an_ambiguous_prefix_lists_the_candidates() {
+    let server = catalog(vec![
+        with(1, json!({ "uuid": FIRST })),
+        with(2, json!({ "uuid": SECOND })),
+    ]);
+    let (status, stderr) = resolved(&server, "1d05e788").unwrap_err();
+    assert_eq!(status, Some(1), "{}", stderr);
+    assert!(stderr.contains("1d05e788 matches 2 images"), "{}", stderr);
+    assert!(
+        stderr.contains(FIRST) && stderr.contains(SECOND),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn a_prefix_no_uuid_starts_with_is_tried_as_a_name() {
+    let server = catalog(vec![
+        with(1, json!({ "uuid": FIRST })),
+        with(2, json!({ "name": "cafe1234" })),
+    ]);
+    assert_eq!(resolved(&server, "cafe1234"), Ok(uuid(2)));
+    assert_eq!(resolved(&server, "1d05e788"), Ok(FIRST.to_string()));
+
+    let (status, stderr) = resolved(&server, "deadbeef").unwrap_err();
+    assert_eq!(status, Some(3), "{}", stderr);
+    assert!(
+        stderr.contains("or active image named \"deadbeef\""),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn resolves_name_at_version_in_any_state() {
+    let server = catalog(vec![
+        with(1, json!({ "version": "1.0.0" })),
+        with(2, json!({ "version": "1.1.0", "state": "disabled" })),
+        with(3, json!({ "name": "base-64", "version": "1.0.0" })),
+    ]);
+    assert_eq!(resolved(&server, "base@1.0.0"), Ok(uuid(1)));
+    assert_eq!(resolved(&server, "base@1.1.0"), Ok(uuid(2)));
+    assert_eq!(resolved(&server, "base-64@1.0.0"), Ok(uuid(3)));
+    let list = &server.requests()[0];
+    assert_eq!(list.query("name"), Some("base"));
+    assert_eq!(list.query("version"), Some("1.0.0"));
+    assert_eq!(list.query("state"), Some("all"));
+
+    let (status, stderr) = resolved(&server, "base@2.0.0").unwrap_err();
+    assert_eq!(status, Some(3), "{}", stderr);
+    assert!(stderr.contains("no image base@2.0.0"), "{}", stderr);
+}
+
+#[test]
+fn resolves_a_bare_name_to_the_latest_active_version() {
+    let server = catalog(vec![
+        with(1, json!({ "version": "1.9.0" })),
+        with(2, json!({ "version": "1.10.0" })),
+        with(3, json!({ "version": "1.11.0", "disabled": true })),
+        with(4, json!({ "version": "2.0.0", "state": "unactivated" })),
+        with(5, json!({ "name": "base-64", "version": "9.0.0" })),
+    ]);
+    assert_eq!(resolved(&server, "base"), Ok(uuid(2)));
+    assert_eq!(resolved(&server, "base-64"), Ok(uuid(5)));
+
+    let (status, stderr) = resolved(&server, "minimal").unwrap_err();
+    assert_eq!(status, Some(3), "{}", stderr);
+    assert!(
+        stderr.contains("no active image named \"minimal\""),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn a_name_at_version_from_two_owners_is_ambiguous() {
+    let server = catalog(vec![
+        with(1, json!({})),
+        with(2, json!({ "version": "1.0.1", "owner": uuid(9) })),
+    ]);
+    let (status, stderr) = resolved(&server, "base@1.0.1").unwrap_err();
+    assert_eq!(status, Some(1), "{}", stderr);
+    assert!(stderr.contains("base@1.0.1 matches 2 images"), "{}", stderr);
+    assert!(stderr.contains(&format!("owner {}", uuid(9))), "{}", stderr);
+}
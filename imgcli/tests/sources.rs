@@ -0,0 +1,110 @@
+//! Configured sources and Triton profiles.
+
+use serde_json::{json, Value};
+
+mod common;
+
+use common::*;
+
+#[test]
+fn sources_are_kept_in_the_config_dir() {
+    let home = tempfile::tempdir().unwrap();
+    let home = home.path();
+    let (a, b) = (listing(1), listing(2));
+    assert_status(&run_in(home, &["sources", "add", "a", &a.url]), 0);
+    assert_status(&run_in(home, &["sources", "add", "b", &b.url]), 0);
+    let path = home.join("config").join("img").join("sources.json");
+    assert!(path.exists());
+
+    let output = run_in(home, &["--json", "sources", "list"]);
+    assert_status(&output, 0);
+    let sources: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(sources[0]["name"], "a");
+    assert_eq!(sources[0]["default"], true);
+    assert_eq!(sources[1].get("default"), None);
+
+    // The default source is used unless another is picked with -S.
+    assert_eq!(lines(&run_in(home, &["list", "-q"])), [uuid(1)]);
+    assert_eq!(lines(&run_in(home, &["-S", "b", "list", "-q"])).len(), 2);
+    assert_status(&run_in(home, &["sources", "set-default", "b"]), 0);
+    assert_eq!(lines(&run_in(home, &["list", "-q"])).len(), 2);
+
+    assert_status(&run_in(home, &["sources", "remove", "a"]), 0);
+    let output = run_in(home, &["-S", "a", "list"]);
+    assert_status(&output, 1);
+    assert!(
+        stderr(&output).contains("no source named \"a\" (expected one of: b)"),
+        "{}",
+        stderr(&output)
+    );
+    let output = run_in(home, &["sources", "add", "b", &a.url]);
+    assert_status(&output, 1);
+    assert!(stderr(&output).contains("there's already a source named \"b\""));
+}
+
+#[test]
+fn a_corrupt_sources_file_says_how_to_recover() {
+    let home = tempfile::tempdir().unwrap();
+    let home = home.path();
+    let dir = home.join("config").join("img");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("sources.json"), "{\"sources\": [").unwrap();
+
+    let output = run_in(home, &["sources", "list"]);
+    assert_status(&output, 1);
+    assert!(
+        stderr(&output).contains("(fix the file, or remove it to start over)"),
+        "{}",
+        stderr(&output)
+    );
+    let server = listing(1);
+    assert_status(&run_in(home, &["sources", "add", "a", &server.url]), 1);
+
+    std::fs::remove_file(dir.join("sources.json")).unwrap();
+    assert_status(&run_in(home, &["sources", "add", "a", &server.url]), 0);
+    assert_eq!(lines(&run_in(home, &["list", "-q"])), [uuid(1)]);
+}
+
+#[test]
+fn lists_triton_profiles_marking_the_one_in_use() {
+    let home = tempfile::tempdir().unwrap();
+    let triton = home.path().join(".triton");
+    std::fs::create_dir_all(&triton).unwrap();
+    let profile = |url: &str| json!({ "url": url, "account": "me", "keyId": "SHA256:abc" });
+    let profiles = json!({
+        "west": profile("https://cloudapi.us-west-1.example.com"),
+        "lab": profile("https://api.lab.example.com"),
+    });
+    std::fs::write(triton.join("profiles.json"), profiles.to_string()).unwrap();
+
+    let output = img_in(home.path(), &["profiles", "-H"])
+        .env("TRITON_PROFILE", "west")
+        .output()
+        .unwrap();
+    assert_status(&output, 0);
+    let rows: Vec<Vec<String>> = lines(&output)
+        .iter()
+        .map(|l| l.split_whitespace().map(str::to_string).collect())
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            vec!["lab", "me", "https://api.lab.example.com/", "-"],
+            vec![
+                "*",
+                "west",
+                "me",
+                "https://cloudapi.us-west-1.example.com/",
+                "https://imgapi.us-west-1.example.com/"
+            ],
+        ]
+    );
+
+    let output = run_in(home.path(), &["--profile", "east", "list"]);
+    assert_status(&output, 1);
+    assert!(
+        stderr(&output).contains("no profile \"east\" (the profiles are lab, west)"),
+        "{}",
+        stderr(&output)
+    );
+}